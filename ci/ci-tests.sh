@@ -50,6 +50,10 @@ cargo build --verbose --color always --features rpc-client,rest-client
 cargo test --verbose --color always --features rpc-client,rest-client
 cargo build --verbose --color always --features rpc-client,rest-client,tokio
 cargo test --verbose --color always --features rpc-client,rest-client,tokio
+cargo build --verbose --color always --features esplora-client
+cargo test --verbose --color always --features esplora-client
+cargo build --verbose --color always --features esplora-client,tokio
+cargo test --verbose --color always --features esplora-client,tokio
 popd
 
 if [[ $RUSTC_MINOR_VERSION -gt 67 && "$HOST_PLATFORM" != *windows* ]]; then
@@ -61,6 +65,8 @@ if [[ $RUSTC_MINOR_VERSION -gt 67 && "$HOST_PLATFORM" != *windows* ]]; then
 	cargo test --verbose --color always --features esplora-async
 	cargo build --verbose --color always --features esplora-async-https
 	cargo test --verbose --color always --features esplora-async-https
+	cargo build --verbose --color always --features electrum
+	cargo test --verbose --color always --features electrum
 	popd
 fi
 
@@ -137,3 +143,8 @@ echo -e "\n\nTest Taproot builds"
 pushd lightning
 RUSTFLAGS="$RUSTFLAGS --cfg=taproot" cargo test --verbose --color always -p lightning
 popd
+
+echo -e "\n\nTest dual-funding builds"
+pushd lightning
+RUSTFLAGS="$RUSTFLAGS --cfg=dual_funding" cargo test --verbose --color always -p lightning
+popd
@@ -251,11 +251,7 @@ impl SignerProvider for KeyProvider {
 		let inner: InMemorySigner = ReadableArgs::read(&mut reader, self)?;
 		let state = self.make_enforcement_state_cell(inner.commitment_seed);
 
-		Ok(EnforcingSigner {
-			inner,
-			state,
-			disable_revocation_policy_check: false,
-		})
+		Ok(EnforcingSigner::new_with_revoked(inner, state, false))
 	}
 
 	fn get_destination_script(&self) -> Result<Script, ()> {
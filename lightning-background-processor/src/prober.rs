@@ -0,0 +1,483 @@
+//! A background prober which continuously trains a scorer by sending payment probes.
+//!
+//! A routing node which doesn't see much outbound payment traffic of its own has little data to
+//! train its [`ProbabilisticScorer`] with. [`Prober`] fills this gap by periodically picking a
+//! target node from the [`NetworkGraph`] and sending payment probes to it along the paths the
+//! [`Router`] would choose.
+//!
+//! [`BackgroundProcessor`] does not drive the [`Prober`]. Instead, callers are expected to call
+//! [`Prober::probe`] on a timer of their own, e.g., from a thread which sleeps between calls or a
+//! `tokio::time::interval`.
+//!
+//! The results of probes surface as [`Event::ProbeSuccessful`] and [`Event::ProbeFailed`], which
+//! [`BackgroundProcessor`] already feeds into the scorer. Events should additionally be passed to
+//! [`Prober::handle_event`] so that the [`Prober`] can keep track of its in-flight probes.
+//!
+//! [`ProbabilisticScorer`]: lightning::routing::scoring::ProbabilisticScorer
+//! [`Router`]: lightning::routing::router::Router
+//! [`BackgroundProcessor`]: crate::BackgroundProcessor
+
+use bitcoin::secp256k1::PublicKey;
+
+use lightning::chain;
+use lightning::chain::chaininterface::{BroadcasterInterface, FeeEstimator};
+use lightning::events::Event;
+use lightning::ln::PaymentHash;
+use lightning::ln::channelmanager::{ChannelManager, MIN_FINAL_CLTV_EXPIRY_DELTA, PaymentId, ProbeSendFailure, RecentPaymentDetails};
+use lightning::routing::gossip::{ChannelInfo, NetworkGraph, NodeId};
+use lightning::routing::router::{PaymentParameters, RouteParameters, Router};
+use lightning::sign::{EntropySource, NodeSigner, SignerProvider};
+use lightning::util::logger::Logger;
+
+use core::convert::TryInto;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// How a [`Prober`] picks the node to send its next probe to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProbeTargetSelection {
+	/// Pick a node from the [`NetworkGraph`] uniformly at random.
+	Random,
+	/// Pick a node from the [`NetworkGraph`] at random, weighted by the total capacity of its
+	/// public channels.
+	///
+	/// This focuses probing on the parts of the network which are most likely to be used for
+	/// payments.
+	ByCapacity,
+	/// Cycle through the given list of nodes.
+	Fixed(Vec<PublicKey>),
+}
+
+/// Configuration for a [`Prober`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProberConfig {
+	/// How the target of each probe is picked.
+	///
+	/// Default value: [`ProbeTargetSelection::Random`]
+	pub target_selection: ProbeTargetSelection,
+	/// The amount, in millisatoshis, to probe each target with.
+	///
+	/// If less than this amount remains available under [`Self::max_in_flight_msat`], targets are
+	/// probed with the remaining amount instead.
+	///
+	/// Default value: 50,000,000 msat
+	pub probe_amount_msat: u64,
+	/// The maximum number of probes which may be in-flight at once. No further probes will be sent
+	/// until earlier probes resolve.
+	///
+	/// As a target may be probed over several paths, each of which is sent as a separate probe,
+	/// this also limits the number of paths used for a target.
+	///
+	/// Default value: 3
+	pub max_in_flight_probes: usize,
+	/// The maximum total amount, in millisatoshis, which may be locked up in in-flight probes at
+	/// once, excluding any routing fees paid to intermediate nodes.
+	///
+	/// Default value: 150,000,000 msat
+	pub max_in_flight_msat: u64,
+	/// Passed through to [`ChannelManager::send_preflight_probes`], which skips first hops that
+	/// would be left with less than this multiple of the probed amount in outbound liquidity.
+	///
+	/// Default value: `None`, i.e., [`ChannelManager::send_preflight_probes`]'s default.
+	pub liquidity_limit_multiplier: Option<u64>,
+}
+
+impl Default for ProberConfig {
+	fn default() -> Self {
+		Self {
+			target_selection: ProbeTargetSelection::Random,
+			probe_amount_msat: 50_000_000,
+			max_in_flight_probes: 3,
+			max_in_flight_msat: 150_000_000,
+			liquidity_limit_multiplier: None,
+		}
+	}
+}
+
+/// Sends payment probes to nodes picked from the [`NetworkGraph`] so that a scorer stays accurate
+/// without relying on real payment traffic.
+///
+/// [`Prober::probe`] should be called periodically by the user, e.g., once a minute, and all
+/// [`Event`]s should be passed to [`Prober::handle_event`]. The scorer itself is updated with the results of the
+/// probes by [`BackgroundProcessor`] or, if it is not used, by the user's event handler.
+///
+/// [`BackgroundProcessor`]: crate::BackgroundProcessor
+pub struct Prober<
+	CW: Deref,
+	T: Deref,
+	ES: Deref,
+	NS: Deref,
+	SP: Deref,
+	F: Deref,
+	R: Deref,
+	L: Deref,
+	CM: Deref<Target = ChannelManager<CW, T, ES, NS, SP, F, R, L>>,
+	G: Deref<Target = NetworkGraph<L>>,
+> where
+	CW::Target: chain::Watch<<SP::Target as SignerProvider>::Signer>,
+	T::Target: BroadcasterInterface,
+	ES::Target: EntropySource,
+	NS::Target: NodeSigner,
+	SP::Target: SignerProvider,
+	F::Target: FeeEstimator,
+	R::Target: Router,
+	L::Target: Logger,
+{
+	channel_manager: CM,
+	network_graph: G,
+	entropy_source: ES,
+	logger: L,
+	config: ProberConfig,
+	next_fixed_target: AtomicUsize,
+	// Maps the `PaymentId` of each in-flight probe to its `PaymentHash` and the amount it locks up.
+	in_flight_probes: Mutex<HashMap<PaymentId, (PaymentHash, u64)>>,
+}
+
+impl<
+	CW: Deref,
+	T: Deref,
+	ES: Deref,
+	NS: Deref,
+	SP: Deref,
+	F: Deref,
+	R: Deref,
+	L: Deref,
+	CM: Deref<Target = ChannelManager<CW, T, ES, NS, SP, F, R, L>>,
+	G: Deref<Target = NetworkGraph<L>>,
+> Prober<CW, T, ES, NS, SP, F, R, L, CM, G> where
+	CW::Target: chain::Watch<<SP::Target as SignerProvider>::Signer>,
+	T::Target: BroadcasterInterface,
+	ES::Target: EntropySource,
+	NS::Target: NodeSigner,
+	SP::Target: SignerProvider,
+	F::Target: FeeEstimator,
+	R::Target: Router,
+	L::Target: Logger,
+{
+	/// Constructs a new [`Prober`].
+	///
+	/// The `entropy_source` is used to pick random probe targets.
+	pub fn new(
+		channel_manager: CM, network_graph: G, entropy_source: ES, logger: L, config: ProberConfig,
+	) -> Self {
+		Self {
+			channel_manager,
+			network_graph,
+			entropy_source,
+			logger,
+			config,
+			next_fixed_target: AtomicUsize::new(0),
+			in_flight_probes: Mutex::new(HashMap::new()),
+		}
+	}
+
+	/// Picks a target and sends probes to it, unless the configured in-flight limits have been
+	/// reached or no target is available.
+	///
+	/// This is not called by [`BackgroundProcessor`], so should be called on a timer by the user.
+	///
+	/// Returns the [`PaymentHash`] and [`PaymentId`] of each probe sent.
+	///
+	/// [`BackgroundProcessor`]: crate::BackgroundProcessor
+	pub fn probe(&self) -> Result<Vec<(PaymentHash, PaymentId)>, ProbeSendFailure> {
+		let mut in_flight_probes = self.in_flight_probes.lock().unwrap();
+		self.prune_resolved_probes(&mut in_flight_probes);
+		let available_probes = self.config.max_in_flight_probes.saturating_sub(in_flight_probes.len());
+		if available_probes == 0 {
+			log_trace!(self.logger, "Not probing as {} probes are already in-flight", in_flight_probes.len());
+			return Ok(Vec::new());
+		}
+		let in_flight_msat = in_flight_probes.values().fold(0u64, |acc, (_, amt)| acc.saturating_add(*amt));
+		let available_msat = self.config.max_in_flight_msat.saturating_sub(in_flight_msat);
+		if available_msat == 0 {
+			log_trace!(self.logger, "Not probing as {} msat is already locked up in in-flight probes", in_flight_msat);
+			return Ok(Vec::new());
+		}
+
+		let target = match self.next_target() {
+			Some(target) => target,
+			None => {
+				log_trace!(self.logger, "Not probing as no probe target is available");
+				return Ok(Vec::new());
+			},
+		};
+
+		// Each path of the route is probed separately, so limit the number of paths to the number
+		// of probes we may still send, and the amount to what we may still lock up.
+		let amount_msat = core::cmp::min(self.config.probe_amount_msat, available_msat);
+		let max_path_count = core::cmp::min(available_probes, u8::max_value() as usize) as u8;
+		let payment_params = PaymentParameters::from_node_id(target, MIN_FINAL_CLTV_EXPIRY_DELTA as u32)
+			.with_max_path_count(max_path_count);
+		let route_params = RouteParameters { payment_params, final_value_msat: amount_msat };
+
+		log_debug!(self.logger, "Probing node {} with {} msat over at most {} paths", target, amount_msat, max_path_count);
+		let probes = self.channel_manager.send_preflight_probes(route_params, self.config.liquidity_limit_multiplier)?;
+
+		// Some paths may have been skipped, so track the amount each probe actually locks up.
+		let pending_amounts = self.pending_payment_amounts();
+		for (payment_hash, payment_id) in probes.iter() {
+			if let Some(amount_msat) = pending_amounts.get(payment_hash) {
+				in_flight_probes.insert(*payment_id, (*payment_hash, *amount_msat));
+			}
+		}
+		debug_assert!(in_flight_probes.len() <= self.config.max_in_flight_probes);
+		debug_assert!(in_flight_probes.values().fold(0u64, |acc, (_, amt)| acc.saturating_add(*amt))
+			<= self.config.max_in_flight_msat);
+		Ok(probes)
+	}
+
+	/// Returns the total amount of each payment the [`ChannelManager`] considers pending, keyed by
+	/// its [`PaymentHash`].
+	fn pending_payment_amounts(&self) -> HashMap<PaymentHash, u64> {
+		self.channel_manager.list_recent_payments().into_iter()
+			.filter_map(|details| match details {
+				RecentPaymentDetails::Pending { payment_hash, total_msat } => Some((payment_hash, total_msat)),
+				_ => None,
+			})
+			.collect()
+	}
+
+	/// Stops tracking probes which the [`ChannelManager`] no longer considers pending, e.g., as
+	/// their resolution wasn't passed to [`Prober::handle_event`] before a restart.
+	fn prune_resolved_probes(&self, in_flight_probes: &mut HashMap<PaymentId, (PaymentHash, u64)>) {
+		let pending_amounts = self.pending_payment_amounts();
+		in_flight_probes.retain(|_, (payment_hash, _)| pending_amounts.contains_key(payment_hash));
+	}
+
+	/// Stops tracking the probe an [`Event::ProbeSuccessful`] or [`Event::ProbeFailed`] refers to,
+	/// freeing up room under the configured in-flight limits.
+	///
+	/// Returns whether the event concerned a probe sent by this [`Prober`].
+	pub fn handle_event(&self, event: &Event) -> bool {
+		match event {
+			Event::ProbeSuccessful { payment_id, .. } | Event::ProbeFailed { payment_id, .. } => {
+				self.in_flight_probes.lock().unwrap().remove(payment_id).is_some()
+			},
+			_ => false,
+		}
+	}
+
+	/// Returns the number of probes currently in-flight.
+	pub fn in_flight_probes(&self) -> usize {
+		let mut in_flight_probes = self.in_flight_probes.lock().unwrap();
+		self.prune_resolved_probes(&mut in_flight_probes);
+		in_flight_probes.len()
+	}
+
+	fn random_u64s(&self) -> (u64, u64) {
+		let bytes = self.entropy_source.get_secure_random_bytes();
+		(u64::from_be_bytes(bytes[..8].try_into().unwrap()),
+			u64::from_be_bytes(bytes[8..16].try_into().unwrap()))
+	}
+
+	fn next_target(&self) -> Option<PublicKey> {
+		let our_node_id = NodeId::from_pubkey(&self.channel_manager.get_our_node_id());
+		match &self.config.target_selection {
+			ProbeTargetSelection::Fixed(targets) => {
+				if targets.is_empty() { return None; }
+				let idx = self.next_fixed_target.fetch_add(1, Ordering::AcqRel) % targets.len();
+				Some(targets[idx])
+			},
+			ProbeTargetSelection::Random => {
+				let graph = self.network_graph.read_only();
+				let candidates = graph.nodes().unordered_keys()
+					.filter(|node_id| **node_id != our_node_id)
+					.collect::<Vec<_>>();
+				if candidates.is_empty() { return None; }
+				let (rand, _) = self.random_u64s();
+				candidates[(rand % candidates.len() as u64) as usize].as_pubkey().ok()
+			},
+			ProbeTargetSelection::ByCapacity => {
+				let graph = self.network_graph.read_only();
+				let total_capacity_sats = graph.channels().unordered_iter()
+					.map(|(_, chan)| channel_capacity_sats(chan))
+					.fold(0u64, |acc, capacity| acc.saturating_add(capacity));
+				if total_capacity_sats == 0 { return None; }
+
+				// Walk the channels until we hit the randomly chosen satoshi, then pick one of the
+				// channel's counterparties, avoiding ourselves.
+				let (rand, side) = self.random_u64s();
+				let mut remaining_sats = rand % total_capacity_sats;
+				for (_, chan) in graph.channels().unordered_iter() {
+					let capacity_sats = channel_capacity_sats(chan);
+					if remaining_sats >= capacity_sats {
+						remaining_sats -= capacity_sats;
+						continue;
+					}
+					let target = if chan.node_one == our_node_id {
+						&chan.node_two
+					} else if chan.node_two == our_node_id || side % 2 == 0 {
+						&chan.node_one
+					} else {
+						&chan.node_two
+					};
+					return target.as_pubkey().ok();
+				}
+				None
+			},
+		}
+	}
+}
+
+/// Returns the capacity of the given channel, falling back to the largest HTLC it may carry if
+/// the capacity is unknown, e.g., as the channel's funding output wasn't looked up.
+fn channel_capacity_sats(chan: &ChannelInfo) -> u64 {
+	chan.capacity_sats.unwrap_or_else(|| {
+		let htlc_maximum_msat = chan.one_to_two.iter().chain(chan.two_to_one.iter())
+			.map(|update| update.htlc_maximum_msat)
+			.max()
+			.unwrap_or(0);
+		htlc_maximum_msat / 1000
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Prober, ProberConfig, ProbeTargetSelection};
+
+	use lightning::events::{Event, MessageSendEventsProvider};
+	use lightning::ln::channelmanager::RecentPaymentDetails;
+	use lightning::ln::functional_test_utils::*;
+	use lightning::routing::router::PaymentParameters;
+
+	#[test]
+	fn respects_in_flight_limits() {
+		let chanmon_cfgs = create_chanmon_cfgs(3);
+		let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+		let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+
+		create_announced_chan_between_nodes(&nodes, 0, 1);
+		create_announced_chan_between_nodes(&nodes, 1, 2);
+
+		let config = ProberConfig {
+			target_selection: ProbeTargetSelection::Fixed(vec![nodes[2].node.get_our_node_id()]),
+			probe_amount_msat: 100_000,
+			max_in_flight_probes: 1,
+			..ProberConfig::default()
+		};
+		let prober = Prober::new(nodes[0].node, nodes[0].network_graph, nodes[0].keys_manager,
+			nodes[0].logger, config);
+
+		let probes = prober.probe().unwrap();
+		assert_eq!(probes.len(), 1);
+		assert_eq!(prober.in_flight_probes(), 1);
+		check_added_monitors!(nodes[0], 1);
+		let _ = SendEvent::from_node(&nodes[0]);
+
+		// We've hit the in-flight limit, so no further probe goes out.
+		assert!(prober.probe().unwrap().is_empty());
+		assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+
+		// Once the probe resolves we're free to probe again.
+		let (payment_hash, payment_id) = probes[0];
+		let payment_params = PaymentParameters::from_node_id(nodes[2].node.get_our_node_id(), TEST_FINAL_CLTV);
+		let path = get_route(&nodes[0], &payment_params, 100_000).unwrap().paths[0].clone();
+		assert!(prober.handle_event(&Event::ProbeFailed { payment_id, payment_hash, path, short_channel_id: None }));
+		assert_eq!(prober.in_flight_probes(), 0);
+
+		// As we're still awaiting the counterparty's `revoke_and_ack`, the new probe's HTLC is
+		// held in the holding cell for now.
+		assert_eq!(prober.probe().unwrap().len(), 1);
+		check_added_monitors!(nodes[0], 0);
+	}
+
+	#[test]
+	fn limits_probes_to_remaining_budget() {
+		let chanmon_cfgs = create_chanmon_cfgs(3);
+		let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+		let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+
+		create_announced_chan_between_nodes(&nodes, 0, 1);
+		create_announced_chan_between_nodes(&nodes, 1, 2);
+
+		let config = ProberConfig {
+			target_selection: ProbeTargetSelection::Fixed(vec![nodes[2].node.get_our_node_id()]),
+			probe_amount_msat: 100_000,
+			max_in_flight_probes: 3,
+			max_in_flight_msat: 150_000,
+			..ProberConfig::default()
+		};
+		let prober = Prober::new(nodes[0].node, nodes[0].network_graph, nodes[0].keys_manager,
+			nodes[0].logger, config);
+
+		assert_eq!(prober.probe().unwrap().len(), 1);
+		check_added_monitors!(nodes[0], 1);
+		let _ = SendEvent::from_node(&nodes[0]);
+
+		// Only 50,000 msat remain available, so the second probe is sent with that amount.
+		assert_eq!(prober.probe().unwrap().len(), 1);
+		let mut amounts = nodes[0].node.list_recent_payments().into_iter()
+			.map(|details| match details {
+				RecentPaymentDetails::Pending { total_msat, .. } => total_msat,
+				_ => panic!("Unexpected payment details"),
+			})
+			.collect::<Vec<_>>();
+		amounts.sort_unstable();
+		assert_eq!(amounts, vec![50_000, 100_000]);
+		assert_eq!(prober.in_flight_probes(), 2);
+
+		// With the budget used up, no further probe goes out even though we may send another one.
+		assert!(prober.probe().unwrap().is_empty());
+		assert_eq!(prober.in_flight_probes(), 2);
+	}
+
+	#[test]
+	fn picks_targets_from_graph() {
+		let chanmon_cfgs = create_chanmon_cfgs(3);
+		let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+		let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+
+		create_announced_chan_between_nodes(&nodes, 0, 1);
+		create_announced_chan_between_nodes(&nodes, 1, 2);
+
+		for target_selection in [ProbeTargetSelection::Random, ProbeTargetSelection::ByCapacity].iter() {
+			let config = ProberConfig { target_selection: target_selection.clone(), ..ProberConfig::default() };
+			let prober = Prober::new(nodes[0].node, nodes[0].network_graph, nodes[0].keys_manager,
+				nodes[0].logger, config);
+			for _ in 0..10 {
+				let target = prober.next_target().unwrap();
+				assert_ne!(target, nodes[0].node.get_our_node_id());
+			}
+		}
+	}
+
+	#[test]
+	fn stops_tracking_resolved_probes_without_events() {
+		let chanmon_cfgs = create_chanmon_cfgs(3);
+		let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+		let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+
+		create_announced_chan_between_nodes(&nodes, 0, 1);
+		create_announced_chan_between_nodes(&nodes, 1, 2);
+
+		let config = ProberConfig {
+			target_selection: ProbeTargetSelection::Fixed(vec![nodes[2].node.get_our_node_id()]),
+			probe_amount_msat: 100_000,
+			max_in_flight_probes: 1,
+			..ProberConfig::default()
+		};
+		let prober = Prober::new(nodes[0].node, nodes[0].network_graph, nodes[0].keys_manager,
+			nodes[0].logger, config);
+
+		let probes = prober.probe().unwrap();
+		assert_eq!(probes.len(), 1);
+		assert_eq!(prober.in_flight_probes(), 1);
+		check_added_monitors!(nodes[0], 1);
+		let _ = SendEvent::from_node(&nodes[0]);
+
+		// Even if the probe's resolution is never passed to the prober, it stops tracking the probe
+		// once the `ChannelManager` no longer considers it pending.
+		let (_, payment_id) = probes[0];
+		nodes[0].node.abandon_payment(payment_id);
+		assert_eq!(prober.in_flight_probes(), 0);
+		assert_eq!(prober.probe().unwrap().len(), 1);
+		check_added_monitors!(nodes[0], 0);
+		nodes[0].node.get_and_clear_pending_events();
+	}
+}
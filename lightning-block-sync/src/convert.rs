@@ -4,8 +4,9 @@ use crate::{BlockHeaderData, BlockSourceError};
 
 use bitcoin::blockdata::block::{Block, BlockHeader};
 use bitcoin::consensus::encode;
-use bitcoin::hash_types::{BlockHash, TxMerkleNode, Txid};
+use bitcoin::hash_types::{BlockHash, FilterHeader, TxMerkleNode, Txid};
 use bitcoin::hashes::hex::FromHex;
+use bitcoin::util::bip158::BlockFilter;
 use bitcoin::Transaction;
 
 use serde_json;
@@ -38,6 +39,32 @@ impl TryInto<Block> for BinaryResponse {
 	}
 }
 
+/// Parses binary data as a BIP 158 block filter, as served by Bitcoin Core's REST `blockfilter`
+/// endpoint, i.e., the filter type and block hash followed by the encoded filter.
+impl TryInto<BlockFilter> for BinaryResponse {
+	type Error = std::io::Error;
+
+	fn try_into(self) -> std::io::Result<BlockFilter> {
+		match encode::deserialize::<(u8, BlockHash, Vec<u8>)>(&self.0) {
+			Err(_) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid block filter data")),
+			Ok((_, _, content)) => Ok(BlockFilter::new(&content)),
+		}
+	}
+}
+
+/// Parses binary data as a BIP 157 filter header, as served by Bitcoin Core's REST
+/// `blockfilterheaders` endpoint when requesting a single header.
+impl TryInto<FilterHeader> for BinaryResponse {
+	type Error = std::io::Error;
+
+	fn try_into(self) -> std::io::Result<FilterHeader> {
+		match encode::deserialize(&self.0) {
+			Err(_) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid filter header data")),
+			Ok(filter_header) => Ok(filter_header),
+		}
+	}
+}
+
 /// Converts a JSON value into block header data. The JSON value may be an object representing a
 /// block header or an array of such objects. In the latter case, the first object is converted.
 impl TryInto<BlockHeaderData> for JsonResponse {
@@ -138,6 +165,44 @@ impl TryInto<(BlockHash, Option<u32>)> for JsonResponse {
 	}
 }
 
+/// Converts a JSON value into a BIP 158 block filter, as returned by the `getblockfilter` RPC.
+impl TryInto<BlockFilter> for JsonResponse {
+	type Error = std::io::Error;
+
+	fn try_into(self) -> std::io::Result<BlockFilter> {
+		if !self.0.is_object() {
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected JSON object"));
+		}
+
+		match &self.0["filter"] {
+			serde_json::Value::String(hex_data) => match Vec::<u8>::from_hex(&hex_data) {
+				Err(_) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid hex data")),
+				Ok(content) => Ok(BlockFilter::new(&content)),
+			},
+			_ => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected JSON string")),
+		}
+	}
+}
+
+/// Converts a JSON value into a BIP 157 filter header, as returned by the `getblockfilter` RPC.
+impl TryInto<FilterHeader> for JsonResponse {
+	type Error = std::io::Error;
+
+	fn try_into(self) -> std::io::Result<FilterHeader> {
+		if !self.0.is_object() {
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected JSON object"));
+		}
+
+		match &self.0["header"] {
+			serde_json::Value::String(hex_data) => match FilterHeader::from_hex(&hex_data) {
+				Err(_) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid hex data")),
+				Ok(filter_header) => Ok(filter_header),
+			},
+			_ => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected JSON string")),
+		}
+	}
+}
+
 impl TryInto<Txid> for JsonResponse {
 	type Error = std::io::Error;
 	fn try_into(self) -> std::io::Result<Txid> {
@@ -444,6 +509,105 @@ pub(crate) mod tests {
 		}
 	}
 
+	#[test]
+	fn into_block_filter_from_invalid_binary_response() {
+		let response = BinaryResponse(b"foo".to_vec());
+		match TryInto::<BlockFilter>::try_into(response) {
+			Err(e) => {
+				assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+				assert_eq!(e.get_ref().unwrap().to_string(), "invalid block filter data");
+			},
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+
+	#[test]
+	fn into_block_filter_from_valid_binary_response() {
+		let block_hash = genesis_block(Network::Bitcoin).block_hash();
+		let response = BinaryResponse(encode::serialize(&(0u8, block_hash, vec![1u8, 2, 3])));
+		match TryInto::<BlockFilter>::try_into(response) {
+			Err(e) => panic!("Unexpected error: {:?}", e),
+			Ok(filter) => assert_eq!(filter, BlockFilter::new(&[1, 2, 3])),
+		}
+	}
+
+	#[test]
+	fn into_block_filter_from_json_response_with_unexpected_type() {
+		let response = JsonResponse(serde_json::json!("foo"));
+		match TryInto::<BlockFilter>::try_into(response) {
+			Err(e) => {
+				assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+				assert_eq!(e.get_ref().unwrap().to_string(), "expected JSON object");
+			},
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+
+	#[test]
+	fn into_block_filter_from_json_response_with_invalid_hex_data() {
+		let response = JsonResponse(serde_json::json!({ "filter": "foobar" }));
+		match TryInto::<BlockFilter>::try_into(response) {
+			Err(e) => {
+				assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+				assert_eq!(e.get_ref().unwrap().to_string(), "invalid hex data");
+			},
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+
+	#[test]
+	fn into_block_filter_from_json_response_with_valid_filter_data() {
+		let response = JsonResponse(serde_json::json!({ "filter": "010203", "header": "00" }));
+		match TryInto::<BlockFilter>::try_into(response) {
+			Err(e) => panic!("Unexpected error: {:?}", e),
+			Ok(filter) => assert_eq!(filter, BlockFilter::new(&[1, 2, 3])),
+		}
+	}
+
+	#[test]
+	fn into_filter_header_from_invalid_binary_response() {
+		let response = BinaryResponse(b"foo".to_vec());
+		match TryInto::<FilterHeader>::try_into(response) {
+			Err(e) => {
+				assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+				assert_eq!(e.get_ref().unwrap().to_string(), "invalid filter header data");
+			},
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+
+	#[test]
+	fn into_filter_header_from_valid_binary_response() {
+		let filter_header = FilterHeader::from_inner([42; 32]);
+		let response = BinaryResponse(encode::serialize(&filter_header));
+		match TryInto::<FilterHeader>::try_into(response) {
+			Err(e) => panic!("Unexpected error: {:?}", e),
+			Ok(header) => assert_eq!(header, filter_header),
+		}
+	}
+
+	#[test]
+	fn into_filter_header_from_json_response_with_invalid_hex_data() {
+		let response = JsonResponse(serde_json::json!({ "filter": "010203", "header": "foobar" }));
+		match TryInto::<FilterHeader>::try_into(response) {
+			Err(e) => {
+				assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+				assert_eq!(e.get_ref().unwrap().to_string(), "invalid hex data");
+			},
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+
+	#[test]
+	fn into_filter_header_from_json_response_with_valid_header_data() {
+		let filter_header = FilterHeader::from_inner([42; 32]);
+		let response = JsonResponse(serde_json::json!({ "filter": "010203", "header": filter_header.to_hex() }));
+		match TryInto::<FilterHeader>::try_into(response) {
+			Err(e) => panic!("Unexpected error: {:?}", e),
+			Ok(header) => assert_eq!(header, filter_header),
+		}
+	}
+
 	#[test]
 	fn into_block_hash_from_json_response_with_unexpected_type() {
 		let response = JsonResponse(serde_json::json!("foo"));
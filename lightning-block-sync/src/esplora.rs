@@ -0,0 +1,519 @@
+//! Simple client implementation which implements [`BlockSource`], [`FeeEstimator`] and
+//! [`BroadcasterInterface`] against an [Esplora] HTTP endpoint.
+//!
+//! [Esplora]: https://github.com/Blockstream/esplora/blob/master/API.md
+
+use crate::{BlockData, BlockHeaderData, BlockSource, AsyncBlockSourceResult, BlockSourceError, BlockSourceResult};
+use crate::http::{BinaryResponse, HttpClient, HttpEndpoint, HttpError, JsonResponse};
+use crate::utils::{confirmation_target_blocks, fallback_sat_per_1000_weight};
+
+use bitcoin::blockdata::block::BlockHeader;
+use bitcoin::blockdata::transaction::Transaction;
+use bitcoin::consensus::encode;
+use bitcoin::hash_types::{BlockHash, TxMerkleNode};
+use bitcoin::hashes::Hash;
+use bitcoin::hashes::hex::{FromHex, ToHex};
+use bitcoin::util::uint::Uint256;
+
+use lightning::chain::chaininterface::{BroadcasterInterface, ConfirmationTarget, FeeEstimator, FEERATE_FLOOR_SATS_PER_KW};
+use lightning::util::logger::Logger;
+use lightning::{log_debug, log_error};
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::convert::TryInto;
+use std::ops::Deref;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// A simple client for Esplora's HTTP API.
+///
+/// Note that Esplora doesn't expose the chainwork of blocks. Hence, the chainwork reported via
+/// [`BlockSource::get_header`] is derived from the first header the client fetched, assuming its
+/// chainwork to be its work times its height, and from there on accumulated along the chain. It is
+/// thus consistent across headers served by this client, but not comparable to the values
+/// reported by other block sources, as indicated by [`BlockSource::reports_chainwork`]. Up to a
+/// difficulty period's worth of headers is cached along with their derived chainwork.
+pub struct EsploraClient {
+	endpoint: HttpEndpoint,
+	client: Mutex<Option<HttpClient>>,
+	// The headers fetched so far, along with their derived chainwork.
+	headers: Mutex<HeaderCache>,
+}
+
+// The maximum number of headers cached along with their derived chainwork.
+const MAX_CACHED_HEADERS: usize = 2016;
+
+#[derive(Default)]
+struct HeaderCache {
+	headers: HashMap<BlockHash, BlockHeaderData>,
+}
+
+impl HeaderCache {
+	/// Inserts the given header, evicting the header farthest from it by height if the cache is
+	/// full. Hence, the lowest headers are evicted while following the chain, while the highest
+	/// ones are evicted when extending the cache downwards.
+	fn insert(&mut self, header_data: BlockHeaderData) {
+		self.headers.insert(header_data.header.block_hash(), header_data);
+		if self.headers.len() > MAX_CACHED_HEADERS {
+			let distance = |height: u32| (height as i64 - header_data.height as i64).abs();
+			let farthest = self.headers.iter()
+				.max_by_key(|(_, cached)| distance(cached.height))
+				.map(|(block_hash, _)| *block_hash)
+				.expect("Cache is not empty");
+			self.headers.remove(&farthest);
+		}
+	}
+
+	/// Returns the cached header with the lowest height, if any.
+	fn lowest(&self) -> Option<BlockHeaderData> {
+		self.headers.values().min_by_key(|cached| cached.height).copied()
+	}
+}
+
+impl EsploraClient {
+	/// Creates a new Esplora client connected to the given endpoint.
+	///
+	/// The endpoint should contain the API path component, if any (e.g.,
+	/// http://127.0.0.1:3000/api).
+	pub fn new(endpoint: HttpEndpoint) -> std::io::Result<Self> {
+		Ok(Self { endpoint, client: Mutex::new(None), headers: Mutex::new(HeaderCache::default()) })
+	}
+
+	/// Requests the resource at the given path, encoded in `F` format.
+	async fn get_resource<F>(&self, resource_path: &str) -> std::io::Result<F>
+	where F: TryFrom<Vec<u8>, Error = std::io::Error> {
+		let host = format!("{}:{}", self.endpoint.host(), self.endpoint.port());
+		let uri = format!("{}/{}", self.endpoint.path().trim_end_matches("/"), resource_path);
+		let mut client = if let Some(client) = self.client.lock().unwrap().take() { client }
+			else { HttpClient::connect(&self.endpoint)? };
+		let res = client.get::<F>(&uri, &host).await;
+		*self.client.lock().unwrap() = Some(client);
+		res
+	}
+
+	/// Posts the given `content` of type `content_type` to the resource at the given path.
+	async fn post_resource(&self, resource_path: &str, content_type: &str, content: &str) -> std::io::Result<()> {
+		let host = format!("{}:{}", self.endpoint.host(), self.endpoint.port());
+		let uri = format!("{}/{}", self.endpoint.path().trim_end_matches("/"), resource_path);
+		let mut client = if let Some(client) = self.client.lock().unwrap().take() { client }
+			else { HttpClient::connect(&self.endpoint)? };
+		let res = client.post_content::<BinaryResponse>(&uri, &host, content_type, content).await;
+		*self.client.lock().unwrap() = Some(client);
+		res.map(|_| ())
+	}
+
+	/// Returns the fee estimates in sat/vB keyed by confirmation target in blocks, as served by
+	/// the `fee-estimates` endpoint.
+	pub async fn get_fee_estimates(&self) -> std::io::Result<HashMap<u16, f64>> {
+		let response = self.get_resource::<JsonResponse>("fee-estimates").await?;
+		parse_fee_estimates(response.0)
+	}
+
+	/// Broadcasts the given transaction via the `tx` endpoint.
+	pub async fn broadcast_transaction(&self, tx: &Transaction) -> std::io::Result<()> {
+		let tx_hex = encode::serialize_hex(tx);
+		self.post_resource("tx", "text/plain", &tx_hex).await
+	}
+
+	/// Submits the given transactions as a package via the `txs/package` endpoint, if supported
+	/// by the server. Otherwise, broadcasts them one by one in the given order, which is expected
+	/// to have parents precede their children.
+	pub async fn broadcast_package(&self, txs: &[Transaction]) -> std::io::Result<()> {
+		let txs_hex = txs.iter().map(|tx| encode::serialize_hex(tx)).collect::<Vec<_>>();
+		let content = serde_json::json!(txs_hex).to_string();
+		match self.post_resource("txs/package", "application/json", &content).await {
+			Err(e) if is_not_found(&e) => {
+				for tx in txs {
+					self.broadcast_transaction(tx).await?;
+				}
+				Ok(())
+			},
+			res => res,
+		}
+	}
+
+	/// Fetches the header of the block with the given hash along with its height.
+	async fn fetch_header(&self, header_hash: &BlockHash) -> BlockSourceResult<(BlockHeader, u32)> {
+		let resource_path = format!("block/{}", header_hash.to_hex());
+		let response = self.get_resource::<JsonResponse>(&resource_path).await?;
+		let (header, height) = parse_block_header(&response.0)?;
+		if header.block_hash() != *header_hash {
+			return Err(BlockSourceError::persistent("invalid block hash"));
+		}
+		Ok((header, height))
+	}
+
+	/// Fetches the header with the given hash, deriving its chainwork from the headers fetched
+	/// previously.
+	async fn get_header_data(&self, header_hash: &BlockHash) -> BlockSourceResult<BlockHeaderData> {
+		if let Some(header_data) = self.headers.lock().unwrap().headers.get(header_hash) {
+			return Ok(*header_data);
+		}
+
+		let (header, height) = self.fetch_header(header_hash).await?;
+		// Walk back from the header until we find a parent with known chainwork. If we walked
+		// below all known headers, extend the known headers downwards instead, as the header may
+		// be an ancestor of them.
+		let mut pending = vec![(header, height)];
+		let mut chainwork = loop {
+			let (last_header, last_height) = *pending.last().unwrap();
+			let lowest = {
+				let headers = self.headers.lock().unwrap();
+				if let Some(known) = headers.headers.get(&last_header.block_hash()) {
+					pending.pop();
+					break known.chainwork;
+				}
+				if let Some(parent) = headers.headers.get(&last_header.prev_blockhash) {
+					break parent.chainwork;
+				}
+				headers.lowest()
+			};
+
+			match lowest {
+				None => {
+					// This is the first header we fetch, so assume a constant work since genesis.
+					let blocks = Uint256::from_u64(last_height as u64).unwrap();
+					break last_header.work() * blocks;
+				},
+				Some(lowest) if last_height > lowest.height => {
+					let (parent, parent_height) = self.fetch_header(&last_header.prev_blockhash).await?;
+					pending.push((parent, parent_height));
+				},
+				Some(lowest) if lowest.height > 0 => {
+					let (parent, parent_height) = self.fetch_header(&lowest.header.prev_blockhash).await?;
+					let parent_chainwork = lowest.chainwork - lowest.header.work();
+					self.headers.lock().unwrap().insert(
+						BlockHeaderData { header: parent, height: parent_height, chainwork: parent_chainwork });
+				},
+				Some(_) => return Err(BlockSourceError::persistent("header not connected to genesis")),
+			}
+		};
+
+		let mut headers = self.headers.lock().unwrap();
+		for (header, height) in pending.drain(..).rev() {
+			chainwork = chainwork + header.work();
+			headers.insert(BlockHeaderData { header, height, chainwork });
+		}
+		Ok(*headers.headers.get(header_hash).expect("Header was just inserted"))
+	}
+}
+
+impl BlockSource for EsploraClient {
+	fn get_header<'a>(&'a self, header_hash: &'a BlockHash, _height: Option<u32>) -> AsyncBlockSourceResult<'a, BlockHeaderData> {
+		Box::pin(async move {
+			self.get_header_data(header_hash).await
+		})
+	}
+
+	fn get_block<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, BlockData> {
+		Box::pin(async move {
+			let resource_path = format!("block/{}/raw", header_hash.to_hex());
+			Ok(BlockData::FullBlock(self.get_resource::<BinaryResponse>(&resource_path).await?.try_into()?))
+		})
+	}
+
+	fn get_best_block<'a>(&'a self) -> AsyncBlockSourceResult<'a, (BlockHash, Option<u32>)> {
+		Box::pin(async move {
+			let response = self.get_resource::<BinaryResponse>("blocks/tip/hash").await?;
+			match std::str::from_utf8(&response.0).ok().and_then(|hex_data| BlockHash::from_hex(hex_data.trim()).ok()) {
+				None => Err(BlockSourceError::persistent("invalid block hash")),
+				Some(block_hash) => Ok((block_hash, None)),
+			}
+		})
+	}
+
+	fn reports_chainwork(&self) -> bool { false }
+}
+
+/// Returns whether the given error is due to the server not knowing the requested resource.
+fn is_not_found(e: &std::io::Error) -> bool {
+	match e.get_ref().and_then(|inner| inner.downcast_ref::<HttpError>()) {
+		Some(http_error) => http_error.status_code == "404",
+		None => false,
+	}
+}
+
+/// Parses Esplora's JSON representation of a block into its header and height.
+fn parse_block_header(response: &serde_json::Value) -> std::io::Result<(BlockHeader, u32)> {
+	fn invalid_data() -> std::io::Error {
+		std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid block data")
+	}
+
+	let prev_blockhash = match &response["previousblockhash"] {
+		serde_json::Value::Null => BlockHash::all_zeros(),
+		serde_json::Value::String(hex_data) => BlockHash::from_hex(hex_data).map_err(|_| invalid_data())?,
+		_ => return Err(invalid_data()),
+	};
+	let merkle_root = response["merkle_root"].as_str()
+		.and_then(|hex_data| TxMerkleNode::from_hex(hex_data).ok())
+		.ok_or_else(invalid_data)?;
+	let get_u32 = |key: &str| response[key].as_u64()
+		.and_then(|value| u32::try_from(value).ok())
+		.ok_or_else(invalid_data);
+	let header = BlockHeader {
+		version: response["version"].as_i64().and_then(|v| i32::try_from(v).ok()).ok_or_else(invalid_data)?,
+		prev_blockhash,
+		merkle_root,
+		time: get_u32("timestamp")?,
+		bits: get_u32("bits")?,
+		nonce: get_u32("nonce")?,
+	};
+	Ok((header, get_u32("height")?))
+}
+
+/// Parses the response of the `fee-estimates` endpoint.
+fn parse_fee_estimates(response: serde_json::Value) -> std::io::Result<HashMap<u16, f64>> {
+	let estimates = match response.as_object() {
+		None => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected JSON object")),
+		Some(estimates) => estimates,
+	};
+
+	let mut fee_estimates = HashMap::new();
+	for (target, feerate) in estimates {
+		match (u16::from_str(target), feerate.as_f64()) {
+			(Ok(target), Some(feerate)) => { fee_estimates.insert(target, feerate); },
+			_ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid fee estimate")),
+		}
+	}
+	Ok(fee_estimates)
+}
+
+/// A [`FeeEstimator`] serving the fee estimates retrieved from an Esplora server.
+///
+/// Each [`ConfirmationTarget`] is mapped to a confirmation target in blocks, using the estimate
+/// for the closest lower target served if there's none for the exact target, and the closest
+/// higher one otherwise. The estimates are cached and need to be refreshed regularly via
+/// [`EsploraFeeEstimator::update_fee_estimates`]. Until the first successful update, fixed
+/// fallback feerates are used.
+pub struct EsploraFeeEstimator<B: Deref<Target=EsploraClient>> {
+	client: B,
+	// The most recently retrieved fee estimates in sat/KW, keyed by target in blocks.
+	fee_estimates: Mutex<HashMap<u16, u32>>,
+}
+
+impl<B: Deref<Target=EsploraClient>> EsploraFeeEstimator<B> {
+	/// Creates a new fee estimator using the given client.
+	pub fn new(client: B) -> Self {
+		Self { client, fee_estimates: Mutex::new(HashMap::new()) }
+	}
+
+	/// Retrieves the current fee estimates from the server. On failure, the previously retrieved
+	/// estimates are kept.
+	pub async fn update_fee_estimates(&self) -> std::io::Result<()> {
+		let fee_estimates = self.client.get_fee_estimates().await?;
+		if fee_estimates.is_empty() {
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "no fee estimates"));
+		}
+
+		// Convert from sat/vB to sat/KW.
+		let fee_estimates = fee_estimates.into_iter()
+			.map(|(target, sat_per_vb)| (target, (sat_per_vb * 250.0).round() as u32))
+			.collect();
+		*self.fee_estimates.lock().unwrap() = fee_estimates;
+		Ok(())
+	}
+}
+
+impl<B: Deref<Target=EsploraClient>> FeeEstimator for EsploraFeeEstimator<B> {
+	fn get_est_sat_per_1000_weight(&self, confirmation_target: ConfirmationTarget) -> u32 {
+		let target = confirmation_target_blocks(confirmation_target);
+		let fee_estimates = self.fee_estimates.lock().unwrap();
+		let closest_lower = fee_estimates.iter()
+			.filter(|(t, _)| **t <= target)
+			.max_by_key(|(t, _)| **t);
+		let closest_higher = fee_estimates.iter()
+			.filter(|(t, _)| **t > target)
+			.min_by_key(|(t, _)| **t);
+		let sat_per_1000_weight = match closest_lower.or(closest_higher) {
+			Some((_, sat_per_1000_weight)) => *sat_per_1000_weight,
+			None => fallback_sat_per_1000_weight(confirmation_target),
+		};
+		core::cmp::max(sat_per_1000_weight, FEERATE_FLOOR_SATS_PER_KW)
+	}
+}
+
+/// A [`BroadcasterInterface`] broadcasting transactions via an Esplora server.
+///
+/// As [`BroadcasterInterface::broadcast_transactions`] may not block on I/O, transactions are
+/// queued and actually broadcast when [`EsploraBroadcaster::broadcast_pending`] is called, which
+/// should happen regularly, e.g., after processing events. Multiple transactions passed at once
+/// are submitted as a package.
+pub struct EsploraBroadcaster<B: Deref<Target=EsploraClient>, L: Deref> where L::Target: Logger {
+	client: B,
+	pending_broadcasts: Mutex<Vec<Vec<Transaction>>>,
+	logger: L,
+}
+
+impl<B: Deref<Target=EsploraClient>, L: Deref> EsploraBroadcaster<B, L> where L::Target: Logger {
+	/// Creates a new broadcaster using the given client.
+	pub fn new(client: B, logger: L) -> Self {
+		Self { client, pending_broadcasts: Mutex::new(Vec::new()), logger }
+	}
+
+	/// Broadcasts any transactions queued since the last call. Failed broadcasts are logged, but
+	/// not retried, as LDK rebroadcasts transactions as needed.
+	pub async fn broadcast_pending(&self) {
+		let pending_broadcasts = core::mem::take(&mut *self.pending_broadcasts.lock().unwrap());
+		for txs in pending_broadcasts {
+			let res = match &txs[..] {
+				[tx] => self.client.broadcast_transaction(tx).await,
+				_ => self.client.broadcast_package(&txs).await,
+			};
+			match res {
+				Ok(()) => {
+					for tx in &txs {
+						log_debug!(self.logger, "Broadcast transaction {}", tx.txid());
+					}
+				},
+				Err(e) => {
+					for tx in &txs {
+						log_error!(self.logger, "Failed to broadcast transaction {}: {}", tx.txid(), e);
+					}
+				},
+			}
+		}
+	}
+}
+
+impl<B: Deref<Target=EsploraClient>, L: Deref> BroadcasterInterface for EsploraBroadcaster<B, L> where L::Target: Logger {
+	fn broadcast_transactions(&self, txs: &[&Transaction]) {
+		if txs.is_empty() {
+			return;
+		}
+		let txs = txs.iter().map(|tx| (*tx).clone()).collect();
+		self.pending_broadcasts.lock().unwrap().push(txs);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::http::client_tests::{HttpServer, MessageBody};
+	use crate::test_utils::Blockchain;
+
+	use bitcoin::blockdata::constants::genesis_block;
+	use bitcoin::network::constants::Network;
+
+	use lightning::util::test_utils::TestLogger;
+
+	#[tokio::test]
+	async fn get_best_block_from_tip_hash() {
+		let block_hash = genesis_block(Network::Bitcoin).block_hash();
+		let server = HttpServer::responding_with_ok(MessageBody::Content(block_hash.to_hex()));
+		let client = EsploraClient::new(server.endpoint()).unwrap();
+
+		match client.get_best_block().await {
+			Err(e) => panic!("Unexpected error: {:?}", e),
+			Ok(best_block) => assert_eq!(best_block, (block_hash, None)),
+		}
+	}
+
+	#[tokio::test]
+	async fn get_header_derives_chainwork() {
+		let genesis = genesis_block(Network::Bitcoin).header;
+		let response = serde_json::json!({
+			"id": genesis.block_hash().to_hex(),
+			"height": 0,
+			"version": genesis.version,
+			"timestamp": genesis.time,
+			"bits": genesis.bits,
+			"nonce": genesis.nonce,
+			"merkle_root": genesis.merkle_root.to_hex(),
+			"previousblockhash": null,
+		});
+		let server = HttpServer::responding_with_ok(MessageBody::Content(response));
+		let client = EsploraClient::new(server.endpoint()).unwrap();
+
+		match client.get_header(&genesis.block_hash(), None).await {
+			Err(e) => panic!("Unexpected error: {:?}", e),
+			Ok(header_data) => {
+				assert_eq!(header_data.header, genesis);
+				assert_eq!(header_data.height, 0);
+				assert_eq!(header_data.chainwork, genesis.work());
+			},
+		}
+	}
+
+	#[tokio::test]
+	async fn get_header_with_mismatched_hash() {
+		let genesis = genesis_block(Network::Bitcoin).header;
+		let response = serde_json::json!({
+			"height": 0,
+			"version": genesis.version,
+			"timestamp": genesis.time + 1,
+			"bits": genesis.bits,
+			"nonce": genesis.nonce,
+			"merkle_root": genesis.merkle_root.to_hex(),
+		});
+		let server = HttpServer::responding_with_ok(MessageBody::Content(response));
+		let client = EsploraClient::new(server.endpoint()).unwrap();
+
+		match client.get_header(&genesis.block_hash(), None).await {
+			Err(e) => assert_eq!(e.into_inner().to_string(), "invalid block hash"),
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+
+	#[test]
+	fn header_cache_evicts_farthest_header() {
+		let chain = Blockchain::default().with_height(MAX_CACHED_HEADERS);
+		let mut cache = HeaderCache::default();
+
+		// While following the chain, the lowest headers are evicted.
+		for height in 0..=MAX_CACHED_HEADERS {
+			cache.insert(*chain.at_height(height));
+		}
+		assert_eq!(cache.headers.len(), MAX_CACHED_HEADERS);
+		assert_eq!(cache.lowest(), Some(*chain.at_height(1)));
+
+		// While extending the cache downwards, the highest headers are evicted.
+		cache.insert(*chain.at_height(0));
+		assert_eq!(cache.headers.len(), MAX_CACHED_HEADERS);
+		assert_eq!(cache.lowest(), Some(*chain.at_height(0)));
+		assert!(!cache.headers.contains_key(&chain.tip().block_hash));
+	}
+
+	#[tokio::test]
+	async fn fee_estimator_maps_confirmation_targets() {
+		let response = serde_json::json!({ "1": 50.0, "3": 20.0, "6": 10.0, "144": 2.0 });
+		let server = HttpServer::responding_with_ok(MessageBody::Content(response));
+		let client = EsploraClient::new(server.endpoint()).unwrap();
+		let fee_estimator = EsploraFeeEstimator::new(&client);
+
+		fee_estimator.update_fee_estimates().await.unwrap();
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::HighPriority), 5000);
+		// There's no estimate for 12 blocks, so the closest lower target is used.
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::Normal), 2500);
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::Background), 500);
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::MempoolMinimum), 500);
+	}
+
+	#[tokio::test]
+	async fn fee_estimator_falls_back_without_estimates() {
+		let server = HttpServer::responding_with_server_error("foo");
+		let client = EsploraClient::new(server.endpoint()).unwrap();
+		let fee_estimator = EsploraFeeEstimator::new(&client);
+
+		assert!(fee_estimator.update_fee_estimates().await.is_err());
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::HighPriority), 5000);
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::Normal), 2000);
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::MempoolMinimum), FEERATE_FLOOR_SATS_PER_KW);
+	}
+
+	#[tokio::test]
+	async fn broadcaster_queues_until_broadcast_pending() {
+		let server = HttpServer::responding_with_ok(MessageBody::Content("txid"));
+		let client = EsploraClient::new(server.endpoint()).unwrap();
+		let logger = TestLogger::new();
+		let broadcaster = EsploraBroadcaster::new(&client, &logger);
+
+		let tx = genesis_block(Network::Bitcoin).txdata[0].clone();
+		broadcaster.broadcast_transactions(&[&tx]);
+		assert_eq!(broadcaster.pending_broadcasts.lock().unwrap().len(), 1);
+
+		broadcaster.broadcast_pending().await;
+		assert!(broadcaster.pending_broadcasts.lock().unwrap().is_empty());
+		logger.assert_log_contains("lightning_block_sync::esplora", "Broadcast transaction", 1);
+	}
+}
@@ -0,0 +1,517 @@
+//! Utilities for syncing [`Confirm`] implementations using compact block filters (BIP 157/158).
+//!
+//! Defines a [`FilterSource`] trait, which extends [`BlockSource`] with retrieving the BIP 158
+//! basic filter of a block and its BIP 157 filter header, and a [`FilterSyncClient`] which uses
+//! such a source to only fetch the blocks containing transactions pertinent to the items
+//! registered via [`chain::Filter`].
+//!
+//! [`Confirm`]: lightning::chain::Confirm
+
+use crate::{AsyncBlockSourceResult, BlockData, BlockSource, BlockSourceError, BlockSourceResult, Cache};
+use crate::poll::{ChainPoller, ChainTip, Poll, ValidatedBlockHeader};
+
+use bitcoin::blockdata::block::Block;
+use bitcoin::blockdata::script::Script;
+use bitcoin::blockdata::transaction::{OutPoint, Transaction};
+use bitcoin::hash_types::{BlockHash, FilterHeader, Txid};
+use bitcoin::network::constants::Network;
+use bitcoin::util::bip158::BlockFilter;
+
+use lightning::chain;
+use lightning::chain::{Confirm, WatchedOutput};
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Deref;
+use std::sync::Mutex;
+
+/// A [`BlockSource`] which is also able to provide the BIP 158 basic block filter of a block and
+/// the BIP 157 filter header committing to it.
+pub trait FilterSource : BlockSource {
+	/// Returns the basic block filter for the block with the given hash.
+	fn get_filter<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, BlockFilter>;
+
+	/// Returns the basic filter header for the block with the given hash, i.e., the hash of the
+	/// block's filter chained to the filter header of the previous block.
+	fn get_filter_header<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, FilterHeader>;
+}
+
+// The transactions and outputs registered via `chain::Filter`, along with the scripts used to
+// match them against block filters.
+struct WatchedItems {
+	transactions: HashMap<Txid, Script>,
+	outputs: HashMap<OutPoint, Script>,
+}
+
+impl WatchedItems {
+	fn is_empty(&self) -> bool {
+		self.transactions.is_empty() && self.outputs.is_empty()
+	}
+
+	fn scripts(&self) -> HashSet<Script> {
+		self.transactions.values().chain(self.outputs.values()).cloned().collect()
+	}
+
+	// Returns whether the given transaction is registered or spends a registered output.
+	fn matches(&self, tx: &Transaction) -> bool {
+		self.transactions.contains_key(&tx.txid()) ||
+			tx.input.iter().any(|input| self.outputs.contains_key(&input.previous_output))
+	}
+}
+
+/// Keeps [`Confirm`] implementations in sync with the chain using compact block filters.
+///
+/// Needs to be registered with a [`ChainMonitor`] via the [`chain::Filter`] interface to be
+/// informed of transactions and outputs to monitor. Each sync downloads the headers and BIP 158
+/// filters of any new blocks, matches them against the scripts of the registered items, and only
+/// fetches the blocks which match. The pertinent transactions of these blocks are then handed to
+/// [`Confirm::transactions_confirmed`], while transactions confirmed in blocks which were
+/// reorganized out are handed to [`Confirm::transaction_unconfirmed`].
+///
+/// As matching is only done against blocks connected after registration, items need to be
+/// registered before they may confirm, as is the case when registering with a [`ChainMonitor`]
+/// before calling [`Watch::watch_channel`].
+///
+/// Each filter is checked to be committed to by the source's chain of filter headers, while
+/// fetched blocks are validated against their headers. As a single source could serve a bogus
+/// filter header chain, omitting matching scripts from its filters, filter headers should be
+/// cross-checked against further sources added via [`Self::add_filter_header_source`].
+///
+/// [`ChainMonitor`]: lightning::chain::chainmonitor::ChainMonitor
+/// [`Watch::watch_channel`]: lightning::chain::Watch::watch_channel
+pub struct FilterSyncClient<B: Deref<Target=F> + Sized + Send + Sync, F: FilterSource + ?Sized, C: Cache> {
+	chain_tip: Mutex<ValidatedBlockHeader>,
+	header_cache: Mutex<C>,
+	watched: Mutex<WatchedItems>,
+	// The filter header of the last block whose filter was checked.
+	last_filter_header: Mutex<Option<(BlockHash, FilterHeader)>>,
+	block_source: B,
+	filter_header_sources: Vec<B>,
+	network: Network,
+}
+
+impl<B: Deref<Target=F> + Sized + Send + Sync, F: FilterSource + ?Sized, C: Cache> FilterSyncClient<B, F, C> {
+	/// Creates a new client using `chain_tip` as the best block known to the [`Confirm`]
+	/// implementations which are to be synced.
+	///
+	/// `header_cache` is used to look up and store headers on the best chain, allowing to detect
+	/// reorganizations even if `block_source` doesn't serve stale blocks anymore.
+	pub fn new(chain_tip: ValidatedBlockHeader, block_source: B, header_cache: C, network: Network) -> Self {
+		let watched = WatchedItems { transactions: HashMap::new(), outputs: HashMap::new() };
+		Self {
+			chain_tip: Mutex::new(chain_tip),
+			header_cache: Mutex::new(header_cache),
+			watched: Mutex::new(watched),
+			last_filter_header: Mutex::new(None),
+			block_source,
+			filter_header_sources: Vec::new(),
+			network,
+		}
+	}
+
+	/// Adds a source which the filter headers served by the block source are cross-checked
+	/// against. Syncing fails if any source disagrees with the block source.
+	pub fn add_filter_header_source(&mut self, source: B) {
+		self.filter_header_sources.push(source);
+	}
+
+	/// Returns the best block the client synced to.
+	pub fn chain_tip(&self) -> ValidatedBlockHeader {
+		*self.chain_tip.lock().unwrap()
+	}
+
+	/// Synchronizes the given `confirmables` with the best chain of the block source.
+	///
+	/// Any transactions confirmed in blocks which are no longer part of the best chain are marked
+	/// unconfirmed, after which the pertinent transactions of any newly connected blocks are marked
+	/// confirmed and the best block is updated. If an error is returned, the client keeps track of
+	/// its progress and resumes from there on the next call.
+	///
+	/// This method must not be called concurrently.
+	pub async fn sync(&self, confirmables: Vec<&(dyn Confirm + Sync + Send)>) -> BlockSourceResult<()> {
+		let chain_poller = ChainPoller::new(&*self.block_source, self.network);
+		let old_tip = self.chain_tip();
+		let new_tip = match chain_poller.poll_chain_tip(old_tip).await? {
+			ChainTip::Better(new_tip) => new_tip,
+			ChainTip::Common | ChainTip::Worse(_) => return Ok(()),
+		};
+
+		// Walk back from both tips to their common ancestor, only walking back the header with the
+		// greater height, or both if equal heights.
+		let mut disconnected_blocks = Vec::new();
+		let mut connected_blocks = Vec::new();
+		let mut current = new_tip;
+		let mut previous = old_tip;
+		while current.block_hash != previous.block_hash {
+			let current_height = current.height;
+			let previous_height = previous.height;
+			if current_height <= previous_height {
+				disconnected_blocks.push(previous);
+				previous = self.look_up_previous_header(&chain_poller, &previous).await?;
+			}
+			if current_height >= previous_height {
+				connected_blocks.push(current);
+				current = self.look_up_previous_header(&chain_poller, &current).await?;
+			}
+		}
+		let common_ancestor = current;
+
+		if !disconnected_blocks.is_empty() {
+			let mut disconnected_hashes = HashSet::new();
+			{
+				let mut header_cache = self.header_cache.lock().unwrap();
+				for header in &disconnected_blocks {
+					header_cache.block_disconnected(&header.block_hash);
+					disconnected_hashes.insert(header.block_hash);
+				}
+			}
+
+			let unconfirmed_txids = confirmables.iter()
+				.flat_map(|c| c.get_relevant_txids())
+				.filter(|(_, block_hash)| match block_hash {
+					Some(block_hash) => disconnected_hashes.contains(block_hash),
+					None => false,
+				})
+				.map(|(txid, _)| txid)
+				.collect::<HashSet<Txid>>();
+			for txid in &unconfirmed_txids {
+				for c in &confirmables {
+					c.transaction_unconfirmed(txid);
+				}
+			}
+			*self.chain_tip.lock().unwrap() = common_ancestor;
+		}
+
+		for header in connected_blocks.drain(..).rev() {
+			if let Err(e) = self.connect_block(&chain_poller, &confirmables, &header).await {
+				// Let the confirmables know how far we got before failing.
+				let chain_tip = self.chain_tip();
+				for c in &confirmables {
+					c.best_block_updated(&chain_tip.header, chain_tip.height);
+				}
+				return Err(e);
+			}
+			self.header_cache.lock().unwrap().block_connected(header.block_hash, header);
+			*self.chain_tip.lock().unwrap() = header;
+		}
+
+		for c in &confirmables {
+			c.best_block_updated(&new_tip.header, new_tip.height);
+		}
+		Ok(())
+	}
+
+	/// Returns the previous header for the given header, either by looking it up in the cache or
+	/// fetching it if not found.
+	async fn look_up_previous_header<P: Poll>(
+		&self, chain_poller: &P, header: &ValidatedBlockHeader,
+	) -> BlockSourceResult<ValidatedBlockHeader> {
+		let cached_header = self.header_cache.lock().unwrap()
+			.look_up(&header.header.prev_blockhash).copied();
+		match cached_header {
+			Some(prev_header) => Ok(prev_header),
+			None => chain_poller.look_up_previous_header(header).await,
+		}
+	}
+
+	/// Matches the block's filter against the watched scripts, fetching the block and marking any
+	/// pertinent transactions confirmed if it matches.
+	async fn connect_block<P: Poll>(
+		&self, chain_poller: &P, confirmables: &Vec<&(dyn Confirm + Sync + Send)>,
+		header: &ValidatedBlockHeader,
+	) -> BlockSourceResult<()> {
+		let scripts = {
+			let watched = self.watched.lock().unwrap();
+			if watched.is_empty() {
+				return Ok(());
+			}
+			watched.scripts()
+		};
+
+		let filter = self.block_source.get_filter(&header.block_hash).await?;
+		self.check_filter(&filter, header).await?;
+		let mut query = scripts.iter().map(|script| script.as_bytes());
+		if !filter.match_any(&header.block_hash, &mut query).map_err(BlockSourceError::persistent)? {
+			return Ok(());
+		}
+
+		let block_data = chain_poller.fetch_block(header).await?;
+		match block_data.deref() {
+			BlockData::FullBlock(block) => {
+				self.confirm_transactions(confirmables, block, header.height);
+				Ok(())
+			},
+			BlockData::HeaderOnly(_) => Err(BlockSourceError::persistent("expected full block")),
+		}
+	}
+
+	/// Checks that the given filter of the given block is committed to by the filter header chain.
+	async fn check_filter(&self, filter: &BlockFilter, header: &ValidatedBlockHeader) -> BlockSourceResult<()> {
+		let prev_blockhash = header.header.prev_blockhash;
+		let last_filter_header = *self.last_filter_header.lock().unwrap();
+		let prev_filter_header = match last_filter_header {
+			Some((block_hash, filter_header)) if block_hash == prev_blockhash => filter_header,
+			_ => self.get_filter_header(&prev_blockhash).await?,
+		};
+		let filter_header = self.get_filter_header(&header.block_hash).await?;
+		if filter.filter_header(&prev_filter_header) != filter_header {
+			return Err(BlockSourceError::persistent("block filter not committed to by filter header"));
+		}
+		*self.last_filter_header.lock().unwrap() = Some((header.block_hash, filter_header));
+		Ok(())
+	}
+
+	/// Returns the filter header of the given block, checking that all sources agree on it.
+	async fn get_filter_header(&self, block_hash: &BlockHash) -> BlockSourceResult<FilterHeader> {
+		let filter_header = self.block_source.get_filter_header(block_hash).await?;
+		for source in self.filter_header_sources.iter() {
+			if source.get_filter_header(block_hash).await? != filter_header {
+				return Err(BlockSourceError::persistent("filter header mismatch between sources"));
+			}
+		}
+		Ok(filter_header)
+	}
+
+	/// Marks the pertinent transactions of the given block confirmed.
+	fn confirm_transactions(
+		&self, confirmables: &Vec<&(dyn Confirm + Sync + Send)>, block: &Block, height: u32,
+	) {
+		// Confirming transactions may lead to new items being registered, e.g., the outputs of a
+		// confirmed commitment transaction, which may be spent within the same block. Hence, we
+		// rescan the block until no further transactions match.
+		let mut confirmed_positions = HashSet::new();
+		loop {
+			let txdata: Vec<(usize, &Transaction)> = {
+				let watched = self.watched.lock().unwrap();
+				block.txdata.iter().enumerate()
+					.filter(|(pos, tx)| !confirmed_positions.contains(pos) && watched.matches(tx))
+					.collect()
+			};
+			if txdata.is_empty() {
+				break;
+			}
+
+			confirmed_positions.extend(txdata.iter().map(|(pos, _)| *pos));
+			for c in confirmables {
+				c.transactions_confirmed(&block.header, &txdata, height);
+			}
+		}
+	}
+}
+
+impl<B: Deref<Target=F> + Sized + Send + Sync, F: FilterSource + ?Sized, C: Cache> chain::Filter for FilterSyncClient<B, F, C> {
+	fn register_tx(&self, txid: &Txid, script_pubkey: &Script) {
+		self.watched.lock().unwrap().transactions.insert(*txid, script_pubkey.clone());
+	}
+
+	fn register_output(&self, output: WatchedOutput) {
+		self.watched.lock().unwrap().outputs
+			.insert(output.outpoint.into_bitcoin_outpoint(), output.script_pubkey);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::test_utils::Blockchain;
+	use crate::{BlockSourceErrorKind, UnboundedCache};
+	use super::*;
+
+	use bitcoin::blockdata::block::BlockHeader;
+	use bitcoin::{PackedLockTime, Sequence, TxIn, TxOut, Witness};
+
+	use lightning::chain::Filter;
+	use lightning::chain::transaction::TransactionData;
+
+	#[derive(Default)]
+	struct TestConfirmable {
+		confirmed_txs: Mutex<HashMap<Txid, (BlockHash, u32)>>,
+		unconfirmed_txs: Mutex<Vec<Txid>>,
+		best_block: Mutex<Option<(BlockHash, u32)>>,
+	}
+
+	impl Confirm for TestConfirmable {
+		fn transactions_confirmed(&self, header: &BlockHeader, txdata: &TransactionData<'_>, height: u32) {
+			for (_, tx) in txdata {
+				self.confirmed_txs.lock().unwrap().insert(tx.txid(), (header.block_hash(), height));
+			}
+		}
+
+		fn transaction_unconfirmed(&self, txid: &Txid) {
+			self.confirmed_txs.lock().unwrap().remove(txid);
+			self.unconfirmed_txs.lock().unwrap().push(*txid);
+		}
+
+		fn best_block_updated(&self, header: &BlockHeader, height: u32) {
+			*self.best_block.lock().unwrap() = Some((header.block_hash(), height));
+		}
+
+		fn get_relevant_txids(&self) -> Vec<(Txid, Option<BlockHash>)> {
+			self.confirmed_txs.lock().unwrap().iter()
+				.map(|(txid, (block_hash, _))| (*txid, Some(*block_hash)))
+				.collect()
+		}
+	}
+
+	fn funding_tx(script_pubkey: Script) -> Transaction {
+		Transaction {
+			version: 2,
+			lock_time: PackedLockTime::ZERO,
+			input: vec![TxIn {
+				previous_output: OutPoint::null(),
+				script_sig: Script::new(),
+				sequence: Sequence::MAX,
+				witness: Witness::new(),
+			}],
+			output: vec![TxOut { value: 1_000, script_pubkey }],
+		}
+	}
+
+	fn spending_tx(outpoint: OutPoint) -> Transaction {
+		Transaction {
+			version: 2,
+			lock_time: PackedLockTime::ZERO,
+			input: vec![TxIn {
+				previous_output: outpoint,
+				script_sig: Script::new(),
+				sequence: Sequence::MAX,
+				witness: Witness::new(),
+			}],
+			output: vec![TxOut { value: 900, script_pubkey: Script::new_op_return(&[]) }],
+		}
+	}
+
+	fn watched_script() -> Script {
+		Script::from(vec![0x51])
+	}
+
+	#[tokio::test]
+	async fn sync_without_watched_items_skips_filters_and_blocks() {
+		let chain = Blockchain::default().with_height(3).without_blocks(1..).without_filters();
+		let client = FilterSyncClient::new(chain.at_height(0), &chain, UnboundedCache::new(), Network::Testnet);
+
+		let confirmable = TestConfirmable::default();
+		client.sync(vec![&confirmable]).await.unwrap();
+		assert_eq!(client.chain_tip(), chain.tip());
+		assert_eq!(*confirmable.best_block.lock().unwrap(), Some((chain.tip().block_hash, 3)));
+	}
+
+	#[tokio::test]
+	async fn sync_only_fetches_matching_blocks() {
+		let chain = Blockchain::default().with_height(3).without_blocks(1..);
+		let client = FilterSyncClient::new(chain.at_height(0), &chain, UnboundedCache::new(), Network::Testnet);
+		let tx = funding_tx(watched_script());
+		client.register_tx(&tx.txid(), &watched_script());
+
+		let confirmable = TestConfirmable::default();
+		client.sync(vec![&confirmable]).await.unwrap();
+		assert!(confirmable.confirmed_txs.lock().unwrap().is_empty());
+		assert_eq!(*confirmable.best_block.lock().unwrap(), Some((chain.tip().block_hash, 3)));
+	}
+
+	#[tokio::test]
+	async fn sync_confirms_registered_transactions_and_spends() {
+		let tx = funding_tx(watched_script());
+		let spend = spending_tx(OutPoint { txid: tx.txid(), vout: 0 });
+		let chain = Blockchain::default().with_height(4)
+			.with_transaction(2, tx.clone())
+			.with_transaction(4, spend.clone());
+		let client = FilterSyncClient::new(chain.at_height(1), &chain, UnboundedCache::new(), Network::Testnet);
+		client.register_tx(&tx.txid(), &watched_script());
+		client.register_output(WatchedOutput {
+			block_hash: None,
+			outpoint: lightning::chain::transaction::OutPoint { txid: tx.txid(), index: 0 },
+			script_pubkey: watched_script(),
+		});
+
+		let confirmable = TestConfirmable::default();
+		client.sync(vec![&confirmable]).await.unwrap();
+		let confirmed_txs = confirmable.confirmed_txs.lock().unwrap();
+		assert_eq!(confirmed_txs.len(), 2);
+		assert_eq!(confirmed_txs.get(&tx.txid()), Some(&(chain.at_height(2).block_hash, 2)));
+		assert_eq!(confirmed_txs.get(&spend.txid()), Some(&(chain.at_height(4).block_hash, 4)));
+		assert_eq!(*confirmable.best_block.lock().unwrap(), Some((chain.tip().block_hash, 4)));
+	}
+
+	#[tokio::test]
+	async fn sync_unconfirms_transactions_of_disconnected_blocks() {
+		let tx = funding_tx(watched_script());
+		// Both chains are built to the same height so they share their blocks up to the fork.
+		let mut old_chain = Blockchain::default().with_height(4).with_transaction(2, tx.clone());
+		old_chain.disconnect_tip();
+		let new_chain = Blockchain::default().with_height(4);
+		assert_eq!(old_chain.at_height(1), new_chain.at_height(1));
+
+		let client = FilterSyncClient::new(
+			old_chain.tip(), &new_chain, old_chain.header_cache(0..=3), Network::Testnet);
+		client.register_tx(&tx.txid(), &watched_script());
+
+		let confirmable = TestConfirmable::default();
+		confirmable.confirmed_txs.lock().unwrap()
+			.insert(tx.txid(), (old_chain.at_height(2).block_hash, 2));
+
+		client.sync(vec![&confirmable]).await.unwrap();
+		assert!(confirmable.confirmed_txs.lock().unwrap().is_empty());
+		assert_eq!(*confirmable.unconfirmed_txs.lock().unwrap(), vec![tx.txid()]);
+		assert_eq!(*confirmable.best_block.lock().unwrap(), Some((new_chain.tip().block_hash, 4)));
+		assert_eq!(client.chain_tip(), new_chain.tip());
+	}
+
+	#[tokio::test]
+	async fn sync_fails_on_filter_not_committed_to_by_filter_header() {
+		let tx = funding_tx(watched_script());
+		let chain = Blockchain::default().with_height(3)
+			.with_transaction(2, tx.clone())
+			.with_bogus_filters();
+		let client = FilterSyncClient::new(chain.at_height(0), &chain, UnboundedCache::new(), Network::Testnet);
+		client.register_tx(&tx.txid(), &watched_script());
+
+		let confirmable = TestConfirmable::default();
+		match client.sync(vec![&confirmable]).await {
+			Err(e) => assert_eq!(e.kind(), BlockSourceErrorKind::Persistent),
+			Ok(_) => panic!("Expected error"),
+		}
+		assert!(confirmable.confirmed_txs.lock().unwrap().is_empty());
+		// The first block's actual filter is empty, so only the second block's filter is bogus.
+		assert_eq!(client.chain_tip(), chain.at_height(1));
+	}
+
+	#[tokio::test]
+	async fn sync_cross_checks_filter_headers() {
+		let tx = funding_tx(watched_script());
+		let chain = Blockchain::default().with_height(3).with_transaction(2, tx.clone());
+		let other_chain = Blockchain::default().with_height(3).with_transaction(2, tx.clone());
+		let bogus_chain = Blockchain::default().with_height(3).with_transaction(2, tx.clone())
+			.with_bogus_filter_headers();
+
+		// Sources agreeing with the block source allow syncing.
+		let mut client = FilterSyncClient::new(chain.at_height(0), &chain, UnboundedCache::new(), Network::Testnet);
+		client.add_filter_header_source(&other_chain);
+		client.register_tx(&tx.txid(), &watched_script());
+		let confirmable = TestConfirmable::default();
+		client.sync(vec![&confirmable]).await.unwrap();
+		assert!(confirmable.confirmed_txs.lock().unwrap().contains_key(&tx.txid()));
+
+		// A single disagreeing source fails syncing.
+		let mut client = FilterSyncClient::new(chain.at_height(0), &chain, UnboundedCache::new(), Network::Testnet);
+		client.add_filter_header_source(&other_chain);
+		client.add_filter_header_source(&bogus_chain);
+		client.register_tx(&tx.txid(), &watched_script());
+		let confirmable = TestConfirmable::default();
+		match client.sync(vec![&confirmable]).await {
+			Err(e) => assert_eq!(e.kind(), BlockSourceErrorKind::Persistent),
+			Ok(_) => panic!("Expected error"),
+		}
+		assert!(confirmable.confirmed_txs.lock().unwrap().is_empty());
+	}
+
+	#[tokio::test]
+	async fn sync_resumes_after_failing_to_fetch_filter() {
+		let chain = Blockchain::default().with_height(3).without_filters();
+		let client = FilterSyncClient::new(chain.at_height(0), &chain, UnboundedCache::new(), Network::Testnet);
+		client.register_tx(&funding_tx(watched_script()).txid(), &watched_script());
+
+		let confirmable = TestConfirmable::default();
+		assert!(client.sync(vec![&confirmable]).await.is_err());
+		assert_eq!(client.chain_tip(), chain.at_height(0));
+		assert_eq!(*confirmable.best_block.lock().unwrap(), Some((chain.at_height(0).block_hash, 0)));
+	}
+}
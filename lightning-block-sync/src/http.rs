@@ -161,6 +161,25 @@ impl HttpClient {
 		F::try_from(response_body)
 	}
 
+	/// Sends an unauthenticated `POST` request for a resource identified by `uri` at the `host`.
+	///
+	/// The request body consists of the provided `content` of the given `content_type`. Returns the
+	/// response body in `F` format.
+	#[allow(dead_code)]
+	pub async fn post_content<F>(&mut self, uri: &str, host: &str, content_type: &str, content: &str) -> std::io::Result<F>
+	where F: TryFrom<Vec<u8>, Error = std::io::Error> {
+		let request = format!(
+			"POST {} HTTP/1.1\r\n\
+			 Host: {}\r\n\
+			 Connection: keep-alive\r\n\
+			 Content-Type: {}\r\n\
+			 Content-Length: {}\r\n\
+			 \r\n\
+			 {}", uri, host, content_type, content.len(), content);
+		let response_body = self.send_request_with_retry(&request).await?;
+		F::try_from(response_body)
+	}
+
 	/// Sends an HTTP request message and reads the response, returning its body. Attempts to
 	/// reconnect and retry if the connection has been closed.
 	async fn send_request_with_retry(&mut self, request: &str) -> std::io::Result<Vec<u8>> {
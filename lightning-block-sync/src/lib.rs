@@ -8,10 +8,25 @@
 //! and data.
 //!
 //! Enabling feature `rest-client` or `rpc-client` allows configuring the client to fetch blocks
-//! using Bitcoin Core's REST or RPC interface, respectively.
+//! using Bitcoin Core's REST or RPC interface, respectively. The latter also provides fee
+//! estimation and transaction broadcasting backed by Bitcoin Core.
 //!
-//! Both features support either blocking I/O using `std::net::TcpStream` or, with feature `tokio`,
-//! non-blocking I/O using `tokio::net::TcpStream` from inside a Tokio runtime.
+//! Enabling feature `esplora-client` allows fetching blocks, fee estimates and broadcasting
+//! transactions via an Esplora server's HTTP API.
+//!
+//! All of these features support either blocking I/O using `std::net::TcpStream` or, with feature
+//! `tokio`, non-blocking I/O using `tokio::net::TcpStream` from inside a Tokio runtime.
+//!
+//! Defines a [`FilterSyncClient`] utility for keeping [`chain::Confirm`] implementations in sync
+//! using compact block filters (BIP 157/158), only fetching the blocks pertinent to the items
+//! registered via [`chain::Filter`].
+//!
+//! Defines a [`MultiBlockSource`] combining several block sources, following the cross-validated
+//! tip with the most work among them, failing over when one of them is unavailable, and warning
+//! when they disagree.
+//!
+//! [`FilterSyncClient`]: filter::FilterSyncClient
+//! [`MultiBlockSource`]: multi_source::MultiBlockSource
 
 // Prefix these with `rustdoc::` when we update our MSRV to be >= 1.52 to remove warnings.
 #![deny(broken_intra_doc_links)]
@@ -22,10 +37,12 @@
 
 #![cfg_attr(docsrs, feature(doc_auto_cfg))]
 
-#[cfg(any(feature = "rest-client", feature = "rpc-client"))]
+#[cfg(any(feature = "rest-client", feature = "rpc-client", feature = "esplora-client"))]
 pub mod http;
 
+pub mod filter;
 pub mod init;
+pub mod multi_source;
 pub mod poll;
 
 #[cfg(feature = "rest-client")]
@@ -34,13 +51,16 @@ pub mod rest;
 #[cfg(feature = "rpc-client")]
 pub mod rpc;
 
-#[cfg(any(feature = "rest-client", feature = "rpc-client"))]
+#[cfg(feature = "esplora-client")]
+pub mod esplora;
+
+#[cfg(any(feature = "rest-client", feature = "rpc-client", feature = "esplora-client"))]
 mod convert;
 
 #[cfg(test)]
 mod test_utils;
 
-#[cfg(any(feature = "rest-client", feature = "rpc-client"))]
+#[cfg(any(feature = "rest-client", feature = "rpc-client", feature = "esplora-client"))]
 mod utils;
 
 use crate::poll::{ChainTip, Poll, ValidatedBlockHeader};
@@ -77,6 +97,13 @@ pub trait BlockSource : Sync + Send {
 	///
 	/// [`get_header`]: Self::get_header
 	fn get_best_block<'a>(&'a self) -> AsyncBlockSourceResult<(BlockHash, Option<u32>)>;
+
+	/// Returns whether the chainwork of headers returned by [`get_header`] is the actual total work
+	/// of the chain up to them, as opposed to one derived by the block source which is only
+	/// consistent among the headers it returns.
+	///
+	/// [`get_header`]: Self::get_header
+	fn reports_chainwork(&self) -> bool { true }
 }
 
 /// Result type for `BlockSource` requests.
@@ -0,0 +1,462 @@
+//! A [`BlockSource`] combining several block sources, following the tip with the most work among
+//! them and failing over to the others if one of them becomes unavailable.
+
+use crate::{AsyncBlockSourceResult, BlockData, BlockHeaderData, BlockSource, BlockSourceError, BlockSourceErrorKind, BlockSourceResult};
+use crate::poll::{Validate, ValidatedBlockHeader};
+
+use bitcoin::hash_types::BlockHash;
+use bitcoin::util::uint::Uint256;
+
+use lightning::util::logger::Logger;
+use lightning::{log_debug, log_warn};
+
+use std::ops::Deref;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A [`BlockSource`] querying several underlying block sources, e.g., a Bitcoin Core node via RPC
+/// and a public Esplora instance.
+///
+/// When polled for the best block, all sources are queried for their validated tip. As the
+/// chainwork a source reports can't be validated on its own, a tip is only followed if another
+/// source which responded also knows its header at the same height, and tips are ranked by the
+/// least chainwork any source reports for them. The tip with the most work among these is
+/// returned. Hence, a single source can't make us follow a chain others don't know, nor inflate
+/// the work of a chain they do know. If only one source responds, its tip is followed as is.
+///
+/// If any sources disagree on the tip, a warning is logged, as this may indicate one of them being
+/// eclipsed or otherwise misbehaving. Note that this includes a source merely being ahead of the
+/// others, whose tip is only followed once another source has caught up with it. Sources which
+/// fail to respond are skipped, so the combined source only fails if all of them do, or if none of
+/// their tips can be cross-validated.
+///
+/// Headers and blocks are requested from the source which reported the least chainwork for the
+/// best tip first, failing over to the remaining sources on any error or on data not matching the
+/// requested block hash. Hence, a single unavailable or misbehaving source can neither stall
+/// syncing nor make us accept invalid chain data.
+///
+/// Sources which don't report the actual chainwork (see [`BlockSource::reports_chainwork`]), such
+/// as the Esplora client, may be combined with ones which do. The chainwork they report is
+/// translated by the difference to that reported by another source for a header both of them
+/// know, such that headers served by either are consistent. Until such a header has been seen when
+/// polling for the best block, their headers aren't served and their tips are compared to others
+/// by height. If none of the sources report the actual chainwork, that of the first source is used
+/// as the reference instead.
+pub struct MultiBlockSource<B: Deref<Target=T> + Send + Sync, T: BlockSource + ?Sized, L: Deref + Send + Sync>
+where L::Target: Logger {
+	sources: Vec<B>,
+	// For each source, the amount to add to the chainwork it reports to get the reference
+	// chainwork, wrapping around if negative, or `None` if not known yet.
+	chainwork_offsets: Mutex<Vec<Option<Uint256>>>,
+	// The index of the source which reported the least chainwork for the last best tip.
+	preferred_source: AtomicUsize,
+	logger: L,
+}
+
+impl<B: Deref<Target=T> + Send + Sync, T: BlockSource + ?Sized, L: Deref + Send + Sync> MultiBlockSource<B, T, L>
+where L::Target: Logger {
+	/// Creates a new combined block source querying the given `sources`, initially preferring the
+	/// first one.
+	///
+	/// Panics if `sources` is empty.
+	pub fn new(sources: Vec<B>, logger: L) -> Self {
+		assert!(!sources.is_empty(), "At least one block source is required");
+		let zero = Uint256::from_u64(0).unwrap();
+		let mut chainwork_offsets = sources.iter()
+			.map(|source| if source.reports_chainwork() { Some(zero) } else { None })
+			.collect::<Vec<_>>();
+		if chainwork_offsets.iter().all(|offset| offset.is_none()) {
+			chainwork_offsets[0] = Some(zero);
+		}
+		Self {
+			sources, chainwork_offsets: Mutex::new(chainwork_offsets),
+			preferred_source: AtomicUsize::new(0), logger,
+		}
+	}
+
+	/// Returns the sources ordered by preference, i.e., starting with the one which reported the
+	/// least chainwork for the last best tip.
+	fn sources_by_preference(&self) -> impl Iterator<Item = (usize, &B)> {
+		let preferred_source = self.preferred_source.load(Ordering::Acquire);
+		self.sources.iter().enumerate().skip(preferred_source)
+			.chain(self.sources.iter().enumerate().take(preferred_source))
+	}
+
+	/// Returns the validated best block header of the given source.
+	async fn get_validated_tip(&self, source: &B) -> BlockSourceResult<ValidatedBlockHeader> {
+		let (block_hash, height_hint) = source.get_best_block().await?;
+		let header_data = source.get_header(&block_hash, height_hint).await?;
+		header_data.validate(block_hash)
+	}
+
+	/// Translates the chainwork reported by the source at `idx` into the reference chainwork.
+	fn translate_chainwork(&self, idx: usize, chainwork: Uint256) -> BlockSourceResult<Uint256> {
+		match self.chainwork_offsets.lock().unwrap()[idx] {
+			Some(offset) => Ok(chainwork + offset),
+			None => Err(BlockSourceError::transient("chainwork of block source not yet known")),
+		}
+	}
+
+	/// Learns the chainwork offsets of any sources knowing a header along with a source whose
+	/// offset is already known, given the chainwork each source reports for each header.
+	fn learn_chainwork_offsets(&self, known_by: &[Vec<(usize, Uint256)>]) {
+		let mut chainwork_offsets = self.chainwork_offsets.lock().unwrap();
+		// An offset learned from one header may allow learning others from another one.
+		let mut learned = true;
+		while learned {
+			learned = false;
+			for sources in known_by {
+				let reference = sources.iter()
+					.filter_map(|(idx, chainwork)| chainwork_offsets[*idx].map(|offset| *chainwork + offset))
+					.min();
+				if let Some(reference) = reference {
+					for (idx, chainwork) in sources {
+						if chainwork_offsets[*idx].is_none() {
+							log_debug!(self.logger, "Learned chainwork offset of block source {}", idx);
+							chainwork_offsets[*idx] = Some(reference - *chainwork);
+							learned = true;
+						}
+					}
+				}
+			}
+		}
+	}
+}
+
+/// Returns the error to surface once all sources failed, which is only persistent if each of the
+/// sources failed persistently.
+fn all_sources_failed(errors: Vec<BlockSourceError>) -> BlockSourceError {
+	let error = format!("all block sources failed: {:?}", errors);
+	if errors.iter().all(|e| e.kind() == BlockSourceErrorKind::Persistent) {
+		BlockSourceError::persistent(error)
+	} else {
+		BlockSourceError::transient(error)
+	}
+}
+
+impl<B: Deref<Target=T> + Send + Sync, T: BlockSource + ?Sized, L: Deref + Send + Sync> BlockSource for MultiBlockSource<B, T, L>
+where L::Target: Logger {
+	fn get_header<'a>(&'a self, header_hash: &'a BlockHash, height_hint: Option<u32>) -> AsyncBlockSourceResult<'a, BlockHeaderData> {
+		Box::pin(async move {
+			let mut errors = Vec::new();
+			for (idx, source) in self.sources_by_preference() {
+				let result = source.get_header(header_hash, height_hint).await
+					.and_then(|header_data| header_data.validate(*header_hash))
+					.and_then(|header| Ok(BlockHeaderData {
+						chainwork: self.translate_chainwork(idx, header.chainwork)?, ..*header
+					}));
+				match result {
+					Ok(header_data) => return Ok(header_data),
+					Err(e) => {
+						log_debug!(self.logger, "Block source {} failed to provide header {}: {:?}", idx, header_hash, e);
+						errors.push(e);
+					},
+				}
+			}
+			Err(all_sources_failed(errors))
+		})
+	}
+
+	fn get_block<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, BlockData> {
+		Box::pin(async move {
+			let mut errors = Vec::new();
+			for (idx, source) in self.sources_by_preference() {
+				let result = source.get_block(header_hash).await
+					.and_then(|block_data| block_data.validate(*header_hash));
+				match result {
+					Ok(block) => return Ok(block.into_inner()),
+					Err(e) => {
+						log_debug!(self.logger, "Block source {} failed to provide block {}: {:?}", idx, header_hash, e);
+						errors.push(e);
+					},
+				}
+			}
+			Err(all_sources_failed(errors))
+		})
+	}
+
+	fn get_best_block<'a>(&'a self) -> AsyncBlockSourceResult<'a, (BlockHash, Option<u32>)> {
+		Box::pin(async move {
+			let mut tips: Vec<(usize, ValidatedBlockHeader)> = Vec::new();
+			let mut errors = Vec::new();
+			for (idx, source) in self.sources.iter().enumerate() {
+				match self.get_validated_tip(source).await {
+					Ok(tip) => tips.push((idx, tip)),
+					Err(e) => {
+						log_warn!(self.logger, "Block source {} failed to provide its best block: {:?}", idx, e);
+						errors.push(e);
+					},
+				}
+			}
+			if tips.is_empty() {
+				return Err(all_sources_failed(errors));
+			}
+
+			// Cross-validate each distinct tip with the sources which didn't report it, keeping track
+			// of the sources knowing it along with the chainwork they report for it.
+			let mut distinct_tips: Vec<ValidatedBlockHeader> = Vec::new();
+			let mut known_by: Vec<Vec<(usize, Uint256)>> = Vec::new();
+			for (idx, tip) in tips.iter() {
+				if distinct_tips.iter().any(|distinct_tip| distinct_tip.block_hash == tip.block_hash) {
+					continue;
+				}
+				let mut sources = vec![(*idx, tip.chainwork)];
+				for (other_idx, other_tip) in tips.iter().filter(|(other_idx, _)| other_idx != idx) {
+					let header = if other_tip.block_hash == tip.block_hash {
+						Ok(*other_tip)
+					} else {
+						self.sources[*other_idx].get_header(&tip.block_hash, Some(tip.height)).await
+							.and_then(|header_data| header_data.validate(tip.block_hash))
+					};
+					match header {
+						Ok(header) if header.height == tip.height => sources.push((*other_idx, header.chainwork)),
+						Ok(header) => log_warn!(self.logger,
+							"Block source {} reported tip {} at height {}, while block source {} has it at height {}.",
+							idx, tip.block_hash, tip.height, other_idx, header.height),
+						Err(_) => {},
+					}
+				}
+				distinct_tips.push(*tip);
+				known_by.push(sources);
+			}
+			self.learn_chainwork_offsets(&known_by);
+
+			// For each cross-validated tip, the least chainwork reported for it, if known, along with
+			// the source reporting it.
+			let mut candidates: Vec<(ValidatedBlockHeader, Option<Uint256>, usize)> = Vec::new();
+			for (tip, sources) in distinct_tips.iter().zip(known_by.iter()) {
+				if sources.len() < 2 && tips.len() > 1 {
+					log_warn!(self.logger, "Ignoring tip {} at height {} reported by block source {} as no other block source knows it.",
+						tip.block_hash, tip.height, sources[0].0);
+					continue;
+				}
+				let least_work = sources.iter()
+					.filter_map(|(idx, chainwork)| self.translate_chainwork(*idx, *chainwork).ok().map(|chainwork| (chainwork, *idx)))
+					.min();
+				match least_work {
+					Some((chainwork, idx)) => candidates.push((*tip, Some(chainwork), idx)),
+					None => candidates.push((*tip, None, sources[0].0)),
+				}
+			}
+
+			// Tips are compared by height if the chainwork of any of them isn't known.
+			let best = if candidates.iter().all(|(_, chainwork, _)| chainwork.is_some()) {
+				candidates.iter().max_by_key(|(_, chainwork, _)| *chainwork)
+			} else {
+				candidates.iter().max_by_key(|(tip, _, _)| tip.height)
+			};
+			let (best_tip, best_idx) = match best {
+				Some((tip, _, idx)) => (*tip, *idx),
+				None => return Err(BlockSourceError::transient("no block source tip could be cross-validated")),
+			};
+
+			for (idx, tip) in tips.iter().filter(|(_, tip)| tip.block_hash != best_tip.block_hash) {
+				log_warn!(self.logger,
+					"Block source {} reported tip {} at height {}, while the best tip is {} at height {}. If this persists, one of them may be eclipsed or misbehaving.",
+					idx, tip.block_hash, tip.height, best_tip.block_hash, best_tip.height);
+			}
+
+			self.preferred_source.store(best_idx, Ordering::Release);
+			Ok((best_tip.block_hash, Some(best_tip.height)))
+		})
+	}
+
+	fn reports_chainwork(&self) -> bool {
+		self.sources.iter().any(|source| source.reports_chainwork())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::test_utils::Blockchain;
+	use super::*;
+
+	use lightning::util::test_utils::TestLogger;
+
+	const MODULE: &str = "lightning_block_sync::multi_source";
+
+	#[tokio::test]
+	async fn follows_tip_with_most_work() {
+		let long_chain = Blockchain::default().with_height(3);
+		let mut short_chain = Blockchain::default().with_height(3);
+		short_chain.disconnect_tip();
+		let logger = TestLogger::new();
+		let source = MultiBlockSource::new(vec![&short_chain, &long_chain, &long_chain], &logger);
+
+		let (block_hash, height) = source.get_best_block().await.unwrap();
+		assert_eq!(block_hash, long_chain.tip().block_hash);
+		assert_eq!(height, Some(3));
+		logger.assert_log_contains(MODULE, "If this persists, one of them may be eclipsed or misbehaving.", 1);
+
+		// Blocks only known to the sources with the most work are still served.
+		let block_hash = long_chain.tip().block_hash;
+		match source.get_block(&block_hash).await.unwrap() {
+			BlockData::FullBlock(block) => assert_eq!(block.block_hash(), block_hash),
+			BlockData::HeaderOnly(_) => panic!("Expected full block"),
+		}
+	}
+
+	#[tokio::test]
+	async fn waits_for_tip_to_be_cross_validated() {
+		// A source ahead of the others is only followed once another source knows its tip.
+		let long_chain = Blockchain::default().with_height(3);
+		let mut short_chain = Blockchain::default().with_height(3);
+		short_chain.disconnect_tip();
+		let logger = TestLogger::new();
+		let source = MultiBlockSource::new(vec![&short_chain, &long_chain], &logger);
+
+		assert_eq!(source.get_best_block().await.unwrap(), (short_chain.tip().block_hash, Some(2)));
+		logger.assert_log_contains(MODULE, "reported by block source 1 as no other block source knows it", 1);
+
+		let source = MultiBlockSource::new(vec![&long_chain, &long_chain], &logger);
+		assert_eq!(source.get_best_block().await.unwrap(), (long_chain.tip().block_hash, Some(3)));
+	}
+
+	#[tokio::test]
+	async fn ignores_tip_unknown_to_other_sources() {
+		let chain = Blockchain::default().with_height(3);
+		let attacker_chain = Blockchain::default().with_height(5);
+		assert!(attacker_chain.tip().chainwork > chain.tip().chainwork);
+		let logger = TestLogger::new();
+		let source = MultiBlockSource::new(vec![&attacker_chain, &chain, &chain], &logger);
+
+		assert_eq!(source.get_best_block().await.unwrap(), (chain.tip().block_hash, Some(3)));
+		logger.assert_log_contains(MODULE, "reported by block source 0 as no other block source knows it", 1);
+
+		// If no tip can be cross-validated, we don't follow any of them.
+		let source = MultiBlockSource::new(vec![&attacker_chain, &chain], &logger);
+		match source.get_best_block().await {
+			Err(e) => assert_eq!(e.kind(), BlockSourceErrorKind::Transient),
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+
+	#[tokio::test]
+	async fn ignores_inflated_chainwork() {
+		// A source inflating the chainwork of a block known to the others doesn't get it followed
+		// over a tip with more work.
+		let chain = Blockchain::default().with_height(3);
+		let mut inflated_chain = Blockchain::default().with_height(3).with_chainwork_offset(1_000_000);
+		inflated_chain.disconnect_tip();
+		let logger = TestLogger::new();
+		let source = MultiBlockSource::new(vec![&inflated_chain, &chain, &chain], &logger);
+
+		assert_eq!(source.get_best_block().await.unwrap(), (chain.tip().block_hash, Some(3)));
+
+		// Nor are headers served with the inflated chainwork if it reports the best tip as well.
+		let inflated_chain = Blockchain::default().with_height(3).with_chainwork_offset(1_000_000);
+		let source = MultiBlockSource::new(vec![&inflated_chain, &chain], &logger);
+		assert_eq!(source.get_best_block().await.unwrap(), (chain.tip().block_hash, Some(3)));
+		let block_hash = chain.tip().block_hash;
+		assert_eq!(source.get_header(&block_hash, Some(3)).await.unwrap(), *chain.tip());
+	}
+
+	#[tokio::test]
+	async fn translates_derived_chainwork() {
+		let chain = Blockchain::default().with_height(4);
+		let derived_chain = Blockchain::default().with_height(4)
+			.with_chainwork_offset(1_000_000).with_derived_chainwork();
+		let logger = TestLogger::new();
+		let source = MultiBlockSource::new(vec![&derived_chain, &chain], &logger);
+		assert!(source.reports_chainwork());
+
+		// Headers aren't served with derived chainwork before it can be translated.
+		let block_hash = chain.tip().block_hash;
+		assert_eq!(source.get_header(&block_hash, Some(4)).await.unwrap(), *chain.tip());
+		logger.assert_log_contains(MODULE, "Block source 0 failed to provide header", 1);
+
+		// Once both sources reported the same tip, headers are served by the preferred source with
+		// its chainwork translated.
+		assert_eq!(source.get_best_block().await.unwrap(), (block_hash, Some(4)));
+		for height in 0..=4 {
+			let block_hash = chain.at_height(height).block_hash;
+			assert_eq!(source.get_header(&block_hash, None).await.unwrap(), *chain.at_height(height));
+		}
+		logger.assert_log_contains(MODULE, "Block source 0 failed to provide header", 1);
+	}
+
+	#[tokio::test]
+	async fn follows_derived_chainwork_sources_ahead() {
+		let mut chain = Blockchain::default().with_height(4);
+		chain.disconnect_tip();
+		let derived_chain = Blockchain::default().with_height(4).with_derived_chainwork();
+		let other_derived_chain = Blockchain::default().with_height(4)
+			.with_chainwork_offset(1_000_000).with_derived_chainwork();
+		let logger = TestLogger::new();
+		let source = MultiBlockSource::new(vec![&chain, &derived_chain, &other_derived_chain], &logger);
+
+		// The derived chainwork is translated via the tip the sources agree on, such that the tip
+		// the derived chainwork sources are ahead with is followed.
+		let tip = Blockchain::default().with_height(4).tip();
+		assert_eq!(source.get_best_block().await.unwrap(), (tip.block_hash, Some(4)));
+		assert_eq!(source.get_header(&tip.block_hash, Some(4)).await.unwrap(), *tip);
+
+		// Without any source reporting the actual chainwork, tips are compared by height.
+		let mut empty_chain = Blockchain::default();
+		empty_chain.disconnect_tip();
+		let source = MultiBlockSource::new(vec![&empty_chain, &derived_chain, &other_derived_chain], &logger);
+		assert_eq!(source.get_best_block().await.unwrap(), (tip.block_hash, Some(4)));
+		match source.get_header(&tip.block_hash, Some(4)).await {
+			Err(e) => assert_eq!(e.kind(), BlockSourceErrorKind::Transient),
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+
+	#[tokio::test]
+	async fn agreeing_sources_do_not_warn() {
+		let chain = Blockchain::default().with_height(3);
+		let logger = TestLogger::new();
+		let source = MultiBlockSource::new(vec![&chain, &chain], &logger);
+
+		assert_eq!(source.get_best_block().await.unwrap(), (chain.tip().block_hash, Some(3)));
+		logger.assert_log_contains(MODULE, "may be eclipsed", 0);
+	}
+
+	#[tokio::test]
+	async fn fails_over_to_available_source() {
+		let mut empty_chain = Blockchain::default();
+		empty_chain.disconnect_tip();
+		let chain = Blockchain::default().with_height(3);
+		let logger = TestLogger::new();
+		let source = MultiBlockSource::new(vec![&empty_chain, &chain], &logger);
+
+		assert_eq!(source.get_best_block().await.unwrap(), (chain.tip().block_hash, Some(3)));
+		logger.assert_log_contains(MODULE, "Block source 0 failed to provide its best block", 1);
+
+		let block_hash = chain.at_height(2).block_hash;
+		let header = source.get_header(&block_hash, None).await.unwrap();
+		assert_eq!(header, *chain.at_height(2));
+	}
+
+	#[tokio::test]
+	async fn rejects_data_not_matching_requested_hash() {
+		let malformed_chain = Blockchain::default().with_height(3).malformed_headers();
+		let chain = Blockchain::default().with_height(3);
+		let logger = TestLogger::new();
+		let source = MultiBlockSource::new(vec![&malformed_chain, &chain], &logger);
+
+		let block_hash = chain.tip().block_hash;
+		assert_eq!(source.get_header(&block_hash, None).await.unwrap(), *chain.tip());
+	}
+
+	#[tokio::test]
+	async fn fails_if_all_sources_fail() {
+		let chain = Blockchain::default().with_height(3).without_blocks(0..);
+		let logger = TestLogger::new();
+		let source = MultiBlockSource::new(vec![&chain, &chain], &logger);
+
+		let block_hash = chain.tip().block_hash;
+		match source.get_block(&block_hash).await {
+			Err(e) => assert_eq!(e.kind(), BlockSourceErrorKind::Persistent),
+			Ok(_) => panic!("Expected error"),
+		}
+
+		let mut empty_chain = Blockchain::default();
+		empty_chain.disconnect_tip();
+		let source = MultiBlockSource::new(vec![&empty_chain, &empty_chain], &logger);
+		match source.get_best_block().await {
+			Err(e) => assert_eq!(e.kind(), BlockSourceErrorKind::Transient),
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+}
@@ -179,6 +179,13 @@ impl std::ops::Deref for ValidatedBlock {
 	}
 }
 
+impl ValidatedBlock {
+	/// Returns the underlying validated block data.
+	pub(crate) fn into_inner(self) -> BlockData {
+		self.inner
+	}
+}
+
 mod sealed {
 	/// Used to prevent implementing [`super::Validate`] outside the crate but still allow its use.
 	pub trait Validate {}
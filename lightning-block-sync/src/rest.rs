@@ -2,10 +2,12 @@
 //! endpoint.
 
 use crate::{BlockData, BlockHeaderData, BlockSource, AsyncBlockSourceResult};
+use crate::filter::FilterSource;
 use crate::http::{BinaryResponse, HttpEndpoint, HttpClient, JsonResponse};
 
-use bitcoin::hash_types::BlockHash;
+use bitcoin::hash_types::{BlockHash, FilterHeader};
 use bitcoin::hashes::hex::ToHex;
+use bitcoin::util::bip158::BlockFilter;
 
 use std::convert::TryFrom;
 use std::convert::TryInto;
@@ -60,6 +62,22 @@ impl BlockSource for RestClient {
 	}
 }
 
+impl FilterSource for RestClient {
+	fn get_filter<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, BlockFilter> {
+		Box::pin(async move {
+			let resource_path = format!("blockfilter/basic/{}.bin", header_hash.to_hex());
+			Ok(self.request_resource::<BinaryResponse, _>(&resource_path).await?)
+		})
+	}
+
+	fn get_filter_header<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, FilterHeader> {
+		Box::pin(async move {
+			let resource_path = format!("blockfilterheaders/basic/1/{}.bin", header_hash.to_hex());
+			Ok(self.request_resource::<BinaryResponse, _>(&resource_path).await?)
+		})
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
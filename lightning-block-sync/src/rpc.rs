@@ -1,13 +1,28 @@
 //! Simple RPC client implementation which implements [`BlockSource`] against a Bitcoin Core RPC
-//! endpoint.
+//! endpoint, along with [`FeeEstimator`] and [`BroadcasterInterface`] implementations backed by it.
 
 use crate::{BlockData, BlockHeaderData, BlockSource, AsyncBlockSourceResult};
+use crate::filter::FilterSource;
 use crate::http::{HttpClient, HttpEndpoint, HttpError, JsonResponse};
+use crate::utils::{confirmation_target_blocks, fallback_sat_per_1000_weight};
 
-use bitcoin::hash_types::BlockHash;
+use bitcoin::blockdata::transaction::Transaction;
+use bitcoin::consensus::encode;
+use bitcoin::hash_types::{BlockHash, FilterHeader, Txid};
 use bitcoin::hashes::hex::ToHex;
+use bitcoin::util::bip158::BlockFilter;
 
+use lightning::chain::chaininterface::{BroadcasterInterface, ConfirmationTarget, FeeEstimator, FEERATE_FLOOR_SATS_PER_KW};
+use lightning::util::logger::Logger;
+use lightning::{log_debug, log_error};
+
+use std::collections::HashMap;
+use std::ops::Deref;
 use std::sync::Mutex;
+#[cfg(feature = "tokio")]
+use std::sync::Arc;
+#[cfg(feature = "tokio")]
+use std::time::Duration;
 
 use serde_json;
 
@@ -137,11 +152,257 @@ impl BlockSource for RpcClient {
 	}
 }
 
+impl FilterSource for RpcClient {
+	fn get_filter<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, BlockFilter> {
+		Box::pin(async move {
+			let header_hash = serde_json::json!(header_hash.to_hex());
+			let filter_type = serde_json::json!("basic");
+			Ok(self.call_method("getblockfilter", &[header_hash, filter_type]).await?)
+		})
+	}
+
+	fn get_filter_header<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, FilterHeader> {
+		Box::pin(async move {
+			let header_hash = serde_json::json!(header_hash.to_hex());
+			let filter_type = serde_json::json!("basic");
+			Ok(self.call_method("getblockfilter", &[header_hash, filter_type]).await?)
+		})
+	}
+}
+
+/// Converts a feerate in BTC/kvB, as reported by Bitcoin Core, into sat/KW.
+fn btc_per_kvb_to_sat_per_1000_weight(btc_per_kvb: f64) -> u32 {
+	// A kvB corresponds to 4000 weight units.
+	(btc_per_kvb * 100_000_000.0 / 4.0).round() as u32
+}
+
+/// The feerate estimate in sat/KW returned by `estimatesmartfee`, if the node has sufficient data
+/// to provide one.
+struct FeerateEstimate(Option<u32>);
+
+impl TryInto<FeerateEstimate> for JsonResponse {
+	type Error = std::io::Error;
+
+	fn try_into(self) -> std::io::Result<FeerateEstimate> {
+		if !self.0.is_object() {
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected JSON object"));
+		}
+
+		match &self.0["feerate"] {
+			// The node reports why it couldn't provide an estimate in `errors` instead.
+			serde_json::Value::Null => Ok(FeerateEstimate(None)),
+			serde_json::Value::Number(feerate) => match feerate.as_f64() {
+				None => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid feerate")),
+				Some(feerate) => Ok(FeerateEstimate(Some(btc_per_kvb_to_sat_per_1000_weight(feerate)))),
+			},
+			_ => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected JSON number")),
+		}
+	}
+}
+
+/// The minimum feerate in sat/KW for transactions to be accepted into the node's mempool, as
+/// returned by `getmempoolinfo`.
+struct MempoolMinFee(u32);
+
+impl TryInto<MempoolMinFee> for JsonResponse {
+	type Error = std::io::Error;
+
+	fn try_into(self) -> std::io::Result<MempoolMinFee> {
+		if !self.0.is_object() {
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected JSON object"));
+		}
+
+		match self.0["mempoolminfee"].as_f64() {
+			None => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected JSON number")),
+			Some(feerate) => Ok(MempoolMinFee(btc_per_kvb_to_sat_per_1000_weight(feerate))),
+		}
+	}
+}
+
+/// Indicates a package was accepted by `submitpackage`.
+struct PackageAccepted;
+
+impl TryInto<PackageAccepted> for JsonResponse {
+	type Error = std::io::Error;
+
+	fn try_into(self) -> std::io::Result<PackageAccepted> {
+		if !self.0.is_object() {
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected JSON object"));
+		}
+
+		// Versions prior to Bitcoin Core 28.0 don't report `package_msg`, but fail with an RPC
+		// error if the package was rejected.
+		match &self.0["package_msg"] {
+			serde_json::Value::Null => Ok(PackageAccepted),
+			serde_json::Value::String(message) if message == "success" => Ok(PackageAccepted),
+			serde_json::Value::String(message) => Err(std::io::Error::new(std::io::ErrorKind::Other, message.clone())),
+			_ => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected JSON string")),
+		}
+	}
+}
+
+/// A [`FeeEstimator`] serving the fee estimates of a Bitcoin Core node.
+///
+/// [`ConfirmationTarget::MempoolMinimum`] is served from `getmempoolinfo`'s `mempoolminfee`, while
+/// the remaining targets are mapped to confirmation targets in blocks passed to
+/// `estimatesmartfee`. No estimate is lower than the mempool minimum fee.
+///
+/// As [`FeeEstimator::get_est_sat_per_1000_weight`] may not block on I/O, the estimates are cached
+/// and need to be refreshed regularly in the background via
+/// [`RpcFeeEstimator::update_fee_estimates`], e.g., from a task run every few minutes as spawned by
+/// [`RpcFeeEstimator::spawn_update_task`] with the `tokio` feature. Until an estimate for a target
+/// was retrieved, a fixed fallback feerate is used.
+pub struct RpcFeeEstimator<B: Deref<Target=RpcClient>> {
+	client: B,
+	// The most recently retrieved fee estimates in sat/KW.
+	fee_estimates: Mutex<HashMap<ConfirmationTarget, u32>>,
+}
+
+impl<B: Deref<Target=RpcClient>> RpcFeeEstimator<B> {
+	/// Creates a new fee estimator using the given client.
+	pub fn new(client: B) -> Self {
+		Self { client, fee_estimates: Mutex::new(HashMap::new()) }
+	}
+
+	/// Retrieves the current fee estimates from the node. Estimates the node can't provide, e.g.,
+	/// due to lacking sufficient data, and any estimates which failed to be retrieved are left as
+	/// previously retrieved.
+	///
+	/// All estimates are attempted even if some fail, in which case the first error is returned
+	/// after the retrieved estimates have been stored.
+	pub async fn update_fee_estimates(&self) -> std::io::Result<()> {
+		let mut fee_estimates = Vec::new();
+		let mut first_error = None;
+		match self.client.call_method::<MempoolMinFee>("getmempoolinfo", &[]).await {
+			Ok(mempool_min_fee) => fee_estimates.push((ConfirmationTarget::MempoolMinimum, mempool_min_fee.0)),
+			Err(e) => first_error = Some(e),
+		}
+
+		let confirmation_targets = [
+			ConfirmationTarget::Background, ConfirmationTarget::Normal, ConfirmationTarget::HighPriority,
+		];
+		for confirmation_target in confirmation_targets.iter() {
+			let blocks = serde_json::json!(confirmation_target_blocks(*confirmation_target));
+			match self.client.call_method::<FeerateEstimate>("estimatesmartfee", &[blocks]).await {
+				Ok(FeerateEstimate(Some(sat_per_1000_weight))) => {
+					fee_estimates.push((*confirmation_target, sat_per_1000_weight));
+				},
+				Ok(FeerateEstimate(None)) => {},
+				Err(e) => if first_error.is_none() { first_error = Some(e); },
+			}
+		}
+
+		self.fee_estimates.lock().unwrap().extend(fee_estimates);
+		match first_error {
+			Some(e) => Err(e),
+			None => Ok(()),
+		}
+	}
+}
+
+#[cfg(feature = "tokio")]
+impl<B: Deref<Target=RpcClient> + Send + Sync + 'static> RpcFeeEstimator<B> {
+	/// Spawns a task on the current tokio runtime which calls
+	/// [`RpcFeeEstimator::update_fee_estimates`] immediately and then every `interval`, ignoring
+	/// any failures as the estimates are retried on the next tick.
+	///
+	/// The task runs until the returned handle is aborted or the runtime shuts down.
+	pub fn spawn_update_task(
+		fee_estimator: Arc<Self>, interval: Duration,
+	) -> tokio::task::JoinHandle<()> {
+		tokio::spawn(async move {
+			let mut interval = tokio::time::interval(interval);
+			loop {
+				interval.tick().await;
+				let _ = fee_estimator.update_fee_estimates().await;
+			}
+		})
+	}
+}
+
+impl<B: Deref<Target=RpcClient>> FeeEstimator for RpcFeeEstimator<B> {
+	fn get_est_sat_per_1000_weight(&self, confirmation_target: ConfirmationTarget) -> u32 {
+		let fee_estimates = self.fee_estimates.lock().unwrap();
+		let sat_per_1000_weight = fee_estimates.get(&confirmation_target).copied()
+			.unwrap_or_else(|| fallback_sat_per_1000_weight(confirmation_target));
+		let mempool_min_fee = fee_estimates.get(&ConfirmationTarget::MempoolMinimum).copied()
+			.unwrap_or(FEERATE_FLOOR_SATS_PER_KW);
+		core::cmp::max(sat_per_1000_weight, core::cmp::max(mempool_min_fee, FEERATE_FLOOR_SATS_PER_KW))
+	}
+}
+
+/// A [`BroadcasterInterface`] broadcasting transactions via a Bitcoin Core node.
+///
+/// As [`BroadcasterInterface::broadcast_transactions`] may not block on I/O, transactions are
+/// queued and actually broadcast when [`RpcBroadcaster::broadcast_pending`] is called, which
+/// should happen regularly, e.g., after processing events. Multiple transactions passed at once
+/// are submitted as a package via `submitpackage`, falling back to submitting them one by one via
+/// `sendrawtransaction` if the node doesn't accept the package.
+pub struct RpcBroadcaster<B: Deref<Target=RpcClient>, L: Deref> where L::Target: Logger {
+	client: B,
+	pending_broadcasts: Mutex<Vec<Vec<Transaction>>>,
+	logger: L,
+}
+
+impl<B: Deref<Target=RpcClient>, L: Deref> RpcBroadcaster<B, L> where L::Target: Logger {
+	/// Creates a new broadcaster using the given client.
+	pub fn new(client: B, logger: L) -> Self {
+		Self { client, pending_broadcasts: Mutex::new(Vec::new()), logger }
+	}
+
+	/// Broadcasts any transactions queued since the last call. Failed broadcasts are logged, but
+	/// not retried, as LDK rebroadcasts transactions as needed.
+	pub async fn broadcast_pending(&self) {
+		let pending_broadcasts = core::mem::take(&mut *self.pending_broadcasts.lock().unwrap());
+		for txs in pending_broadcasts {
+			if txs.len() > 1 {
+				let txs_hex = txs.iter().map(|tx| encode::serialize_hex(tx)).collect::<Vec<_>>();
+				let package = serde_json::json!(txs_hex);
+				match self.client.call_method::<PackageAccepted>("submitpackage", &[package]).await {
+					Ok(PackageAccepted) => {
+						for tx in &txs {
+							log_debug!(self.logger, "Broadcast transaction {} as part of a package", tx.txid());
+						}
+						continue;
+					},
+					Err(e) => {
+						log_debug!(self.logger, "Failed to submit package, broadcasting transactions individually: {}", e);
+					},
+				}
+			}
+
+			// Transactions are expected to be passed with parents preceding their children.
+			for tx in &txs {
+				let tx_hex = serde_json::json!(encode::serialize_hex(tx));
+				match self.client.call_method::<Txid>("sendrawtransaction", &[tx_hex]).await {
+					Ok(_) => log_debug!(self.logger, "Broadcast transaction {}", tx.txid()),
+					Err(e) => log_error!(self.logger, "Failed to broadcast transaction {}: {}", tx.txid(), e),
+				}
+			}
+		}
+	}
+}
+
+impl<B: Deref<Target=RpcClient>, L: Deref> BroadcasterInterface for RpcBroadcaster<B, L> where L::Target: Logger {
+	fn broadcast_transactions(&self, txs: &[&Transaction]) {
+		if txs.is_empty() {
+			return;
+		}
+		let txs = txs.iter().map(|tx| (*tx).clone()).collect();
+		self.pending_broadcasts.lock().unwrap().push(txs);
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
 	use crate::http::client_tests::{HttpServer, MessageBody};
 
+	use bitcoin::blockdata::constants::genesis_block;
+	use bitcoin::network::constants::Network;
+
+	use lightning::util::test_utils::TestLogger;
+
 	/// Credentials encoded in base64.
 	const CREDENTIALS: &'static str = "dXNlcjpwYXNzd29yZA==";
 
@@ -244,4 +505,101 @@ mod tests {
 			Ok(count) => assert_eq!(count, 654470),
 		}
 	}
+
+	#[tokio::test]
+	async fn fee_estimator_serves_estimates_above_mempool_min_fee() {
+		let response = serde_json::json!({ "result": { "mempoolminfee": 0.00001, "feerate": 0.0002 } });
+		let server = HttpServer::responding_with_ok(MessageBody::Content(response));
+		let client = RpcClient::new(CREDENTIALS, server.endpoint()).unwrap();
+		let fee_estimator = RpcFeeEstimator::new(&client);
+
+		fee_estimator.update_fee_estimates().await.unwrap();
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::MempoolMinimum), FEERATE_FLOOR_SATS_PER_KW);
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::Background), 5000);
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::HighPriority), 5000);
+	}
+
+	#[tokio::test]
+	async fn fee_estimator_falls_back_without_estimates() {
+		let response = serde_json::json!({
+			"result": { "mempoolminfee": 0.0001, "errors": ["Insufficient data or no feerate found"] },
+		});
+		let server = HttpServer::responding_with_ok(MessageBody::Content(response));
+		let client = RpcClient::new(CREDENTIALS, server.endpoint()).unwrap();
+		let fee_estimator = RpcFeeEstimator::new(&client);
+
+		fee_estimator.update_fee_estimates().await.unwrap();
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::MempoolMinimum), 2500);
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::Normal), 2500);
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::HighPriority), 5000);
+	}
+
+	#[tokio::test]
+	async fn fee_estimator_keeps_estimates_retrieved_before_failure() {
+		let response = serde_json::json!({ "result": { "feerate": 0.0002 } });
+		let server = HttpServer::responding_with_ok(MessageBody::Content(response));
+		let client = RpcClient::new(CREDENTIALS, server.endpoint()).unwrap();
+		let fee_estimator = RpcFeeEstimator::new(&client);
+
+		// Failing to retrieve the mempool minimum fee doesn't prevent retrieving the estimates.
+		match fee_estimator.update_fee_estimates().await {
+			Err(e) => assert_eq!(e.kind(), std::io::ErrorKind::InvalidData),
+			Ok(_) => panic!("Expected error"),
+		}
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::MempoolMinimum), FEERATE_FLOOR_SATS_PER_KW);
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::Background), 5000);
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::HighPriority), 5000);
+	}
+
+	#[cfg(feature = "tokio")]
+	#[tokio::test]
+	async fn fee_estimator_updates_from_spawned_task() {
+		let response = serde_json::json!({ "result": { "mempoolminfee": 0.00001, "feerate": 0.0002 } });
+		let server = HttpServer::responding_with_ok(MessageBody::Content(response));
+		let client = std::sync::Arc::new(RpcClient::new(CREDENTIALS, server.endpoint()).unwrap());
+		let fee_estimator = std::sync::Arc::new(RpcFeeEstimator::new(client));
+
+		let handle = RpcFeeEstimator::spawn_update_task(
+			std::sync::Arc::clone(&fee_estimator), std::time::Duration::from_secs(60));
+		for _ in 0..100 {
+			if fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::Background) == 5000 {
+				break;
+			}
+			tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+		}
+		handle.abort();
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::Background), 5000);
+	}
+
+	#[tokio::test]
+	async fn broadcaster_sends_single_transaction() {
+		let tx = genesis_block(Network::Bitcoin).txdata[0].clone();
+		let response = serde_json::json!({ "result": tx.txid().to_hex() });
+		let server = HttpServer::responding_with_ok(MessageBody::Content(response));
+		let client = RpcClient::new(CREDENTIALS, server.endpoint()).unwrap();
+		let logger = TestLogger::new();
+		let broadcaster = RpcBroadcaster::new(&client, &logger);
+
+		broadcaster.broadcast_transactions(&[&tx]);
+		broadcaster.broadcast_pending().await;
+		logger.assert_log_contains("lightning_block_sync::rpc", "Broadcast transaction", 1);
+		assert!(broadcaster.pending_broadcasts.lock().unwrap().is_empty());
+	}
+
+	#[tokio::test]
+	async fn broadcaster_falls_back_to_individual_transactions() {
+		let response = serde_json::json!({
+			"error": { "code": -32601, "message": "Method not found" },
+		});
+		let server = HttpServer::responding_with_server_error(response);
+		let client = RpcClient::new(CREDENTIALS, server.endpoint()).unwrap();
+		let logger = TestLogger::new();
+		let broadcaster = RpcBroadcaster::new(&client, &logger);
+
+		let tx = genesis_block(Network::Bitcoin).txdata[0].clone();
+		broadcaster.broadcast_transactions(&[&tx, &tx]);
+		broadcaster.broadcast_pending().await;
+		logger.assert_log_contains("lightning_block_sync::rpc", "Failed to submit package", 1);
+		logger.assert_log_contains("lightning_block_sync::rpc", "Failed to broadcast transaction", 2);
+	}
 }
@@ -1,13 +1,17 @@
 use crate::{AsyncBlockSourceResult, BlockData, BlockHeaderData, BlockSource, BlockSourceError, UnboundedCache};
+use crate::filter::FilterSource;
 use crate::poll::{Validate, ValidatedBlockHeader};
 
 use bitcoin::blockdata::block::{Block, BlockHeader};
 use bitcoin::blockdata::constants::genesis_block;
-use bitcoin::hash_types::BlockHash;
+use bitcoin::hash_types::{BlockHash, FilterHeader};
+use bitcoin::hashes::Hash;
 use bitcoin::network::constants::Network;
 use bitcoin::util::uint::Uint256;
 use bitcoin::util::hash::bitcoin_merkle_root;
-use bitcoin::{PackedLockTime, Transaction};
+use bitcoin::util::bip158;
+use bitcoin::util::bip158::BlockFilter;
+use bitcoin::{PackedLockTime, Script, Transaction};
 
 use lightning::chain;
 
@@ -21,6 +25,11 @@ pub struct Blockchain {
 	without_headers: bool,
 	malformed_headers: bool,
 	filtered_blocks: bool,
+	without_filters: bool,
+	bogus_filters: bool,
+	bogus_filter_headers: bool,
+	chainwork_offset: u64,
+	derived_chainwork: bool,
 }
 
 impl Blockchain {
@@ -82,6 +91,46 @@ impl Blockchain {
 		Self { filtered_blocks: true, ..self }
 	}
 
+	pub fn without_filters(self) -> Self {
+		Self { without_filters: true, ..self }
+	}
+
+	/// Serves filters which don't match any script, while the served filter headers still commit to
+	/// the actual filters.
+	pub fn with_bogus_filters(self) -> Self {
+		Self { bogus_filters: true, ..self }
+	}
+
+	/// Serves filter headers which don't commit to the actual filters.
+	pub fn with_bogus_filter_headers(self) -> Self {
+		Self { bogus_filter_headers: true, ..self }
+	}
+
+	/// Inflates the chainwork of the served headers by the given amount.
+	pub fn with_chainwork_offset(self, chainwork_offset: u64) -> Self {
+		Self { chainwork_offset, ..self }
+	}
+
+	/// Reports the served chainwork as derived rather than the actual total work, like the Esplora
+	/// client does.
+	pub fn with_derived_chainwork(self) -> Self {
+		Self { derived_chainwork: true, ..self }
+	}
+
+	/// Adds the given transaction to the block at the given height, updating the following blocks
+	/// to build on the modified block.
+	pub fn with_transaction(mut self, height: usize, tx: Transaction) -> Self {
+		let block = &mut self.blocks[height];
+		block.txdata.push(tx);
+		block.header.merkle_root = block.compute_merkle_root().unwrap();
+		let mut prev_blockhash = block.block_hash();
+		for block in self.blocks.iter_mut().skip(height + 1) {
+			block.header.prev_blockhash = prev_blockhash;
+			prev_blockhash = block.block_hash();
+		}
+		self
+	}
+
 	pub fn fork_at_height(&self, height: usize) -> Self {
 		assert!(height + 1 < self.blocks.len());
 		let mut blocks = self.blocks.clone();
@@ -143,6 +192,7 @@ impl BlockSource for Blockchain {
 					if self.malformed_headers {
 						header_data.header.time += 1;
 					}
+					header_data.chainwork = header_data.chainwork + Uint256::from_u64(self.chainwork_offset).unwrap();
 
 					return Ok(header_data);
 				}
@@ -183,6 +233,65 @@ impl BlockSource for Blockchain {
 			}
 		})
 	}
+
+	fn reports_chainwork(&self) -> bool {
+		!self.derived_chainwork
+	}
+}
+
+impl Blockchain {
+	fn filter_for_block(&self, block: &Block) -> Result<BlockFilter, BlockSourceError> {
+		// Spent outputs not found in the chain are treated as paying to an empty script.
+		let script_for_coin = |outpoint: &bitcoin::OutPoint| -> Result<Script, bip158::Error> {
+			Ok(self.blocks.iter()
+				.flat_map(|block| block.txdata.iter())
+				.find(|tx| tx.txid() == outpoint.txid)
+				.and_then(|tx| tx.output.get(outpoint.vout as usize))
+				.map(|txout| txout.script_pubkey.clone())
+				.unwrap_or_else(Script::new))
+		};
+		BlockFilter::new_script_filter(block, script_for_coin).map_err(BlockSourceError::persistent)
+	}
+}
+
+impl FilterSource for Blockchain {
+	fn get_filter<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, BlockFilter> {
+		Box::pin(async move {
+			if self.without_filters {
+				return Err(BlockSourceError::transient("filter not found"));
+			}
+
+			for block in self.blocks.iter() {
+				if block.header.block_hash() == *header_hash {
+					if self.bogus_filters {
+						return Ok(BlockFilter::new(&[0]));
+					}
+					return self.filter_for_block(block);
+				}
+			}
+			Err(BlockSourceError::transient("filter not found"))
+		})
+	}
+
+	fn get_filter_header<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, FilterHeader> {
+		Box::pin(async move {
+			if self.without_filters {
+				return Err(BlockSourceError::transient("filter header not found"));
+			}
+
+			let mut filter_header = FilterHeader::all_zeros();
+			for block in self.blocks.iter() {
+				filter_header = self.filter_for_block(block)?.filter_header(&filter_header);
+				if block.header.block_hash() == *header_hash {
+					if self.bogus_filter_headers {
+						return Ok(FilterHeader::hash(&filter_header[..]));
+					}
+					return Ok(filter_header);
+				}
+			}
+			Err(BlockSourceError::transient("filter header not found"))
+		})
+	}
 }
 
 pub struct NullChainListener;
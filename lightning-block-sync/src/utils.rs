@@ -1,11 +1,34 @@
 use bitcoin::hashes::hex::FromHex;
 use bitcoin::util::uint::Uint256;
 
+use lightning::chain::chaininterface::{ConfirmationTarget, FEERATE_FLOOR_SATS_PER_KW};
+
 pub fn hex_to_uint256(hex: &str) -> Result<Uint256, bitcoin::hashes::hex::Error> {
 	let bytes = <[u8; 32]>::from_hex(hex)?;
 	Ok(Uint256::from_be_bytes(bytes))
 }
 
+/// Returns the confirmation target in blocks used to estimate the feerate for the given
+/// [`ConfirmationTarget`].
+pub fn confirmation_target_blocks(confirmation_target: ConfirmationTarget) -> u16 {
+	match confirmation_target {
+		ConfirmationTarget::MempoolMinimum => 1008,
+		ConfirmationTarget::Background => 144,
+		ConfirmationTarget::Normal => 12,
+		ConfirmationTarget::HighPriority => 3,
+	}
+}
+
+/// Returns the feerate in sat/KW used if no estimates are available.
+pub fn fallback_sat_per_1000_weight(confirmation_target: ConfirmationTarget) -> u32 {
+	match confirmation_target {
+		ConfirmationTarget::MempoolMinimum => FEERATE_FLOOR_SATS_PER_KW,
+		ConfirmationTarget::Background => 500,
+		ConfirmationTarget::Normal => 2000,
+		ConfirmationTarget::HighPriority => 5000,
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
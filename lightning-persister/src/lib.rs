@@ -16,20 +16,16 @@ extern crate lightning;
 extern crate bitcoin;
 extern crate libc;
 
-use bitcoin::hash_types::{BlockHash, Txid};
-use bitcoin::hashes::hex::FromHex;
-use lightning::chain::channelmonitor::ChannelMonitor;
-use lightning::sign::{EntropySource, SignerProvider};
-use lightning::util::ser::{ReadableArgs, Writeable};
-use lightning::util::persist::KVStorePersister;
+use lightning::util::persist::{KVStore, KVSTORE_NAMESPACE_KEY_ALPHABET, KVSTORE_NAMESPACE_KEY_MAX_LEN};
 use std::fs;
-use std::io::Cursor;
-use std::ops::Deref;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 
 /// FilesystemPersister persists channel data on disk, where each channel's
 /// data is stored in a file named after its funding outpoint.
 ///
+/// It implements [`KVStore`], storing each `namespace` and `sub_namespace` as a directory below
+/// the data directory and each key as a file therein.
+///
 /// Warning: this module does the best it can with calls to persist data, but it
 /// can only guarantee that the data is passed to the drive. It is up to the
 /// drive manufacturers to do the actual persistence properly, which they often
@@ -57,76 +53,91 @@ impl FilesystemPersister {
 		self.path_to_channel_data.clone()
 	}
 
-	/// Read `ChannelMonitor`s from disk.
-	pub fn read_channelmonitors<ES: Deref, SP: Deref> (
-		&self, entropy_source: ES, signer_provider: SP
-	) -> std::io::Result<Vec<(BlockHash, ChannelMonitor<<SP::Target as SignerProvider>::Signer>)>>
-		where
-			ES::Target: EntropySource + Sized,
-			SP::Target: SignerProvider + Sized
-	{
-		let mut path = PathBuf::from(&self.path_to_channel_data);
-		path.push("monitors");
-		if !Path::new(&path).exists() {
+	fn get_dest_dir_path(&self, namespace: &str, sub_namespace: &str) -> PathBuf {
+		let mut dest_dir_path = PathBuf::from(&self.path_to_channel_data);
+		dest_dir_path.push(namespace);
+		// Pushing an empty sub-namespace would add a trailing separator, making the path only
+		// resolve if `namespace` is a directory.
+		if !sub_namespace.is_empty() {
+			dest_dir_path.push(sub_namespace);
+		}
+		dest_dir_path
+	}
+}
+
+fn is_valid_kvstore_str(key: &str) -> bool {
+	key.len() <= KVSTORE_NAMESPACE_KEY_MAX_LEN && key.chars().all(|c| KVSTORE_NAMESPACE_KEY_ALPHABET.contains(c))
+}
+
+fn check_namespace_key_validity(namespace: &str, sub_namespace: &str, key: Option<&str>) -> std::io::Result<()> {
+	if namespace.is_empty() && !sub_namespace.is_empty() {
+		return Err(std::io::Error::new(std::io::ErrorKind::Other,
+			"Sub-namespace may only be given together with a non-empty namespace"));
+	}
+	if !is_valid_kvstore_str(namespace) || !is_valid_kvstore_str(sub_namespace) {
+		return Err(std::io::Error::new(std::io::ErrorKind::Other, "Namespace contains invalid characters"));
+	}
+	if let Some(key) = key {
+		if key.is_empty() || !is_valid_kvstore_str(key) {
+			return Err(std::io::Error::new(std::io::ErrorKind::Other, "Key is empty or contains invalid characters"));
+		}
+	}
+	Ok(())
+}
+
+impl KVStore for FilesystemPersister {
+	fn read(&self, namespace: &str, sub_namespace: &str, key: &str) -> std::io::Result<Vec<u8>> {
+		check_namespace_key_validity(namespace, sub_namespace, Some(key))?;
+		let mut dest_file_path = self.get_dest_dir_path(namespace, sub_namespace);
+		dest_file_path.push(key);
+		fs::read(dest_file_path)
+	}
+
+	fn write(&self, namespace: &str, sub_namespace: &str, key: &str, buf: &[u8]) -> std::io::Result<()> {
+		check_namespace_key_validity(namespace, sub_namespace, Some(key))?;
+		let mut dest_file_path = self.get_dest_dir_path(namespace, sub_namespace);
+		dest_file_path.push(key);
+		util::write_to_file(dest_file_path, buf)
+	}
+
+	fn remove(&self, namespace: &str, sub_namespace: &str, key: &str, lazy: bool) -> std::io::Result<()> {
+		check_namespace_key_validity(namespace, sub_namespace, Some(key))?;
+		let mut dest_file_path = self.get_dest_dir_path(namespace, sub_namespace);
+		dest_file_path.push(key);
+		util::remove_file(dest_file_path, lazy)
+	}
+
+	fn list(&self, namespace: &str, sub_namespace: &str) -> std::io::Result<Vec<String>> {
+		check_namespace_key_validity(namespace, sub_namespace, None)?;
+		let dest_dir_path = self.get_dest_dir_path(namespace, sub_namespace);
+		if !dest_dir_path.exists() {
 			return Ok(Vec::new());
 		}
-		let mut res = Vec::new();
-		for file_option in fs::read_dir(path)? {
-			let file = file_option.unwrap();
-			let owned_file_name = file.file_name();
-			let filename = owned_file_name.to_str()
+
+		let mut keys = Vec::new();
+		for entry in fs::read_dir(dest_dir_path)? {
+			let entry = entry?;
+			// Skip any (sub-)namespace directories, we only return keys.
+			if !entry.file_type()?.is_file() {
+				continue;
+			}
+			let owned_file_name = entry.file_name();
+			let file_name = owned_file_name.to_str()
 				.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData,
 					"File name is not a valid utf8 string"))?;
-			if !filename.is_ascii() || filename.len() < 65 {
-				return Err(std::io::Error::new(
-					std::io::ErrorKind::InvalidData,
-					"Invalid ChannelMonitor file name",
-				));
-			}
-			if filename.ends_with(".tmp") {
+			if file_name.ends_with(".tmp") {
 				// If we were in the middle of committing an new update and crashed, it should be
 				// safe to ignore the update - we should never have returned to the caller and
 				// irrevocably committed to the new state in any way.
 				continue;
 			}
-
-			let txid: Txid = Txid::from_hex(filename.split_at(64).0)
-				.map_err(|_| std::io::Error::new(
-					std::io::ErrorKind::InvalidData,
-					"Invalid tx ID in filename",
-				))?;
-
-			let index: u16 = filename.split_at(65).1.parse()
-				.map_err(|_| std::io::Error::new(
-					std::io::ErrorKind::InvalidData,
-					"Invalid tx index in filename",
-				))?;
-
-			let contents = fs::read(&file.path())?;
-			let mut buffer = Cursor::new(&contents);
-			match <(BlockHash, ChannelMonitor<<SP::Target as SignerProvider>::Signer>)>::read(&mut buffer, (&*entropy_source, &*signer_provider)) {
-				Ok((blockhash, channel_monitor)) => {
-					if channel_monitor.get_funding_txo().0.txid != txid || channel_monitor.get_funding_txo().0.index != index {
-						return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
-									       "ChannelMonitor was stored in the wrong file"));
-					}
-					res.push((blockhash, channel_monitor));
-				}
-				Err(e) => return Err(std::io::Error::new(
-					std::io::ErrorKind::InvalidData,
-					format!("Failed to deserialize ChannelMonitor: {}", e),
-				))
+			if !is_valid_kvstore_str(file_name) {
+				return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+					"Stored key contains invalid characters"));
 			}
+			keys.push(file_name.to_string());
 		}
-		Ok(res)
-	}
-}
-
-impl KVStorePersister for FilesystemPersister {
-	fn persist<W: Writeable>(&self, key: &str, object: &W) -> std::io::Result<()> {
-		let mut dest_file = PathBuf::from(self.path_to_channel_data.clone());
-		dest_file.push(key);
-		util::write_to_file(dest_file, object)
+		Ok(keys)
 	}
 }
 
@@ -144,6 +155,7 @@ mod tests {
 	use lightning::{check_closed_broadcast, check_closed_event, check_added_monitors};
 	use lightning::events::{ClosureReason, MessageSendEventsProvider};
 	use lightning::ln::functional_test_utils::*;
+	use lightning::util::persist::{KVStore, read_channel_monitors};
 	use lightning::util::test_utils;
 	use std::fs;
 	#[cfg(target_os = "windows")]
@@ -179,9 +191,47 @@ mod tests {
 		let node_chanmgrs = create_node_chanmgrs(1, &node_cfgs, &[None]);
 		let nodes = create_network(1, &node_cfgs, &node_chanmgrs);
 
-		// Check that read_channelmonitors() returns error if monitors/ is not a
+		// Check that read_channel_monitors() returns error if monitors/ is not a
 		// directory.
-		assert!(persister.read_channelmonitors(nodes[0].keys_manager, nodes[0].keys_manager).is_err());
+		assert!(read_channel_monitors(&persister, nodes[0].keys_manager, nodes[0].keys_manager).is_err());
+	}
+
+	#[test]
+	fn read_write_remove_list_persist() {
+		let persister = FilesystemPersister::new("test_read_write_remove_list_persist".to_string());
+		let data = [42u8; 32];
+
+		let namespace = "testspace";
+		let sub_namespace = "testsubspace";
+		let key = "testkey";
+
+		// Test the basic KVStore operations.
+		persister.write(namespace, sub_namespace, key, &data).unwrap();
+
+		// Test empty namespace/sub_namespace is allowed, but not empty namespace and non-empty
+		// sub-namespace, and not empty key.
+		persister.write("", "", key, &data).unwrap();
+		assert!(persister.write("", sub_namespace, key, &data).is_err());
+		assert!(persister.write(namespace, sub_namespace, "", &data).is_err());
+		assert!(persister.write(namespace, sub_namespace, "bad/key", &data).is_err());
+
+		let listed_keys = persister.list(namespace, sub_namespace).unwrap();
+		assert_eq!(listed_keys, vec![key.to_string()]);
+
+		let read_data = persister.read(namespace, sub_namespace, key).unwrap();
+		assert_eq!(data, &*read_data);
+
+		persister.remove(namespace, sub_namespace, key, false).unwrap();
+
+		let listed_keys = persister.list(namespace, sub_namespace).unwrap();
+		assert_eq!(listed_keys.len(), 0);
+		assert_eq!(persister.read(namespace, sub_namespace, key).unwrap_err().kind(), std::io::ErrorKind::NotFound);
+
+		// Removing a non-existent key succeeds.
+		persister.remove(namespace, sub_namespace, key, false).unwrap();
+
+		// Sub-namespace directories are not listed as keys.
+		assert_eq!(persister.list("", "").unwrap(), vec![key.to_string()]);
 	}
 
 	// Integration-test the FilesystemPersister. Test relaying a few payments
@@ -203,20 +253,20 @@ mod tests {
 
 		// Check that the persisted channel data is empty before any channels are
 		// open.
-		let mut persisted_chan_data_0 = persister_0.read_channelmonitors(nodes[0].keys_manager, nodes[0].keys_manager).unwrap();
+		let mut persisted_chan_data_0 = read_channel_monitors(&persister_0, nodes[0].keys_manager, nodes[0].keys_manager).unwrap();
 		assert_eq!(persisted_chan_data_0.len(), 0);
-		let mut persisted_chan_data_1 = persister_1.read_channelmonitors(nodes[1].keys_manager, nodes[1].keys_manager).unwrap();
+		let mut persisted_chan_data_1 = read_channel_monitors(&persister_1, nodes[1].keys_manager, nodes[1].keys_manager).unwrap();
 		assert_eq!(persisted_chan_data_1.len(), 0);
 
 		// Helper to make sure the channel is on the expected update ID.
 		macro_rules! check_persisted_data {
 			($expected_update_id: expr) => {
-				persisted_chan_data_0 = persister_0.read_channelmonitors(nodes[0].keys_manager, nodes[0].keys_manager).unwrap();
+				persisted_chan_data_0 = read_channel_monitors(&persister_0, nodes[0].keys_manager, nodes[0].keys_manager).unwrap();
 				assert_eq!(persisted_chan_data_0.len(), 1);
 				for (_, mon) in persisted_chan_data_0.iter() {
 					assert_eq!(mon.get_latest_update_id(), $expected_update_id);
 				}
-				persisted_chan_data_1 = persister_1.read_channelmonitors(nodes[1].keys_manager, nodes[1].keys_manager).unwrap();
+				persisted_chan_data_1 = read_channel_monitors(&persister_1, nodes[1].keys_manager, nodes[1].keys_manager).unwrap();
 				assert_eq!(persisted_chan_data_1.len(), 1);
 				for (_, mon) in persisted_chan_data_1.iter() {
 					assert_eq!(mon.get_latest_update_id(), $expected_update_id);
@@ -3,13 +3,11 @@ extern crate winapi;
 
 use std::fs;
 use std::path::PathBuf;
-use std::io::BufWriter;
+use std::io::{BufWriter, Write};
 
 #[cfg(not(target_os = "windows"))]
 use std::os::unix::io::AsRawFd;
 
-use lightning::util::ser::Writeable;
-
 #[cfg(target_os = "windows")]
 use {
 	std::ffi::OsStr,
@@ -33,7 +31,7 @@ fn path_to_windows_str<T: AsRef<OsStr>>(path: T) -> Vec<winapi::shared::ntdef::W
 }
 
 #[allow(bare_trait_objects)]
-pub(crate) fn write_to_file<W: Writeable>(dest_file: PathBuf, data: &W) -> std::io::Result<()> {
+pub(crate) fn write_to_file(dest_file: PathBuf, data: &[u8]) -> std::io::Result<()> {
 	let mut tmp_file = dest_file.clone();
 	tmp_file.set_extension("tmp");
 
@@ -48,7 +46,7 @@ pub(crate) fn write_to_file<W: Writeable>(dest_file: PathBuf, data: &W) -> std::
 		// Note that going by rust-lang/rust@d602a6b, on MacOS it is only safe to use
 		// rust stdlib 1.36 or higher.
 		let mut buf = BufWriter::new(fs::File::create(&tmp_file)?);
-		data.write(&mut buf)?;
+		buf.write_all(data)?;
 		buf.into_inner()?.sync_all()?;
 	}
 	// Fsync the parent directory on Unix.
@@ -77,29 +75,37 @@ pub(crate) fn write_to_file<W: Writeable>(dest_file: PathBuf, data: &W) -> std::
 	Ok(())
 }
 
+pub(crate) fn remove_file(dest_file: PathBuf, lazy: bool) -> std::io::Result<()> {
+	if !dest_file.is_file() {
+		return Ok(());
+	}
+
+	fs::remove_file(&dest_file)?;
+	if !lazy {
+		// Fsync the parent directory on Unix to make sure the removal is persisted.
+		#[cfg(not(target_os = "windows"))]
+		{
+			let parent_directory = dest_file.parent().unwrap();
+			let dir_file = fs::OpenOptions::new().read(true).open(parent_directory)?;
+			unsafe { libc::fsync(dir_file.as_raw_fd()); }
+		}
+	}
+	Ok(())
+}
+
 #[cfg(test)]
 mod tests {
-	use lightning::util::ser::{Writer, Writeable};
-
 	use super::{write_to_file};
 	use std::fs;
 	use std::io;
 	use std::path::PathBuf;
 
-	struct TestWriteable{}
-	impl Writeable for TestWriteable {
-		fn write<W: Writer>(&self, writer: &mut W) -> Result<(), std::io::Error> {
-			writer.write_all(&[42; 1])
-		}
-	}
-
 	// Test that if the persister's path to channel data is read-only, writing
 	// data to it fails. Windows ignores the read-only flag for folders, so this
 	// test is Unix-only.
 	#[cfg(not(target_os = "windows"))]
 	#[test]
 	fn test_readonly_dir() {
-		let test_writeable = TestWriteable{};
 		let filename = "test_readonly_dir_persister_filename".to_string();
 		let path = "test_readonly_dir_persister_dir";
 		fs::create_dir_all(path).unwrap();
@@ -108,7 +114,7 @@ mod tests {
 		fs::set_permissions(path, perms).unwrap();
 		let mut dest_file = PathBuf::from(path);
 		dest_file.push(filename);
-		match write_to_file(dest_file, &test_writeable) {
+		match write_to_file(dest_file, &[42; 1]) {
 			Err(e) => assert_eq!(e.kind(), io::ErrorKind::PermissionDenied),
 			_ => panic!("Unexpected error message")
 		}
@@ -124,50 +130,24 @@ mod tests {
 	#[cfg(not(target_os = "windows"))]
 	#[test]
 	fn test_rename_failure() {
-		let test_writeable = TestWriteable{};
 		let filename = "test_rename_failure_filename";
 		let path = "test_rename_failure_dir";
 		let mut dest_file = PathBuf::from(path);
 		dest_file.push(filename);
 		// Create the channel data file and make it a directory.
 		fs::create_dir_all(dest_file.clone()).unwrap();
-		match write_to_file(dest_file, &test_writeable) {
+		match write_to_file(dest_file, &[42; 1]) {
 			Err(e) => assert_eq!(e.raw_os_error(), Some(libc::EISDIR)),
 			_ => panic!("Unexpected Ok(())")
 		}
 		fs::remove_dir_all(path).unwrap();
 	}
 
-	#[test]
-	fn test_diskwriteable_failure() {
-		struct FailingWriteable {}
-		impl Writeable for FailingWriteable {
-			fn write<W: Writer>(&self, _writer: &mut W) -> Result<(), std::io::Error> {
-				Err(std::io::Error::new(std::io::ErrorKind::Other, "expected failure"))
-			}
-		}
-
-		let filename = "test_diskwriteable_failure";
-		let path = "test_diskwriteable_failure_dir";
-		let test_writeable = FailingWriteable{};
-		let mut dest_file = PathBuf::from(path);
-		dest_file.push(filename);
-		match write_to_file(dest_file, &test_writeable) {
-			Err(e) => {
-				assert_eq!(e.kind(), std::io::ErrorKind::Other);
-				assert_eq!(e.get_ref().unwrap().to_string(), "expected failure");
-			},
-			_ => panic!("unexpected result")
-		}
-		fs::remove_dir_all(path).unwrap();
-	}
-
 	// Test failure to create the temporary file in the persistence process.
 	// We induce this failure by having the temp file already exist and be a
 	// directory.
 	#[test]
 	fn test_tmp_file_creation_failure() {
-		let test_writeable = TestWriteable{};
 		let filename = "test_tmp_file_creation_failure_filename".to_string();
 		let path = "test_tmp_file_creation_failure_dir";
 		let mut dest_file = PathBuf::from(path);
@@ -175,7 +155,7 @@ mod tests {
 		let mut tmp_file = dest_file.clone();
 		tmp_file.set_extension("tmp");
 		fs::create_dir_all(tmp_file).unwrap();
-		match write_to_file(dest_file, &test_writeable) {
+		match write_to_file(dest_file, &[42; 1]) {
 			Err(e) => {
 				#[cfg(not(target_os = "windows"))]
 				assert_eq!(e.raw_os_error(), Some(libc::EISDIR));
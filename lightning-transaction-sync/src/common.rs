@@ -1,5 +1,5 @@
 use lightning::chain::WatchedOutput;
-use bitcoin::{Txid, BlockHash, Transaction, BlockHeader, OutPoint};
+use bitcoin::{Txid, BlockHash, Transaction, BlockHeader, OutPoint, Script};
 
 use std::collections::{HashSet, HashMap};
 
@@ -9,6 +9,11 @@ pub(crate) struct SyncState {
 	// Transactions that were previously processed, but must not be forgotten
 	// yet since they still need to be monitored for confirmation on-chain.
 	pub watched_transactions: HashSet<Txid>,
+	// The output scripts transactions were registered with, which allow backends that index
+	// transactions by script to look them up. These are kept until the transaction is neither
+	// watched nor relevant to any `Confirm` anymore, as it may need to be watched again if it gets
+	// unconfirmed.
+	pub watched_tx_scripts: HashMap<Txid, Script>,
 	// Outputs that were previously processed, but must not be forgotten yet as
 	// as we still need to monitor any spends on-chain.
 	pub watched_outputs: HashMap<OutPoint, WatchedOutput>,
@@ -22,18 +27,27 @@ impl SyncState {
 	pub fn new() -> Self {
 		Self {
 			watched_transactions: HashSet::new(),
+			watched_tx_scripts: HashMap::new(),
 			watched_outputs: HashMap::new(),
 			last_sync_hash: None,
 			pending_sync: false,
 		}
 	}
+
+	// Drops the scripts of transactions that are neither watched nor in `relevant_txids`.
+	pub fn prune_tx_scripts(&mut self, relevant_txids: &HashSet<Txid>) {
+		let watched_transactions = &self.watched_transactions;
+		self.watched_tx_scripts.retain(|txid, _| {
+			watched_transactions.contains(txid) || relevant_txids.contains(txid)
+		});
+	}
 }
 
 
 // A queue that is to be filled by `Filter` and drained during the next syncing round.
 pub(crate) struct FilterQueue {
 	// Transactions that were registered via the `Filter` interface and have to be processed.
-	pub transactions: HashSet<Txid>,
+	pub transactions: HashMap<Txid, Script>,
 	// Outputs that were registered via the `Filter` interface and have to be processed.
 	pub outputs: HashMap<OutPoint, WatchedOutput>,
 }
@@ -41,7 +55,7 @@ pub(crate) struct FilterQueue {
 impl FilterQueue {
 	pub fn new() -> Self {
 		Self {
-			transactions: HashSet::new(),
+			transactions: HashMap::new(),
 			outputs: HashMap::new(),
 		}
 	}
@@ -55,7 +69,10 @@ impl FilterQueue {
 		if !self.transactions.is_empty() {
 			pending_registrations = true;
 
-			sync_state.watched_transactions.extend(self.transactions.drain());
+			for (txid, script_pubkey) in self.transactions.drain() {
+				sync_state.watched_transactions.insert(txid);
+				sync_state.watched_tx_scripts.insert(txid, script_pubkey);
+			}
 		}
 
 		if !self.outputs.is_empty() {
@@ -0,0 +1,536 @@
+use crate::error::{TxSyncError, InternalError};
+use crate::common::{SyncState, FilterQueue, ConfirmedTx};
+
+use lightning::util::logger::Logger;
+use lightning::{log_error, log_info, log_debug, log_trace};
+use lightning::chain::WatchedOutput;
+use lightning::chain::{Confirm, Filter};
+
+use bitcoin::{BlockHash, BlockHeader, Script, Transaction, Txid, TxMerkleNode};
+use bitcoin::hashes::{Hash, HashEngine};
+use bitcoin::hashes::sha256d::Hash as Sha256d;
+
+use electrum_client::Client as ElectrumClient;
+use electrum_client::ElectrumApi;
+use electrum_client::GetMerkleRes;
+
+use std::collections::HashSet;
+use std::sync::Mutex;
+use core::ops::Deref;
+
+/// Synchronizes LDK with a given Electrum server.
+///
+/// Needs to be registered with a [`ChainMonitor`] via the [`Filter`] interface to be informed of
+/// transactions and outputs to monitor for on-chain confirmation, unconfirmation, and
+/// reconfirmation.
+///
+/// Note that registration via [`Filter`] needs to happen before any calls to
+/// [`Watch::watch_channel`] to ensure we get notified of the items to monitor.
+///
+/// The client subscribes to the status of the scripts related to the monitored items, allowing
+/// [`ElectrumSyncClient::sync`] to pick up any changes to them even if the chain tip didn't move.
+///
+/// [`ChainMonitor`]: lightning::chain::chainmonitor::ChainMonitor
+/// [`Watch::watch_channel`]: lightning::chain::Watch::watch_channel
+/// [`Filter`]: lightning::chain::Filter
+pub struct ElectrumSyncClient<L: Deref>
+where
+	L::Target: Logger,
+{
+	sync_state: Mutex<SyncState>,
+	queue: Mutex<FilterQueue>,
+	// The scripts whose status we're currently subscribed to.
+	subscribed_scripts: Mutex<HashSet<Script>>,
+	client: ElectrumClient,
+	logger: L,
+}
+
+impl<L: Deref> ElectrumSyncClient<L>
+where
+	L::Target: Logger,
+{
+	/// Returns a new [`ElectrumSyncClient`] object, connecting to the given Electrum server.
+	pub fn new(server_url: String, logger: L) -> Result<Self, TxSyncError> {
+		let client = ElectrumClient::new(&server_url).map_err(|e| {
+			log_error!(logger, "Failed to connect to electrum server '{}': {}", server_url, e);
+			e
+		})?;
+
+		Ok(Self::from_client(client, logger))
+	}
+
+	/// Returns a new [`ElectrumSyncClient`] object using the given Electrum client.
+	pub fn from_client(client: ElectrumClient, logger: L) -> Self {
+		let sync_state = Mutex::new(SyncState::new());
+		let queue = Mutex::new(FilterQueue::new());
+		let subscribed_scripts = Mutex::new(HashSet::new());
+		Self {
+			sync_state,
+			queue,
+			subscribed_scripts,
+			client,
+			logger,
+		}
+	}
+
+	/// Synchronizes the given `confirmables` via their [`Confirm`] interface implementations. This
+	/// method should be called regularly to keep LDK up-to-date with current chain data.
+	///
+	/// For example, instances of [`ChannelManager`] and [`ChainMonitor`] can be informed about the
+	/// newest on-chain activity related to the items previously registered via the [`Filter`]
+	/// interface.
+	///
+	/// [`Confirm`]: lightning::chain::Confirm
+	/// [`ChainMonitor`]: lightning::chain::chainmonitor::ChainMonitor
+	/// [`ChannelManager`]: lightning::ln::channelmanager::ChannelManager
+	/// [`Filter`]: lightning::chain::Filter
+	pub fn sync(&self, confirmables: Vec<&(dyn Confirm + Sync + Send)>) -> Result<(), TxSyncError> {
+		// This lock makes sure we're syncing once at a time.
+		let mut sync_state = self.sync_state.lock().unwrap();
+
+		log_info!(self.logger, "Starting transaction sync.");
+
+		let (mut tip_header, mut tip_height) = self.get_tip()?;
+
+		loop {
+			let pending_registrations = self.queue.lock().unwrap().process_queues(&mut sync_state);
+			let tip_is_new = Some(tip_header.block_hash()) != sync_state.last_sync_hash;
+			let scripts_changed = match self.pop_script_notifications() {
+				Ok(scripts_changed) => scripts_changed,
+				Err(err) => {
+					log_error!(self.logger, "Failed during transaction sync, aborting.");
+					sync_state.pending_sync = true;
+					return Err(TxSyncError::from(err));
+				}
+			};
+
+			// We loop until any registered transactions have been processed at least once, or the
+			// tip hasn't been updated during the last iteration.
+			if !sync_state.pending_sync && !pending_registrations && !tip_is_new && !scripts_changed {
+				// Nothing to do.
+				break;
+			} else {
+				// Update the known tip to the newest one.
+				if tip_is_new {
+					// First check for any unconfirmed transactions and act on it immediately.
+					match self.get_unconfirmed_transactions(&mut sync_state, &confirmables) {
+						Ok(unconfirmed_txs) => {
+							// Double-check the tip. If it changed, a reorg happened since we
+							// started syncing and we need to restart last-minute.
+							let (check_tip_header, check_tip_height) = self.get_tip()?;
+							if check_tip_header != tip_header {
+								tip_header = check_tip_header;
+								tip_height = check_tip_height;
+								continue;
+							}
+
+							self.sync_unconfirmed_transactions(&mut sync_state, &confirmables, unconfirmed_txs);
+						},
+						Err(err) => {
+							// (Semi-)permanent failure, retry later.
+							log_error!(self.logger, "Failed during transaction sync, aborting.");
+							sync_state.pending_sync = true;
+							return Err(TxSyncError::from(err));
+						}
+					}
+
+					// Inform the interface of the new block. As it was reported by the server's
+					// header subscription, the tip is always part of the best chain.
+					for c in &confirmables {
+						c.best_block_updated(&tip_header, tip_height);
+					}
+				}
+
+				match self.get_confirmed_transactions(&sync_state) {
+					Ok(confirmed_txs) => {
+						// Double-check the tip. If it changed, a reorg happened since we
+						// started syncing and we need to restart last-minute.
+						let (check_tip_header, check_tip_height) = self.get_tip()?;
+						if check_tip_header != tip_header {
+							tip_header = check_tip_header;
+							tip_height = check_tip_height;
+							continue;
+						}
+
+						self.sync_confirmed_transactions(
+							&mut sync_state,
+							&confirmables,
+							confirmed_txs,
+						);
+					}
+					Err(InternalError::Inconsistency) => {
+						// Immediately restart syncing when we encounter any inconsistencies.
+						log_debug!(self.logger, "Encountered inconsistency during transaction sync, restarting.");
+						sync_state.pending_sync = true;
+						continue;
+					}
+					Err(err) => {
+						// (Semi-)permanent failure, retry later.
+						log_error!(self.logger, "Failed during transaction sync, aborting.");
+						sync_state.pending_sync = true;
+						return Err(TxSyncError::from(err));
+					}
+				}
+				sync_state.last_sync_hash = Some(tip_header.block_hash());
+				sync_state.pending_sync = false;
+			}
+		}
+		log_info!(self.logger, "Finished transaction sync.");
+		Ok(())
+	}
+
+	// Returns the header and height of the current chain tip as reported by the server.
+	fn get_tip(&self) -> Result<(BlockHeader, u32), InternalError> {
+		// Subscribing returns the current tip. As we're only interested in the latest tip, we drop
+		// any stale notifications which may have queued up since the last call.
+		let tip_notification = self.client.block_headers_subscribe()?;
+		while self.client.block_headers_pop()?.is_some() {}
+		Ok((tip_notification.header, tip_notification.height as u32))
+	}
+
+	// Drains any pending script status notifications, returning whether the status of any of the
+	// scripts we're subscribed to changed.
+	fn pop_script_notifications(&self) -> Result<bool, InternalError> {
+		let subscribed_scripts = self.subscribed_scripts.lock().unwrap();
+		if subscribed_scripts.is_empty() {
+			return Ok(false);
+		}
+
+		// Make sure we read any notifications the server sent us in the meantime.
+		self.client.ping()?;
+
+		let mut scripts_changed = false;
+		for script in subscribed_scripts.iter() {
+			while self.client.script_pop(script)?.is_some() {
+				scripts_changed = true;
+			}
+		}
+		Ok(scripts_changed)
+	}
+
+	// Subscribes to the status of the given scripts, and unsubscribes from any scripts we no
+	// longer need to watch.
+	fn update_script_subscriptions(&self, watched_scripts: &HashSet<&Script>) -> Result<(), InternalError> {
+		let mut subscribed_scripts = self.subscribed_scripts.lock().unwrap();
+
+		subscribed_scripts.retain(|script| {
+			if watched_scripts.contains(script) {
+				return true;
+			}
+			// Failing to unsubscribe just means we may receive superfluous notifications, which is
+			// harmless.
+			let _ = self.client.script_unsubscribe(script);
+			false
+		});
+
+		for script in watched_scripts {
+			if subscribed_scripts.contains(*script) {
+				continue;
+			}
+			match self.client.script_subscribe(script) {
+				Ok(_) | Err(electrum_client::Error::AlreadySubscribed(_)) => {
+					subscribed_scripts.insert((*script).clone());
+				}
+				Err(e) => {
+					log_error!(self.logger, "Failed to subscribe to script {}: {}", script, e);
+					return Err(InternalError::Failed);
+				}
+			}
+		}
+		Ok(())
+	}
+
+	fn sync_confirmed_transactions(
+		&self, sync_state: &mut SyncState, confirmables: &Vec<&(dyn Confirm + Sync + Send)>, confirmed_txs: Vec<ConfirmedTx>,
+	) {
+		for ctx in confirmed_txs {
+			for c in confirmables {
+				c.transactions_confirmed(
+					&ctx.block_header,
+					&[(ctx.pos, &ctx.tx)],
+					ctx.block_height,
+				);
+			}
+
+			sync_state.watched_transactions.remove(&ctx.tx.txid());
+
+			for input in &ctx.tx.input {
+				sync_state.watched_outputs.remove(&input.previous_output);
+			}
+		}
+	}
+
+	fn get_confirmed_transactions(
+		&self, sync_state: &SyncState,
+	) -> Result<Vec<ConfirmedTx>, InternalError> {
+
+		// First, check the confirmation status of registered transactions as well as the
+		// status of dependent transactions of registered outputs.
+
+		let mut confirmed_txs = Vec::new();
+
+		// As Electrum only indexes transactions by the scripts they touch, we look the registered
+		// transactions up via the script they were registered with. This doesn't require the
+		// server to know about the transaction yet, e.g., if it hasn't been broadcast.
+		let mut watched_txids = Vec::with_capacity(sync_state.watched_transactions.len());
+		let mut watched_script_pubkeys = Vec::with_capacity(
+			sync_state.watched_transactions.len() + sync_state.watched_outputs.len());
+		for txid in &sync_state.watched_transactions {
+			if let Some(script_pubkey) = self.get_tx_script(sync_state, txid)? {
+				watched_txids.push(*txid);
+				watched_script_pubkeys.push(script_pubkey);
+			}
+		}
+
+		for output in sync_state.watched_outputs.values() {
+			watched_script_pubkeys.push(output.script_pubkey.clone());
+		}
+
+		self.update_script_subscriptions(&watched_script_pubkeys.iter().collect())?;
+
+		let script_histories = self.client.batch_script_get_history(watched_script_pubkeys.iter())?;
+		if script_histories.len() != watched_script_pubkeys.len() {
+			log_error!(self.logger, "Electrum server returned an unexpected number of script histories. This should not happen. Please verify server integrity.");
+			return Err(InternalError::Failed);
+		}
+
+		let (tx_histories, output_histories) = script_histories.split_at(watched_txids.len());
+
+		for (txid, history) in watched_txids.iter().zip(tx_histories) {
+			for entry in history.iter().filter(|h| h.tx_hash == *txid) {
+				// Entries with a non-positive height are still unconfirmed.
+				if entry.height <= 0 {
+					continue;
+				}
+				let tx = self.client.transaction_get(txid)?;
+				if tx.txid() != *txid {
+					log_error!(self.logger, "Electrum server returned a transaction not matching the requested txid {}. This should not happen. Please verify server integrity.", txid);
+					return Err(InternalError::Failed);
+				}
+				if let Some(confirmed_tx) = self.get_confirmed_tx(tx, entry.height as u32)? {
+					confirmed_txs.push(confirmed_tx);
+				}
+			}
+		}
+
+		for (output, history) in sync_state.watched_outputs.values().zip(output_histories) {
+			for entry in history {
+				if entry.height <= 0 || sync_state.watched_transactions.contains(&entry.tx_hash) {
+					// Skip unconfirmed transactions as well as any we already checked above.
+					continue;
+				}
+
+				let tx = self.client.transaction_get(&entry.tx_hash)?;
+				let spends_output = tx.input.iter().any(|input| {
+					input.previous_output == output.outpoint.into_bitcoin_outpoint()
+				});
+				if !spends_output {
+					continue;
+				}
+
+				if let Some(confirmed_tx) = self.get_confirmed_tx(tx, entry.height as u32)? {
+					confirmed_txs.push(confirmed_tx);
+				}
+			}
+		}
+
+		// Sort all confirmed transactions first by block height, then by in-block
+		// position, and finally feed them to the interface in order.
+		confirmed_txs.sort_unstable_by(|tx1, tx2| {
+			tx1.block_height.cmp(&tx2.block_height).then_with(|| tx1.pos.cmp(&tx2.pos))
+		});
+		confirmed_txs.dedup_by(|tx1, tx2| tx1.tx.txid() == tx2.tx.txid());
+
+		Ok(confirmed_txs)
+	}
+
+	// Returns the given transaction along with its confirmation data, or `None` if we can't verify
+	// its inclusion in the block at the given height.
+	fn get_confirmed_tx(
+		&self, tx: Transaction, block_height: u32,
+	) -> Result<Option<ConfirmedTx>, InternalError> {
+		let txid = tx.txid();
+		if is_merkle_ambiguous(&tx) {
+			log_error!(self.logger, "Skipping transaction {} as its 64-byte serialization can't be told apart from an inner Merkle node.", txid);
+			return Ok(None);
+		}
+
+		let merkle_res = self.client.transaction_get_merkle(&txid, block_height as usize)?;
+		if merkle_res.block_height != block_height as usize {
+			log_trace!(self.logger, "Inconsistency: Tx {} expected at height {}, but is confirmed at {}", txid, block_height, merkle_res.block_height);
+			return Err(InternalError::Inconsistency);
+		}
+
+		let block_header = self.client.block_header(block_height as usize)?;
+		if !validate_merkle_proof(&tx, &block_header.merkle_root, &merkle_res) {
+			// If the proof doesn't match the header at the given height, the block was likely
+			// reorged out in the meantime and we should start over.
+			log_trace!(self.logger, "Inconsistency: Merkle proof for tx {} doesn't match block at height {}", txid, block_height);
+			return Err(InternalError::Inconsistency);
+		}
+
+		Ok(Some(ConfirmedTx { tx, block_header, block_height, pos: merkle_res.pos }))
+	}
+
+	fn get_unconfirmed_transactions(
+		&self, sync_state: &mut SyncState, confirmables: &Vec<&(dyn Confirm + Sync + Send)>,
+	) -> Result<Vec<Txid>, InternalError> {
+		// Query the interface for relevant txids and check whether they are still confirmed in
+		// the relevant blocks, mark them unconfirmed otherwise.
+		let relevant_txids = confirmables
+			.iter()
+			.flat_map(|c| c.get_relevant_txids())
+			.collect::<HashSet<(Txid, Option<BlockHash>)>>();
+		sync_state.prune_tx_scripts(&relevant_txids.iter().map(|(txid, _)| *txid).collect());
+
+		let mut unconfirmed_txs = Vec::new();
+
+		for (txid, block_hash_opt) in relevant_txids {
+			if let Some(block_hash) = block_hash_opt {
+				if self.is_confirmed_in(sync_state, &txid, &block_hash)? {
+					// Skip if the transaction is still confirmed in the block in question.
+					continue;
+				}
+
+				unconfirmed_txs.push(txid);
+			} else {
+				log_error!(self.logger, "Untracked confirmation of funding transaction. Please ensure none of your channels had been created with LDK prior to version 0.0.113!");
+				panic!("Untracked confirmation of funding transaction. Please ensure none of your channels had been created with LDK prior to version 0.0.113!");
+			}
+		}
+		Ok(unconfirmed_txs)
+	}
+
+	// Returns whether the transaction with the given txid is still confirmed in the block with the
+	// given hash.
+	fn is_confirmed_in(
+		&self, sync_state: &SyncState, txid: &Txid, block_hash: &BlockHash,
+	) -> Result<bool, InternalError> {
+		let script_pubkey = match self.get_tx_script(sync_state, txid)? {
+			Some(script_pubkey) => script_pubkey,
+			// The server doesn't know about the transaction anymore, so it can't be confirmed.
+			None => return Ok(false),
+		};
+
+		let history = self.client.script_get_history(&script_pubkey)?;
+		if let Some(entry) = history.iter().find(|h| h.tx_hash == *txid) {
+			if entry.height > 0 {
+				let block_header = self.client.block_header(entry.height as usize)?;
+				return Ok(block_header.block_hash() == *block_hash);
+			}
+		}
+		Ok(false)
+	}
+
+	// Returns the script to look up the transaction with the given txid by. Prefers the script the
+	// transaction was registered with, falling back to the script of its first output if the
+	// transaction is known to the server. Returns `None` if neither is available.
+	fn get_tx_script(&self, sync_state: &SyncState, txid: &Txid) -> Result<Option<Script>, InternalError> {
+		if let Some(script_pubkey) = sync_state.watched_tx_scripts.get(txid) {
+			return Ok(Some(script_pubkey.clone()));
+		}
+
+		let tx = match self.client.transaction_get(txid) {
+			Ok(tx) => tx,
+			Err(e) if is_tx_not_found_error(&e) => return Ok(None),
+			Err(e) => {
+				log_error!(self.logger, "Failed to retrieve transaction {}: {:?}", txid, e);
+				return Err(InternalError::Failed);
+			}
+		};
+
+		match tx.output.first() {
+			Some(txout) => Ok(Some(txout.script_pubkey.clone())),
+			None => {
+				log_error!(self.logger, "Failed to retrieve output script for txid {}", txid);
+				Err(InternalError::Failed)
+			}
+		}
+	}
+
+	fn sync_unconfirmed_transactions(
+		&self, sync_state: &mut SyncState, confirmables: &Vec<&(dyn Confirm + Sync + Send)>, unconfirmed_txs: Vec<Txid>,
+	) {
+		for txid in unconfirmed_txs {
+			for c in confirmables {
+				c.transaction_unconfirmed(&txid);
+			}
+
+			sync_state.watched_transactions.insert(txid);
+		}
+	}
+
+	/// Returns a reference to the underlying Electrum client.
+	pub fn client(&self) -> &ElectrumClient {
+		&self.client
+	}
+}
+
+// Returns whether the given transaction's non-witness serialization is 64 bytes long. As the Merkle
+// tree doesn't distinguish leaves from inner nodes, such a transaction may be crafted to match the
+// concatenation of two inner node hashes, letting a server "prove" the inclusion of a transaction
+// that was never mined (see CVE-2017-12842).
+fn is_merkle_ambiguous(tx: &Transaction) -> bool {
+	tx.strippedsize() == 64
+}
+
+// Returns whether the given error is the server telling us it doesn't know the requested
+// transaction. There's no dedicated error code for this, so we match on the messages returned by
+// common server implementations, which are either forwarded from bitcoind (ElectrumX, Fulcrum) or
+// generated by the server itself (electrs).
+fn is_tx_not_found_error(e: &electrum_client::Error) -> bool {
+	const NOT_FOUND_MESSAGES: [&str; 3] =
+		["no such mempool or blockchain transaction", "missing transaction", "not found"];
+	match e {
+		electrum_client::Error::Protocol(err) => {
+			let message = err.get("message").and_then(|m| m.as_str()).or_else(|| err.as_str());
+			message.map_or(false, |m| {
+				let m = m.to_lowercase();
+				NOT_FOUND_MESSAGES.iter().any(|s| m.contains(s))
+			})
+		},
+		_ => false,
+	}
+}
+
+// Checks that the Merkle branch returned by `blockchain.transaction.get_merkle` connects the given
+// transaction to the given Merkle root.
+fn validate_merkle_proof(tx: &Transaction, merkle_root: &TxMerkleNode, merkle_res: &GetMerkleRes) -> bool {
+	if is_merkle_ambiguous(tx) {
+		return false;
+	}
+
+	let mut index = merkle_res.pos;
+	let mut cur = tx.txid().as_hash();
+	for mut bytes in merkle_res.merkle.iter().cloned() {
+		// The branch hashes are given in RPC byte order.
+		bytes.reverse();
+		let next_hash = Sha256d::from_inner(bytes);
+		let mut engine = Sha256d::engine();
+		if index % 2 == 0 {
+			engine.input(&cur[..]);
+			engine.input(&next_hash[..]);
+		} else {
+			engine.input(&next_hash[..]);
+			engine.input(&cur[..]);
+		}
+		cur = Sha256d::from_engine(engine);
+		index /= 2;
+	}
+	cur == merkle_root.as_hash()
+}
+
+impl<L: Deref> Filter for ElectrumSyncClient<L>
+where
+	L::Target: Logger,
+{
+	fn register_tx(&self, txid: &Txid, script_pubkey: &Script) {
+		let mut locked_queue = self.queue.lock().unwrap();
+		locked_queue.transactions.insert(*txid, script_pubkey.clone());
+	}
+
+	fn register_output(&self, output: WatchedOutput) {
+		let mut locked_queue = self.queue.lock().unwrap();
+		locked_queue.outputs.insert(output.outpoint.into_bitcoin_outpoint(), output);
+	}
+}
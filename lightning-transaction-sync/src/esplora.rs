@@ -106,7 +106,7 @@ where
 				// Update the known tip to the newest one.
 				if tip_is_new {
 					// First check for any unconfirmed transactions and act on it immediately.
-					match maybe_await!(self.get_unconfirmed_transactions(&confirmables)) {
+					match maybe_await!(self.get_unconfirmed_transactions(&mut sync_state, &confirmables)) {
 						Ok(unconfirmed_txs) => {
 							// Double-check the tip hash. If it changed, a reorg happened since
 							// we started syncing and we need to restart last-minute.
@@ -309,7 +309,7 @@ where
 
 	#[maybe_async]
 	fn get_unconfirmed_transactions(
-		&self, confirmables: &Vec<&(dyn Confirm + Sync + Send)>,
+		&self, sync_state: &mut SyncState, confirmables: &Vec<&(dyn Confirm + Sync + Send)>,
 	) -> Result<Vec<Txid>, InternalError> {
 		// Query the interface for relevant txids and check whether the relevant blocks are still
 		// in the best chain, mark them unconfirmed otherwise
@@ -317,6 +317,7 @@ where
 			.iter()
 			.flat_map(|c| c.get_relevant_txids())
 			.collect::<HashSet<(Txid, Option<BlockHash>)>>();
+		sync_state.prune_tx_scripts(&relevant_txids.iter().map(|(txid, _)| *txid).collect());
 
 		let mut unconfirmed_txs = Vec::new();
 
@@ -371,9 +372,9 @@ impl<L: Deref> Filter for EsploraSyncClient<L>
 where
 	L::Target: Logger,
 {
-	fn register_tx(&self, txid: &Txid, _script_pubkey: &Script) {
+	fn register_tx(&self, txid: &Txid, script_pubkey: &Script) {
 		let mut locked_queue = self.queue.lock().unwrap();
-		locked_queue.transactions.insert(*txid);
+		locked_queue.transactions.insert(*txid, script_pubkey.clone());
 	}
 
 	fn register_output(&self, output: WatchedOutput) {
@@ -10,6 +10,7 @@
 //!- `esplora-blocking` enables syncing against an Esplora backend based on a blocking client.
 //!- `esplora-async` enables syncing against an Esplora backend based on an async client.
 //!- `esplora-async-https` enables the async Esplora client with support for HTTPS.
+//!- `electrum` enables syncing against an Electrum backend based on a blocking client.
 //!
 //! ## Version Compatibility
 //!
@@ -74,7 +75,10 @@ extern crate bdk_macros;
 #[cfg(any(feature = "esplora-blocking", feature = "esplora-async"))]
 mod esplora;
 
-#[cfg(any(feature = "esplora-blocking", feature = "esplora-async"))]
+#[cfg(feature = "electrum")]
+mod electrum;
+
+#[cfg(any(feature = "esplora-blocking", feature = "esplora-async", feature = "electrum"))]
 mod common;
 
 mod error;
@@ -82,3 +86,6 @@ pub use error::TxSyncError;
 
 #[cfg(any(feature = "esplora-blocking", feature = "esplora-async"))]
 pub use esplora::EsploraSyncClient;
+
+#[cfg(feature = "electrum")]
+pub use electrum::ElectrumSyncClient;
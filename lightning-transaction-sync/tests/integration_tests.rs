@@ -1,5 +1,8 @@
-#![cfg(any(feature = "esplora-blocking", feature = "esplora-async"))]
+#![cfg(any(feature = "esplora-blocking", feature = "esplora-async", feature = "electrum"))]
+#[cfg(any(feature = "esplora-blocking", feature = "esplora-async"))]
 use lightning_transaction_sync::EsploraSyncClient;
+#[cfg(feature = "electrum")]
+use lightning_transaction_sync::ElectrumSyncClient;
 use lightning::chain::{Confirm, Filter};
 use lightning::chain::transaction::TransactionData;
 use lightning::util::logger::{Logger, Record};
@@ -239,6 +242,131 @@ fn test_esplora_syncs() {
 	}
 }
 
+#[test]
+#[cfg(feature = "electrum")]
+fn test_electrum_syncs() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	generate_blocks_and_wait(&bitcoind, &electrsd, 101);
+	let mut logger = TestLogger {};
+	let electrum_url = format!("tcp://{}", electrsd.electrum_url);
+	let tx_sync = ElectrumSyncClient::new(electrum_url, &mut logger).unwrap();
+	let confirmable = TestConfirmable::new();
+
+	// Check we pick up on new best blocks
+	assert_eq!(confirmable.best_block.lock().unwrap().1, 0);
+
+	tx_sync.sync(vec![&confirmable]).unwrap();
+	assert_eq!(confirmable.best_block.lock().unwrap().1, 102);
+
+	let events = std::mem::take(&mut *confirmable.events.lock().unwrap());
+	assert_eq!(events.len(), 1);
+
+	// Check registered confirmed transactions are marked confirmed
+	let new_address = bitcoind.client.get_new_address(Some("test"), Some(AddressType::Legacy)).unwrap();
+	let txid = bitcoind.client.send_to_address(&new_address, Amount::from_sat(5000), None, None, None, None, None, None).unwrap();
+	tx_sync.register_tx(&txid, &new_address.script_pubkey());
+
+	tx_sync.sync(vec![&confirmable]).unwrap();
+
+	let events = std::mem::take(&mut *confirmable.events.lock().unwrap());
+	assert_eq!(events.len(), 0);
+	assert!(confirmable.confirmed_txs.lock().unwrap().is_empty());
+	assert!(confirmable.unconfirmed_txs.lock().unwrap().is_empty());
+
+	generate_blocks_and_wait(&bitcoind, &electrsd, 1);
+	tx_sync.sync(vec![&confirmable]).unwrap();
+
+	let events = std::mem::take(&mut *confirmable.events.lock().unwrap());
+	assert_eq!(events.len(), 2);
+	assert!(confirmable.confirmed_txs.lock().unwrap().contains_key(&txid));
+	assert!(confirmable.unconfirmed_txs.lock().unwrap().is_empty());
+
+	// Check previously confirmed transactions are marked unconfirmed when they are reorged.
+	let best_block_hash = bitcoind.client.get_best_block_hash().unwrap();
+	bitcoind.client.invalidate_block(&best_block_hash).unwrap();
+
+	// We're getting back to the previous height with a new tip, but best block shouldn't change.
+	generate_blocks_and_wait(&bitcoind, &electrsd, 1);
+	assert_ne!(bitcoind.client.get_best_block_hash().unwrap(), best_block_hash);
+	tx_sync.sync(vec![&confirmable]).unwrap();
+	let events = std::mem::take(&mut *confirmable.events.lock().unwrap());
+	assert_eq!(events.len(), 0);
+
+	// Now we're surpassing previous height, getting new tip.
+	generate_blocks_and_wait(&bitcoind, &electrsd, 1);
+	assert_ne!(bitcoind.client.get_best_block_hash().unwrap(), best_block_hash);
+	tx_sync.sync(vec![&confirmable]).unwrap();
+
+	// Transaction still confirmed but under new tip.
+	assert!(confirmable.confirmed_txs.lock().unwrap().contains_key(&txid));
+	assert!(confirmable.unconfirmed_txs.lock().unwrap().is_empty());
+
+	// Check we got unconfirmed, then reconfirmed in the meantime.
+	let events = std::mem::take(&mut *confirmable.events.lock().unwrap());
+	assert_eq!(events.len(), 3);
+
+	match events[0] {
+		TestConfirmableEvent::Unconfirmed(t) => {
+			assert_eq!(t, txid);
+		},
+		_ => panic!("Unexpected event"),
+	}
+
+	match events[1] {
+		TestConfirmableEvent::BestBlockUpdated(..) => {},
+		_ => panic!("Unexpected event"),
+	}
+
+	match events[2] {
+		TestConfirmableEvent::Confirmed(t, _, _) => {
+			assert_eq!(t, txid);
+		},
+		_ => panic!("Unexpected event"),
+	}
+}
+
+#[test]
+#[cfg(feature = "electrum")]
+fn test_electrum_syncs_unbroadcast_tx() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	generate_blocks_and_wait(&bitcoind, &electrsd, 101);
+	let mut logger = TestLogger {};
+	let electrum_url = format!("tcp://{}", electrsd.electrum_url);
+	let tx_sync = ElectrumSyncClient::new(electrum_url, &mut logger).unwrap();
+	let confirmable = TestConfirmable::new();
+
+	tx_sync.sync(vec![&confirmable]).unwrap();
+	std::mem::take(&mut *confirmable.events.lock().unwrap());
+
+	// Create and sign a transaction, but don't broadcast it yet.
+	let new_address = bitcoind.client.get_new_address(Some("test"), Some(AddressType::Legacy)).unwrap();
+	let mut outs = HashMap::new();
+	outs.insert(new_address.to_string(), Amount::from_sat(5000));
+	let raw_tx = bitcoind.client.create_raw_transaction_hex(&[], &outs, None, None).unwrap();
+	let funded_tx = bitcoind.client.fund_raw_transaction(raw_tx, None, None).unwrap();
+	let signed_tx = bitcoind.client.sign_raw_transaction_with_wallet(&funded_tx.hex, None, None).unwrap();
+	let tx = signed_tx.transaction().unwrap();
+	let txid = tx.txid();
+
+	// Check syncing succeeds even though the server doesn't know the registered transaction.
+	tx_sync.register_tx(&txid, &new_address.script_pubkey());
+	tx_sync.sync(vec![&confirmable]).unwrap();
+
+	let events = std::mem::take(&mut *confirmable.events.lock().unwrap());
+	assert_eq!(events.len(), 0);
+	assert!(confirmable.confirmed_txs.lock().unwrap().is_empty());
+
+	// Check the transaction is marked confirmed once it got broadcast and mined.
+	bitcoind.client.send_raw_transaction(&tx).unwrap();
+	generate_blocks_and_wait(&bitcoind, &electrsd, 1);
+	tx_sync.sync(vec![&confirmable]).unwrap();
+
+	let events = std::mem::take(&mut *confirmable.events.lock().unwrap());
+	assert_eq!(events.len(), 2);
+	assert!(confirmable.confirmed_txs.lock().unwrap().contains_key(&txid));
+	assert!(confirmable.unconfirmed_txs.lock().unwrap().is_empty());
+}
+
 #[tokio::test]
 #[cfg(feature = "esplora-async")]
 async fn test_esplora_syncs() {
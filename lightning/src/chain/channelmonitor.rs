@@ -21,7 +21,7 @@
 //! ChannelMonitors to get out of the HSM and onto monitoring devices.
 
 use bitcoin::blockdata::block::BlockHeader;
-use bitcoin::blockdata::transaction::{OutPoint as BitcoinOutPoint, TxOut, Transaction};
+use bitcoin::blockdata::transaction::{OutPoint as BitcoinOutPoint, TxOut, Transaction, EcdsaSighashType};
 use bitcoin::blockdata::script::{Script, Builder};
 use bitcoin::blockdata::opcodes;
 
@@ -36,7 +36,8 @@ use bitcoin::secp256k1;
 use crate::ln::{PaymentHash, PaymentPreimage};
 use crate::ln::msgs::DecodeError;
 use crate::ln::chan_utils;
-use crate::ln::chan_utils::{CounterpartyCommitmentSecrets, HTLCOutputInCommitment, HTLCClaim, ChannelTransactionParameters, HolderCommitmentTransaction};
+use crate::ln::chan_utils::{CounterpartyCommitmentSecrets, HTLCOutputInCommitment, HTLCClaim, ChannelTransactionParameters, HolderCommitmentTransaction, CommitmentTransaction, TxCreationKeys};
+use crate::ln::channel::INITIAL_COMMITMENT_NUMBER;
 use crate::ln::channelmanager::{HTLCSource, SentHTLCId};
 use crate::chain;
 use crate::chain::{BestBlock, WatchedOutput};
@@ -502,6 +503,9 @@ pub(crate) enum ChannelMonitorUpdateStep {
 		htlc_outputs: Vec<(HTLCOutputInCommitment, Option<Box<HTLCSource>>)>,
 		commitment_number: u64,
 		their_per_commitment_point: PublicKey,
+		feerate_per_kw: Option<u32>,
+		to_broadcaster_value_sat: Option<u64>,
+		to_countersignatory_value_sat: Option<u64>,
 	},
 	PaymentPreimage {
 		payment_preimage: PaymentPreimage,
@@ -544,8 +548,11 @@ impl_writeable_tlv_based_enum_upgradable!(ChannelMonitorUpdateStep,
 	},
 	(1, LatestCounterpartyCommitmentTXInfo) => {
 		(0, commitment_txid, required),
+		(1, feerate_per_kw, option),
 		(2, commitment_number, required),
+		(3, to_broadcaster_value_sat, option),
 		(4, their_per_commitment_point, required),
+		(5, to_countersignatory_value_sat, option),
 		(6, htlc_outputs, required_vec),
 	},
 	(2, PaymentPreimage) => {
@@ -882,6 +889,15 @@ pub(crate) struct ChannelMonitorImpl<Signer: WriteableEcdsaChannelSigner> {
 
 	/// The node_id of our counterparty
 	counterparty_node_id: Option<PublicKey>,
+
+	/// The data needed to rebuild the initial counterparty commitment transaction, which is not
+	/// provided via a [`ChannelMonitorUpdate`] and thus would otherwise be unavailable to
+	/// [`ChannelMonitor::counterparty_commitment_txs_from_update`] users such as watchtowers.
+	/// Stored as `(their_per_commitment_point, feerate_per_kw, to_broadcaster_value_sat,
+	/// to_countersignatory_value_sat)`.
+	///
+	/// Will be `None` for monitors created on LDK versions prior to 0.0.117.
+	initial_counterparty_commitment_info: Option<(PublicKey, u32, u64, u64)>,
 }
 
 /// Transaction outputs to watch for on-chain spends.
@@ -1072,6 +1088,7 @@ impl<Signer: WriteableEcdsaChannelSigner> Writeable for ChannelMonitorImpl<Signe
 			(11, self.confirmed_commitment_tx_counterparty_output, option),
 			(13, self.spendable_txids_confirmed, required_vec),
 			(15, self.counterparty_fulfilled_htlcs, required),
+			(17, self.initial_counterparty_commitment_info, option),
 		});
 
 		Ok(())
@@ -1222,6 +1239,7 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitor<Signer> {
 
 			best_block,
 			counterparty_node_id: Some(counterparty_node_id),
+			initial_counterparty_commitment_info: None,
 		})
 	}
 
@@ -1246,6 +1264,19 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitor<Signer> {
 			txid, htlc_outputs, commitment_number, their_per_commitment_point, logger)
 	}
 
+	/// Informs this monitor of the initial counterparty commitment transaction. Unlike later
+	/// counterparty commitments, it is not provided via a [`ChannelMonitorUpdate`], so we also
+	/// store what is needed to rebuild it in [`Self::initial_counterparty_commitment_tx`].
+	pub(crate) fn provide_initial_counterparty_commitment_tx<L: Deref>(
+		&self, txid: Txid, htlc_outputs: Vec<(HTLCOutputInCommitment, Option<Box<HTLCSource>>)>,
+		commitment_number: u64, their_per_commitment_point: PublicKey, feerate_per_kw: u32,
+		to_broadcaster_value_sat: u64, to_countersignatory_value_sat: u64, logger: &L,
+	) where L::Target: Logger {
+		self.inner.lock().unwrap().provide_initial_counterparty_commitment_tx(txid, htlc_outputs,
+			commitment_number, their_per_commitment_point, feerate_per_kw, to_broadcaster_value_sat,
+			to_countersignatory_value_sat, logger)
+	}
+
 	#[cfg(test)]
 	fn provide_latest_holder_commitment_tx(
 		&self, holder_commitment_tx: HolderCommitmentTransaction,
@@ -1390,6 +1421,62 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitor<Signer> {
 		self.inner.lock().unwrap().counterparty_node_id
 	}
 
+	/// Returns the initial counterparty commitment transaction. Because it is never included in a
+	/// [`ChannelMonitorUpdate`], watchtower clients should use this (e.g. in
+	/// [`Persist::persist_new_channel`]) alongside [`Self::counterparty_commitment_txs_from_update`]
+	/// to learn about every counterparty commitment transaction.
+	///
+	/// Will be `None` for monitors created on LDK versions prior to 0.0.117.
+	///
+	/// [`Persist::persist_new_channel`]: crate::chain::chainmonitor::Persist::persist_new_channel
+	pub fn initial_counterparty_commitment_tx(&self) -> Option<CommitmentTransaction> {
+		self.inner.lock().unwrap().initial_counterparty_commitment_tx()
+	}
+
+	/// Returns the counterparty commitment transactions introduced by the given
+	/// [`ChannelMonitorUpdate`], rebuilt from the data the update carries. This is intended for
+	/// feeding a watchtower from [`Persist::update_persisted_channel`] without handing it the full
+	/// [`ChannelMonitor`].
+	///
+	/// Once a returned commitment transaction has been revoked, a justice transaction spending its
+	/// outputs can be built with [`TrustedCommitmentTransaction::build_to_local_justice_tx`] and
+	/// [`TrustedCommitmentTransaction::build_htlc_justice_tx`] and then signed with
+	/// [`Self::sign_to_local_justice_tx`] and [`Self::sign_htlc_justice_tx`], respectively.
+	///
+	/// Updates generated by LDK versions prior to 0.0.117 do not carry enough data to rebuild the
+	/// commitment transaction, and are thus ignored.
+	///
+	/// [`Persist::update_persisted_channel`]: crate::chain::chainmonitor::Persist::update_persisted_channel
+	/// [`TrustedCommitmentTransaction::build_to_local_justice_tx`]: crate::ln::chan_utils::TrustedCommitmentTransaction::build_to_local_justice_tx
+	/// [`TrustedCommitmentTransaction::build_htlc_justice_tx`]: crate::ln::chan_utils::TrustedCommitmentTransaction::build_htlc_justice_tx
+	pub fn counterparty_commitment_txs_from_update(&self, update: &ChannelMonitorUpdate) -> Vec<CommitmentTransaction> {
+		self.inner.lock().unwrap().counterparty_commitment_txs_from_update(update)
+	}
+
+	/// Signs the input at `input_idx` of a justice transaction spending the revokeable `to_local`
+	/// output of the counterparty commitment transaction numbered `commitment_number`, filling in
+	/// its witness. `value` is the value of the output being spent.
+	///
+	/// Fails if the counterparty has not yet revoked the given commitment transaction or if the
+	/// signer refuses to sign.
+	pub fn sign_to_local_justice_tx(
+		&self, justice_tx: Transaction, input_idx: usize, value: u64, commitment_number: u64
+	) -> Result<Transaction, ()> {
+		self.inner.lock().unwrap().sign_to_local_justice_tx(justice_tx, input_idx, value, commitment_number)
+	}
+
+	/// Signs the input at `input_idx` of a justice transaction spending the output of `htlc` on the
+	/// counterparty commitment transaction numbered `commitment_number`, filling in its witness.
+	///
+	/// Fails if the counterparty has not yet revoked the given commitment transaction or if the
+	/// signer refuses to sign.
+	pub fn sign_htlc_justice_tx(
+		&self, justice_tx: Transaction, input_idx: usize, commitment_number: u64,
+		htlc: &HTLCOutputInCommitment
+	) -> Result<Transaction, ()> {
+		self.inner.lock().unwrap().sign_htlc_justice_tx(justice_tx, input_idx, commitment_number, htlc)
+	}
+
 	/// Used by ChannelManager deserialization to broadcast the latest holder state if its copy of
 	/// the Channel was out-of-date.
 	///
@@ -2226,6 +2313,23 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 		Ok(())
 	}
 
+	fn provide_initial_counterparty_commitment_tx<L: Deref>(
+		&mut self, txid: Txid, htlc_outputs: Vec<(HTLCOutputInCommitment, Option<Box<HTLCSource>>)>,
+		commitment_number: u64, their_per_commitment_point: PublicKey, feerate_per_kw: u32,
+		to_broadcaster_value: u64, to_countersignatory_value: u64, logger: &L
+	) where L::Target: Logger {
+		self.initial_counterparty_commitment_info = Some((their_per_commitment_point.clone(),
+			feerate_per_kw, to_broadcaster_value, to_countersignatory_value));
+
+		#[cfg(debug_assertions)] {
+			let rebuilt_commitment_tx = self.initial_counterparty_commitment_tx().unwrap();
+			debug_assert_eq!(rebuilt_commitment_tx.trust().txid(), txid);
+		}
+
+		self.provide_latest_counterparty_commitment_tx(txid, htlc_outputs, commitment_number,
+				their_per_commitment_point, logger);
+	}
+
 	pub(crate) fn provide_latest_counterparty_commitment_tx<L: Deref>(&mut self, txid: Txid, htlc_outputs: Vec<(HTLCOutputInCommitment, Option<Box<HTLCSource>>)>, commitment_number: u64, their_per_commitment_point: PublicKey, logger: &L) where L::Target: Logger {
 		// TODO: Encrypt the htlc_outputs data with the single-hash of the commitment transaction
 		// so that a remote monitor doesn't learn anything unless there is a malicious close.
@@ -2471,7 +2575,7 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 						ret = Err(());
 					}
 				}
-				ChannelMonitorUpdateStep::LatestCounterpartyCommitmentTXInfo { commitment_txid, htlc_outputs, commitment_number, their_per_commitment_point } => {
+				ChannelMonitorUpdateStep::LatestCounterpartyCommitmentTXInfo { commitment_txid, htlc_outputs, commitment_number, their_per_commitment_point, .. } => {
 					log_trace!(logger, "Updating ChannelMonitor with latest counterparty commitment transaction info");
 					self.provide_latest_counterparty_commitment_tx(*commitment_txid, htlc_outputs.clone(), *commitment_number, *their_per_commitment_point, logger)
 				},
@@ -2656,6 +2760,115 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 		self.commitment_secrets.get_secret(idx)
 	}
 
+	fn initial_counterparty_commitment_tx(&self) -> Option<CommitmentTransaction> {
+		let (their_per_commitment_point, feerate_per_kw, to_broadcaster_value,
+			to_countersignatory_value) = self.initial_counterparty_commitment_info?;
+		let htlc_outputs = vec![];
+
+		let commitment_tx = self.build_counterparty_commitment_tx(INITIAL_COMMITMENT_NUMBER,
+			&their_per_commitment_point, to_broadcaster_value, to_countersignatory_value,
+			feerate_per_kw, htlc_outputs);
+		Some(commitment_tx)
+	}
+
+	fn build_counterparty_commitment_tx(
+		&self, commitment_number: u64, their_per_commitment_point: &PublicKey,
+		to_broadcaster_value: u64, to_countersignatory_value: u64, feerate_per_kw: u32,
+		mut nondust_htlcs: Vec<(HTLCOutputInCommitment, ())>
+	) -> CommitmentTransaction {
+		let broadcaster_keys = &self.onchain_tx_handler.channel_transaction_parameters
+			.counterparty_parameters.as_ref().unwrap().pubkeys;
+		let countersignatory_keys =
+			&self.onchain_tx_handler.channel_transaction_parameters.holder_pubkeys;
+
+		let broadcaster_funding_key = broadcaster_keys.funding_pubkey;
+		let countersignatory_funding_key = countersignatory_keys.funding_pubkey;
+		let keys = TxCreationKeys::from_channel_static_keys(&their_per_commitment_point,
+			&broadcaster_keys, &countersignatory_keys, &self.onchain_tx_handler.secp_ctx);
+		let channel_parameters =
+			&self.onchain_tx_handler.channel_transaction_parameters.as_counterparty_broadcastable();
+
+		CommitmentTransaction::new_with_auxiliary_htlc_data(commitment_number,
+			to_broadcaster_value, to_countersignatory_value, broadcaster_funding_key,
+			countersignatory_funding_key, keys, feerate_per_kw, &mut nondust_htlcs,
+			channel_parameters)
+	}
+
+	fn counterparty_commitment_txs_from_update(&self, update: &ChannelMonitorUpdate) -> Vec<CommitmentTransaction> {
+		update.updates.iter().filter_map(|update| {
+			match update {
+				&ChannelMonitorUpdateStep::LatestCounterpartyCommitmentTXInfo { commitment_txid,
+					ref htlc_outputs, commitment_number, their_per_commitment_point,
+					feerate_per_kw: Some(feerate_per_kw),
+					to_broadcaster_value_sat: Some(to_broadcaster_value),
+					to_countersignatory_value_sat: Some(to_countersignatory_value) } => {
+
+					let nondust_htlcs = htlc_outputs.iter().filter_map(|(htlc, _)| {
+						htlc.transaction_output_index.map(|_| (htlc.clone(), ()))
+					}).collect::<Vec<_>>();
+
+					let commitment_tx = self.build_counterparty_commitment_tx(commitment_number,
+							&their_per_commitment_point, to_broadcaster_value,
+							to_countersignatory_value, feerate_per_kw, nondust_htlcs);
+
+					debug_assert_eq!(commitment_tx.trust().txid(), commitment_txid);
+
+					Some(commitment_tx)
+				},
+				_ => None,
+			}
+		}).collect()
+	}
+
+	/// Gets the revocation secret for the given counterparty commitment number, along with the
+	/// transaction creation keys of that commitment transaction.
+	fn revoked_counterparty_commitment_keys(&self, commitment_number: u64) -> Result<(SecretKey, TxCreationKeys), ()> {
+		let secret = self.get_secret(commitment_number).ok_or(())?;
+		let per_commitment_key = SecretKey::from_slice(&secret).map_err(|_| ())?;
+		let their_per_commitment_point = PublicKey::from_secret_key(
+			&self.onchain_tx_handler.secp_ctx, &per_commitment_key);
+		let keys = TxCreationKeys::derive_new(&self.onchain_tx_handler.secp_ctx,
+			&their_per_commitment_point,
+			&self.counterparty_commitment_params.counterparty_delayed_payment_base_key,
+			&self.counterparty_commitment_params.counterparty_htlc_base_key,
+			&self.holder_revocation_basepoint, &self.onchain_tx_handler.signer.pubkeys().htlc_basepoint);
+		Ok((per_commitment_key, keys))
+	}
+
+	fn sign_to_local_justice_tx(
+		&self, mut justice_tx: Transaction, input_idx: usize, value: u64, commitment_number: u64
+	) -> Result<Transaction, ()> {
+		if input_idx >= justice_tx.input.len() { return Err(()); }
+		let (per_commitment_key, keys) = self.revoked_counterparty_commitment_keys(commitment_number)?;
+		let revokeable_redeemscript = chan_utils::get_revokeable_redeemscript(&keys.revocation_key,
+			self.counterparty_commitment_params.on_counterparty_tx_csv, &keys.broadcaster_delayed_payment_key);
+
+		let sig = self.onchain_tx_handler.signer.sign_justice_revoked_output(
+			&justice_tx, input_idx, value, &per_commitment_key, &self.onchain_tx_handler.secp_ctx)?;
+		justice_tx.input[input_idx].witness.push_bitcoin_signature(&sig.serialize_der(), EcdsaSighashType::All);
+		justice_tx.input[input_idx].witness.push(&[1u8]);
+		justice_tx.input[input_idx].witness.push(revokeable_redeemscript.as_bytes());
+		Ok(justice_tx)
+	}
+
+	fn sign_htlc_justice_tx(
+		&self, mut justice_tx: Transaction, input_idx: usize, commitment_number: u64,
+		htlc: &HTLCOutputInCommitment
+	) -> Result<Transaction, ()> {
+		if input_idx >= justice_tx.input.len() { return Err(()); }
+		let (per_commitment_key, keys) = self.revoked_counterparty_commitment_keys(commitment_number)?;
+		let htlc_redeemscript = chan_utils::get_htlc_redeemscript_with_explicit_keys(htlc,
+			self.onchain_tx_handler.channel_type_features(), &keys.broadcaster_htlc_key,
+			&keys.countersignatory_htlc_key, &keys.revocation_key);
+
+		let sig = self.onchain_tx_handler.signer.sign_justice_revoked_htlc(&justice_tx, input_idx,
+			htlc.amount_msat / 1000, &per_commitment_key, htlc, &self.onchain_tx_handler.secp_ctx)?;
+		justice_tx.input[input_idx].witness.push_bitcoin_signature(&sig.serialize_der(), EcdsaSighashType::All);
+		justice_tx.input[input_idx].witness.push(&keys.revocation_key.serialize());
+		justice_tx.input[input_idx].witness.push(htlc_redeemscript.as_bytes());
+		Ok(justice_tx)
+	}
+
 	pub(crate) fn get_min_seen_secret(&self) -> u64 {
 		self.commitment_secrets.get_min_seen_secret()
 	}
@@ -4112,6 +4325,7 @@ impl<'a, 'b, ES: EntropySource, SP: SignerProvider> ReadableArgs<(&'a ES, &'b SP
 		let mut confirmed_commitment_tx_counterparty_output = None;
 		let mut spendable_txids_confirmed = Some(Vec::new());
 		let mut counterparty_fulfilled_htlcs = Some(HashMap::new());
+		let mut initial_counterparty_commitment_info = None;
 		read_tlv_fields!(reader, {
 			(1, funding_spend_confirmed, option),
 			(3, htlcs_resolved_on_chain, optional_vec),
@@ -4121,6 +4335,7 @@ impl<'a, 'b, ES: EntropySource, SP: SignerProvider> ReadableArgs<(&'a ES, &'b SP
 			(11, confirmed_commitment_tx_counterparty_output, option),
 			(13, spendable_txids_confirmed, optional_vec),
 			(15, counterparty_fulfilled_htlcs, option),
+			(17, initial_counterparty_commitment_info, option),
 		});
 
 		Ok((best_block.block_hash(), ChannelMonitor::from_impl(ChannelMonitorImpl {
@@ -4176,6 +4391,7 @@ impl<'a, 'b, ES: EntropySource, SP: SignerProvider> ReadableArgs<(&'a ES, &'b SP
 
 			best_block,
 			counterparty_node_id,
+			initial_counterparty_commitment_info,
 		})))
 	}
 }
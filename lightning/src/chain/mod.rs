@@ -26,6 +26,7 @@ pub mod chaininterface;
 pub mod chainmonitor;
 pub mod channelmonitor;
 pub mod transaction;
+pub mod watchtower;
 pub(crate) mod onchaintx;
 pub(crate) mod package;
 
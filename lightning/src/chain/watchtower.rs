@@ -0,0 +1,597 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! A standalone watchtower which watches the chain for revoked commitment transactions on behalf
+//! of its clients.
+//!
+//! Clients hand the [`Watchtower`] a [`JusticeBlob`] for each revoked counterparty commitment,
+//! keyed by a [`CommitmentTxidHint`]. The blob is encrypted with a key derived from the full
+//! commitment transaction id, so the tower learns nothing about the channel until the revoked
+//! commitment actually confirms, at which point it can decrypt the justice transaction and
+//! broadcast it.
+//!
+//! Justice transactions themselves can be built and signed using
+//! [`ChannelMonitor::sign_to_local_justice_tx`] and [`ChannelMonitor::sign_htlc_justice_tx`]
+//! from within a [`Persist`] implementation.
+//!
+//! [`ChannelMonitor::sign_to_local_justice_tx`]: crate::chain::channelmonitor::ChannelMonitor::sign_to_local_justice_tx
+//! [`ChannelMonitor::sign_htlc_justice_tx`]: crate::chain::channelmonitor::ChannelMonitor::sign_htlc_justice_tx
+//! [`Persist`]: crate::chain::chainmonitor::Persist
+
+use bitcoin::blockdata::block::BlockHeader;
+use bitcoin::blockdata::transaction::Transaction;
+use bitcoin::hash_types::{BlockHash, Txid};
+use bitcoin::hashes::Hash;
+use bitcoin::hashes::sha256::Hash as Sha256;
+
+use crate::chain;
+use crate::chain::chaininterface::BroadcasterInterface;
+use crate::chain::channelmonitor::ANTI_REORG_DELAY;
+use crate::chain::transaction::TransactionData;
+use crate::io;
+use crate::ln::msgs::DecodeError;
+use crate::sign::EntropySource;
+use crate::util::chacha20poly1305rfc::ChaCha20Poly1305RFC;
+use crate::util::logger::Logger;
+use crate::util::ser::{Readable, Writeable, Writer};
+
+use crate::prelude::*;
+use crate::sync::Mutex;
+use core::cmp;
+use core::ops::Deref;
+
+/// The first 16 bytes of a commitment transaction's txid, used by a [`Watchtower`] to look up
+/// the [`JusticeBlob`]s which may be decrypted once a transaction with a matching txid confirms.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct CommitmentTxidHint(pub [u8; 16]);
+
+impl CommitmentTxidHint {
+	/// Builds the hint for the given commitment transaction id.
+	pub fn from_txid(txid: &Txid) -> Self {
+		let mut hint = [0; 16];
+		hint.copy_from_slice(&txid[..16]);
+		Self(hint)
+	}
+}
+
+impl Writeable for CommitmentTxidHint {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		self.0.write(w)
+	}
+}
+
+impl Readable for CommitmentTxidHint {
+	fn read<R: io::Read>(r: &mut R) -> Result<Self, DecodeError> {
+		Ok(Self(Readable::read(r)?))
+	}
+}
+
+/// A justice transaction encrypted under a key derived from the txid of the revoked commitment
+/// transaction it spends.
+///
+/// A [`JusticeBlob`] can only be decrypted by someone who knows the full commitment txid, i.e.
+/// once the revoked commitment transaction has been broadcast.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JusticeBlob {
+	nonce: [u8; 12],
+	ciphertext: Vec<u8>,
+	tag: [u8; 16],
+}
+
+impl JusticeBlob {
+	/// Encrypts `justice_tx` under a key derived from `commitment_txid`.
+	///
+	/// The nonce is drawn from `entropy_source` and stored alongside the ciphertext.
+	pub fn encrypt<ES: Deref>(commitment_txid: &Txid, justice_tx: &Transaction, entropy_source: &ES) -> Self
+	where ES::Target: EntropySource,
+	{
+		// ChaCha20Poly1305RFC requires the first four bytes of the nonce be zero.
+		let mut nonce = [0; 12];
+		nonce[4..].copy_from_slice(&entropy_source.get_secure_random_bytes()[..8]);
+
+		let plaintext = justice_tx.encode();
+		let mut ciphertext = vec![0; plaintext.len()];
+		let mut tag = [0; 16];
+		let key = Sha256::hash(&commitment_txid[..]);
+		let mut chacha = ChaCha20Poly1305RFC::new(&key[..], &nonce, &[]);
+		chacha.encrypt(&plaintext, &mut ciphertext, &mut tag);
+		Self { nonce, ciphertext, tag }
+	}
+
+	/// Decrypts the justice transaction using the txid of the commitment transaction it spends.
+	///
+	/// Fails if `commitment_txid` is not the txid this blob was encrypted for or the blob was
+	/// otherwise corrupted.
+	pub fn decrypt(&self, commitment_txid: &Txid) -> Result<Transaction, ()> {
+		if self.nonce[..4] != [0; 4] { return Err(()); }
+		let key = Sha256::hash(&commitment_txid[..]);
+		let mut chacha = ChaCha20Poly1305RFC::new(&key[..], &self.nonce, &[]);
+		let mut plaintext = vec![0; self.ciphertext.len()];
+		if !chacha.decrypt(&self.ciphertext, &mut plaintext, &self.tag) {
+			return Err(());
+		}
+		Readable::read(&mut &plaintext[..]).map_err(|_| ())
+	}
+}
+
+impl_writeable_tlv_based!(JusticeBlob, {
+	(0, nonce, required),
+	(2, ciphertext, required),
+	(4, tag, required),
+});
+
+/// A justice transaction which has been decrypted as its revoked commitment transaction
+/// confirmed, but which may still need to be rebroadcast.
+struct PendingJustice {
+	hint: CommitmentTxidHint,
+	blob: JusticeBlob,
+	justice_tx: Transaction,
+	commitment_txid: Txid,
+	commitment_height: u32,
+	commitment_block_hash: Option<BlockHash>,
+	/// The height and hash of the block in which the justice transaction, or a conflicting
+	/// transaction spending any of its inputs, confirmed.
+	spend_height: Option<u32>,
+	spend_block_hash: Option<BlockHash>,
+	spending_txid: Option<Txid>,
+}
+
+impl_writeable_tlv_based!(PendingJustice, {
+	(0, hint, required),
+	(2, blob, required),
+	(4, justice_tx, required),
+	(6, commitment_txid, required),
+	(8, commitment_height, required),
+	(10, commitment_block_hash, option),
+	(12, spend_height, option),
+	(14, spend_block_hash, option),
+	(16, spending_txid, option),
+});
+
+/// Watches the chain for revoked commitment transactions and broadcasts the corresponding justice
+/// transactions.
+///
+/// A [`Watchtower`] only needs to be fed blocks or confirmed transactions via [`chain::Listen`]
+/// or [`chain::Confirm`]; it does not require a [`ChannelManager`] or any [`ChannelMonitor`]s.
+/// Because it does not know which outputs it is watching until a commitment transaction
+/// confirms, it must be given full blocks rather than filtered ones.
+///
+/// Once a revoked commitment transaction confirms, its justice transaction is rebroadcast on each
+/// new block until it, or a conflicting transaction, has [`ANTI_REORG_DELAY`] confirmations. If
+/// the commitment transaction is reorged out before then, its [`JusticeBlob`] is watched again.
+///
+/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+/// [`ChannelMonitor`]: crate::chain::channelmonitor::ChannelMonitor
+pub struct Watchtower<B: Deref, L: Deref>
+where B::Target: BroadcasterInterface, L::Target: Logger,
+{
+	justice_blobs: Mutex<HashMap<CommitmentTxidHint, Vec<JusticeBlob>>>,
+	pending_justice: Mutex<Vec<PendingJustice>>,
+	max_justice_blobs: usize,
+	broadcaster: B,
+	logger: L,
+}
+
+impl<B: Deref, L: Deref> Watchtower<B, L>
+where B::Target: BroadcasterInterface, L::Target: Logger,
+{
+	/// Creates a new [`Watchtower`] with no stored [`JusticeBlob`]s.
+	///
+	/// At most `max_justice_blobs` [`JusticeBlob`]s will be watched at once, see
+	/// [`Self::add_justice_blob`].
+	pub fn new(broadcaster: B, logger: L, max_justice_blobs: usize) -> Self {
+		Self {
+			justice_blobs: Mutex::new(HashMap::new()),
+			pending_justice: Mutex::new(Vec::new()),
+			max_justice_blobs,
+			broadcaster,
+			logger,
+		}
+	}
+
+	/// Stores a [`JusticeBlob`] to be decrypted and broadcast if a commitment transaction matching
+	/// `hint` confirms.
+	///
+	/// Fails if the [`Watchtower`] is already watching as many [`JusticeBlob`]s as it was
+	/// configured to, in which case the blob is not stored.
+	pub fn add_justice_blob(&self, hint: CommitmentTxidHint, blob: JusticeBlob) -> Result<(), ()> {
+		let mut justice_blobs = self.justice_blobs.lock().unwrap();
+		let blob_count: usize = justice_blobs.values().map(|blobs| blobs.len()).sum();
+		if blob_count >= self.max_justice_blobs {
+			log_debug!(self.logger, "Rejecting justice blob as we're already watching {} blobs", blob_count);
+			return Err(());
+		}
+		justice_blobs.entry(hint).or_insert_with(Vec::new).push(blob);
+		Ok(())
+	}
+
+	/// Returns the number of [`JusticeBlob`]s currently being watched.
+	///
+	/// This does not include the blobs of justice transactions which are still being rebroadcast.
+	pub fn justice_blob_count(&self) -> usize {
+		self.justice_blobs.lock().unwrap().values().map(|blobs| blobs.len()).sum()
+	}
+
+	/// Returns the number of justice transactions which are still being rebroadcast.
+	pub fn pending_justice_tx_count(&self) -> usize {
+		self.pending_justice.lock().unwrap().len()
+	}
+
+	fn process_transactions(&self, block_hash: Option<BlockHash>, txdata: &TransactionData, height: u32) {
+		let mut justice_txs = Vec::new();
+		let mut justice_blobs = self.justice_blobs.lock().unwrap();
+		let mut pending_justice = self.pending_justice.lock().unwrap();
+		for (_, tx) in txdata.iter() {
+			let txid = tx.txid();
+			for pending in pending_justice.iter_mut() {
+				if pending.spend_height.is_some() { continue; }
+				let spends_justice_input = tx.input.iter().any(|input| {
+					pending.justice_tx.input.iter().any(|justice_input| justice_input.previous_output == input.previous_output)
+				});
+				if spends_justice_input {
+					log_info!(self.logger, "Justice transaction {} for revoked commitment transaction {} resolved by {} at height {}",
+						pending.justice_tx.txid(), pending.commitment_txid, txid, height);
+					pending.spend_height = Some(height);
+					pending.spend_block_hash = block_hash;
+					pending.spending_txid = Some(txid);
+				}
+			}
+
+			let hint = CommitmentTxidHint::from_txid(&txid);
+			let blobs = match justice_blobs.get_mut(&hint) {
+				Some(blobs) => blobs,
+				None => continue,
+			};
+			// Hints may collide, so only take the blobs which actually decrypt to a transaction
+			// spending this commitment.
+			let mut idx = 0;
+			while idx < blobs.len() {
+				match blobs[idx].decrypt(&txid) {
+					Ok(justice_tx) if justice_tx.input.iter().any(|input| input.previous_output.txid == txid) => {
+						log_info!(self.logger, "Broadcasting justice transaction {} for revoked commitment transaction {} confirmed at height {}",
+							justice_tx.txid(), txid, height);
+						justice_txs.push(justice_tx.clone());
+						pending_justice.push(PendingJustice {
+							hint, blob: blobs.remove(idx), justice_tx, commitment_txid: txid,
+							commitment_height: height, commitment_block_hash: block_hash,
+							spend_height: None, spend_block_hash: None, spending_txid: None,
+						});
+					},
+					Ok(_) => {
+						log_debug!(self.logger, "Dropping justice blob for {} which does not spend it", txid);
+						blobs.remove(idx);
+					},
+					Err(()) => idx += 1,
+				}
+			}
+			if blobs.is_empty() {
+				justice_blobs.remove(&hint);
+			}
+		}
+		core::mem::drop(justice_blobs);
+		core::mem::drop(pending_justice);
+		if !justice_txs.is_empty() {
+			self.broadcaster.broadcast_transactions(&justice_txs.iter().collect::<Vec<_>>());
+		}
+	}
+
+	/// Stops tracking justice transactions which are buried deep enough and rebroadcasts the rest.
+	fn best_block_updated(&self, height: u32) {
+		let mut justice_txs = Vec::new();
+		{
+			let mut pending_justice = self.pending_justice.lock().unwrap();
+			pending_justice.retain(|pending| match pending.spend_height {
+				Some(spend_height) => {
+					if height + 1 >= spend_height + ANTI_REORG_DELAY {
+						log_debug!(self.logger, "Justice transaction {} for revoked commitment transaction {} has been resolved",
+							pending.justice_tx.txid(), pending.commitment_txid);
+						false
+					} else { true }
+				},
+				None => {
+					// Justice transactions for commitments confirmed in this block were just broadcast.
+					if pending.commitment_height < height {
+						justice_txs.push(pending.justice_tx.clone());
+					}
+					true
+				},
+			});
+		}
+		if !justice_txs.is_empty() {
+			log_trace!(self.logger, "Rebroadcasting {} pending justice transactions", justice_txs.len());
+			self.broadcaster.broadcast_transactions(&justice_txs.iter().collect::<Vec<_>>());
+		}
+	}
+
+	/// Undoes the effects of the given transaction confirming, or of any transaction at or above
+	/// `height` if no `txid` is given.
+	fn unconfirm(&self, txid: Option<&Txid>, height: Option<u32>) {
+		let mut justice_blobs = self.justice_blobs.lock().unwrap();
+		let mut pending_justice = self.pending_justice.lock().unwrap();
+		let mut idx = 0;
+		while idx < pending_justice.len() {
+			let pending = &mut pending_justice[idx];
+			let spend_unconfirmed = match (txid, height, pending.spend_height) {
+				(Some(txid), _, _) => pending.spending_txid.as_ref() == Some(txid),
+				(None, Some(height), Some(spend_height)) => spend_height >= height,
+				_ => false,
+			};
+			if spend_unconfirmed {
+				log_info!(self.logger, "Spend of justice transaction {}'s inputs was unconfirmed, will rebroadcast it",
+					pending.justice_tx.txid());
+				pending.spend_height = None;
+				pending.spend_block_hash = None;
+				pending.spending_txid = None;
+			}
+			let commitment_unconfirmed = match (txid, height) {
+				(Some(txid), _) => pending.commitment_txid == *txid,
+				(None, Some(height)) => pending.commitment_height >= height,
+				_ => false,
+			};
+			if commitment_unconfirmed {
+				let pending = pending_justice.remove(idx);
+				log_info!(self.logger, "Revoked commitment transaction {} was unconfirmed, watching for it again",
+					pending.commitment_txid);
+				justice_blobs.entry(pending.hint).or_insert_with(Vec::new).push(pending.blob);
+			} else {
+				idx += 1;
+			}
+		}
+	}
+}
+
+impl<B: Deref, L: Deref> chain::Listen for Watchtower<B, L>
+where B::Target: BroadcasterInterface, L::Target: Logger,
+{
+	fn filtered_block_connected(&self, header: &BlockHeader, txdata: &TransactionData, height: u32) {
+		self.process_transactions(Some(header.block_hash()), txdata, height);
+		self.best_block_updated(height);
+	}
+
+	fn block_disconnected(&self, _header: &BlockHeader, height: u32) {
+		self.unconfirm(None, Some(height));
+	}
+}
+
+impl<B: Deref, L: Deref> chain::Confirm for Watchtower<B, L>
+where B::Target: BroadcasterInterface, L::Target: Logger,
+{
+	fn transactions_confirmed(&self, header: &BlockHeader, txdata: &TransactionData, height: u32) {
+		self.process_transactions(Some(header.block_hash()), txdata, height);
+	}
+
+	fn transaction_unconfirmed(&self, txid: &Txid) {
+		self.unconfirm(Some(txid), None);
+	}
+
+	fn best_block_updated(&self, _header: &BlockHeader, height: u32) {
+		Watchtower::best_block_updated(self, height);
+	}
+
+	fn get_relevant_txids(&self) -> Vec<(Txid, Option<BlockHash>)> {
+		let pending_justice = self.pending_justice.lock().unwrap();
+		let mut txids = Vec::with_capacity(pending_justice.len() * 2);
+		for pending in pending_justice.iter() {
+			txids.push((pending.commitment_txid, pending.commitment_block_hash));
+			if let Some(spending_txid) = pending.spending_txid {
+				txids.push((spending_txid, pending.spend_block_hash));
+			}
+		}
+		txids
+	}
+}
+
+impl<B: Deref, L: Deref> Writeable for Watchtower<B, L>
+where B::Target: BroadcasterInterface, L::Target: Logger,
+{
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		let justice_blobs = self.justice_blobs.lock().unwrap();
+		(justice_blobs.len() as u64).write(w)?;
+		for (hint, blobs) in justice_blobs.iter() {
+			hint.write(w)?;
+			blobs.write(w)?;
+		}
+		let pending_justice = self.pending_justice.lock().unwrap();
+		write_tlv_fields!(w, {
+			(1, *pending_justice, optional_vec),
+		});
+		Ok(())
+	}
+}
+
+impl<B: Deref, L: Deref> crate::util::ser::ReadableArgs<(B, L, usize)> for Watchtower<B, L>
+where B::Target: BroadcasterInterface, L::Target: Logger,
+{
+	fn read<R: io::Read>(r: &mut R, args: (B, L, usize)) -> Result<Self, DecodeError> {
+		let (broadcaster, logger, max_justice_blobs) = args;
+		let len: u64 = Readable::read(r)?;
+		let mut justice_blobs = HashMap::with_capacity(cmp::min(len as usize, 1024));
+		for _ in 0..len {
+			let hint: CommitmentTxidHint = Readable::read(r)?;
+			let blobs: Vec<JusticeBlob> = Readable::read(r)?;
+			if justice_blobs.insert(hint, blobs).is_some() {
+				return Err(DecodeError::InvalidValue);
+			}
+		}
+		let mut pending_justice: Option<Vec<PendingJustice>> = None;
+		read_tlv_fields!(r, {
+			(1, pending_justice, optional_vec),
+		});
+		Ok(Self {
+			justice_blobs: Mutex::new(justice_blobs),
+			pending_justice: Mutex::new(pending_justice.unwrap_or_else(Vec::new)),
+			max_justice_blobs,
+			broadcaster,
+			logger,
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{CommitmentTxidHint, JusticeBlob, Watchtower};
+
+	use bitcoin::blockdata::script::Script;
+	use bitcoin::blockdata::transaction::{OutPoint, Transaction, TxIn, TxOut};
+	use bitcoin::hash_types::Txid;
+	use bitcoin::hashes::Hash;
+	use bitcoin::network::constants::Network;
+	use bitcoin::{PackedLockTime, Sequence, Witness};
+
+	use crate::chain::{Confirm, Listen};
+	use crate::chain::channelmonitor::ANTI_REORG_DELAY;
+	use crate::ln::functional_test_utils::create_dummy_block;
+	use crate::util::ser::{ReadableArgs, Writeable};
+	use crate::util::test_utils::{TestBroadcaster, TestKeysInterface, TestLogger};
+
+	fn commitment_tx() -> Transaction {
+		Transaction {
+			version: 2, lock_time: PackedLockTime(0),
+			input: vec![TxIn {
+				previous_output: OutPoint { txid: Txid::from_inner([42; 32]), vout: 0 },
+				script_sig: Script::new(), sequence: Sequence(0), witness: Witness::new(),
+			}],
+			output: vec![TxOut { value: 100_000, script_pubkey: Script::new() }],
+		}
+	}
+
+	fn justice_tx(commitment_txid: Txid) -> Transaction {
+		Transaction {
+			version: 2, lock_time: PackedLockTime(0),
+			input: vec![TxIn {
+				previous_output: OutPoint { txid: commitment_txid, vout: 0 },
+				script_sig: Script::new(), sequence: Sequence::MAX, witness: Witness::new(),
+			}],
+			output: vec![TxOut { value: 99_000, script_pubkey: Script::new() }],
+		}
+	}
+
+	#[test]
+	fn justice_blob_roundtrip() {
+		let keys = TestKeysInterface::new(&[0; 32], Network::Testnet);
+		let commitment_txid = commitment_tx().txid();
+		let justice_tx = justice_tx(commitment_txid);
+
+		let blob = JusticeBlob::encrypt(&commitment_txid, &justice_tx, &&keys);
+		assert_eq!(blob.decrypt(&commitment_txid), Ok(justice_tx));
+		assert!(blob.decrypt(&Txid::from_inner([1; 32])).is_err());
+	}
+
+	#[test]
+	fn broadcasts_justice_tx_on_revoked_commitment() {
+		let keys = TestKeysInterface::new(&[0; 32], Network::Testnet);
+		let broadcaster = TestBroadcaster::new(Network::Testnet);
+		let logger = TestLogger::new();
+		let watchtower = Watchtower::new(&broadcaster, &logger, 10);
+
+		let commitment_tx = commitment_tx();
+		let commitment_txid = commitment_tx.txid();
+		let justice_tx = justice_tx(commitment_txid);
+		watchtower.add_justice_blob(CommitmentTxidHint::from_txid(&commitment_txid),
+			JusticeBlob::encrypt(&commitment_txid, &justice_tx, &&keys)).unwrap();
+		assert_eq!(watchtower.justice_blob_count(), 1);
+
+		// The tower should survive a serialization roundtrip with its blobs intact.
+		let watchtower = Watchtower::read(&mut &watchtower.encode()[..], (&broadcaster, &logger, 10)).unwrap();
+		assert_eq!(watchtower.justice_blob_count(), 1);
+
+		// Blocks without the revoked commitment don't trigger a broadcast.
+		let genesis_hash = bitcoin::blockdata::constants::genesis_block(Network::Testnet).block_hash();
+		let block = create_dummy_block(genesis_hash, 42, vec![justice_tx.clone()]);
+		watchtower.block_connected(&block, 1);
+		assert!(broadcaster.txn_broadcast().is_empty());
+		assert_eq!(watchtower.justice_blob_count(), 1);
+
+		let block = create_dummy_block(block.block_hash(), 42, vec![commitment_tx]);
+		watchtower.block_connected(&block, 2);
+		assert_eq!(broadcaster.txn_broadcast(), vec![justice_tx.clone()]);
+		assert_eq!(watchtower.justice_blob_count(), 0);
+		assert_eq!(watchtower.pending_justice_tx_count(), 1);
+
+		// Until the justice transaction confirms, it's rebroadcast on every block, also after a
+		// serialization roundtrip.
+		let watchtower = Watchtower::read(&mut &watchtower.encode()[..], (&broadcaster, &logger, 10)).unwrap();
+		let mut block = create_dummy_block(block.block_hash(), 42, Vec::new());
+		watchtower.block_connected(&block, 3);
+		assert_eq!(broadcaster.txn_broadcast(), vec![justice_tx.clone()]);
+
+		// Once it confirms, it's tracked until it has `ANTI_REORG_DELAY` confirmations.
+		block = create_dummy_block(block.block_hash(), 42, vec![justice_tx.clone()]);
+		watchtower.block_connected(&block, 4);
+		assert!(broadcaster.txn_broadcast().is_empty());
+		for height in 5..4 + ANTI_REORG_DELAY - 1 {
+			block = create_dummy_block(block.block_hash(), 42, Vec::new());
+			watchtower.block_connected(&block, height);
+			assert_eq!(watchtower.pending_justice_tx_count(), 1);
+		}
+		block = create_dummy_block(block.block_hash(), 42, Vec::new());
+		watchtower.block_connected(&block, 4 + ANTI_REORG_DELAY - 1);
+		assert!(broadcaster.txn_broadcast().is_empty());
+		assert_eq!(watchtower.pending_justice_tx_count(), 0);
+	}
+
+	#[test]
+	fn rewatches_reorged_out_commitment() {
+		let keys = TestKeysInterface::new(&[0; 32], Network::Testnet);
+		let broadcaster = TestBroadcaster::new(Network::Testnet);
+		let logger = TestLogger::new();
+		let watchtower = Watchtower::new(&broadcaster, &logger, 10);
+
+		let commitment_tx = commitment_tx();
+		let commitment_txid = commitment_tx.txid();
+		let justice_tx = justice_tx(commitment_txid);
+		watchtower.add_justice_blob(CommitmentTxidHint::from_txid(&commitment_txid),
+			JusticeBlob::encrypt(&commitment_txid, &justice_tx, &&keys)).unwrap();
+
+		let genesis_hash = bitcoin::blockdata::constants::genesis_block(Network::Testnet).block_hash();
+		let commitment_block = create_dummy_block(genesis_hash, 42, vec![commitment_tx.clone()]);
+		watchtower.block_connected(&commitment_block, 1);
+		let justice_block = create_dummy_block(commitment_block.block_hash(), 42, vec![justice_tx.clone()]);
+		watchtower.block_connected(&justice_block, 2);
+		assert_eq!(broadcaster.txn_broadcast(), vec![justice_tx.clone()]);
+
+		// If the justice transaction is reorged out, we resume rebroadcasting it.
+		watchtower.block_disconnected(&justice_block.header, 2);
+		let block = create_dummy_block(commitment_block.block_hash(), 43, Vec::new());
+		watchtower.block_connected(&block, 2);
+		assert_eq!(broadcaster.txn_broadcast(), vec![justice_tx.clone()]);
+
+		// If the commitment transaction is reorged out, we go back to watching for it.
+		watchtower.block_disconnected(&block.header, 2);
+		watchtower.block_disconnected(&commitment_block.header, 1);
+		assert_eq!(watchtower.justice_blob_count(), 1);
+		assert_eq!(watchtower.pending_justice_tx_count(), 0);
+
+		// The same holds when we're notified via `chain::Confirm`.
+		let block = create_dummy_block(genesis_hash, 43, vec![commitment_tx.clone()]);
+		watchtower.transactions_confirmed(&block.header, &[(0, &commitment_tx)], 1);
+		assert_eq!(broadcaster.txn_broadcast(), vec![justice_tx]);
+		assert_eq!(watchtower.get_relevant_txids(), vec![(commitment_txid, Some(block.block_hash()))]);
+		watchtower.transaction_unconfirmed(&commitment_txid);
+		assert_eq!(watchtower.justice_blob_count(), 1);
+		assert!(watchtower.get_relevant_txids().is_empty());
+	}
+
+	#[test]
+	fn limits_watched_justice_blobs() {
+		let keys = TestKeysInterface::new(&[0; 32], Network::Testnet);
+		let broadcaster = TestBroadcaster::new(Network::Testnet);
+		let logger = TestLogger::new();
+		let watchtower = Watchtower::new(&broadcaster, &logger, 2);
+
+		let commitment_txid = commitment_tx().txid();
+		let justice_tx = justice_tx(commitment_txid);
+		let hint = CommitmentTxidHint::from_txid(&commitment_txid);
+		let blob = JusticeBlob::encrypt(&commitment_txid, &justice_tx, &&keys);
+		assert!(watchtower.add_justice_blob(hint, blob.clone()).is_ok());
+		assert!(watchtower.add_justice_blob(CommitmentTxidHint([1; 16]), blob.clone()).is_ok());
+		assert!(watchtower.add_justice_blob(CommitmentTxidHint([2; 16]), blob).is_err());
+		assert_eq!(watchtower.justice_blob_count(), 2);
+	}
+}
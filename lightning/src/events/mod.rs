@@ -506,6 +506,14 @@ pub enum Event {
 		/// by versions prior to 0.0.115.
 		reason: Option<PaymentFailureReason>,
 	},
+	/// Indicates a request for an invoice failed to yield a response in a reasonable amount of time
+	/// or was explicitly abandoned by [`ChannelManager::abandon_payment`].
+	///
+	/// [`ChannelManager::abandon_payment`]: crate::ln::channelmanager::ChannelManager::abandon_payment
+	InvoiceRequestFailed {
+		/// The `payment_id` to have been associated with payment for the requested invoice.
+		payment_id: PaymentId,
+	},
 	/// Indicates that a path for an outbound payment was successful.
 	///
 	/// Always generated after [`Event::PaymentSent`] and thus useful for scoring channels. See
@@ -1063,6 +1071,12 @@ impl Writeable for Event {
 					(8, funding_txo, required),
 				});
 			},
+			&Event::InvoiceRequestFailed { ref payment_id } => {
+				33u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, payment_id, required),
+				})
+			},
 			// Note that, going forward, all new events must only write data inside of
 			// `write_tlv_fields`. Versions 0.0.101+ will ignore odd-numbered events that write
 			// data via `write_tlv_fields`.
@@ -1437,6 +1451,17 @@ impl MaybeReadable for Event {
 				};
 				f()
 			},
+			33u8 => {
+				let f = || {
+					_init_and_read_tlv_fields!(reader, {
+						(0, payment_id, required),
+					});
+					Ok(Some(Event::InvoiceRequestFailed {
+						payment_id: payment_id.0.unwrap(),
+					}))
+				};
+				f()
+			},
 			// Versions prior to 0.0.100 did not ignore odd types, instead returning InvalidValue.
 			// Version 0.0.100 failed to properly ignore odd types, possibly resulting in corrupt
 			// reads.
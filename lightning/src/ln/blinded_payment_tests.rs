@@ -0,0 +1,283 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Tests for sending, forwarding and receiving payments over blinded paths, including how
+//! failures within a blinded path are masked.
+
+use bitcoin::secp256k1::{PublicKey, Secp256k1};
+use crate::blinded_path::BlindedPath;
+use crate::blinded_path::payment::{ForwardNode, ForwardTlvs, PaymentConstraints, PaymentRelay, ReceiveTlvs};
+use crate::events::{ClosureReason, Event, HTLCDestination};
+use crate::ln::PaymentSecret;
+use crate::ln::channelmanager::{PaymentId, RecipientOnionFields};
+use crate::ln::features::BlindedHopFeatures;
+use crate::ln::functional_test_utils::*;
+use crate::ln::msgs::{self, ChannelMessageHandler};
+use crate::ln::onion_utils::INVALID_ONION_BLINDING;
+use crate::offers::invoice::BlindedPayInfo;
+use crate::routing::router::PaymentParameters;
+use crate::util::test_utils;
+use crate::prelude::*;
+
+/// Builds a blinded payment path through `node_ids`, where the last node is the recipient and
+/// each preceding node forwards over the corresponding channel in `channel_upds`.
+fn blinded_payment_path(
+	payment_secret: PaymentSecret, node_ids: Vec<PublicKey>,
+	channel_upds: &[&msgs::UnsignedChannelUpdate], keys_manager: &test_utils::TestKeysInterface
+) -> (BlindedPayInfo, BlindedPath) {
+	let mut intermediate_nodes = Vec::new();
+	for (node_id, chan_upd) in node_ids.iter().zip(channel_upds) {
+		intermediate_nodes.push(ForwardNode {
+			node_id: *node_id,
+			tlvs: ForwardTlvs {
+				short_channel_id: chan_upd.short_channel_id,
+				payment_relay: PaymentRelay {
+					cltv_expiry_delta: chan_upd.cltv_expiry_delta,
+					fee_proportional_millionths: chan_upd.fee_proportional_millionths,
+					fee_base_msat: chan_upd.fee_base_msat,
+				},
+				payment_constraints: PaymentConstraints {
+					max_cltv_expiry: u32::max_value(),
+					htlc_minimum_msat: chan_upd.htlc_minimum_msat,
+				},
+				features: BlindedHopFeatures::empty(),
+			},
+			htlc_maximum_msat: chan_upd.htlc_maximum_msat,
+		});
+	}
+	let payee_tlvs = ReceiveTlvs {
+		payment_secret,
+		payment_constraints: PaymentConstraints {
+			max_cltv_expiry: u32::max_value(),
+			htlc_minimum_msat: channel_upds.last().map(|upd| upd.htlc_minimum_msat).unwrap_or(1),
+		},
+	};
+	let secp_ctx = Secp256k1::new();
+	BlindedPath::new_for_payment(
+		&intermediate_nodes[..], *node_ids.last().unwrap(), payee_tlvs,
+		channel_upds.last().map(|upd| upd.htlc_maximum_msat).unwrap_or(u64::max_value()),
+		TEST_FINAL_CLTV as u16, keys_manager, &secp_ctx
+	).unwrap()
+}
+
+#[test]
+fn one_hop_blinded_path() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 1_000_000, 0);
+	let amt_msat = 5000;
+	let (payment_preimage, payment_hash, payment_secret) = get_payment_preimage_hash(&nodes[1], Some(amt_msat), None);
+
+	let blinded_path = blinded_payment_path(payment_secret, vec![nodes[1].node.get_our_node_id()],
+		&[], &chanmon_cfgs[1].keys_manager);
+	let route_params = PaymentParameters::blinded(vec![blinded_path]);
+	let route = get_route(&nodes[0], &route_params, amt_msat).unwrap();
+	nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::spontaneous_empty(), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors(&nodes[0], 1);
+	pass_along_route(&nodes[0], &[&[&nodes[1]]], amt_msat, payment_hash, payment_secret);
+	claim_payment(&nodes[0], &[&nodes[1]], payment_preimage);
+}
+
+#[test]
+fn forward_blinded_payment() {
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 1_000_000, 0);
+	let chan_upd_1_2 = create_announced_chan_between_nodes_with_value(&nodes, 1, 2, 1_000_000, 0).0.contents;
+
+	let amt_msat = 5000;
+	let (payment_preimage, payment_hash, payment_secret) = get_payment_preimage_hash(&nodes[2], Some(amt_msat), None);
+	let blinded_path = blinded_payment_path(payment_secret,
+		vec![nodes[1].node.get_our_node_id(), nodes[2].node.get_our_node_id()], &[&chan_upd_1_2],
+		&chanmon_cfgs[2].keys_manager);
+	let route_params = PaymentParameters::blinded(vec![blinded_path]);
+	let route = get_route(&nodes[0], &route_params, amt_msat).unwrap();
+	// The introduction node is the last unblinded hop, after which the blinded tail follows.
+	assert_eq!(route.paths[0].hops.last().unwrap().pubkey, nodes[1].node.get_our_node_id());
+	assert_eq!(route.paths[0].blinded_tail.as_ref().unwrap().hops.len(), 2);
+
+	nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::spontaneous_empty(), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors(&nodes[0], 1);
+	pass_along_route(&nodes[0], &[&[&nodes[1], &nodes[2]]], amt_msat, payment_hash, payment_secret);
+	claim_payment(&nodes[0], &[&nodes[1], &nodes[2]], payment_preimage);
+}
+
+#[test]
+fn blinded_intro_node_failure() {
+	// If the introduction node fails to forward an HTLC, it fails back with an encrypted
+	// `invalid_onion_blinding` error in `update_fail_htlc`.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 1_000_000, 0);
+	let (chan_upd_1_2, _, chan_id_1_2, _) = create_announced_chan_between_nodes_with_value(&nodes, 1, 2, 1_000_000, 0);
+
+	let amt_msat = 5000;
+	let (_, payment_hash, payment_secret) = get_payment_preimage_hash(&nodes[2], Some(amt_msat), None);
+	let blinded_path = blinded_payment_path(payment_secret,
+		vec![nodes[1].node.get_our_node_id(), nodes[2].node.get_our_node_id()], &[&chan_upd_1_2.contents],
+		&chanmon_cfgs[2].keys_manager);
+	let route_params = PaymentParameters::blinded(vec![blinded_path]);
+	let route = get_route(&nodes[0], &route_params, amt_msat).unwrap();
+	nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::spontaneous_empty(), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors(&nodes[0], 1);
+
+	let payment_event = SendEvent::from_node(&nodes[0]);
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &payment_event.msgs[0]);
+	check_added_monitors!(nodes[1], 0);
+	commitment_signed_dance!(nodes[1], nodes[0], payment_event.commitment_msg, false);
+
+	// Close the outbound channel before the introduction node gets around to forwarding over it.
+	nodes[1].node.force_close_broadcasting_latest_txn(&chan_id_1_2, &nodes[2].node.get_our_node_id()).unwrap();
+	check_closed_broadcast(&nodes[1], 1, true);
+	check_added_monitors(&nodes[1], 1);
+	let events = nodes[1].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 2);
+	assert!(events.iter().any(|ev| matches!(ev, Event::PendingHTLCsForwardable { .. })));
+	assert!(events.iter().any(|ev| matches!(ev,
+		Event::ChannelClosed { reason: ClosureReason::HolderForceClosed, .. })));
+
+	nodes[1].node.process_pending_htlc_forwards();
+	expect_htlc_handling_failed_destinations!(nodes[1].node.get_and_clear_pending_events(),
+		&[HTLCDestination::UnknownNextHop { requested_forward_scid: chan_upd_1_2.contents.short_channel_id }]);
+	// Process the failure queued by the failed forward.
+	nodes[1].node.process_pending_htlc_forwards();
+	check_added_monitors(&nodes[1], 1);
+
+	let updates = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	assert_eq!(updates.update_fail_htlcs.len(), 1);
+	assert!(updates.update_fail_malformed_htlcs.is_empty());
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &updates.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], updates.commitment_signed, false);
+	expect_payment_failed_conditions(&nodes[0], payment_hash, false,
+		PaymentFailedConditions::new().expected_htlc_error_data(INVALID_ONION_BLINDING, &[0; 32]));
+}
+
+#[test]
+fn blinded_recipient_failure() {
+	// A recipient within a blinded path fails back with `update_fail_malformed_htlc`, which the
+	// introduction node turns into an encrypted `invalid_onion_blinding` error for the sender.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 1_000_000, 0);
+	let chan_upd_1_2 = create_announced_chan_between_nodes_with_value(&nodes, 1, 2, 1_000_000, 0).0.contents;
+
+	let amt_msat = 5000;
+	let (_, payment_hash, payment_secret) = get_payment_preimage_hash(&nodes[2], Some(amt_msat), None);
+	let blinded_path = blinded_payment_path(payment_secret,
+		vec![nodes[1].node.get_our_node_id(), nodes[2].node.get_our_node_id()], &[&chan_upd_1_2],
+		&chanmon_cfgs[2].keys_manager);
+	let route_params = PaymentParameters::blinded(vec![blinded_path]);
+	let route = get_route(&nodes[0], &route_params, amt_msat).unwrap();
+	nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::spontaneous_empty(), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors(&nodes[0], 1);
+	pass_along_route(&nodes[0], &[&[&nodes[1], &nodes[2]]], amt_msat, payment_hash, payment_secret);
+
+	nodes[2].node.fail_htlc_backwards(&payment_hash);
+	expect_pending_htlcs_forwardable_and_htlc_handling_failed!(nodes[2],
+		vec![HTLCDestination::FailedPayment { payment_hash }]);
+	check_added_monitors(&nodes[2], 1);
+
+	let updates_2_1 = get_htlc_update_msgs!(nodes[2], nodes[1].node.get_our_node_id());
+	assert!(updates_2_1.update_fail_htlcs.is_empty());
+	assert_eq!(updates_2_1.update_fail_malformed_htlcs.len(), 1);
+	let update_malformed = &updates_2_1.update_fail_malformed_htlcs[0];
+	assert_eq!(update_malformed.failure_code, INVALID_ONION_BLINDING);
+	assert_eq!(update_malformed.sha256_of_onion, [0; 32]);
+	nodes[1].node.handle_update_fail_malformed_htlc(&nodes[2].node.get_our_node_id(), update_malformed);
+	commitment_signed_dance!(nodes[1], nodes[2], updates_2_1.commitment_signed, true);
+
+	let updates_1_0 = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	assert_eq!(updates_1_0.update_fail_htlcs.len(), 1);
+	assert!(updates_1_0.update_fail_malformed_htlcs.is_empty());
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &updates_1_0.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], updates_1_0.commitment_signed, false);
+	expect_payment_failed_conditions(&nodes[0], payment_hash, false,
+		PaymentFailedConditions::new().expected_htlc_error_data(INVALID_ONION_BLINDING, &[0; 32]));
+}
+
+#[test]
+fn blinded_forwarding_node_failure() {
+	// A forwarding node within a blinded path which isn't the introduction node fails back with
+	// `update_fail_malformed_htlc`.
+	let chanmon_cfgs = create_chanmon_cfgs(4);
+	let node_cfgs = create_node_cfgs(4, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(4, &node_cfgs, &[None, None, None, None]);
+	let nodes = create_network(4, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 1_000_000, 0);
+	let chan_upd_1_2 = create_announced_chan_between_nodes_with_value(&nodes, 1, 2, 1_000_000, 0).0.contents;
+	let (chan_upd_2_3, _, chan_id_2_3, _) = create_announced_chan_between_nodes_with_value(&nodes, 2, 3, 1_000_000, 0);
+
+	let amt_msat = 5000;
+	let (_, payment_hash, payment_secret) = get_payment_preimage_hash(&nodes[3], Some(amt_msat), None);
+	let blinded_path = blinded_payment_path(payment_secret,
+		vec![nodes[1].node.get_our_node_id(), nodes[2].node.get_our_node_id(), nodes[3].node.get_our_node_id()],
+		&[&chan_upd_1_2, &chan_upd_2_3.contents], &chanmon_cfgs[3].keys_manager);
+	let route_params = PaymentParameters::blinded(vec![blinded_path]);
+	let route = get_route(&nodes[0], &route_params, amt_msat).unwrap();
+	nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::spontaneous_empty(), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors(&nodes[0], 1);
+
+	let mut payment_event = SendEvent::from_node(&nodes[0]);
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &payment_event.msgs[0]);
+	check_added_monitors!(nodes[1], 0);
+	commitment_signed_dance!(nodes[1], nodes[0], payment_event.commitment_msg, false);
+	expect_pending_htlcs_forwardable!(nodes[1]);
+	check_added_monitors!(nodes[1], 1);
+
+	payment_event = SendEvent::from_node(&nodes[1]);
+	nodes[2].node.handle_update_add_htlc(&nodes[1].node.get_our_node_id(), &payment_event.msgs[0]);
+	check_added_monitors!(nodes[2], 0);
+	commitment_signed_dance!(nodes[2], nodes[1], payment_event.commitment_msg, false);
+
+	// Close the outbound channel before the blinded forwarding node gets around to forwarding over it.
+	nodes[2].node.force_close_broadcasting_latest_txn(&chan_id_2_3, &nodes[3].node.get_our_node_id()).unwrap();
+	check_closed_broadcast(&nodes[2], 1, true);
+	check_added_monitors(&nodes[2], 1);
+	let events = nodes[2].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 2);
+	assert!(events.iter().any(|ev| matches!(ev, Event::PendingHTLCsForwardable { .. })));
+	assert!(events.iter().any(|ev| matches!(ev,
+		Event::ChannelClosed { reason: ClosureReason::HolderForceClosed, .. })));
+
+	nodes[2].node.process_pending_htlc_forwards();
+	expect_htlc_handling_failed_destinations!(nodes[2].node.get_and_clear_pending_events(),
+		&[HTLCDestination::UnknownNextHop { requested_forward_scid: chan_upd_2_3.contents.short_channel_id }]);
+	// Process the failure queued by the failed forward.
+	nodes[2].node.process_pending_htlc_forwards();
+	check_added_monitors(&nodes[2], 1);
+
+	let updates_2_1 = get_htlc_update_msgs!(nodes[2], nodes[1].node.get_our_node_id());
+	assert!(updates_2_1.update_fail_htlcs.is_empty());
+	assert_eq!(updates_2_1.update_fail_malformed_htlcs.len(), 1);
+	let update_malformed = &updates_2_1.update_fail_malformed_htlcs[0];
+	assert_eq!(update_malformed.failure_code, INVALID_ONION_BLINDING);
+	assert_eq!(update_malformed.sha256_of_onion, [0; 32]);
+	nodes[1].node.handle_update_fail_malformed_htlc(&nodes[2].node.get_our_node_id(), update_malformed);
+	commitment_signed_dance!(nodes[1], nodes[2], updates_2_1.commitment_signed, true);
+
+	let updates_1_0 = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	assert_eq!(updates_1_0.update_fail_htlcs.len(), 1);
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &updates_1_0.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], updates_1_0.commitment_signed, false);
+	expect_payment_failed_conditions(&nodes[0], payment_hash, false,
+		PaymentFailedConditions::new().expected_htlc_error_data(INVALID_ONION_BLINDING, &[0; 32]));
+}
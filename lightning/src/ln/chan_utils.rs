@@ -42,6 +42,7 @@ use core::ops::Deref;
 use crate::chain;
 use crate::ln::features::ChannelTypeFeatures;
 use crate::util::crypto::{sign, sign_with_aux_rand};
+use crate::chain::package::{weight_revoked_offered_htlc, weight_revoked_received_htlc, WEIGHT_REVOKED_OUTPUT};
 
 /// Maximum number of one-way in-flight HTLC (protocol-level value).
 pub const MAX_HTLCS: u16 = 483;
@@ -1310,6 +1311,9 @@ pub struct CommitmentTransaction {
 	to_countersignatory_value_sat: u64,
 	feerate_per_kw: u32,
 	htlcs: Vec<HTLCOutputInCommitment>,
+	// The CSV delay on the broadcaster's revokeable output. Will be `None` for transactions
+	// serialized by LDK versions prior to 0.0.117.
+	to_broadcaster_delay: Option<u16>,
 	// Note that on upgrades, some features of existing outputs may be missed.
 	channel_type_features: ChannelTypeFeatures,
 	// A cache of the parties' pubkeys required to construct the transaction, see doc for trust()
@@ -1341,6 +1345,7 @@ impl Writeable for CommitmentTransaction {
 		let legacy_deserialization_prevention_marker = legacy_deserialization_prevention_marker_for_channel_type_features(&self.channel_type_features);
 		write_tlv_fields!(writer, {
 			(0, self.commitment_number, required),
+			(1, self.to_broadcaster_delay, option),
 			(2, self.to_broadcaster_value_sat, required),
 			(4, self.to_countersignatory_value_sat, required),
 			(6, self.feerate_per_kw, required),
@@ -1358,6 +1363,7 @@ impl Readable for CommitmentTransaction {
 	fn read<R: io::Read>(reader: &mut R) -> Result<Self, DecodeError> {
 		_init_and_read_tlv_fields!(reader, {
 			(0, commitment_number, required),
+			(1, to_broadcaster_delay, option),
 			(2, to_broadcaster_value_sat, required),
 			(4, to_countersignatory_value_sat, required),
 			(6, feerate_per_kw, required),
@@ -1376,6 +1382,7 @@ impl Readable for CommitmentTransaction {
 			commitment_number: commitment_number.0.unwrap(),
 			to_broadcaster_value_sat: to_broadcaster_value_sat.0.unwrap(),
 			to_countersignatory_value_sat: to_countersignatory_value_sat.0.unwrap(),
+			to_broadcaster_delay,
 			feerate_per_kw: feerate_per_kw.0.unwrap(),
 			keys: keys.0.unwrap(),
 			built: built.0.unwrap(),
@@ -1407,6 +1414,7 @@ impl CommitmentTransaction {
 			commitment_number,
 			to_broadcaster_value_sat,
 			to_countersignatory_value_sat,
+			to_broadcaster_delay: Some(channel_parameters.contest_delay()),
 			feerate_per_kw,
 			htlcs,
 			channel_type_features: channel_parameters.channel_type_features().clone(),
@@ -1703,6 +1711,93 @@ impl<'a> TrustedCommitmentTransaction<'a> {
 		Ok(ret)
 	}
 
+	/// Returns the index of the revokeable output, i.e. the `to_local` output sending funds to
+	/// the broadcaster, in the built transaction, if any exists.
+	///
+	/// There are two cases where this may return `None`:
+	/// - The balance of the revokeable output is below the dust limit (only found on commitments
+	/// early in the channel's lifetime, i.e. before the channel reserve is met).
+	/// - This commitment was created before LDK 0.0.117. In this case, the
+	/// commitment transaction previously didn't contain enough information to locate the
+	/// revokeable output.
+	pub fn revokeable_output_index(&self) -> Option<usize> {
+		let revokeable_redeemscript = get_revokeable_redeemscript(
+			&self.keys().revocation_key,
+			self.inner.to_broadcaster_delay?,
+			&self.keys().broadcaster_delayed_payment_key,
+		);
+		let revokeable_p2wsh = revokeable_redeemscript.to_v0_p2wsh();
+		let outputs = &self.inner.built.transaction.output;
+		outputs.iter().enumerate()
+			.find(|(_, out)| out.script_pubkey == revokeable_p2wsh)
+			.map(|(idx, _)| idx)
+	}
+
+	/// Helper method to build an unsigned justice transaction spending the revokeable
+	/// `to_local` output to a destination script. Fee estimation accounts for the expected
+	/// revocation witness data that will be added when signed.
+	///
+	/// This method will error if the given fee rate results in a fee greater than the value
+	/// of the output being spent, or if there exists no revokeable `to_local` output on this
+	/// commitment transaction. See [`Self::revokeable_output_index`] for more details.
+	///
+	/// The built transaction will allow fee bumping with RBF, and this method takes
+	/// `feerate_per_kw` as an input such that multiple copies of a justice transaction at different
+	/// fee rates may be built.
+	pub fn build_to_local_justice_tx(&self, feerate_per_kw: u64, destination_script: Script)
+	-> Result<Transaction, ()> {
+		let output_idx = self.revokeable_output_index().ok_or(())?;
+		self.build_justice_tx(output_idx, WEIGHT_REVOKED_OUTPUT, feerate_per_kw, destination_script)
+	}
+
+	/// Helper method to build an unsigned justice transaction spending the output of the given
+	/// non-dust `htlc` to a destination script. As with [`Self::build_to_local_justice_tx`], fee
+	/// estimation accounts for the revocation witness data that will be added when signed.
+	///
+	/// This method will error if the given fee rate results in a fee greater than the value of
+	/// the HTLC output, or if `htlc` has no output on this commitment transaction.
+	pub fn build_htlc_justice_tx(&self, htlc: &HTLCOutputInCommitment, feerate_per_kw: u64,
+		destination_script: Script
+	) -> Result<Transaction, ()> {
+		let output_idx = htlc.transaction_output_index.ok_or(())? as usize;
+		if !self.inner.htlcs.contains(htlc) { return Err(()); }
+		let witness_weight = if htlc.offered {
+			weight_revoked_offered_htlc(&self.inner.channel_type_features)
+		} else {
+			weight_revoked_received_htlc(&self.inner.channel_type_features)
+		};
+		self.build_justice_tx(output_idx, witness_weight, feerate_per_kw, destination_script)
+	}
+
+	fn build_justice_tx(&self, output_idx: usize, witness_weight: u64, feerate_per_kw: u64,
+		destination_script: Script
+	) -> Result<Transaction, ()> {
+		let input = vec![TxIn {
+			previous_output: OutPoint {
+				txid: self.txid(),
+				vout: output_idx as u32,
+			},
+			script_sig: Script::new(),
+			sequence: Sequence(0xfffffffd),
+			witness: Witness::new(),
+		}];
+		let value = self.inner.built.transaction.output.get(output_idx).ok_or(())?.value;
+		let output = vec![TxOut {
+			script_pubkey: destination_script,
+			value,
+		}];
+		let mut justice_tx = Transaction {
+			version: 2,
+			lock_time: PackedLockTime::ZERO,
+			input,
+			output,
+		};
+		let weight = justice_tx.weight() as u64 + witness_weight;
+		let fee = weight * feerate_per_kw / 1000;
+		justice_tx.output[0].value = value.checked_sub(fee).ok_or(())?;
+		Ok(justice_tx)
+	}
+
 	/// Gets a signed HTLC transaction given a preimage (for !htlc.offered) and the holder HTLC transaction signature.
 	pub(crate) fn get_signed_htlc_tx(&self, channel_parameters: &DirectedChannelTransactionParameters, htlc_index: usize, counterparty_signature: &Signature, signature: &Signature, preimage: &Option<PaymentPreimage>) -> Transaction {
 		let inner = self.inner;
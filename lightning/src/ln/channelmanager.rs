@@ -22,7 +22,7 @@ use bitcoin::blockdata::transaction::Transaction;
 use bitcoin::blockdata::constants::{genesis_block, ChainHash};
 use bitcoin::network::constants::Network;
 
-use bitcoin::hashes::Hash;
+use bitcoin::hashes::{Hash, HashEngine};
 use bitcoin::hashes::sha256::Hash as Sha256;
 use bitcoin::hash_types::{BlockHash, Txid};
 
@@ -30,6 +30,8 @@ use bitcoin::secp256k1::{SecretKey,PublicKey};
 use bitcoin::secp256k1::Secp256k1;
 use bitcoin::{LockTime, secp256k1, Sequence};
 
+use crate::blinded_path::BlindedPath;
+use crate::blinded_path::payment::{ForwardNode, ForwardTlvs, PaymentConstraints, PaymentRelay, ReceiveTlvs, amt_to_forward_msat};
 use crate::chain;
 use crate::chain::{Confirm, ChannelMonitorUpdateStatus, Watch, BestBlock};
 use crate::chain::chaininterface::{BroadcasterInterface, ConfirmationTarget, FeeEstimator, LowerBoundedFeeEstimator};
@@ -41,20 +43,25 @@ use crate::events::{Event, EventHandler, EventsProvider, MessageSendEvent, Messa
 // construct one themselves.
 use crate::ln::{inbound_payment, PaymentHash, PaymentPreimage, PaymentSecret};
 use crate::ln::channel::{Channel, ChannelContext, ChannelError, ChannelUpdateStatus, ShutdownResult, UnfundedChannelContext, UpdateFulfillCommitFetch, OutboundV1Channel, InboundV1Channel};
-use crate::ln::features::{ChannelFeatures, ChannelTypeFeatures, InitFeatures, NodeFeatures};
+use crate::ln::features::{BlindedHopFeatures, ChannelFeatures, ChannelTypeFeatures, InitFeatures, NodeFeatures};
 #[cfg(any(feature = "_test_utils", test))]
 use crate::ln::features::Bolt11InvoiceFeatures;
 use crate::routing::gossip::NetworkGraph;
-use crate::routing::router::{BlindedTail, DefaultRouter, InFlightHtlcs, Path, Payee, PaymentParameters, Route, RouteParameters, Router};
+use crate::routing::router::{BlindedTail, DefaultRouter, InFlightHtlcs, Path, Payee, PaymentFeeEstimate, PaymentParameters, Route, RouteParameters, Router};
 use crate::routing::scoring::{ProbabilisticScorer, ProbabilisticScoringFeeParameters};
 use crate::ln::msgs;
 use crate::ln::onion_utils;
 use crate::ln::onion_utils::HTLCFailReason;
 use crate::ln::msgs::{ChannelMessageHandler, DecodeError, LightningError};
-#[cfg(test)]
 use crate::ln::outbound_payment;
-use crate::ln::outbound_payment::{OutboundPayments, PaymentAttempts, PendingOutboundPayment, SendAlongPathArgs};
+use crate::ln::outbound_payment::{Bolt12PaymentError, OutboundPayments, PaymentAttempts, PendingOutboundPayment, SendAlongPathArgs};
 use crate::ln::wire::Encode;
+use crate::offers::invoice::{BlindedPayInfo, Bolt12Invoice, DEFAULT_RELATIVE_EXPIRY, DerivedSigningPubkey, InvoiceBuilder};
+use crate::offers::invoice_error::InvoiceError;
+use crate::offers::offer::{DerivedMetadata, Offer, OfferBuilder};
+use crate::offers::parse::Bolt12SemanticError;
+use crate::offers::refund::{Refund, RefundBuilder};
+use crate::onion_message::{Destination, OffersMessage, OffersMessageHandler, PendingOnionMessage};
 use crate::sign::{EntropySource, KeysManager, NodeSigner, Recipient, SignerProvider, ChannelSigner, WriteableEcdsaChannelSigner};
 use crate::util::config::{UserConfig, ChannelConfig, ChannelConfigUpdate};
 use crate::util::wakers::{Future, Notifier};
@@ -77,7 +84,7 @@ use core::time::Duration;
 use core::ops::Deref;
 
 // Re-export this for use in the public API.
-pub use crate::ln::outbound_payment::{PaymentSendFailure, Retry, RetryableSendFailure, RecipientOnionFields};
+pub use crate::ln::outbound_payment::{PaymentSendFailure, ProbeSendFailure, Retry, RetryableSendFailure, RecipientOnionFields};
 use crate::ln::script::ShutdownScript;
 
 // We hold various information about HTLC relay in the HTLC objects in Channel itself:
@@ -104,12 +111,17 @@ pub(super) enum PendingHTLCRouting {
 		/// The SCID from the onion that we should forward to. This could be a real SCID or a fake one
 		/// generated using `get_fake_scid` from the scid_utils::fake_scid module.
 		short_channel_id: u64, // This should be NonZero<u64> eventually when we bump MSRV
+		/// Set if this HTLC is being forwarded within a blinded path.
+		blinded: Option<BlindedForward>,
 	},
 	Receive {
 		payment_data: msgs::FinalOnionHopData,
 		payment_metadata: Option<Vec<u8>>,
 		incoming_cltv_expiry: u32, // Used to track when we should expire pending HTLCs that go unclaimed
 		phantom_shared_secret: Option<[u8; 32]>,
+		/// Set if this HTLC was received within a blinded path, in which case any failure must be
+		/// masked as an `invalid_onion_blinding` error.
+		blinded_failure: Option<BlindedFailure>,
 	},
 	ReceiveKeysend {
 		/// This was added in 0.0.116 and will break deserialization on downgrades.
@@ -118,6 +130,53 @@ pub(super) enum PendingHTLCRouting {
 		payment_metadata: Option<Vec<u8>>,
 		incoming_cltv_expiry: u32, // Used to track when we should expire pending HTLCs that go unclaimed
 	},
+	/// An HTLC paying us to forward a payment as a trampoline node, which we'll do once all parts
+	/// of the payment have arrived.
+	TrampolineForward {
+		/// The payment data from the outer onion, used to aggregate the parts paying us.
+		payment_data: msgs::FinalOnionHopData,
+		/// The node the trampoline onion instructs us to pay.
+		outgoing_node_id: PublicKey,
+		/// The remainder of the trampoline onion, to be passed on to `outgoing_node_id`.
+		trampoline_packet: msgs::TrampolineOnionPacket,
+		incoming_cltv_expiry: u32, // Used to track when we should expire pending HTLCs that go unclaimed
+	},
+}
+
+impl PendingHTLCRouting {
+	// Used to override the onion failure code and data if the HTLC is blinded.
+	fn blinded_failure(&self) -> Option<BlindedFailure> {
+		match self {
+			Self::Forward { blinded: Some(BlindedForward { failure, .. }), .. } => Some(*failure),
+			Self::Receive { blinded_failure, .. } => *blinded_failure,
+			_ => None,
+		}
+	}
+}
+
+/// Information used to forward or fail this HTLC that is being forwarded within a blinded path.
+#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+pub(super) struct BlindedForward {
+	/// The `blinding_point` that was set in the inbound [`msgs::UpdateAddHTLC`], or in the inbound
+	/// onion payload if we're the introduction node. Used to calculate the next hop's
+	/// [`msgs::UpdateAddHTLC::blinding_point`].
+	pub(super) inbound_blinding_point: PublicKey,
+	/// Determines how this HTLC should be failed backwards, based on whether we are the
+	/// introduction node.
+	pub(super) failure: BlindedFailure,
+}
+
+/// Whether an HTLC within a blinded path is being failed backwards by the introduction node or by
+/// a blinded node, which determines how the failure is masked.
+#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+pub(super) enum BlindedFailure {
+	/// This HTLC is being failed backwards by the introduction node, and thus should be failed with
+	/// an encrypted [`msgs::UpdateFailHTLC`] carrying error code `0x8000|0x4000|24`.
+	FromIntroductionNode,
+	/// This HTLC is being failed backwards by a blinded node within the path (including the
+	/// recipient), and thus should be failed with [`msgs::UpdateFailMalformedHTLC`] with failure
+	/// code `0x8000|0x4000|24` and an all-zero `sha256_of_onion`.
+	FromBlindedNode,
 }
 
 #[derive(Clone)] // See Channel::revoke_and_ack for why, tl;dr: Rust bug
@@ -170,6 +229,11 @@ pub(super) enum HTLCForwardInfo {
 		htlc_id: u64,
 		err_packet: msgs::OnionErrorPacket,
 	},
+	FailMalformedHTLC {
+		htlc_id: u64,
+		failure_code: u16,
+		sha256_of_onion: [u8; 32],
+	},
 }
 
 /// Tracks the inbound corresponding to an outbound HTLC
@@ -180,6 +244,7 @@ pub(crate) struct HTLCPreviousHopData {
 	htlc_id: u64,
 	incoming_packet_shared_secret: [u8; 32],
 	phantom_shared_secret: Option<[u8; 32]>,
+	blinded_failure: Option<BlindedFailure>,
 
 	// This field is consumed by `claim_funds_from_hop()` when updating a force-closed backwards
 	// channel with a preimage provided by the forward channel.
@@ -221,7 +286,12 @@ struct ClaimableHTLC {
 ///
 /// This is not exported to bindings users as we just use [u8; 32] directly
 #[derive(Hash, Copy, Clone, PartialEq, Eq, Debug)]
-pub struct PaymentId(pub [u8; 32]);
+pub struct PaymentId(pub [u8; Self::LENGTH]);
+
+impl PaymentId {
+	/// Number of bytes in the id.
+	pub const LENGTH: usize = 32;
+}
 
 impl Writeable for PaymentId {
 	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
@@ -347,6 +417,28 @@ struct ReceiveError {
 	msg: &'static str,
 }
 
+/// Checks that an HTLC forwarded within a blinded path satisfies the path's constraints, returning
+/// the amount and CLTV expiry to forward to the next hop.
+fn check_blinded_forward(
+	inbound_amt_msat: u64, inbound_cltv_expiry: u32, payment_relay: &PaymentRelay,
+	payment_constraints: &PaymentConstraints
+) -> Result<(u64, u32), ()> {
+	let outgoing_amt_msat = amt_to_forward_msat(inbound_amt_msat, payment_relay).ok_or(())?;
+	let outgoing_cltv_value = inbound_cltv_expiry
+		.checked_sub(payment_relay.cltv_expiry_delta as u32).ok_or(())?;
+	check_blinded_payment_constraints(inbound_amt_msat, inbound_cltv_expiry, payment_constraints)?;
+	Ok((outgoing_amt_msat, outgoing_cltv_value))
+}
+
+fn check_blinded_payment_constraints(
+	amt_msat: u64, cltv_expiry: u32, constraints: &PaymentConstraints
+) -> Result<(), ()> {
+	if amt_msat < constraints.htlc_minimum_msat || cltv_expiry > constraints.max_cltv_expiry {
+		return Err(())
+	}
+	Ok(())
+}
+
 /// This enum is used to specify which error data to send to peers when failing back an HTLC
 /// using [`ChannelManager::fail_htlc_backwards_with_reason`].
 ///
@@ -502,6 +594,50 @@ struct ClaimablePayments {
 	pending_claiming_payments: HashMap<PaymentHash, ClaimingPayment>,
 }
 
+/// An HTLC paying us to forward a payment as a trampoline node.
+struct TrampolineHTLC {
+	prev_hop: HTLCPreviousHopData,
+	/// The amount (in msats) of this MPP part
+	value: u64,
+	cltv_expiry: u32,
+}
+
+impl_writeable_tlv_based!(TrampolineHTLC, {
+	(0, prev_hop, required),
+	(2, value, required),
+	(4, cltv_expiry, required),
+});
+
+/// A payment we're forwarding as a trampoline node, holding the inbound HTLCs paying us until
+/// we've received all of them and then until our payment to the next hop has been resolved.
+struct PendingTrampolineForward {
+	htlcs: Vec<TrampolineHTLC>,
+	/// The payment secret from the outer onion, which must match across all parts.
+	payment_secret: PaymentSecret,
+	/// The sender intended sum total of all parts paying us.
+	total_msat: u64,
+	outgoing_node_id: PublicKey,
+	outgoing_amt_msat: u64,
+	outgoing_cltv_value: u32,
+	trampoline_packet: msgs::TrampolineOnionPacket,
+	/// Set once all parts have arrived and we've started paying `outgoing_node_id`.
+	outbound_payment_id: Option<PaymentId>,
+	/// Used to time out the forward if not all parts arrive.
+	timer_ticks: u8,
+}
+
+impl_writeable_tlv_based!(PendingTrampolineForward, {
+	(0, htlcs, required_vec),
+	(2, payment_secret, required),
+	(4, total_msat, required),
+	(6, outgoing_node_id, required),
+	(8, outgoing_amt_msat, required),
+	(10, outgoing_cltv_value, required),
+	(12, trampoline_packet, required),
+	(14, outbound_payment_id, option),
+	(not_written, timer_ticks, (static_value, 0)),
+});
+
 /// Events which we process internally but cannot be processed immediately at the generation site
 /// usually because we're running pre-full-init. They are handled immediately once we detect we are
 /// running normally, and specifically must be processed before any other non-background
@@ -904,6 +1040,8 @@ where
 //  |   |
 //  |   |__`pending_intercepted_htlcs`
 //  |
+//  |__`pending_trampoline_forwards`
+//  |
 //  |__`per_peer_state`
 //  |   |
 //  |   |__`pending_inbound_payments`
@@ -1000,6 +1138,12 @@ where
 	/// See `ChannelManager` struct-level documentation for lock order requirements.
 	claimable_payments: Mutex<ClaimablePayments>,
 
+	/// Payments we're forwarding as a trampoline node, keyed by payment hash. See
+	/// [`PendingTrampolineForward`] for more info.
+	///
+	/// See `ChannelManager` struct-level documentation for lock order requirements.
+	pending_trampoline_forwards: Mutex<HashMap<PaymentHash, PendingTrampolineForward>>,
+
 	/// The set of outbound SCID aliases across all our channels, including unconfirmed channels
 	/// and some closed channels which reached a usable state prior to being closed. This is used
 	/// only to avoid duplicates, and is not persisted explicitly to disk, but rebuilt from the
@@ -1062,6 +1206,9 @@ where
 	/// When we send payment probes, we generate the [`PaymentHash`] based on this cookie secret
 	/// and a random [`PaymentId`]. This allows us to discern probes from real payments, without
 	/// keeping additional state.
+	///
+	/// Similarly, the [`PaymentId`]s of the payments we make when forwarding trampoline payments
+	/// are derived from this secret and the [`PaymentHash`].
 	probing_cookie_secret: [u8; 32],
 
 	/// The highest block timestamp we've seen, which is usually a good guess at the current time.
@@ -1113,6 +1260,13 @@ where
 	///
 	/// [`ChainMonitor`]: crate::chain::chainmonitor::ChainMonitor
 	pending_background_events: Mutex<Vec<BackgroundEvent>>,
+	/// [`OffersMessage`]s initiated by us (e.g., an [`InvoiceRequest`] sent by
+	/// [`ChannelManager::pay_for_offer`]) which are waiting to be released to the
+	/// [`OnionMessenger`] via [`OffersMessageHandler::release_pending_messages`].
+	///
+	/// [`InvoiceRequest`]: crate::offers::invoice_request::InvoiceRequest
+	/// [`OnionMessenger`]: crate::onion_message::OnionMessenger
+	pending_offers_messages: Mutex<Vec<PendingOnionMessage<OffersMessage>>>,
 	/// Used when we have to take a BIG lock to make sure everything is self-consistent.
 	/// Essentially just when we're serializing ourselves out.
 	/// Taken first everywhere where we are making changes before any other locks.
@@ -1236,6 +1390,12 @@ pub const MIN_CLTV_EXPIRY_DELTA: u16 = 6*7;
 // routing failure for any HTLC sender picking up an LDK node among the first hops.
 pub(super) const CLTV_FAR_FAR_AWAY: u32 = 14 * 24 * 6;
 
+/// The maximum number of blinded payment paths included in a [`Bolt12Invoice`] created in response
+/// to an [`InvoiceRequest`].
+///
+/// [`InvoiceRequest`]: crate::offers::invoice_request::InvoiceRequest
+const MAX_BLINDED_PAYMENT_PATHS: usize = 3;
+
 /// Minimum CLTV difference between the current block height and received inbound payments.
 /// Invoices generated for payment to us must set their `min_final_cltv_expiry_delta` field to at least
 /// this value.
@@ -1263,6 +1423,10 @@ const CHECK_CLTV_EXPIRY_SANITY_2: u32 = MIN_CLTV_EXPIRY_DELTA as u32 - LATENCY_G
 /// The number of ticks of [`ChannelManager::timer_tick_occurred`] until expiry of incomplete MPPs
 pub(crate) const MPP_TIMEOUT_TICKS: u8 = 3;
 
+/// The number of times we retry paying the next hop of a trampoline payment we're forwarding
+/// before failing back the HTLCs paying us.
+const TRAMPOLINE_FORWARD_RETRY_ATTEMPTS: usize = 3;
+
 /// The number of ticks of [`ChannelManager::timer_tick_occurred`] until we time-out the
 /// idempotency of payments by [`PaymentId`]. See
 /// [`OutboundPayments::remove_stale_resolved_payments`].
@@ -1610,6 +1774,11 @@ pub enum ChannelShutdownState {
 /// These include payments that have yet to find a successful path, or have unresolved HTLCs.
 #[derive(Debug, PartialEq)]
 pub enum RecentPaymentDetails {
+	/// When an invoice was requested and thus a payment has not yet been sent.
+	AwaitingInvoice {
+		/// Identifier for the payment to ensure idempotency.
+		payment_id: PaymentId,
+	},
 	/// When a payment is still being sent and awaiting successful delivery.
 	Pending {
 		/// Hash of the payment that is currently being sent but has yet to be fulfilled or
@@ -2016,8 +2185,10 @@ macro_rules! process_events_body {
 			let mut post_event_actions = Vec::new();
 
 			for (event, action_opt) in pending_events {
-				$event_to_handle = event;
-				$handle_event;
+				if !$self.is_trampoline_forward_event(&event) {
+					$event_to_handle = event;
+					$handle_event;
+				}
 				if let Some(action) = action_opt {
 					post_event_actions.push(action);
 				}
@@ -2097,6 +2268,7 @@ where
 			pending_outbound_payments: OutboundPayments::new(),
 			forward_htlcs: Mutex::new(HashMap::new()),
 			claimable_payments: Mutex::new(ClaimablePayments { claimable_payments: HashMap::new(), pending_claiming_payments: HashMap::new() }),
+			pending_trampoline_forwards: Mutex::new(HashMap::new()),
 			pending_intercepted_htlcs: Mutex::new(HashMap::new()),
 			id_to_peer: Mutex::new(HashMap::new()),
 			short_to_chan_info: FairRwLock::new(HashMap::new()),
@@ -2116,6 +2288,7 @@ where
 			pending_events: Mutex::new(VecDeque::new()),
 			pending_events_processor: AtomicBool::new(false),
 			pending_background_events: Mutex::new(Vec::new()),
+			pending_offers_messages: Mutex::new(Vec::new()),
 			total_consistency_lock: RwLock::new(()),
 			background_events_processed_since_startup: AtomicBool::new(false),
 			persistence_notifier: Notifier::new(),
@@ -2339,7 +2512,10 @@ where
 	/// [`Event::PaymentSent`]: events::Event::PaymentSent
 	pub fn list_recent_payments(&self) -> Vec<RecentPaymentDetails> {
 		self.pending_outbound_payments.pending_outbound_payments.lock().unwrap().iter()
-			.filter_map(|(_, pending_outbound_payment)| match pending_outbound_payment {
+			.filter_map(|(payment_id, pending_outbound_payment)| match pending_outbound_payment {
+				PendingOutboundPayment::AwaitingInvoice { .. } => {
+					Some(RecentPaymentDetails::AwaitingInvoice { payment_id: *payment_id })
+				},
 				PendingOutboundPayment::Retryable { payment_hash, total_msat, .. } => {
 					Some(RecentPaymentDetails::Pending {
 						payment_hash: *payment_hash,
@@ -2651,8 +2827,15 @@ where
 		// Also, ensure that, in the case of an unknown preimage for the received payment hash, our
 		// payment logic has enough time to fail the HTLC backward before our onchain logic triggers a
 		// channel closure (see HTLC_FAIL_BACK_BUFFER rationale).
+		//
+		// Senders of blinded payments don't know the CLTV expiry deltas of the blinded hops and
+		// thus set the CLTV in our payload to the current height, so we check the HTLC's instead.
 		let current_height: u32 = self.best_block.read().unwrap().height();
-		if (hop_data.outgoing_cltv_value as u64) <= current_height as u64 + HTLC_FAIL_BACK_BUFFER as u64 + 1 {
+		let final_cltv_expiry = match hop_data.format {
+			msgs::OnionHopDataFormat::BlindedReceive { .. } => cltv_expiry,
+			_ => hop_data.outgoing_cltv_value,
+		};
+		if (final_cltv_expiry as u64) <= current_height as u64 + HTLC_FAIL_BACK_BUFFER as u64 + 1 {
 			let mut err_data = Vec::with_capacity(12);
 			err_data.extend_from_slice(&amt_msat.to_be_bytes());
 			err_data.extend_from_slice(&current_height.to_be_bytes());
@@ -2672,6 +2855,93 @@ where
 			});
 		}
 
+		// If the payment carries a trampoline onion, peel our layer of it. If we're the final
+		// recipient we go on to handle the inner payload as a regular receive, otherwise we'll hold
+		// the HTLC until we've received the full payment and can forward it on.
+		let hop_data = if let msgs::OnionHopDataFormat::TrampolineEntry { payment_data, trampoline_packet } = hop_data.format {
+			macro_rules! return_trampoline_err {
+				($msg: expr) => {
+					return Err(ReceiveError {
+						err_code: 0x4000|22,
+						err_data: Vec::new(),
+						msg: $msg,
+					})
+				}
+			}
+			let recipient = if phantom_shared_secret.is_some() { Recipient::PhantomNode } else { Recipient::Node };
+			let trampoline_shared_secret = match self.node_signer.ecdh(recipient, &trampoline_packet.public_key, None) {
+				Ok(shared_secret) => shared_secret.secret_bytes(),
+				Err(()) => return_trampoline_err!("Failed to compute the trampoline onion's shared secret"),
+			};
+			let (next_hop_data, next_hop) = match onion_utils::decode_next_trampoline_hop(
+				trampoline_shared_secret, &trampoline_packet.hop_data, trampoline_packet.hmac, payment_hash
+			) {
+				Ok(res) => res,
+				Err(onion_utils::OnionDecodeErr::Malformed { err_msg, .. }) |
+				Err(onion_utils::OnionDecodeErr::Relay { err_msg, .. }) => return_trampoline_err!(err_msg),
+			};
+			match (next_hop_data.format, next_hop) {
+				(msgs::OnionHopDataFormat::FinalNode { payment_data: inner_payment_data, keysend_preimage, payment_metadata }, None) => {
+					if next_hop_data.amt_to_forward > payment_data.total_msat {
+						return Err(ReceiveError {
+							err_code: 19,
+							err_data: amt_msat.to_be_bytes().to_vec(),
+							msg: "Trampoline onion asked us to receive more than the outer onion paid",
+						});
+					}
+					if next_hop_data.outgoing_cltv_value > hop_data.outgoing_cltv_value {
+						return Err(ReceiveError {
+							err_code: 18,
+							err_data: cltv_expiry.to_be_bytes().to_vec(),
+							msg: "Trampoline onion set CLTV to more than the CLTV set by the outer onion",
+						});
+					}
+					msgs::OnionHopData {
+						format: msgs::OnionHopDataFormat::FinalNode {
+							payment_data: inner_payment_data, keysend_preimage, payment_metadata,
+						},
+						amt_to_forward: hop_data.amt_to_forward,
+						outgoing_cltv_value: hop_data.outgoing_cltv_value,
+					}
+				},
+				(msgs::OnionHopDataFormat::TrampolineForward { outgoing_node_id }, Some((next_hop_hmac, new_packet_bytes))) => {
+					if phantom_shared_secret.is_some() || !self.default_configuration.accept_trampoline_forwards {
+						return Err(ReceiveError {
+							err_code: 0x4000|0x2000|3,
+							err_data: Vec::new(),
+							msg: "We don't forward trampoline payments",
+						});
+					}
+					let next_packet_pubkey = match onion_utils::next_hop_packet_pubkey(
+						&self.secp_ctx, trampoline_packet.public_key, &trampoline_shared_secret
+					) {
+						Ok(pubkey) => pubkey,
+						Err(_) => return_trampoline_err!("Failed to derive the next trampoline onion's public key"),
+					};
+					return Ok(PendingHTLCInfo {
+						routing: PendingHTLCRouting::TrampolineForward {
+							payment_data,
+							outgoing_node_id,
+							trampoline_packet: msgs::TrampolineOnionPacket {
+								version: 0,
+								public_key: next_packet_pubkey,
+								hop_data: new_packet_bytes,
+								hmac: next_hop_hmac,
+							},
+							incoming_cltv_expiry: hop_data.outgoing_cltv_value,
+						},
+						payment_hash,
+						incoming_shared_secret: shared_secret,
+						incoming_amt_msat: Some(amt_msat),
+						outgoing_amt_msat: next_hop_data.amt_to_forward,
+						outgoing_cltv_value: next_hop_data.outgoing_cltv_value,
+						skimmed_fee_msat: counterparty_skimmed_fee_msat,
+					});
+				},
+				_ => return_trampoline_err!("Got an invalid trampoline onion payload"),
+			}
+		} else { hop_data };
+
 		let routing = match hop_data.format {
 			msgs::OnionHopDataFormat::NonFinalNode { .. } => {
 				return Err(ReceiveError {
@@ -2680,6 +2950,14 @@ where
 					msg: "Got non final data with an HMAC of 0",
 				});
 			},
+			msgs::OnionHopDataFormat::TrampolineEntry { .. } |
+			msgs::OnionHopDataFormat::TrampolineForward { .. } => {
+				return Err(ReceiveError {
+					err_code: 0x4000|22,
+					err_data: Vec::new(),
+					msg: "Got an invalid trampoline payload",
+				});
+			},
 			msgs::OnionHopDataFormat::FinalNode { payment_data, keysend_preimage, payment_metadata } => {
 				if let Some(payment_preimage) = keysend_preimage {
 					// We need to check that the sender knows the keysend preimage before processing this
@@ -2714,6 +2992,7 @@ where
 						payment_metadata,
 						incoming_cltv_expiry: hop_data.outgoing_cltv_value,
 						phantom_shared_secret,
+						blinded_failure: None,
 					}
 				} else {
 					return Err(ReceiveError {
@@ -2723,6 +3002,41 @@ where
 					});
 				}
 			},
+			msgs::OnionHopDataFormat::BlindedForward { .. } => {
+				return Err(ReceiveError {
+					err_code: 0x4000|22,
+					err_data: Vec::new(),
+					msg: "Got blinded non final data with an HMAC of 0",
+				});
+			},
+			msgs::OnionHopDataFormat::OutboundBlinded { .. } => {
+				debug_assert!(false, "Outbound payloads are never decoded");
+				return Err(ReceiveError {
+					err_code: 0x4000|22,
+					err_data: Vec::new(),
+					msg: "Got an invalid blinded payload",
+				});
+			},
+			msgs::OnionHopDataFormat::BlindedReceive {
+				total_msat, payment_secret, ref payment_constraints, intro_node_blinding_point
+			} => {
+				if check_blinded_payment_constraints(amt_msat, cltv_expiry, payment_constraints).is_err() {
+					return Err(ReceiveError {
+						err_code: 0x4000|22,
+						err_data: Vec::new(),
+						msg: "Blinded receive failed to satisfy the blinded path's constraints",
+					});
+				}
+				PendingHTLCRouting::Receive {
+					payment_data: msgs::FinalOnionHopData { payment_secret, total_msat },
+					payment_metadata: None,
+					incoming_cltv_expiry: cltv_expiry,
+					phantom_shared_secret,
+					blinded_failure: Some(if intro_node_blinding_point.is_some() {
+						BlindedFailure::FromIntroductionNode
+					} else { BlindedFailure::FromBlindedNode }),
+				}
+			},
 		};
 		Ok(PendingHTLCInfo {
 			routing,
@@ -2742,11 +3056,16 @@ where
 			($msg: expr, $err_code: expr) => {
 				{
 					log_info!(self.logger, "Failed to accept/forward incoming HTLC: {}", $msg);
+					// If we're a non-introduction node within a blinded path, any failure must be
+					// masked as a malformed `invalid_onion_blinding` error.
+					let failure_code = if msg.blinding_point.is_some() {
+						onion_utils::INVALID_ONION_BLINDING
+					} else { $err_code };
 					return Err(HTLCFailureMsg::Malformed(msgs::UpdateFailMalformedHTLC {
 						channel_id: msg.channel_id,
 						htlc_id: msg.htlc_id,
 						sha256_of_onion: Sha256::hash(&msg.onion_routing_packet.hop_data).into_inner(),
-						failure_code: $err_code,
+						failure_code,
 					}));
 				}
 			}
@@ -2756,8 +3075,15 @@ where
 			return_malformed_err!("invalid ephemeral pubkey", 0x8000 | 0x4000 | 6);
 		}
 
+		let blinded_node_id_tweak = match msg.blinding_point {
+			Some(blinding_point) => match onion_utils::blinded_node_id_tweak(&blinding_point, &self.node_signer) {
+				Ok(tweak) => Some(tweak),
+				Err(()) => return_malformed_err!("Failed to compute blinded node id tweak", onion_utils::INVALID_ONION_BLINDING),
+			},
+			None => None,
+		};
 		let shared_secret = self.node_signer.ecdh(
-			Recipient::Node, &msg.onion_routing_packet.public_key.unwrap(), None
+			Recipient::Node, &msg.onion_routing_packet.public_key.unwrap(), blinded_node_id_tweak.as_ref()
 		).unwrap().secret_bytes();
 
 		if msg.onion_routing_packet.version != 0 {
@@ -2769,21 +3095,33 @@ where
 			//node knows the HMAC matched, so they already know what is there...
 			return_malformed_err!("Unknown onion packet version", 0x8000 | 0x4000 | 4);
 		}
+		// Set once we know we're the introduction node of a blinded path, in which case any failure
+		// must be masked as an `invalid_onion_blinding` error.
+		let mut intro_node_blinded_forward = false;
 		macro_rules! return_err {
 			($msg: expr, $err_code: expr, $data: expr) => {
 				{
+					if msg.blinding_point.is_some() {
+						return_malformed_err!($msg, onion_utils::INVALID_ONION_BLINDING);
+					}
 					log_info!(self.logger, "Failed to accept/forward incoming HTLC: {}", $msg);
+					let (err_code, err_data) = if intro_node_blinded_forward {
+						(onion_utils::INVALID_ONION_BLINDING, vec![0; 32])
+					} else { ($err_code, $data.to_vec()) };
 					return Err(HTLCFailureMsg::Relay(msgs::UpdateFailHTLC {
 						channel_id: msg.channel_id,
 						htlc_id: msg.htlc_id,
-						reason: HTLCFailReason::reason($err_code, $data.to_vec())
+						reason: HTLCFailReason::reason(err_code, err_data)
 							.get_encrypted_failure_packet(&shared_secret, &None),
 					}));
 				}
 			}
 		}
 
-		let next_hop = match onion_utils::decode_next_payment_hop(shared_secret, &msg.onion_routing_packet.hop_data[..], msg.onion_routing_packet.hmac, msg.payment_hash) {
+		let mut next_hop = match onion_utils::decode_next_payment_hop(
+			shared_secret, &msg.onion_routing_packet.hop_data[..], msg.onion_routing_packet.hmac,
+			msg.payment_hash, msg.blinding_point, &self.node_signer
+		) {
 			Ok(res) => res,
 			Err(onion_utils::OnionDecodeErr::Malformed { err_msg, err_code }) => {
 				return_malformed_err!(err_msg, err_code);
@@ -2792,6 +3130,28 @@ where
 				return_err!(err_msg, err_code, &[0; 0]);
 			},
 		};
+		if let onion_utils::Hop::Forward {
+			next_hop_data: msgs::OnionHopData {
+				format: msgs::OnionHopDataFormat::BlindedForward {
+					ref payment_relay, ref payment_constraints, ref features, intro_node_blinding_point, ..
+				},
+				ref mut amt_to_forward, ref mut outgoing_cltv_value,
+			}, ..
+		} = next_hop {
+			intro_node_blinded_forward = intro_node_blinding_point.is_some();
+			if features.requires_unknown_bits() {
+				return_err!("Blinded forward requires unknown features", 0x4000 | 22, &[0; 0]);
+			}
+			match check_blinded_forward(msg.amount_msat, msg.cltv_expiry, payment_relay, payment_constraints) {
+				Ok((amt, cltv)) => {
+					*amt_to_forward = amt;
+					*outgoing_cltv_value = cltv;
+				},
+				Err(()) => {
+					return_err!("Blinded forward failed to satisfy the blinded path's constraints", 0x4000 | 22, &[0; 0]);
+				},
+			}
+		}
 		let (outgoing_scid, outgoing_amt_msat, outgoing_cltv_value, next_packet_pk_opt) = match next_hop {
 			onion_utils::Hop::Forward {
 				next_hop_data: msgs::OnionHopData {
@@ -2803,14 +3163,41 @@ where
 					msg.onion_routing_packet.public_key.unwrap(), &shared_secret);
 				(short_channel_id, amt_to_forward, outgoing_cltv_value, Some(next_pk))
 			},
+			onion_utils::Hop::Forward {
+				next_hop_data: msgs::OnionHopData {
+					format: msgs::OnionHopDataFormat::BlindedForward { short_channel_id, .. }, amt_to_forward,
+					outgoing_cltv_value,
+				}, ..
+			} => {
+				let next_pk = onion_utils::next_hop_packet_pubkey(&self.secp_ctx,
+					msg.onion_routing_packet.public_key.unwrap(), &shared_secret);
+				(short_channel_id, amt_to_forward, outgoing_cltv_value, Some(next_pk))
+			},
 			// We'll do receive checks in [`Self::construct_pending_htlc_info`] so we have access to the
 			// inbound channel's state.
 			onion_utils::Hop::Receive { .. } => return Ok((next_hop, shared_secret, None)),
 			onion_utils::Hop::Forward {
 				next_hop_data: msgs::OnionHopData { format: msgs::OnionHopDataFormat::FinalNode { .. }, .. }, ..
+			} |
+			onion_utils::Hop::Forward {
+				next_hop_data: msgs::OnionHopData { format: msgs::OnionHopDataFormat::BlindedReceive { .. }, .. }, ..
+			} |
+			onion_utils::Hop::Forward {
+				next_hop_data: msgs::OnionHopData { format: msgs::OnionHopDataFormat::TrampolineEntry { .. }, .. }, ..
 			} => {
 				return_err!("Final Node OnionHopData provided for us as an intermediary node", 0x4000 | 22, &[0; 0]);
-			}
+			},
+			onion_utils::Hop::Forward {
+				next_hop_data: msgs::OnionHopData { format: msgs::OnionHopDataFormat::TrampolineForward { .. }, .. }, ..
+			} => {
+				return_err!("Trampoline OnionHopData provided outside of a trampoline onion", 0x4000 | 22, &[0; 0]);
+			},
+			onion_utils::Hop::Forward {
+				next_hop_data: msgs::OnionHopData { format: msgs::OnionHopDataFormat::OutboundBlinded { .. }, .. }, ..
+			} => {
+				debug_assert!(false, "Outbound payloads are never decoded");
+				return_err!("Got an invalid blinded payload", 0x4000 | 22, &[0; 0]);
+			},
 		};
 
 		// Perform outbound checks here instead of in [`Self::construct_pending_htlc_info`] because we
@@ -2968,6 +3355,11 @@ where
 		}
 		match decoded_hop {
 			onion_utils::Hop::Receive(next_hop_data) => {
+				let intro_node_blinded_receive = match next_hop_data.format {
+					msgs::OnionHopDataFormat::BlindedReceive { intro_node_blinding_point, .. } =>
+						intro_node_blinding_point.is_some(),
+					_ => false,
+				};
 				// OUR PAYMENT!
 				match self.construct_recv_pending_htlc_info(next_hop_data, shared_secret, msg.payment_hash,
 					msg.amount_msat, msg.cltv_expiry, None, allow_underpay, msg.skimmed_fee_msat)
@@ -2979,6 +3371,18 @@ where
 						// delay) once they've send us a commitment_signed!
 						PendingHTLCStatus::Forward(info)
 					},
+					Err(ReceiveError { msg: err_msg, .. }) if msg.blinding_point.is_some() => {
+						log_info!(self.logger, "Failed to accept incoming blinded HTLC: {}", err_msg);
+						PendingHTLCStatus::Fail(HTLCFailureMsg::Malformed(msgs::UpdateFailMalformedHTLC {
+							channel_id: msg.channel_id,
+							htlc_id: msg.htlc_id,
+							sha256_of_onion: Sha256::hash(&msg.onion_routing_packet.hop_data).into_inner(),
+							failure_code: onion_utils::INVALID_ONION_BLINDING,
+						}))
+					},
+					Err(ReceiveError { msg, .. }) if intro_node_blinded_receive => {
+						return_err!(msg, onion_utils::INVALID_ONION_BLINDING, &[0; 32])
+					},
 					Err(ReceiveError { err_code, err_data, msg }) => return_err!(msg, err_code, &err_data)
 				}
 			},
@@ -2991,17 +3395,42 @@ where
 					hmac: next_hop_hmac.clone(),
 				};
 
-				let short_channel_id = match next_hop_data.format {
-					msgs::OnionHopDataFormat::NonFinalNode { short_channel_id } => short_channel_id,
-					msgs::OnionHopDataFormat::FinalNode { .. } => {
+				let (short_channel_id, blinded) = match next_hop_data.format {
+					msgs::OnionHopDataFormat::NonFinalNode { short_channel_id } => (short_channel_id, None),
+					msgs::OnionHopDataFormat::BlindedForward { short_channel_id, intro_node_blinding_point, .. } => {
+						// `decode_update_add_htlc_onion` ensures exactly one of these is set.
+						let inbound_blinding_point = match intro_node_blinding_point.or(msg.blinding_point) {
+							Some(blinding_point) => blinding_point,
+							None => {
+								debug_assert!(false);
+								return_err!("Blinded forward is missing a blinding point", 0x4000 | 22, &[0;0]);
+							},
+						};
+						(short_channel_id, Some(BlindedForward {
+							inbound_blinding_point,
+							failure: if intro_node_blinding_point.is_some() {
+								BlindedFailure::FromIntroductionNode
+							} else { BlindedFailure::FromBlindedNode },
+						}))
+					},
+					msgs::OnionHopDataFormat::FinalNode { .. } | msgs::OnionHopDataFormat::BlindedReceive { .. } |
+					msgs::OnionHopDataFormat::TrampolineEntry { .. } => {
 						return_err!("Final Node OnionHopData provided for us as an intermediary node", 0x4000 | 22, &[0;0]);
 					},
+					msgs::OnionHopDataFormat::TrampolineForward { .. } => {
+						return_err!("Trampoline OnionHopData provided outside of a trampoline onion", 0x4000 | 22, &[0;0]);
+					},
+					msgs::OnionHopDataFormat::OutboundBlinded { .. } => {
+						debug_assert!(false, "Outbound payloads are never decoded");
+						return_err!("Got an invalid blinded payload", 0x4000 | 22, &[0;0]);
+					},
 				};
 
 				PendingHTLCStatus::Forward(PendingHTLCInfo {
 					routing: PendingHTLCRouting::Forward {
 						onion_packet: outgoing_packet,
 						short_channel_id,
+						blinded,
 					},
 					payment_hash: msg.payment_hash.clone(),
 					incoming_shared_secret: shared_secret,
@@ -3099,14 +3528,14 @@ where
 		let _lck = self.total_consistency_lock.read().unwrap();
 		self.send_payment_along_path(SendAlongPathArgs {
 			path, payment_hash, recipient_onion, total_value, cur_height, payment_id, keysend_preimage,
-			session_priv_bytes
+			session_priv_bytes, payment_params: None,
 		})
 	}
 
 	fn send_payment_along_path(&self, args: SendAlongPathArgs) -> Result<(), APIError> {
 		let SendAlongPathArgs {
 			path, payment_hash, recipient_onion, total_value, cur_height, payment_id, keysend_preimage,
-			session_priv_bytes
+			session_priv_bytes, payment_params,
 		} = args;
 		// The top-level caller should hold the total_consistency_lock read lock.
 		debug_assert!(self.total_consistency_lock.try_write().is_err());
@@ -3115,9 +3544,48 @@ where
 		let prng_seed = self.entropy_source.get_secure_random_bytes();
 		let session_priv = SecretKey::from_slice(&session_priv_bytes[..]).expect("RNG is busted");
 
+		// If we're paying via a trampoline node, the path ends at the trampoline and the recipient's
+		// onion fields are moved into a trampoline onion which the trampoline node will pass on.
+		let trampoline = payment_params.and_then(|params| match (&params.payee, &params.trampoline_hop) {
+			(Payee::Clear { node_id, final_cltv_expiry_delta, .. }, Some(trampoline_hop)) =>
+				Some((*node_id, *final_cltv_expiry_delta, trampoline_hop)),
+			_ => None,
+		});
+		let (recipient_onion, trampoline_packet, keysend_preimage) = if let Some((recipient_node_id, final_cltv_expiry_delta, trampoline_hop)) = trampoline {
+			let recipient_amt_msat = total_value.checked_sub(trampoline_hop.fee_msat)
+				.ok_or_else(|| APIError::APIMisuseError { err: "Trampoline fee exceeds the payment amount".to_owned() })?;
+			// All parts (and retries) of the payment must share the secret paying the trampoline node
+			// so that it can aggregate them, so derive it rather than picking a random one.
+			let mut secret_engine = Sha256::engine();
+			secret_engine.input(b"LDK trampoline payment secret");
+			secret_engine.input(&payment_hash.0);
+			secret_engine.input(&payment_id.0);
+			if let Some(payment_secret) = recipient_onion.payment_secret {
+				secret_engine.input(&payment_secret.0);
+			}
+			let trampoline_payment_secret = PaymentSecret(Sha256::from_engine(secret_engine).into_inner());
+			let trampoline_session_priv = SecretKey::from_slice(&self.entropy_source.get_secure_random_bytes()[..])
+				.expect("RNG is busted");
+			let trampoline_packet = onion_utils::create_trampoline_onion(&self.secp_ctx,
+				trampoline_hop.node_id, recipient_node_id, recipient_amt_msat,
+				cur_height + final_cltv_expiry_delta, recipient_onion, keysend_preimage,
+				&trampoline_session_priv, self.entropy_source.get_secure_random_bytes(), payment_hash)?;
+			(RecipientOnionFields::secret_only(trampoline_payment_secret), Some(trampoline_packet), &None)
+		} else if outbound_payment::payment_is_trampoline_forward(payment_hash, &payment_id, self.probing_cookie_secret) {
+			// We're forwarding a trampoline payment, so pass on the trampoline onion we received. If
+			// the forward is no longer pending, its inbound HTLCs may have been failed back already,
+			// so we must not pay the next hop.
+			let trampoline_packet = self.pending_trampoline_forwards.lock().unwrap().get(payment_hash)
+				.filter(|forward| forward.outbound_payment_id == Some(payment_id))
+				.map(|forward| forward.trampoline_packet.clone())
+				.ok_or_else(|| APIError::APIMisuseError { err: "Trampoline forward is no longer pending".to_owned() })?;
+			(recipient_onion, Some(trampoline_packet), keysend_preimage)
+		} else { (recipient_onion, None, keysend_preimage) };
+
 		let onion_keys = onion_utils::construct_onion_keys(&self.secp_ctx, &path, &session_priv)
 			.map_err(|_| APIError::InvalidRoute{err: "Pubkey along hop was maliciously selected".to_owned()})?;
-		let (onion_payloads, htlc_msat, htlc_cltv) = onion_utils::build_onion_payloads(path, total_value, recipient_onion, cur_height, keysend_preimage)?;
+		let (onion_payloads, htlc_msat, htlc_cltv) = onion_utils::build_onion_payloads_with_trampoline(
+			path, total_value, recipient_onion, trampoline_packet, cur_height, keysend_preimage)?;
 
 		let onion_packet = onion_utils::construct_onion_packet(onion_payloads, onion_keys, prng_seed, payment_hash)
 			.map_err(|_| APIError::InvalidRoute { err: "Route size too large considering onion data".to_owned()})?;
@@ -3254,6 +3722,20 @@ where
 				&self.pending_events, |args| self.send_payment_along_path(args))
 	}
 
+	fn send_payment_for_bolt12_invoice(
+		&self, invoice: &Bolt12Invoice, payment_id: PaymentId
+	) -> Result<(), Bolt12PaymentError> {
+		let best_block_height = self.best_block.read().unwrap().height();
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		self.pending_outbound_payments
+			.send_payment_for_bolt12_invoice(
+				invoice, payment_id, &self.router, self.list_usable_channels(),
+				|| self.compute_inflight_htlcs(), &self.entropy_source, &self.node_signer,
+				best_block_height, &self.logger, &self.pending_events,
+				|args| self.send_payment_along_path(args)
+			)
+	}
+
 	#[cfg(test)]
 	pub(super) fn test_send_payment_internal(&self, route: &Route, payment_hash: PaymentHash, recipient_onion: RecipientOnionFields, keysend_preimage: Option<PaymentPreimage>, payment_id: PaymentId, recv_value_msat: Option<u64>, onion_session_privs: Vec<[u8; 32]>) -> Result<(), PaymentSendFailure> {
 		let best_block_height = self.best_block.read().unwrap().height();
@@ -3289,8 +3771,15 @@ where
 	/// If an [`Event::PaymentFailed`] event is generated and we restart without this
 	/// [`ChannelManager`] having been persisted, another [`Event::PaymentFailed`] may be generated.
 	///
+	/// If the payment is still awaiting a [`Bolt12Invoice`] requested via [`pay_for_offer`] or
+	/// [`create_refund_builder`], it is removed and an [`Event::InvoiceRequestFailed`] is generated
+	/// instead.
+	///
 	/// [`Event::PaymentFailed`]: events::Event::PaymentFailed
 	/// [`Event::PaymentSent`]: events::Event::PaymentSent
+	/// [`Event::InvoiceRequestFailed`]: events::Event::InvoiceRequestFailed
+	/// [`pay_for_offer`]: Self::pay_for_offer
+	/// [`create_refund_builder`]: Self::create_refund_builder
 	pub fn abandon_payment(&self, payment_id: PaymentId) {
 		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
 		self.pending_outbound_payments.abandon_payment(payment_id, PaymentFailureReason::UserAbandoned, &self.pending_events);
@@ -3344,6 +3833,139 @@ where
 			|args| self.send_payment_along_path(args))
 	}
 
+	/// Sends payment probes over all paths of a route that would be used to pay the given
+	/// amount to the given `node_id`.
+	///
+	/// See [`ChannelManager::send_preflight_probes`] for more information.
+	pub fn send_spontaneous_preflight_probes(
+		&self, node_id: PublicKey, amount_msat: u64, final_cltv_expiry_delta: u32,
+		liquidity_limit_multiplier: Option<u64>,
+	) -> Result<Vec<(PaymentHash, PaymentId)>, ProbeSendFailure> {
+		let payment_params =
+			PaymentParameters::from_node_id(node_id, final_cltv_expiry_delta);
+
+		let route_params = RouteParameters { payment_params, final_value_msat: amount_msat };
+
+		self.send_preflight_probes(route_params, liquidity_limit_multiplier)
+	}
+
+	/// Sends payment probes over all paths of a route that would be used to pay a route found
+	/// according to the given [`RouteParameters`].
+	///
+	/// This may be used to send "pre-flight" probes, i.e., to train our scorer before conducting
+	/// the actual payment. Note this is only useful if there likely is sufficient time for the
+	/// probe to settle before sending out the actual payment, e.g., when waiting for user
+	/// confirmation in a wallet UI.
+	///
+	/// Otherwise, there is a chance the probe could take up some liquidity needed to complete the
+	/// actual payment. Users should therefore be cautious and might avoid sending probes if
+	/// liquidity is scarce and/or they don't expect the probe to return before they send the
+	/// payment. To mitigate this issue, channels with available liquidity less than the required
+	/// amount times the given `liquidity_limit_multiplier` won't be used to send pre-flight
+	/// probes. If `None` is given as `liquidity_limit_multiplier`, it defaults to `3`.
+	///
+	/// Paths consisting only of one of our own channels are skipped, as there is nothing to learn
+	/// from probing them.
+	pub fn send_preflight_probes(
+		&self, route_params: RouteParameters, liquidity_limit_multiplier: Option<u64>,
+	) -> Result<Vec<(PaymentHash, PaymentId)>, ProbeSendFailure> {
+		let liquidity_limit_multiplier = liquidity_limit_multiplier.unwrap_or(3);
+
+		let payer = self.get_our_node_id();
+		let usable_channels = self.list_usable_channels();
+		let first_hops = usable_channels.iter().collect::<Vec<_>>();
+		let inflight_htlcs = self.compute_inflight_htlcs();
+
+		let route = self
+			.router
+			.find_route(&payer, &route_params, Some(&first_hops), inflight_htlcs)
+			.map_err(|e| {
+				log_error!(self.logger, "Failed to find path for payment probe: {:?}", e);
+				ProbeSendFailure::RouteNotFound
+			})?;
+
+		let mut used_liquidity_map = HashMap::with_capacity(first_hops.len());
+
+		let mut res = Vec::new();
+		for path in route.paths {
+			if path.hops.len() < 2 && path.blinded_tail.is_none() {
+				log_debug!(
+					self.logger,
+					"Skipped sending payment probe over path with less than two hops."
+				);
+				continue;
+			}
+
+			if let Some(first_path_hop) = path.hops.first() {
+				if let Some(first_hop) = first_hops.iter().find(|h| {
+					h.get_outbound_payment_scid() == Some(first_path_hop.short_channel_id)
+				}) {
+					let path_value = path.final_value_msat() + path.fee_msat();
+					let used_liquidity: &mut u64 =
+						used_liquidity_map.entry(first_hop.channel_id).or_insert(0);
+
+					if first_hop.next_outbound_htlc_limit_msat
+						< used_liquidity.saturating_add(path_value).saturating_mul(liquidity_limit_multiplier)
+					{
+						log_debug!(
+							self.logger,
+							"Skipped sending payment probe to avoid putting channel {} under the liquidity limit.",
+							first_path_hop.short_channel_id
+						);
+						continue;
+					} else {
+						*used_liquidity = used_liquidity.saturating_add(path_value);
+					}
+				}
+			}
+
+			res.push(self.send_probe(path).map_err(|e| {
+				log_error!(self.logger, "Failed to send pre-flight probe: {:?}", e);
+				ProbeSendFailure::SendingFailed(e)
+			})?);
+		}
+
+		Ok(res)
+	}
+
+	/// Estimates the routing fees of a payment according to the given [`RouteParameters`] and how
+	/// likely it is to succeed, e.g. to show the expected cost to a user before they confirm the
+	/// payment.
+	///
+	/// This queries our [`Router`] with our usable channels and the HTLCs we currently have in
+	/// flight, but doesn't send any HTLCs or track the payment in any way.
+	///
+	/// See [`Router::estimate_payment_fees`] for more details.
+	///
+	/// Errors with the [`Router`]'s [`LightningError`] if no route to the payee could be found.
+	pub fn estimate_payment_fees(&self, route_params: &RouteParameters) -> Result<PaymentFeeEstimate, LightningError> {
+		let payer = self.get_our_node_id();
+		let usable_channels = self.list_usable_channels();
+		let first_hops = usable_channels.iter().collect::<Vec<_>>();
+		let inflight_htlcs = self.compute_inflight_htlcs();
+
+		self.router.estimate_payment_fees(&payer, route_params, Some(&first_hops), inflight_htlcs)
+			.map_err(|e| {
+				log_debug!(self.logger, "Failed to find a route to estimate payment fees: {}", e.err);
+				e
+			})
+	}
+
+	/// Returns whether the given event is a [`PaymentSent`] or [`PaymentFailed`] event for a payment
+	/// we made to forward a trampoline payment. As the inbound HTLCs are claimed or failed back
+	/// automatically, these aren't surfaced to the user.
+	///
+	/// [`PaymentSent`]: events::Event::PaymentSent
+	/// [`PaymentFailed`]: events::Event::PaymentFailed
+	fn is_trampoline_forward_event(&self, event: &events::Event) -> bool {
+		match event {
+			events::Event::PaymentSent { payment_id: Some(payment_id), payment_hash, .. } |
+			events::Event::PaymentFailed { payment_id, payment_hash, .. } =>
+				outbound_payment::payment_is_trampoline_forward(payment_hash, payment_id, self.probing_cookie_secret),
+			_ => false,
+		}
+	}
+
 	/// Returns whether a payment with the given [`PaymentHash`] and [`PaymentId`] is, in fact, a
 	/// payment probe.
 	#[cfg(test)]
@@ -3395,10 +4017,12 @@ where
 			},
 		};
 
-		peer_state.pending_msg_events.push(events::MessageSendEvent::SendFundingCreated {
-			node_id: chan.context.get_counterparty_node_id(),
-			msg,
-		});
+		if let Some(msg) = msg {
+			peer_state.pending_msg_events.push(events::MessageSendEvent::SendFundingCreated {
+				node_id: chan.context.get_counterparty_node_id(),
+				msg,
+			});
+		}
 		match peer_state.channel_by_id.entry(chan.context.channel_id()) {
 			hash_map::Entry::Occupied(_) => {
 				panic!("Generated duplicate funding txid?");
@@ -3669,8 +4293,8 @@ where
 			})?;
 
 		let routing = match payment.forward_info.routing {
-			PendingHTLCRouting::Forward { onion_packet, .. } => {
-				PendingHTLCRouting::Forward { onion_packet, short_channel_id: next_hop_scid }
+			PendingHTLCRouting::Forward { onion_packet, blinded, .. } => {
+				PendingHTLCRouting::Forward { onion_packet, blinded, short_channel_id: next_hop_scid }
 			},
 			_ => unreachable!() // Only `PendingHTLCRouting::Forward`s are intercepted
 		};
@@ -3706,13 +4330,14 @@ where
 				err: format!("Payment with intercept id {} not found", log_bytes!(intercept_id.0))
 			})?;
 
-		if let PendingHTLCRouting::Forward { short_channel_id, .. } = payment.forward_info.routing {
+		if let PendingHTLCRouting::Forward { short_channel_id, blinded, .. } = payment.forward_info.routing {
 			let htlc_source = HTLCSource::PreviousHopData(HTLCPreviousHopData {
 				short_channel_id: payment.prev_short_channel_id,
 				outpoint: payment.prev_funding_outpoint,
 				htlc_id: payment.prev_htlc_id,
 				incoming_packet_shared_secret: payment.forward_info.incoming_shared_secret,
 				phantom_shared_secret: None,
+				blinded_failure: blinded.map(|b| b.failure),
 			});
 
 			let failure_reason = HTLCFailReason::from_failure_code(0x4000 | 10);
@@ -3733,6 +4358,7 @@ where
 		let mut new_events = VecDeque::new();
 		let mut failed_forwards = Vec::new();
 		let mut phantom_receives: Vec<(u64, OutPoint, u128, Vec<(PendingHTLCInfo, u64)>)> = Vec::new();
+		let mut trampoline_forwards = Vec::new();
 		{
 			let mut forward_htlcs = HashMap::new();
 			mem::swap(&mut forward_htlcs, &mut self.forward_htlcs.lock().unwrap());
@@ -3750,6 +4376,7 @@ where
 											outgoing_cltv_value, ..
 										}
 									}) => {
+										let blinded_failure = routing.blinded_failure();
 										macro_rules! failure_handler {
 											($msg: expr, $err_code: expr, $err_data: expr, $phantom_ss: expr, $next_hop_unknown: expr) => {
 												log_info!(self.logger, "Failed to accept/forward incoming HTLC: {}", $msg);
@@ -3760,6 +4387,7 @@ where
 													htlc_id: prev_htlc_id,
 													incoming_packet_shared_secret: incoming_shared_secret,
 													phantom_shared_secret: $phantom_ss,
+													blinded_failure,
 												});
 
 												let reason = if $next_hop_unknown {
@@ -3793,7 +4421,10 @@ where
 											let phantom_pubkey_res = self.node_signer.get_node_id(Recipient::PhantomNode);
 											if phantom_pubkey_res.is_ok() && fake_scid::is_valid_phantom(&self.fake_scid_rand_bytes, short_chan_id, &self.genesis_hash) {
 												let phantom_shared_secret = self.node_signer.ecdh(Recipient::PhantomNode, &onion_packet.public_key.unwrap(), None).unwrap().secret_bytes();
-												let next_hop = match onion_utils::decode_next_payment_hop(phantom_shared_secret, &onion_packet.hop_data, onion_packet.hmac, payment_hash) {
+												let next_hop = match onion_utils::decode_next_payment_hop(
+													phantom_shared_secret, &onion_packet.hop_data, onion_packet.hmac,
+													payment_hash, None, &self.node_signer
+												) {
 													Ok(res) => res,
 													Err(onion_utils::OnionDecodeErr::Malformed { err_msg, err_code }) => {
 														let sha256_of_onion = Sha256::hash(&onion_packet.hop_data).into_inner();
@@ -3826,7 +4457,7 @@ where
 											fail_forward!(format!("Unknown short channel id {} for forward HTLC", short_chan_id), 0x4000 | 10, Vec::new(), None);
 										}
 									},
-									HTLCForwardInfo::FailHTLC { .. } => {
+									HTLCForwardInfo::FailHTLC { .. } | HTLCForwardInfo::FailMalformedHTLC { .. } => {
 										// Channel went away before we could fail it. This implies
 										// the channel is now on chain and our counterparty is
 										// trying to broadcast the HTLC-Timeout, but that's their
@@ -3863,7 +4494,7 @@ where
 										prev_short_channel_id, prev_htlc_id, prev_funding_outpoint, prev_user_channel_id: _,
 										forward_info: PendingHTLCInfo {
 											incoming_shared_secret, payment_hash, outgoing_amt_msat, outgoing_cltv_value,
-											routing: PendingHTLCRouting::Forward { onion_packet, .. }, skimmed_fee_msat, ..
+											routing: PendingHTLCRouting::Forward { onion_packet, blinded, .. }, skimmed_fee_msat, ..
 										},
 									}) => {
 										log_trace!(self.logger, "Adding HTLC from short id {} with payment_hash {} to channel with short id {} after delay", prev_short_channel_id, log_bytes!(payment_hash.0), short_chan_id);
@@ -3874,10 +4505,27 @@ where
 											incoming_packet_shared_secret: incoming_shared_secret,
 											// Phantom payments are only PendingHTLCRouting::Receive.
 											phantom_shared_secret: None,
+											blinded_failure: blinded.map(|b| b.failure),
 										});
+										let next_blinding_point = match blinded {
+											Some(b) => match onion_utils::next_blinding_point(
+												&self.secp_ctx, b.inbound_blinding_point, &self.node_signer
+											) {
+												Ok(next_blinding_point) => Some(next_blinding_point),
+												Err(()) => {
+													log_info!(self.logger, "Failed to compute the next blinding point for HTLC with payment_hash {}", log_bytes!(payment_hash.0));
+													failed_forwards.push((htlc_source, payment_hash,
+														HTLCFailReason::reason(onion_utils::INVALID_ONION_BLINDING, vec![0; 32]),
+														HTLCDestination::NextHopChannel { node_id: Some(chan.get().context.get_counterparty_node_id()), channel_id: forward_chan_id }
+													));
+													continue;
+												},
+											},
+											None => None,
+										};
 										if let Err(e) = chan.get_mut().queue_add_htlc(outgoing_amt_msat,
 											payment_hash, outgoing_cltv_value, htlc_source.clone(),
-											onion_packet, skimmed_fee_msat, &self.fee_estimator,
+											onion_packet, skimmed_fee_msat, next_blinding_point, &self.fee_estimator,
 											&self.logger)
 										{
 											if let ChannelError::Ignore(msg) = e {
@@ -3898,9 +4546,8 @@ where
 									},
 									HTLCForwardInfo::FailHTLC { htlc_id, err_packet } => {
 										log_trace!(self.logger, "Failing HTLC back to channel with short id {} (backward HTLC ID {}) after delay", short_chan_id, htlc_id);
-										if let Err(e) = chan.get_mut().queue_fail_htlc(
-											htlc_id, err_packet, &self.logger
-										) {
+										let res = chan.get_mut().queue_fail_htlc(htlc_id, err_packet, &self.logger);
+										if let Err(e) = res {
 											if let ChannelError::Ignore(msg) = e {
 												log_trace!(self.logger, "Failed to fail HTLC with ID {} backwards to short_id {}: {}", htlc_id, short_chan_id, msg);
 											} else {
@@ -3912,6 +4559,21 @@ where
 											continue;
 										}
 									},
+									HTLCForwardInfo::FailMalformedHTLC { htlc_id, failure_code, sha256_of_onion } => {
+										log_trace!(self.logger, "Failing malformed HTLC back to channel with short id {} (backward HTLC ID {}) after delay", short_chan_id, htlc_id);
+										let res = chan.get_mut().queue_fail_malformed_htlc(
+											htlc_id, failure_code, sha256_of_onion, &self.logger
+										);
+										if let Err(e) = res {
+											if let ChannelError::Ignore(msg) = e {
+												log_trace!(self.logger, "Failed to fail HTLC with ID {} backwards to short_id {}: {}", htlc_id, short_chan_id, msg);
+											} else {
+												panic!("Stated return value requirements in queue_fail_malformed_htlc() were not met");
+											}
+											// See the comment on failing `FailHTLC`s above.
+											continue;
+										}
+									},
 								}
 							}
 						}
@@ -3923,16 +4585,19 @@ where
 								prev_short_channel_id, prev_htlc_id, prev_funding_outpoint, prev_user_channel_id,
 								forward_info: PendingHTLCInfo {
 									routing, incoming_shared_secret, payment_hash, incoming_amt_msat, outgoing_amt_msat,
-									skimmed_fee_msat, ..
+									outgoing_cltv_value, skimmed_fee_msat, ..
 								}
 							}) => {
-								let (cltv_expiry, onion_payload, payment_data, phantom_shared_secret, mut onion_fields) = match routing {
-									PendingHTLCRouting::Receive { payment_data, payment_metadata, incoming_cltv_expiry, phantom_shared_secret } => {
+								let (cltv_expiry, onion_payload, payment_data, phantom_shared_secret, mut onion_fields, blinded_failure) = match routing {
+									PendingHTLCRouting::Receive {
+										payment_data, payment_metadata, incoming_cltv_expiry, phantom_shared_secret,
+										blinded_failure,
+									} => {
 										let _legacy_hop_data = Some(payment_data.clone());
 										let onion_fields =
 											RecipientOnionFields { payment_secret: Some(payment_data.payment_secret), payment_metadata };
 										(incoming_cltv_expiry, OnionPayload::Invoice { _legacy_hop_data },
-											Some(payment_data), phantom_shared_secret, onion_fields)
+											Some(payment_data), phantom_shared_secret, onion_fields, blinded_failure)
 									},
 									PendingHTLCRouting::ReceiveKeysend { payment_data, payment_preimage, payment_metadata, incoming_cltv_expiry } => {
 										let onion_fields = RecipientOnionFields {
@@ -3940,7 +4605,37 @@ where
 											payment_metadata
 										};
 										(incoming_cltv_expiry, OnionPayload::Spontaneous(payment_preimage),
-											payment_data, None, onion_fields)
+											payment_data, None, onion_fields, None)
+									},
+									PendingHTLCRouting::TrampolineForward {
+										payment_data, outgoing_node_id, trampoline_packet, incoming_cltv_expiry,
+									} => {
+										// Trampoline forwards are held until all parts arrive, which is
+										// handled once we've processed all pending HTLCs.
+										let htlc = TrampolineHTLC {
+											prev_hop: HTLCPreviousHopData {
+												short_channel_id: prev_short_channel_id,
+												outpoint: prev_funding_outpoint,
+												htlc_id: prev_htlc_id,
+												incoming_packet_shared_secret: incoming_shared_secret,
+												phantom_shared_secret: None,
+												blinded_failure: None,
+											},
+											value: incoming_amt_msat.unwrap_or(outgoing_amt_msat),
+											cltv_expiry: incoming_cltv_expiry,
+										};
+										trampoline_forwards.push((payment_hash, PendingTrampolineForward {
+											htlcs: vec![htlc],
+											payment_secret: payment_data.payment_secret,
+											total_msat: payment_data.total_msat,
+											outgoing_node_id,
+											outgoing_amt_msat,
+											outgoing_cltv_value,
+											trampoline_packet,
+											outbound_payment_id: None,
+											timer_ticks: 0,
+										}));
+										continue 'next_forwardable_htlc;
 									},
 									_ => {
 										panic!("short_channel_id == 0 should imply any pending_forward entries are of type Receive");
@@ -3953,6 +4648,7 @@ where
 										htlc_id: prev_htlc_id,
 										incoming_packet_shared_secret: incoming_shared_secret,
 										phantom_shared_secret,
+										blinded_failure,
 									},
 									// We differentiate the received value from the sender intended value
 									// if possible so that we don't prematurely mark MPP payments complete
@@ -3982,6 +4678,7 @@ where
 												htlc_id: $htlc.prev_hop.htlc_id,
 												incoming_packet_shared_secret: $htlc.prev_hop.incoming_packet_shared_secret,
 												phantom_shared_secret,
+												blinded_failure: $htlc.prev_hop.blinded_failure,
 											}), payment_hash,
 											HTLCFailReason::reason(0x4000 | 15, htlc_msat_height_data),
 											HTLCDestination::FailedPayment { payment_hash: $payment_hash },
@@ -4155,7 +4852,7 @@ where
 									},
 								};
 							},
-							HTLCForwardInfo::FailHTLC { .. } => {
+							HTLCForwardInfo::FailHTLC { .. } | HTLCForwardInfo::FailMalformedHTLC { .. } => {
 								panic!("Got pending fail of our own HTLC");
 							}
 						}
@@ -4173,6 +4870,10 @@ where
 			self.fail_htlc_backwards_internal(&htlc_source, &payment_hash, &failure_reason, destination);
 		}
 		self.forward_htlcs(&mut phantom_receives);
+		self.forward_trampoline_htlcs(trampoline_forwards);
+		// Some paths of trampoline forwards' outbound payments may have failed, either just now or
+		// when retrying them above, in which case we may need to fail back the inbound HTLCs.
+		self.fail_failed_trampoline_forwards();
 
 		// Freeing the holding cell here is relatively redundant - in practice we'll do it when we
 		// next get a `get_and_clear_pending_msg_events` call, but some tests rely on it, and it's
@@ -4185,6 +4886,161 @@ where
 		events.append(&mut new_events);
 	}
 
+	/// Adds newly-received trampoline HTLCs to their [`PendingTrampolineForward`]s, and begins
+	/// paying the next trampoline hop for any payments we've now received in full.
+	fn forward_trampoline_htlcs(&self, new_forwards: Vec<(PaymentHash, PendingTrampolineForward)>) {
+		if new_forwards.is_empty() { return; }
+
+		let cur_height = self.best_block.read().unwrap().height();
+		let mut failed_htlcs = Vec::new();
+		let mut ready_forwards = Vec::new();
+		{
+			let mut pending_trampoline_forwards = self.pending_trampoline_forwards.lock().unwrap();
+			for (payment_hash, mut new_forward) in new_forwards {
+				let forward = match pending_trampoline_forwards.entry(payment_hash) {
+					hash_map::Entry::Vacant(entry) => entry.insert(new_forward),
+					hash_map::Entry::Occupied(entry) => {
+						let forward = entry.into_mut();
+						if forward.outbound_payment_id.is_some() ||
+							forward.payment_secret != new_forward.payment_secret ||
+							forward.total_msat != new_forward.total_msat
+						{
+							log_trace!(self.logger, "Failing new trampoline HTLC with payment_hash {} as it didn't match the parts we'd already received",
+								log_bytes!(payment_hash.0));
+							for htlc in new_forward.htlcs.drain(..) {
+								let mut htlc_msat_height_data = htlc.value.to_be_bytes().to_vec();
+								htlc_msat_height_data.extend_from_slice(&cur_height.to_be_bytes());
+								failed_htlcs.push((payment_hash, htlc, HTLCFailReason::reason(0x4000 | 15, htlc_msat_height_data)));
+							}
+							continue;
+						}
+						forward.htlcs.append(&mut new_forward.htlcs);
+						forward
+					},
+				};
+
+				let received_msat: u64 = forward.htlcs.iter().map(|htlc| htlc.value).sum();
+				if received_msat < forward.total_msat {
+					// Nothing to do - we haven't received the full payment yet, wait until we
+					// receive more MPP parts.
+					continue;
+				}
+				let earliest_expiry = forward.htlcs.iter().map(|htlc| htlc.cltv_expiry).min().unwrap();
+				let failure_code = if received_msat < forward.outgoing_amt_msat {
+					Some(onion_utils::TRAMPOLINE_FEE_INSUFFICIENT)
+				} else if forward.outgoing_cltv_value <= cur_height ||
+					(earliest_expiry as u64) < forward.outgoing_cltv_value as u64 + MIN_CLTV_EXPIRY_DELTA as u64
+				{
+					Some(onion_utils::TRAMPOLINE_EXPIRY_TOO_SOON)
+				} else { None };
+
+				if let Some(failure_code) = failure_code {
+					log_trace!(self.logger, "Failing trampoline payment with payment_hash {} as it didn't leave enough fee or CLTV delta to forward",
+						log_bytes!(payment_hash.0));
+					let forward = pending_trampoline_forwards.remove(&payment_hash).unwrap();
+					failed_htlcs.extend(forward.htlcs.into_iter()
+						.map(|htlc| (payment_hash, htlc, HTLCFailReason::reason(failure_code, Vec::new()))));
+					continue;
+				}
+
+				let payment_id = outbound_payment::trampoline_forward_payment_id(&payment_hash, self.probing_cookie_secret);
+				forward.outbound_payment_id = Some(payment_id);
+				ready_forwards.push((payment_hash, payment_id, received_msat - forward.outgoing_amt_msat,
+					earliest_expiry, forward.outgoing_node_id, forward.outgoing_amt_msat,
+					forward.outgoing_cltv_value));
+			}
+		}
+
+		for (payment_hash, htlc, reason) in failed_htlcs {
+			let source = HTLCSource::PreviousHopData(htlc.prev_hop);
+			let receiver = HTLCDestination::FailedPayment { payment_hash };
+			self.fail_htlc_backwards_internal(&source, &payment_hash, &reason, receiver);
+		}
+
+		for (payment_hash, payment_id, fee_budget_msat, earliest_expiry, outgoing_node_id, outgoing_amt_msat, outgoing_cltv_value) in ready_forwards {
+			// Leave ourselves at least `MIN_CLTV_EXPIRY_DELTA` blocks between the expiry of the
+			// HTLCs we send and those paying us, as we would for a regular forward. Any retries
+			// share the fee budget, see `OutboundPayments::retry_payment_internal`.
+			let max_total_cltv_expiry_delta = earliest_expiry - MIN_CLTV_EXPIRY_DELTA as u32 - cur_height;
+			let route_params = RouteParameters {
+				payment_params: PaymentParameters::for_keysend(outgoing_node_id, outgoing_cltv_value - cur_height, true)
+					.with_max_total_cltv_expiry_delta(max_total_cltv_expiry_delta)
+					.with_max_total_routing_fee_msat(fee_budget_msat),
+				final_value_msat: outgoing_amt_msat,
+			};
+			// The trampoline onion is picked up by `send_payment_along_path` from the pending
+			// forward, see `outbound_payment::payment_is_trampoline_forward`.
+			let recipient_onion = RecipientOnionFields::secret_only(
+				PaymentSecret(self.entropy_source.get_secure_random_bytes()));
+			let res = self.pending_outbound_payments.send_payment(payment_hash, recipient_onion,
+				payment_id, Retry::Attempts(TRAMPOLINE_FORWARD_RETRY_ATTEMPTS), route_params,
+				&self.router, self.list_usable_channels(), || self.compute_inflight_htlcs(),
+				&self.entropy_source, &self.node_signer, cur_height, &self.logger, &self.pending_events,
+				|args| self.send_payment_along_path(args));
+			// If we failed to send the payment at all we can fail back immediately. Otherwise, any
+			// paths which failed to send will be retried, and we'll only fail back once the payment
+			// has been abandoned, see `Self::fail_failed_trampoline_forwards`.
+			let failure_code = match res {
+				Ok(()) => continue,
+				// As pathfinding is limited to the fee paid to us, we can't tell whether a route
+				// exists at all, but a higher fee may allow us to find one.
+				Err(RetryableSendFailure::RouteNotFound) => onion_utils::TRAMPOLINE_FEE_INSUFFICIENT,
+				Err(RetryableSendFailure::PaymentExpired) | Err(RetryableSendFailure::DuplicatePayment) => 0x2000 | 2,
+			};
+			log_trace!(self.logger, "Failed to forward trampoline payment with payment_hash {}: {:?}",
+				log_bytes!(payment_hash.0), res);
+			let forward = self.pending_trampoline_forwards.lock().unwrap().remove(&payment_hash);
+			if let Some(forward) = forward {
+				self.fail_trampoline_htlcs(payment_hash, forward.htlcs, failure_code);
+			}
+		}
+	}
+
+	fn fail_trampoline_htlcs(&self, payment_hash: PaymentHash, htlcs: Vec<TrampolineHTLC>, failure_code: u16) {
+		for htlc in htlcs {
+			let source = HTLCSource::PreviousHopData(htlc.prev_hop);
+			let reason = HTLCFailReason::reason(failure_code, Vec::new());
+			let receiver = HTLCDestination::FailedPayment { payment_hash };
+			self.fail_htlc_backwards_internal(&source, &payment_hash, &reason, receiver);
+		}
+	}
+
+	/// Fails back the inbound HTLCs of any trampoline forwards whose outbound payment has failed.
+	fn fail_failed_trampoline_forwards(&self) {
+		let outbound_payments = self.pending_trampoline_forwards.lock().unwrap().iter()
+			.filter_map(|(payment_hash, forward)| forward.outbound_payment_id.map(|id| (*payment_hash, id)))
+			.collect::<Vec<_>>();
+		for (payment_hash, payment_id) in outbound_payments {
+			if !self.pending_outbound_payments.has_failed(&payment_id) { continue; }
+			let forward = self.pending_trampoline_forwards.lock().unwrap().remove(&payment_hash);
+			if let Some(forward) = forward {
+				log_trace!(self.logger, "Failing trampoline payment with payment_hash {} back as we failed to pay the next hop",
+					log_bytes!(payment_hash.0));
+				self.fail_trampoline_htlcs(payment_hash, forward.htlcs, 0x2000 | 2);
+			}
+		}
+	}
+
+	/// Claims the inbound HTLCs of a trampoline forward once the outbound payment with the given
+	/// [`PaymentId`] has been claimed.
+	fn claim_trampoline_forward(&self, payment_id: PaymentId, payment_preimage: PaymentPreimage) {
+		let payment_hash = PaymentHash(Sha256::hash(&payment_preimage.0).into_inner());
+		let forward = {
+			let mut pending_trampoline_forwards = self.pending_trampoline_forwards.lock().unwrap();
+			let is_our_forward = pending_trampoline_forwards.get(&payment_hash)
+				.map_or(false, |forward| forward.outbound_payment_id == Some(payment_id));
+			if is_our_forward { pending_trampoline_forwards.remove(&payment_hash) } else { None }
+		};
+		if let Some(forward) = forward {
+			for htlc in forward.htlcs {
+				if let Err((pk, err)) = self.claim_funds_from_hop(htlc.prev_hop, payment_preimage, |_| None) {
+					let result: Result<(), _> = Err(err);
+					let _ = handle_error!(self, result, pk);
+				}
+			}
+		}
+	}
+
 	/// Free the background events, generally called from [`PersistenceNotifierGuard`] constructors.
 	///
 	/// Expects the caller to have a total_consistency_lock read lock.
@@ -4507,6 +5363,23 @@ where
 				self.fail_htlc_backwards_internal(&source, &htlc_source.1, &reason, receiver);
 			}
 
+			// Time out trampoline forwards for which we haven't received all parts, using the same
+			// timeout as MPP payments we receive.
+			let mut timed_out_trampoline_forwards = Vec::new();
+			self.pending_trampoline_forwards.lock().unwrap().retain(|payment_hash, forward| {
+				if forward.outbound_payment_id.is_some() { return true; }
+				forward.timer_ticks += 1;
+				if forward.timer_ticks >= MPP_TIMEOUT_TICKS {
+					timed_out_trampoline_forwards.push((*payment_hash, mem::take(&mut forward.htlcs)));
+					return false;
+				}
+				true
+			});
+			for (payment_hash, htlcs) in timed_out_trampoline_forwards {
+				self.fail_trampoline_htlcs(payment_hash, htlcs, 23);
+			}
+			self.fail_failed_trampoline_forwards();
+
 			for (err, counterparty_node_id) in handle_errors.drain(..) {
 				let _ = handle_error!(self, err, counterparty_node_id);
 			}
@@ -4672,10 +5545,36 @@ where
 					session_priv, payment_id, self.probing_cookie_secret, &self.secp_ctx,
 					&self.pending_events, &self.logger)
 				{ self.push_pending_forwards_ev(); }
+				self.fail_failed_trampoline_forwards();
 			},
-			HTLCSource::PreviousHopData(HTLCPreviousHopData { ref short_channel_id, ref htlc_id, ref incoming_packet_shared_secret, ref phantom_shared_secret, ref outpoint }) => {
-				log_trace!(self.logger, "Failing HTLC with payment_hash {} backwards from us with {:?}", log_bytes!(payment_hash.0), onion_error);
-				let err_packet = onion_error.get_encrypted_failure_packet(incoming_packet_shared_secret, phantom_shared_secret);
+			HTLCSource::PreviousHopData(HTLCPreviousHopData {
+				ref short_channel_id, ref htlc_id, ref incoming_packet_shared_secret,
+				ref phantom_shared_secret, ref outpoint, ref blinded_failure,
+			}) => {
+				log_trace!(self.logger, "Failing {}HTLC with payment_hash {} backwards from us with {:?}",
+					if blinded_failure.is_some() { "blinded " } else { "" }, log_bytes!(payment_hash.0), onion_error);
+				let failure = match blinded_failure {
+					Some(BlindedFailure::FromIntroductionNode) => {
+						let blinded_onion_error = HTLCFailReason::reason(onion_utils::INVALID_ONION_BLINDING, vec![0; 32]);
+						let err_packet = blinded_onion_error.get_encrypted_failure_packet(
+							incoming_packet_shared_secret, phantom_shared_secret
+						);
+						HTLCForwardInfo::FailHTLC { htlc_id: *htlc_id, err_packet }
+					},
+					Some(BlindedFailure::FromBlindedNode) => {
+						HTLCForwardInfo::FailMalformedHTLC {
+							htlc_id: *htlc_id,
+							failure_code: onion_utils::INVALID_ONION_BLINDING,
+							sha256_of_onion: [0; 32]
+						}
+					},
+					None => {
+						let err_packet = onion_error.get_encrypted_failure_packet(
+							incoming_packet_shared_secret, phantom_shared_secret
+						);
+						HTLCForwardInfo::FailHTLC { htlc_id: *htlc_id, err_packet }
+					}
+				};
 
 				let mut push_forward_ev = false;
 				let mut forward_htlcs = self.forward_htlcs.lock().unwrap();
@@ -4684,10 +5583,10 @@ where
 				}
 				match forward_htlcs.entry(*short_channel_id) {
 					hash_map::Entry::Occupied(mut entry) => {
-						entry.get_mut().push(HTLCForwardInfo::FailHTLC { htlc_id: *htlc_id, err_packet });
+						entry.get_mut().push(failure);
 					},
 					hash_map::Entry::Vacant(entry) => {
-						entry.insert(vec!(HTLCForwardInfo::FailHTLC { htlc_id: *htlc_id, err_packet }));
+						entry.insert(vec!(failure));
 					}
 				}
 				mem::drop(forward_htlcs);
@@ -4941,6 +5840,7 @@ where
 				debug_assert!(self.background_events_processed_since_startup.load(Ordering::Acquire),
 					"We don't support claim_htlc claims during startup - monitors may not be available yet");
 				self.pending_outbound_payments.claim_htlc(payment_id, payment_preimage, session_priv, path, from_onchain, &self.pending_events, &self.logger);
+				self.claim_trampoline_forward(payment_id, payment_preimage);
 			},
 			HTLCSource::PreviousHopData(hop_data) => {
 				let prev_outpoint = hop_data.outpoint;
@@ -5395,7 +6295,7 @@ where
 
 		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
 		let peer_state = &mut *peer_state_lock;
-		let (chan, funding_msg, monitor) =
+		let (chan, funding_msg_opt, monitor) =
 			match peer_state.inbound_v1_channel_by_id.remove(&msg.temporary_channel_id) {
 				Some(inbound_chan) => {
 					match inbound_chan.funding_created(msg, best_block, &self.signer_provider, &self.logger) {
@@ -5415,16 +6315,16 @@ where
 				None => return Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.temporary_channel_id))
 			};
 
-		match peer_state.channel_by_id.entry(funding_msg.channel_id) {
+		match peer_state.channel_by_id.entry(chan.context.channel_id()) {
 			hash_map::Entry::Occupied(_) => {
-				Err(MsgHandleErrInternal::send_err_msg_no_close("Already had channel with the new channel_id".to_owned(), funding_msg.channel_id))
+				Err(MsgHandleErrInternal::send_err_msg_no_close("Already had channel with the new channel_id".to_owned(), chan.context.channel_id()))
 			},
 			hash_map::Entry::Vacant(e) => {
 				match self.id_to_peer.lock().unwrap().entry(chan.context.channel_id()) {
 					hash_map::Entry::Occupied(_) => {
 						return Err(MsgHandleErrInternal::send_err_msg_no_close(
 							"The funding_created message had the same funding_txid as an existing channel - funding is not possible".to_owned(),
-							funding_msg.channel_id))
+							chan.context.channel_id()))
 					},
 					hash_map::Entry::Vacant(i_e) => {
 						i_e.insert(chan.context.get_counterparty_node_id());
@@ -5435,11 +6335,13 @@ where
 				// hasn't persisted to disk yet - we can't lose money on a transaction that we haven't
 				// accepted payment from yet. We do, however, need to wait to send our channel_ready
 				// until we have persisted our monitor.
-				let new_channel_id = funding_msg.channel_id;
-				peer_state.pending_msg_events.push(events::MessageSendEvent::SendFundingSigned {
-					node_id: counterparty_node_id.clone(),
-					msg: funding_msg,
-				});
+				let new_channel_id = chan.context.channel_id();
+				if let Some(msg) = funding_msg_opt {
+					peer_state.pending_msg_events.push(events::MessageSendEvent::SendFundingSigned {
+						node_id: counterparty_node_id.clone(),
+						msg,
+					});
+				}
 
 				let monitor_res = self.chain_monitor.watch_channel(monitor.get_funding_txo().0, monitor);
 
@@ -5686,8 +6588,18 @@ where
 					// but if we've sent a shutdown and they haven't acknowledged it yet, we just
 					// want to reject the new HTLC and fail it backwards instead of forwarding.
 					match pending_forward_info {
-						PendingHTLCStatus::Forward(PendingHTLCInfo { ref incoming_shared_secret, .. }) => {
-							let reason = if (error_code & 0x1000) != 0 {
+						PendingHTLCStatus::Forward(_) if msg.blinding_point.is_some() => {
+							PendingHTLCStatus::Fail(HTLCFailureMsg::Malformed(msgs::UpdateFailMalformedHTLC {
+								channel_id: msg.channel_id,
+								htlc_id: msg.htlc_id,
+								sha256_of_onion: Sha256::hash(&msg.onion_routing_packet.hop_data).into_inner(),
+								failure_code: onion_utils::INVALID_ONION_BLINDING,
+							}))
+						},
+						PendingHTLCStatus::Forward(PendingHTLCInfo { ref incoming_shared_secret, ref routing, .. }) => {
+							let reason = if routing.blinded_failure() == Some(BlindedFailure::FromIntroductionNode) {
+								HTLCFailReason::reason(onion_utils::INVALID_ONION_BLINDING, vec![0; 32])
+							} else if (error_code & 0x1000) != 0 {
 								let (real_code, error_data) = self.get_htlc_inbound_temp_fail_err_and_data(error_code, chan);
 								HTLCFailReason::reason(real_code, error_data)
 							} else {
@@ -5805,6 +6717,7 @@ where
 						PendingHTLCRouting::Forward { short_channel_id, .. } => short_channel_id,
 						PendingHTLCRouting::Receive { .. } => 0,
 						PendingHTLCRouting::ReceiveKeysend { .. } => 0,
+						PendingHTLCRouting::TrampolineForward { .. } => 0,
 					};
 					// Pull this now to avoid introducing a lock order with `forward_htlcs`.
 					let is_our_scid = self.short_to_chan_info.read().unwrap().contains_key(&scid);
@@ -5842,6 +6755,7 @@ where
 											htlc_id: prev_htlc_id,
 											incoming_packet_shared_secret: forward_info.incoming_shared_secret,
 											phantom_shared_secret: None,
+											blinded_failure: forward_info.routing.blinded_failure(),
 										});
 
 										failed_intercept_forwards.push((htlc_source, forward_info.payment_hash,
@@ -6231,6 +7145,68 @@ where
 		has_update
 	}
 
+	/// Notifies the [`ChannelManager`] that our [`EcdsaChannelSigner`] may now be able to provide
+	/// signatures it was previously unable to, i.e. that a call which previously returned
+	/// `Err(())` may now succeed.
+	///
+	/// Any `commitment_signed`, `revoke_and_ack`, `funding_created`, `funding_signed` or
+	/// `closing_signed` messages which were held waiting on the signer will be regenerated and
+	/// queued for sending to our counterparty.
+	///
+	/// If `channel_opt` is `None`, all channels will be checked, otherwise only the channel with
+	/// the given counterparty node id and channel id will be.
+	///
+	/// [`EcdsaChannelSigner`]: crate::sign::EcdsaChannelSigner
+	pub fn signer_unblocked(&self, channel_opt: Option<(PublicKey, [u8; 32])>) {
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+
+		let unblock_chan = |chan: &mut Channel<<SP::Target as SignerProvider>::Signer>, pending_msg_events: &mut Vec<MessageSendEvent>| {
+			let node_id = chan.context.get_counterparty_node_id();
+			let msgs = chan.signer_maybe_unblocked(&self.logger);
+			if let Some(msg) = msgs.funding_created {
+				pending_msg_events.push(events::MessageSendEvent::SendFundingCreated { node_id, msg });
+			}
+			if let Some(msg) = msgs.funding_signed {
+				pending_msg_events.push(events::MessageSendEvent::SendFundingSigned { node_id, msg });
+			}
+			if let Some(msg) = msgs.channel_ready {
+				send_channel_ready!(self, pending_msg_events, chan, msg);
+			}
+			let cu_msg = msgs.commitment_update.map(|updates| events::MessageSendEvent::UpdateHTLCs { node_id, updates });
+			let raa_msg = msgs.raa.map(|msg| events::MessageSendEvent::SendRevokeAndACK { node_id, msg });
+			let (first_msg, second_msg) = match msgs.order {
+				RAACommitmentOrder::CommitmentFirst => (cu_msg, raa_msg),
+				RAACommitmentOrder::RevokeAndACKFirst => (raa_msg, cu_msg),
+			};
+			if let Some(msg) = first_msg { pending_msg_events.push(msg); }
+			if let Some(msg) = second_msg { pending_msg_events.push(msg); }
+		};
+
+		{
+			let per_peer_state = self.per_peer_state.read().unwrap();
+			if let Some((counterparty_node_id, channel_id)) = channel_opt {
+				if let Some(peer_state_mutex) = per_peer_state.get(&counterparty_node_id) {
+					let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+					let peer_state = &mut *peer_state_lock;
+					if let Some(chan) = peer_state.channel_by_id.get_mut(&channel_id) {
+						unblock_chan(chan, &mut peer_state.pending_msg_events);
+					}
+				}
+			} else {
+				for (_cp_id, peer_state_mutex) in per_peer_state.iter() {
+					let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+					let peer_state = &mut *peer_state_lock;
+					for (_, chan) in peer_state.channel_by_id.iter_mut() {
+						unblock_chan(chan, &mut peer_state.pending_msg_events);
+					}
+				}
+			}
+		}
+
+		// Any closing_signed we were waiting on the signer for will be retried here.
+		self.maybe_generate_initial_closing_signed();
+	}
+
 	/// Check whether any channels have finished removing all pending updates after a shutdown
 	/// exchange and can now send a closing_signed.
 	/// Returns whether any closing_signed messages were generated.
@@ -6314,6 +7290,334 @@ where
 		}
 	}
 
+	/// Creates an [`OfferBuilder`] such that the [`Offer`] it builds is recognized by the
+	/// [`ChannelManager`] when handling [`InvoiceRequest`] messages for the offer.
+	///
+	/// The builder will have the provided description set and the signing pubkey derived from the
+	/// [`ExpandedKey`] such that [`InvoiceRequest::verify`] succeeds. A blinded path to this node is
+	/// included if a connected peer supports onion messages; otherwise, the offer is reachable via
+	/// the node id directly.
+	///
+	/// [`InvoiceRequest`]: crate::offers::invoice_request::InvoiceRequest
+	/// [`InvoiceRequest::verify`]: crate::offers::invoice_request::InvoiceRequest::verify
+	/// [`ExpandedKey`]: inbound_payment::ExpandedKey
+	pub fn create_offer_builder(
+		&self, description: String
+	) -> OfferBuilder<DerivedMetadata, secp256k1::All> {
+		let node_id = self.get_our_node_id();
+		let expanded_key = &self.inbound_payment_key;
+		let entropy = &*self.entropy_source;
+		let secp_ctx = &self.secp_ctx;
+
+		let builder = OfferBuilder::deriving_signing_pubkey(
+			description, node_id, expanded_key, entropy, secp_ctx
+		)
+			.chain_hash(self.chain_hash());
+
+		match self.create_blinded_path() {
+			Ok(path) => builder.path(path),
+			Err(()) => builder,
+		}
+	}
+
+	/// Creates a [`RefundBuilder`] such that the [`Refund`] it builds is recognized by the
+	/// [`ChannelManager`] when handling [`Bolt12Invoice`] messages for the refund.
+	///
+	/// The builder will have the provided description, amount, and expiry set, along with a
+	/// blinded path to this node for the [`Bolt12Invoice`] to be sent over. The payer id is
+	/// derived such that [`Bolt12Invoice::verify`] succeeds and returns `payment_id`.
+	///
+	/// # Payment
+	///
+	/// The provided `payment_id` is used to ensure that only one invoice is paid for the refund.
+	/// Until an invoice is received, the payment is considered to be awaiting an invoice, as seen
+	/// in [`ChannelManager::list_recent_payments`]. If no invoice is received within a few timer
+	/// ticks of [`ChannelManager::timer_tick_occurred`], the payment is removed and an
+	/// [`Event::InvoiceRequestFailed`] is generated. It may also be removed earlier using
+	/// [`ChannelManager::abandon_payment`].
+	///
+	/// # Errors
+	///
+	/// Errors if:
+	/// - a duplicate `payment_id` is provided given the caveats in the aforementioned link,
+	/// - `amount_msats` is invalid, or
+	/// - no connected peer supports onion messages, so a blinded path cannot be created.
+	///
+	/// [`Refund`]: crate::offers::refund::Refund
+	/// [`Event::InvoiceRequestFailed`]: events::Event::InvoiceRequestFailed
+	pub fn create_refund_builder(
+		&self, description: String, amount_msats: u64, absolute_expiry: Duration,
+		payment_id: PaymentId, retry_strategy: Retry
+	) -> Result<RefundBuilder<secp256k1::All>, Bolt12SemanticError> {
+		let node_id = self.get_our_node_id();
+		let expanded_key = &self.inbound_payment_key;
+		let entropy = &*self.entropy_source;
+		let secp_ctx = &self.secp_ctx;
+		let path = self.create_blinded_path().map_err(|_| Bolt12SemanticError::MissingPaths)?;
+
+		let builder = RefundBuilder::deriving_payer_id(
+			description, node_id, expanded_key, entropy, secp_ctx, amount_msats, payment_id
+		)?
+			.chain_hash(self.chain_hash())
+			.absolute_expiry(absolute_expiry)
+			.path(path);
+
+		self.pending_outbound_payments
+			.add_new_awaiting_invoice(payment_id, retry_strategy)
+			.map_err(|_| Bolt12SemanticError::DuplicatePaymentId)?;
+
+		Ok(builder)
+	}
+
+	/// Pays for an [`Offer`] using the given parameters by creating an [`InvoiceRequest`] and
+	/// enqueuing it to be sent via an onion message. [`ChannelManager`] will pay the actual
+	/// [`Bolt12Invoice`] once it is received.
+	///
+	/// Uses [`InvoiceRequestBuilder`] such that the [`InvoiceRequest`] it builds is recognized by
+	/// the [`ChannelManager`] when handling a [`Bolt12Invoice`] message in response to the request.
+	/// The optional parameters are used in the builder, if `Some`:
+	/// - `quantity` for [`InvoiceRequest::quantity`] which must be set if
+	///   [`Offer::expects_quantity`] is `true`.
+	/// - `amount_msats` if overpaying what is required for the given `quantity` is desired.
+	///
+	/// # Payment
+	///
+	/// The provided `payment_id` is used to ensure that only one invoice is paid for the request.
+	/// Until an invoice is received, the payment is considered to be awaiting an invoice, as seen
+	/// in [`ChannelManager::list_recent_payments`]. If no invoice is received within a few timer
+	/// ticks of [`ChannelManager::timer_tick_occurred`], the payment is removed and an
+	/// [`Event::InvoiceRequestFailed`] is generated. It may also be removed earlier using
+	/// [`ChannelManager::abandon_payment`].
+	///
+	/// # Errors
+	///
+	/// Errors if:
+	/// - a duplicate `payment_id` is provided given the caveats in the aforementioned link,
+	/// - the provided parameters are invalid for the offer,
+	/// - the offer is for an unsupported chain, or
+	/// - no connected peer supports onion messages, so a reply path cannot be created.
+	///
+	/// [`InvoiceRequest`]: crate::offers::invoice_request::InvoiceRequest
+	/// [`InvoiceRequest::quantity`]: crate::offers::invoice_request::InvoiceRequest::quantity
+	/// [`InvoiceRequestBuilder`]: crate::offers::invoice_request::InvoiceRequestBuilder
+	/// [`Event::InvoiceRequestFailed`]: events::Event::InvoiceRequestFailed
+	pub fn pay_for_offer(
+		&self, offer: &Offer, quantity: Option<u64>, amount_msats: Option<u64>,
+		payment_id: PaymentId, retry_strategy: Retry
+	) -> Result<(), Bolt12SemanticError> {
+		let expanded_key = &self.inbound_payment_key;
+		let entropy = &*self.entropy_source;
+		let secp_ctx = &self.secp_ctx;
+
+		let builder = offer
+			.request_invoice_deriving_payer_id(expanded_key, entropy, secp_ctx, payment_id)?
+			.chain_hash(self.chain_hash())?;
+		let builder = match quantity {
+			None => builder,
+			Some(quantity) => builder.quantity(quantity)?,
+		};
+		let builder = match amount_msats {
+			None => builder,
+			Some(amount_msats) => builder.amount_msats(amount_msats)?,
+		};
+		let invoice_request = builder.build_and_sign()?;
+		let reply_path = self.create_blinded_path().map_err(|_| Bolt12SemanticError::MissingPaths)?;
+
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+
+		self.pending_outbound_payments
+			.add_new_awaiting_invoice(payment_id, retry_strategy)
+			.map_err(|_| Bolt12SemanticError::DuplicatePaymentId)?;
+
+		let mut pending_offers_messages = self.pending_offers_messages.lock().unwrap();
+		if offer.paths().is_empty() {
+			let message = PendingOnionMessage {
+				contents: OffersMessage::InvoiceRequest(invoice_request),
+				destination: Destination::Node(offer.signing_pubkey()),
+				reply_path: Some(reply_path),
+			};
+			pending_offers_messages.push(message);
+		} else {
+			// Send as many invoice requests as there are paths in the offer (with an upper bound).
+			// Using only one path could result in a failure if the path no longer exists. But only
+			// one invoice for a given payment id will be paid, even if more than one is received.
+			const REQUEST_LIMIT: usize = 10;
+			for path in offer.paths().iter().take(REQUEST_LIMIT) {
+				let message = PendingOnionMessage {
+					contents: OffersMessage::InvoiceRequest(invoice_request.clone()),
+					destination: Destination::BlindedPath(path.clone()),
+					reply_path: Some(reply_path.clone()),
+				};
+				pending_offers_messages.push(message);
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Creates a [`Bolt12Invoice`] for a [`Refund`] and enqueues it to be sent via an onion
+	/// message.
+	///
+	/// The resulting invoice uses a [`PaymentHash`] recognized by the [`ChannelManager`] and a
+	/// [`BlindedPath`] containing the [`PaymentSecret`] needed to reconstruct the corresponding
+	/// [`PaymentPreimage`].
+	///
+	/// # Errors
+	///
+	/// Errors if:
+	/// - the refund is for an unsupported chain,
+	/// - the refund has expired,
+	/// - the refund's amount can't be received, or
+	/// - no connected peer supports onion messages, so a reply path cannot be created.
+	pub fn request_refund_payment(&self, refund: &Refund) -> Result<(), Bolt12SemanticError> {
+		let expanded_key = &self.inbound_payment_key;
+		let entropy = &*self.entropy_source;
+		let secp_ctx = &self.secp_ctx;
+
+		if refund.chain() != self.chain_hash() {
+			return Err(Bolt12SemanticError::UnsupportedChain);
+		}
+
+		#[cfg(feature = "std")] {
+			if refund.is_expired() {
+				return Err(Bolt12SemanticError::AlreadyExpired);
+			}
+		}
+
+		let amount_msats = refund.amount_msats();
+		let relative_expiry = DEFAULT_RELATIVE_EXPIRY.as_secs() as u32;
+		let (payment_hash, payment_secret) = self
+			.create_inbound_payment(Some(amount_msats), relative_expiry, None)
+			.map_err(|()| Bolt12SemanticError::InvalidAmount)?;
+		let payment_paths = self.create_blinded_payment_paths(amount_msats, payment_secret)
+			.map_err(|()| Bolt12SemanticError::MissingPaths)?;
+
+		#[cfg(feature = "std")]
+		let builder = refund.respond_using_derived_keys(
+			payment_paths, payment_hash, expanded_key, entropy
+		)?;
+		#[cfg(not(feature = "std"))]
+		let builder = refund.respond_using_derived_keys_no_std(
+			payment_paths, payment_hash,
+			Duration::from_secs(self.highest_seen_timestamp.load(Ordering::Acquire) as u64),
+			expanded_key, entropy
+		)?;
+		let invoice = builder.allow_mpp().build_and_sign(secp_ctx)?;
+		let reply_path = self.create_blinded_path().map_err(|_| Bolt12SemanticError::MissingPaths)?;
+
+		let mut pending_offers_messages = self.pending_offers_messages.lock().unwrap();
+		if refund.paths().is_empty() {
+			let message = PendingOnionMessage {
+				contents: OffersMessage::Invoice(invoice),
+				destination: Destination::Node(refund.payer_id()),
+				reply_path: Some(reply_path),
+			};
+			pending_offers_messages.push(message);
+		} else {
+			for path in refund.paths() {
+				let message = PendingOnionMessage {
+					contents: OffersMessage::Invoice(invoice.clone()),
+					destination: Destination::BlindedPath(path.clone()),
+					reply_path: Some(reply_path.clone()),
+				};
+				pending_offers_messages.push(message);
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Creates a blinded path by delegating to [`BlindedPath::new_for_message`] using a connected
+	/// peer supporting onion messages as the introduction node.
+	///
+	/// Errors if no such peer is currently connected.
+	fn create_blinded_path(&self) -> Result<BlindedPath, ()> {
+		let recipient = self.get_our_node_id();
+		let entropy_source = &*self.entropy_source;
+		let secp_ctx = &self.secp_ctx;
+
+		let peers = self.per_peer_state.read().unwrap()
+			.iter()
+			.filter(|(_, peer)| {
+				let peer = peer.lock().unwrap();
+				peer.is_connected && peer.latest_features.supports_onion_messages()
+			})
+			.map(|(node_id, _)| *node_id)
+			.collect::<Vec<_>>();
+
+		match peers.first() {
+			Some(introduction_node_id) => BlindedPath::new_for_message(
+				&[*introduction_node_id, recipient], entropy_source, secp_ctx
+			),
+			None => Err(()),
+		}
+	}
+
+	/// Creates blinded payment paths for the given `amount_msats`, each using one of our channel
+	/// counterparties as the introduction node. Falls back to a path consisting only of us if we
+	/// have no suitable channels.
+	fn create_blinded_payment_paths(
+		&self, amount_msats: u64, payment_secret: PaymentSecret
+	) -> Result<Vec<(BlindedPayInfo, BlindedPath)>, ()> {
+		let entropy_source = &*self.entropy_source;
+		let secp_ctx = &self.secp_ctx;
+		let payee_node_id = self.get_our_node_id();
+
+		let max_cltv_expiry = self.best_block.read().unwrap().height() + CLTV_FAR_FAR_AWAY
+			+ LATENCY_GRACE_PERIOD_BLOCKS;
+		let payee_tlvs = ReceiveTlvs {
+			payment_secret,
+			payment_constraints: PaymentConstraints {
+				max_cltv_expiry,
+				htlc_minimum_msat: 1,
+			},
+		};
+
+		let paths = self.list_usable_channels().into_iter()
+			.filter(|details| details.inbound_capacity_msat >= amount_msats)
+			.filter_map(|details| {
+				let short_channel_id = details.get_inbound_payment_scid()?;
+				let forwarding_info = details.counterparty.forwarding_info?;
+				let htlc_maximum_msat = details.inbound_htlc_maximum_msat.unwrap_or(u64::max_value());
+				let forward_node = ForwardNode {
+					tlvs: ForwardTlvs {
+						short_channel_id,
+						payment_relay: PaymentRelay {
+							cltv_expiry_delta: forwarding_info.cltv_expiry_delta,
+							fee_proportional_millionths: forwarding_info.fee_proportional_millionths,
+							fee_base_msat: forwarding_info.fee_base_msat,
+						},
+						payment_constraints: PaymentConstraints {
+							max_cltv_expiry: max_cltv_expiry + forwarding_info.cltv_expiry_delta as u32,
+							htlc_minimum_msat: details.inbound_htlc_minimum_msat.unwrap_or(0),
+						},
+						features: BlindedHopFeatures::empty(),
+					},
+					node_id: details.counterparty.node_id,
+					htlc_maximum_msat,
+				};
+				BlindedPath::new_for_payment(
+					&[forward_node], payee_node_id, payee_tlvs.clone(), htlc_maximum_msat,
+					MIN_FINAL_CLTV_EXPIRY_DELTA, entropy_source, secp_ctx
+				).ok()
+			})
+			.take(MAX_BLINDED_PAYMENT_PATHS)
+			.collect::<Vec<_>>();
+
+		if !paths.is_empty() {
+			return Ok(paths);
+		}
+
+		BlindedPath::new_for_payment(
+			&[], payee_node_id, payee_tlvs, u64::max_value(), MIN_FINAL_CLTV_EXPIRY_DELTA,
+			entropy_source, secp_ctx
+		).map(|path| vec![path])
+	}
+
+	fn chain_hash(&self) -> ChainHash {
+		ChainHash::from(&self.genesis_hash[..])
+	}
+
 	/// Gets a payment secret and payment hash for use in an invoice given to a third party wishing
 	/// to pay us.
 	///
@@ -6951,6 +8255,26 @@ where
 				!payment.htlcs.is_empty() // Only retain this entry if htlcs has at least one entry.
 			});
 
+			// Similarly, give up on trampoline forwards for which we're still waiting on further
+			// parts once any of the parts we hold is about to expire. Once we've started paying the
+			// next hop we have to wait for that payment to resolve, but as its HTLCs expire before
+			// those paying us, we'll learn of its resolution in time.
+			self.pending_trampoline_forwards.lock().unwrap().retain(|payment_hash, forward| {
+				if forward.outbound_payment_id.is_some() { return true; }
+				if forward.htlcs.iter().all(|htlc| height < htlc.cltv_expiry - HTLC_FAIL_BACK_BUFFER) {
+					return true;
+				}
+				for htlc in forward.htlcs.drain(..) {
+					let mut htlc_msat_height_data = htlc.value.to_be_bytes().to_vec();
+					htlc_msat_height_data.extend_from_slice(&height.to_be_bytes());
+
+					timed_out_htlcs.push((HTLCSource::PreviousHopData(htlc.prev_hop), *payment_hash,
+						HTLCFailReason::reason(0x4000 | 15, htlc_msat_height_data),
+						HTLCDestination::FailedPayment { payment_hash: *payment_hash }));
+				}
+				false
+			});
+
 			let mut intercepted_htlcs = self.pending_intercepted_htlcs.lock().unwrap();
 			intercepted_htlcs.retain(|_, htlc| {
 				if height >= htlc.forward_info.outgoing_cltv_value - HTLC_FAIL_BACK_BUFFER {
@@ -6960,6 +8284,7 @@ where
 						incoming_packet_shared_secret: htlc.forward_info.incoming_shared_secret,
 						phantom_shared_secret: None,
 						outpoint: htlc.prev_funding_outpoint,
+						blinded_failure: htlc.forward_info.routing.blinded_failure(),
 					});
 
 					let requested_forward_scid /* intercept scid */ = match htlc.forward_info.routing {
@@ -7423,6 +8748,93 @@ where
 	}
 }
 
+impl<M: Deref, T: Deref, ES: Deref, NS: Deref, SP: Deref, F: Deref, R: Deref, L: Deref>
+OffersMessageHandler for ChannelManager<M, T, ES, NS, SP, F, R, L>
+where
+	M::Target: chain::Watch<<SP::Target as SignerProvider>::Signer>,
+	T::Target: BroadcasterInterface,
+	ES::Target: EntropySource,
+	NS::Target: NodeSigner,
+	SP::Target: SignerProvider,
+	F::Target: FeeEstimator,
+	R::Target: Router,
+	L::Target: Logger,
+{
+	fn handle_message(&self, message: OffersMessage) -> Option<OffersMessage> {
+		let secp_ctx = &self.secp_ctx;
+		let expanded_key = &self.inbound_payment_key;
+
+		match message {
+			OffersMessage::InvoiceRequest(invoice_request) => {
+				let amount_msats = match InvoiceBuilder::<DerivedSigningPubkey>::check_amount_msats(
+					&invoice_request
+				) {
+					Ok(amount_msats) => amount_msats,
+					Err(error) => return Some(OffersMessage::InvoiceError(error.into())),
+				};
+				let relative_expiry = DEFAULT_RELATIVE_EXPIRY.as_secs() as u32;
+
+				match self.create_inbound_payment(Some(amount_msats), relative_expiry, None) {
+					Ok((payment_hash, payment_secret)) => {
+						let payment_paths = match self.create_blinded_payment_paths(amount_msats, payment_secret) {
+							Ok(payment_paths) => payment_paths,
+							Err(()) => {
+								log_trace!(self.logger, "Failed to create blinded payment paths for invoice request");
+								return Some(OffersMessage::InvoiceError(Bolt12SemanticError::MissingPaths.into()));
+							},
+						};
+
+						#[cfg(feature = "std")]
+						let response = invoice_request.verify_and_respond_using_derived_keys(
+							payment_paths, payment_hash, expanded_key, secp_ctx
+						);
+						#[cfg(not(feature = "std"))]
+						let response = invoice_request.verify_and_respond_using_derived_keys_no_std(
+							payment_paths, payment_hash,
+							Duration::from_secs(self.highest_seen_timestamp.load(Ordering::Acquire) as u64),
+							expanded_key, secp_ctx
+						);
+
+						match response.and_then(|builder| builder.allow_mpp().build_and_sign(secp_ctx)) {
+							Ok(invoice) => Some(OffersMessage::Invoice(invoice)),
+							Err(error) => Some(OffersMessage::InvoiceError(error.into())),
+						}
+					},
+					Err(()) => {
+						Some(OffersMessage::InvoiceError(Bolt12SemanticError::InvalidAmount.into()))
+					},
+				}
+			},
+			OffersMessage::Invoice(invoice) => {
+				match invoice.verify(expanded_key, secp_ctx) {
+					Err(()) => {
+						Some(OffersMessage::InvoiceError(InvoiceError::from_string("Unrecognized invoice".to_owned())))
+					},
+					Ok(_) if invoice.features().requires_unknown_bits() => {
+						Some(OffersMessage::InvoiceError(Bolt12SemanticError::UnknownRequiredFeatures.into()))
+					},
+					Ok(payment_id) => {
+						if let Err(e) = self.send_payment_for_bolt12_invoice(&invoice, payment_id) {
+							log_trace!(self.logger, "Failed paying invoice: {:?}", e);
+							Some(OffersMessage::InvoiceError(InvoiceError::from_string(format!("{:?}", e))))
+						} else {
+							None
+						}
+					},
+				}
+			},
+			OffersMessage::InvoiceError(invoice_error) => {
+				log_trace!(self.logger, "Received invoice_error: {}", invoice_error);
+				None
+			},
+		}
+	}
+
+	fn release_pending_messages(&self) -> Vec<PendingOnionMessage<OffersMessage>> {
+		core::mem::take(&mut self.pending_offers_messages.lock().unwrap())
+	}
+}
+
 /// Fetches the set of [`NodeFeatures`] flags which are provided by or required by
 /// [`ChannelManager`].
 pub(crate) fn provided_node_features(config: &UserConfig) -> NodeFeatures {
@@ -7614,9 +9026,20 @@ impl_writeable_tlv_based!(PhantomRouteHints, {
 	(6, real_node_pubkey, required),
 });
 
+impl_writeable_tlv_based!(BlindedForward, {
+	(0, inbound_blinding_point, required),
+	(2, failure, required),
+});
+
+impl_writeable_tlv_based_enum!(BlindedFailure,
+	(0, FromIntroductionNode) => {},
+	(2, FromBlindedNode) => {}, ;
+);
+
 impl_writeable_tlv_based_enum!(PendingHTLCRouting,
 	(0, Forward) => {
 		(0, onion_packet, required),
+		(1, blinded, option),
 		(2, short_channel_id, required),
 	},
 	(1, Receive) => {
@@ -7624,6 +9047,7 @@ impl_writeable_tlv_based_enum!(PendingHTLCRouting,
 		(1, phantom_shared_secret, option),
 		(2, incoming_cltv_expiry, required),
 		(3, payment_metadata, option),
+		(5, blinded_failure, option),
 	},
 	(2, ReceiveKeysend) => {
 		(0, payment_preimage, required),
@@ -7631,6 +9055,14 @@ impl_writeable_tlv_based_enum!(PendingHTLCRouting,
 		(3, payment_metadata, option),
 		(4, payment_data, option), // Added in 0.0.116
 	},
+	// Note that prior versions fail to read this variant, and thus the `ChannelManager`, while such
+	// an HTLC is pending, see `UserConfig::accept_trampoline_forwards`.
+	(4, TrampolineForward) => {
+		(0, payment_data, required),
+		(2, outgoing_node_id, required),
+		(4, trampoline_packet, required),
+		(6, incoming_cltv_expiry, required),
+	},
 ;);
 
 impl_writeable_tlv_based!(PendingHTLCInfo, {
@@ -7720,6 +9152,7 @@ impl_writeable_tlv_based!(HTLCPreviousHopData, {
 	(0, short_channel_id, required),
 	(1, phantom_shared_secret, option),
 	(2, outpoint, required),
+	(3, blinded_failure, option),
 	(4, htlc_id, required),
 	(6, incoming_packet_shared_secret, required)
 });
@@ -7875,13 +9308,68 @@ impl_writeable_tlv_based!(PendingAddHTLCInfo, {
 	(6, prev_funding_outpoint, required),
 });
 
-impl_writeable_tlv_based_enum!(HTLCForwardInfo,
-	(1, FailHTLC) => {
-		(0, htlc_id, required),
-		(2, err_packet, required),
-	};
-	(0, AddHTLC)
-);
+impl Writeable for HTLCForwardInfo {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		const FAIL_HTLC_VARIANT_ID: u8 = 1;
+		match self {
+			Self::AddHTLC(info) => {
+				0u8.write(w)?;
+				info.write(w)?;
+			},
+			Self::FailHTLC { htlc_id, err_packet } => {
+				FAIL_HTLC_VARIANT_ID.write(w)?;
+				write_tlv_fields!(w, {
+					(0, htlc_id, required),
+					(2, err_packet, required),
+				});
+			},
+			Self::FailMalformedHTLC { htlc_id, failure_code, sha256_of_onion } => {
+				// Write this as `::FailHTLC` with an empty error packet so that prior versions have
+				// something to fail back with, but serialize the real data as odd TLVs for the
+				// benefit of newer versions.
+				FAIL_HTLC_VARIANT_ID.write(w)?;
+				let dummy_err_packet = msgs::OnionErrorPacket { data: Vec::new() };
+				write_tlv_fields!(w, {
+					(0, htlc_id, required),
+					(1, failure_code, required),
+					(2, dummy_err_packet, required),
+					(3, sha256_of_onion, required),
+				});
+			},
+		}
+		Ok(())
+	}
+}
+
+impl Readable for HTLCForwardInfo {
+	fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
+		let id: u8 = Readable::read(r)?;
+		Ok(match id {
+			0 => Self::AddHTLC(Readable::read(r)?),
+			1 => {
+				_init_and_read_tlv_fields!(r, {
+					(0, htlc_id, required),
+					(1, malformed_htlc_failure_code, option),
+					(2, err_packet, required),
+					(3, sha256_of_onion, option),
+				});
+				if let Some(failure_code) = malformed_htlc_failure_code {
+					Self::FailMalformedHTLC {
+						htlc_id: _init_tlv_based_struct_field!(htlc_id, required),
+						failure_code,
+						sha256_of_onion: sha256_of_onion.ok_or(DecodeError::InvalidValue)?,
+					}
+				} else {
+					Self::FailHTLC {
+						htlc_id: _init_tlv_based_struct_field!(htlc_id, required),
+						err_packet: _init_tlv_based_struct_field!(err_packet, required),
+					}
+				}
+			},
+			_ => return Err(DecodeError::InvalidValue),
+		})
+	}
+}
 
 impl_writeable_tlv_based!(PendingInboundPayment, {
 	(0, payment_secret, required),
@@ -8054,6 +9542,7 @@ where
 						session_priv.write(writer)?;
 					}
 				}
+				PendingOutboundPayment::AwaitingInvoice { .. } => {},
 				PendingOutboundPayment::Fulfilled { .. } => {},
 				PendingOutboundPayment::Abandoned { .. } => {},
 			}
@@ -8077,6 +9566,12 @@ where
 			pending_intercepted_htlcs = Some(our_pending_intercepts);
 		}
 
+		let mut pending_trampoline_forwards = None;
+		let our_pending_trampoline_forwards = self.pending_trampoline_forwards.lock().unwrap();
+		if our_pending_trampoline_forwards.len() != 0 {
+			pending_trampoline_forwards = Some(our_pending_trampoline_forwards);
+		}
+
 		let mut pending_claiming_payments = Some(&claimable_payments.pending_claiming_payments);
 		if pending_claiming_payments.as_ref().unwrap().is_empty() {
 			// LDK versions prior to 0.0.113 do not know how to read the pending claimed payments
@@ -8107,6 +9602,10 @@ where
 			(10, in_flight_monitor_updates, option),
 			(11, self.probing_cookie_secret, required),
 			(13, htlc_onion_fields, optional_vec),
+			// Even, as prior versions would otherwise forget the HTLCs we hold, leaving them to
+			// time out on-chain. Only written if we have pending forwards, so that we can still be
+			// downgraded otherwise.
+			(14, pending_trampoline_forwards, option),
 		});
 
 		Ok(())
@@ -8541,6 +10040,7 @@ where
 		let mut monitor_update_blocked_actions_per_peer: Option<Vec<(_, BTreeMap<_, Vec<_>>)>> = Some(Vec::new());
 		let mut events_override = None;
 		let mut in_flight_monitor_updates: Option<HashMap<(PublicKey, OutPoint), Vec<ChannelMonitorUpdate>>> = None;
+		let mut pending_trampoline_forwards: Option<HashMap<PaymentHash, PendingTrampolineForward>> = Some(HashMap::new());
 		read_tlv_fields!(reader, {
 			(1, pending_outbound_payments_no_retry, option),
 			(2, pending_intercepted_htlcs, option),
@@ -8554,6 +10054,7 @@ where
 			(10, in_flight_monitor_updates, option),
 			(11, probing_cookie_secret, option),
 			(13, claimable_htlc_onion_fields, optional_vec),
+			(14, pending_trampoline_forwards, option),
 		});
 		if fake_scid_rand_bytes.is_none() {
 			fake_scid_rand_bytes = Some(args.entropy_source.get_secure_random_bytes());
@@ -8696,6 +10197,9 @@ where
 		// should ensure we try them again on the inbound edge. We put them here and do so after we
 		// have a fully-constructed `ChannelManager` at the end.
 		let mut pending_claims_to_replay = Vec::new();
+		// Similarly, outbound payments which we were making as a trampoline node may have been
+		// claimed, in which case we need to claim the inbound HTLCs paying us.
+		let mut trampoline_claims_to_replay = Vec::new();
 
 		{
 			// If we're tracking pending payments, ensure we haven't lost any by looking at the
@@ -8797,6 +10301,7 @@ where
 									// `ChannelMonitor` is removed.
 									pending_outbounds.claim_htlc(payment_id, preimage, session_priv, path, false, &pending_events, &args.logger);
 									pending_events_read = pending_events.into_inner().unwrap();
+									trampoline_claims_to_replay.push((payment_id, preimage));
 								}
 							},
 						}
@@ -9049,6 +10554,7 @@ where
 
 			forward_htlcs: Mutex::new(forward_htlcs),
 			claimable_payments: Mutex::new(ClaimablePayments { claimable_payments, pending_claiming_payments: pending_claiming_payments.unwrap() }),
+			pending_trampoline_forwards: Mutex::new(pending_trampoline_forwards.unwrap()),
 			outbound_scid_aliases: Mutex::new(outbound_scid_aliases),
 			id_to_peer: Mutex::new(id_to_peer),
 			short_to_chan_info: FairRwLock::new(short_to_chan_info),
@@ -9066,6 +10572,7 @@ where
 			pending_events: Mutex::new(pending_events_read),
 			pending_events_processor: AtomicBool::new(false),
 			pending_background_events: Mutex::new(pending_background_events),
+			pending_offers_messages: Mutex::new(Vec::new()),
 			total_consistency_lock: RwLock::new(()),
 			background_events_processed_since_startup: AtomicBool::new(false),
 			persistence_notifier: Notifier::new(),
@@ -9093,6 +10600,10 @@ where
 				downstream_closed, downstream_chan_id);
 		}
 
+		for (payment_id, preimage) in trampoline_claims_to_replay {
+			channel_manager.claim_trampoline_forward(payment_id, preimage);
+		}
+
 		//TODO: Broadcast channel update for closed channels, but only after we've made a
 		//connection or two.
 
@@ -10259,6 +11770,38 @@ mod tests {
 		let events = nodes[0].node.get_and_clear_pending_msg_events();
 		assert_eq!(events.len(), 0);
 	}
+
+	#[test]
+	fn fail_malformed_htlc_forward_info_ser() {
+		use crate::ln::channelmanager::HTLCForwardInfo;
+		use crate::ln::onion_utils::INVALID_ONION_BLINDING;
+		use crate::util::ser::{Readable, Writeable};
+
+		let malformed = HTLCForwardInfo::FailMalformedHTLC {
+			htlc_id: 42, failure_code: INVALID_ONION_BLINDING, sha256_of_onion: [1; 32],
+		};
+		let encoded = malformed.encode();
+		match HTLCForwardInfo::read(&mut &encoded[..]).unwrap() {
+			HTLCForwardInfo::FailMalformedHTLC { htlc_id, failure_code, sha256_of_onion } => {
+				assert_eq!(htlc_id, 42);
+				assert_eq!(failure_code, INVALID_ONION_BLINDING);
+				assert_eq!(sha256_of_onion, [1; 32]);
+			},
+			_ => panic!("Unexpected forward info"),
+		}
+
+		// Failures without the odd malformed TLVs are still read as `FailHTLC`s.
+		let fail = HTLCForwardInfo::FailHTLC {
+			htlc_id: 42, err_packet: msgs::OnionErrorPacket { data: vec![2; 32] },
+		};
+		match HTLCForwardInfo::read(&mut &fail.encode()[..]).unwrap() {
+			HTLCForwardInfo::FailHTLC { htlc_id, err_packet } => {
+				assert_eq!(htlc_id, 42);
+				assert_eq!(err_packet.data, vec![2; 32]);
+			},
+			_ => panic!("Unexpected forward info"),
+		}
+	}
 }
 
 #[cfg(ldk_bench)]
@@ -10279,7 +11822,7 @@ pub mod bench {
 	use bitcoin::hashes::sha256::Hash as Sha256;
 	use bitcoin::{Block, BlockHeader, PackedLockTime, Transaction, TxMerkleNode, TxOut};
 
-	use crate::sync::{Arc, Mutex};
+	use crate::sync::{Arc, Mutex, RwLock};
 
 	use criterion::Criterion;
 
@@ -10316,7 +11859,7 @@ pub mod bench {
 		let tx_broadcaster = test_utils::TestBroadcaster::new(network);
 		let fee_estimator = test_utils::TestFeeEstimator { sat_per_kw: Mutex::new(253) };
 		let logger_a = test_utils::TestLogger::with_id("node a".to_owned());
-		let scorer = Mutex::new(test_utils::TestScorer::new());
+		let scorer = RwLock::new(test_utils::TestScorer::new());
 		let router = test_utils::TestRouter::new(Arc::new(NetworkGraph::new(network, &logger_a)), &scorer);
 
 		let mut config: UserConfig = Default::default();
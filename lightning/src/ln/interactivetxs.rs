@@ -0,0 +1,1027 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Logic for the interactive construction of a transaction between two peers, as used by the V2
+//! channel establishment protocol (dual-funding).
+//!
+//! Both peers alternate in sending `tx_add_input`, `tx_add_output`, `tx_remove_input` and
+//! `tx_remove_output` messages, each one being acknowledged by the other side's next message, until
+//! both peers consecutively send `tx_complete`. At that point the transaction is fully determined
+//! and each side checks that the other paid its share of the fees.
+//!
+//! Note that this is only groundwork for dual-funded channels: the negotiation state machine on
+//! its own. It is not yet driven by [`ChannelManager`], which does not support V2 channel
+//! establishment and rejects `open_channel2`, `accept_channel2` and the interactive transaction
+//! messages. There is no event for contributing inputs to an inbound channel yet either.
+//!
+//! [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+
+use core::ops::Deref;
+
+use bitcoin::blockdata::script::Script;
+use bitcoin::blockdata::transaction::{OutPoint, Transaction, TxIn, TxOut};
+use bitcoin::PackedLockTime;
+
+use crate::ln::channel::TOTAL_BITCOIN_SUPPLY_SATOSHIS;
+use crate::ln::msgs;
+use crate::sign::EntropySource;
+use crate::util::ser::TransactionU16LenLimited;
+
+use crate::prelude::*;
+
+/// The number of received `tx_add_input` messages during a negotiation at which point the
+/// negotiation MUST be failed.
+const MAX_RECEIVED_TX_ADD_INPUT_COUNT: u16 = 4096;
+
+/// The number of received `tx_add_output` messages during a negotiation at which point the
+/// negotiation MUST be failed.
+const MAX_RECEIVED_TX_ADD_OUTPUT_COUNT: u16 = 4096;
+
+/// The number of inputs or outputs that the state machine can have, before it MUST fail the
+/// negotiation.
+const MAX_INPUTS_OUTPUTS_COUNT: usize = 252;
+
+/// The maximum weight of a transaction which will still be relayed by default.
+const MAX_STANDARD_TX_WEIGHT: u64 = 400_000;
+
+/// The weight of the transaction fields which are paid for by the initiator: version, locktime,
+/// input and output counts as well as the segwit marker and flag.
+const TX_COMMON_FIELDS_WEIGHT: u64 = (4 /* version */ + 4 /* locktime */ + 1 /* input count */ +
+	1 /* output count */) * 4 /* WITNESS_SCALE_FACTOR */ + 2 /* segwit marker + flag */;
+
+/// The weight of an input, excluding its witness: the previous outpoint, the sequence and an empty
+/// `scriptSig`.
+const BASE_INPUT_WEIGHT: u64 = (32 /* txid */ + 4 /* vout */ + 4 /* sequence */ + 1 /* script len */) * 4;
+
+/// Lower bounds on the witness weight of inputs spending the given output types.
+const P2WPKH_WITNESS_WEIGHT_LOWER_BOUND: u64 = 1 /* num stack items */ + 1 /* sig len */ +
+	72 /* sig */ + 1 /* pubkey len */ + 33 /* pubkey */;
+const P2TR_KEY_PATH_WITNESS_WEIGHT_LOWER_BOUND: u64 = 1 /* num stack items */ + 1 /* sig len */ +
+	64 /* sig */;
+const UNKNOWN_WITNESS_WEIGHT_LOWER_BOUND: u64 = P2TR_KEY_PATH_WITNESS_WEIGHT_LOWER_BOUND;
+
+type SerialId = u64;
+
+trait SerialIdExt {
+	fn is_for_initiator(&self) -> bool;
+	fn is_for_non_initiator(&self) -> bool;
+}
+
+impl SerialIdExt for SerialId {
+	fn is_for_initiator(&self) -> bool { self % 2 == 0 }
+	fn is_for_non_initiator(&self) -> bool { !self.is_for_initiator() }
+}
+
+/// The reason an interactive transaction negotiation was aborted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum AbortReason {
+	/// The counterparty sent a message which is not valid in the current negotiation state.
+	UnexpectedCounterpartyMessage,
+	/// The counterparty sent more than [`MAX_RECEIVED_TX_ADD_INPUT_COUNT`] `tx_add_input`s.
+	ReceivedTooManyTxAddInputs,
+	/// The counterparty sent more than [`MAX_RECEIVED_TX_ADD_OUTPUT_COUNT`] `tx_add_output`s.
+	ReceivedTooManyTxAddOutputs,
+	/// An input's `nSequence` does not signal replaceability.
+	IncorrectInputSequenceValue,
+	/// A serial ID with the parity of the wrong peer was used.
+	IncorrectSerialIdParity,
+	/// A serial ID to be removed was never added.
+	SerialIdUnknown,
+	/// A serial ID was used more than once.
+	DuplicateSerialId,
+	/// The `prevtx_out` of a `tx_add_input` is out of bounds, not a SegWit output, or spent twice.
+	PrevTxOutInvalid,
+	/// An output's value exceeds the total bitcoin supply.
+	ExceededMaximumSatsAllowed,
+	/// The constructed transaction has more than [`MAX_INPUTS_OUTPUTS_COUNT`] inputs or outputs.
+	ExceededNumberOfInputsOrOutputs,
+	/// The constructed transaction is larger than [`MAX_STANDARD_TX_WEIGHT`].
+	TransactionTooLarge,
+	/// An output's value is below the dust limit for its script.
+	BelowDustLimit,
+	/// An output's script is not a standard SegWit script.
+	InvalidOutputScript,
+	/// The counterparty did not contribute enough to pay for the fees of its inputs and outputs.
+	InsufficientFees,
+	/// The counterparty's outputs are worth more than its inputs.
+	OutputsValueExceedsInputsValue,
+	/// The constructed transaction does not contain the expected shared funding output.
+	MissingFundingOutput,
+}
+
+impl AbortReason {
+	/// Builds the `tx_abort` message to send to our counterparty for this reason.
+	pub fn into_tx_abort_msg(self, channel_id: [u8; 32]) -> msgs::TxAbort {
+		msgs::TxAbort { channel_id, data: format!("{:?}", self).into_bytes() }
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct TxInputWithPrevOutput {
+	input: TxIn,
+	prev_output: TxOut,
+}
+
+/// Estimates the weight of an input spending `prev_output`, using a lower bound for its witness.
+fn estimate_input_weight(prev_output: &TxOut) -> u64 {
+	BASE_INPUT_WEIGHT + if prev_output.script_pubkey.is_v0_p2wpkh() {
+		P2WPKH_WITNESS_WEIGHT_LOWER_BOUND
+	} else if prev_output.script_pubkey.is_v1_p2tr() {
+		P2TR_KEY_PATH_WITNESS_WEIGHT_LOWER_BOUND
+	} else {
+		UNKNOWN_WITNESS_WEIGHT_LOWER_BOUND
+	}
+}
+
+fn output_weight(output: &TxOut) -> u64 {
+	(8 /* value */ + 1 /* script len */ + output.script_pubkey.len() as u64) * 4
+}
+
+fn fee_for_weight(feerate_sat_per_kw: u32, weight: u64) -> u64 {
+	(feerate_sat_per_kw as u64 * weight + 999) / 1000
+}
+
+#[derive(Clone, Debug)]
+struct NegotiationContext {
+	holder_is_initiator: bool,
+	received_tx_add_input_count: u16,
+	received_tx_add_output_count: u16,
+	inputs: HashMap<SerialId, TxInputWithPrevOutput>,
+	prevtx_outpoints: HashSet<OutPoint>,
+	outputs: HashMap<SerialId, TxOut>,
+	tx_locktime: PackedLockTime,
+	feerate_sat_per_kw: u32,
+	/// The script of the shared output being funded, which must be added by the initiator.
+	funding_output_script: Script,
+	/// The value the holder contributes to the shared funding output.
+	holder_funding_satoshis: u64,
+	/// The value the counterparty contributes to the shared funding output.
+	counterparty_funding_satoshis: u64,
+}
+
+impl NegotiationContext {
+	fn is_serial_id_valid_for_counterparty(&self, serial_id: &SerialId) -> bool {
+		// A received `SerialId`'s parity must match the role of the counterparty.
+		self.holder_is_initiator == serial_id.is_for_non_initiator()
+	}
+
+	fn counterparty_inputs_contributed(&self) -> impl Iterator<Item = &TxInputWithPrevOutput> + Clone {
+		self.inputs.iter()
+			.filter(move |(serial_id, _)| self.is_serial_id_valid_for_counterparty(serial_id))
+			.map(|(_, input_with_prevout)| input_with_prevout)
+	}
+
+	fn counterparty_outputs_contributed(&self) -> impl Iterator<Item = &TxOut> + Clone {
+		self.outputs.iter()
+			.filter(move |(serial_id, _)| self.is_serial_id_valid_for_counterparty(serial_id))
+			.map(|(_, output)| output)
+	}
+
+	fn is_funding_output(&self, output: &TxOut) -> bool {
+		output.script_pubkey == self.funding_output_script &&
+			output.value == self.holder_funding_satoshis + self.counterparty_funding_satoshis
+	}
+
+	fn received_tx_add_input(&mut self, msg: &msgs::TxAddInput) -> Result<(), AbortReason> {
+		// The interactive-txs spec calls for us to fail negotiation if the `prevtx` we receive is
+		// invalid. However, we would not need to account for this explicit negotiation failure
+		// mode here since `PeerManager` would already disconnect the peer if the `prevtx` is
+		// invalid; implicitly ending the negotiation.
+
+		if !self.is_serial_id_valid_for_counterparty(&msg.serial_id) {
+			// The receiving node:
+			//  - MUST fail the negotiation if:
+			//     - the `serial_id` has the wrong parity
+			return Err(AbortReason::IncorrectSerialIdParity);
+		}
+
+		self.received_tx_add_input_count += 1;
+		if self.received_tx_add_input_count > MAX_RECEIVED_TX_ADD_INPUT_COUNT {
+			// The receiving node:
+			//  - MUST fail the negotiation if:
+			//     - if has received 4096 `tx_add_input` messages during this negotiation
+			return Err(AbortReason::ReceivedTooManyTxAddInputs);
+		}
+
+		if msg.sequence >= 0xFFFFFFFE {
+			// The receiving node:
+			//  - MUST fail the negotiation if:
+			//    - `sequence` is set to `0xFFFFFFFE` or `0xFFFFFFFF`
+			return Err(AbortReason::IncorrectInputSequenceValue);
+		}
+
+		let prev_tx = msg.prevtx.as_transaction();
+		let prev_output = match prev_tx.output.get(msg.prevtx_out as usize) {
+			Some(prev_output) => {
+				if !prev_output.script_pubkey.is_witness_program() {
+					// The receiving node:
+					//  - MUST fail the negotiation if:
+					//     - the `scriptPubKey` is not a witness program
+					return Err(AbortReason::PrevTxOutInvalid);
+				}
+				prev_output.clone()
+			},
+			None => {
+				// The receiving node:
+				//  - MUST fail the negotiation if:
+				//     - `prevtx_vout` is greater or equal to the number of outputs on `prevtx`
+				return Err(AbortReason::PrevTxOutInvalid);
+			},
+		};
+
+		let prev_outpoint = OutPoint { txid: prev_tx.txid(), vout: msg.prevtx_out };
+		if self.inputs.contains_key(&msg.serial_id) {
+			// The receiving node:
+			//  - MUST fail the negotiation if:
+			//    - the `serial_id` is already included in the transaction
+			return Err(AbortReason::DuplicateSerialId);
+		}
+		if !self.prevtx_outpoints.insert(prev_outpoint) {
+			// The receiving node:
+			//  - MUST fail the negotiation if:
+			//     - the `prevtx` and `prevtx_vout` are identical to a previously added (and not
+			//     removed) input's
+			return Err(AbortReason::PrevTxOutInvalid);
+		}
+
+		self.inputs.insert(msg.serial_id, TxInputWithPrevOutput {
+			input: TxIn {
+				previous_output: prev_outpoint,
+				sequence: bitcoin::Sequence(msg.sequence),
+				..Default::default()
+			},
+			prev_output,
+		});
+		Ok(())
+	}
+
+	fn received_tx_remove_input(&mut self, msg: &msgs::TxRemoveInput) -> Result<(), AbortReason> {
+		if !self.is_serial_id_valid_for_counterparty(&msg.serial_id) {
+			return Err(AbortReason::IncorrectSerialIdParity);
+		}
+
+		match self.inputs.remove(&msg.serial_id) {
+			Some(removed) => {
+				self.prevtx_outpoints.remove(&removed.input.previous_output);
+				Ok(())
+			},
+			None => {
+				// The receiving node:
+				//  - MUST fail the negotiation if:
+				//    - the input or output identified by the `serial_id` was not added by the sender
+				//    - the `serial_id` does not correspond to a currently added input
+				Err(AbortReason::SerialIdUnknown)
+			},
+		}
+	}
+
+	fn received_tx_add_output(&mut self, msg: &msgs::TxAddOutput) -> Result<(), AbortReason> {
+		// The receiving node:
+		//  - MUST fail the negotiation if:
+		//     - the serial_id has the wrong parity
+		if !self.is_serial_id_valid_for_counterparty(&msg.serial_id) {
+			return Err(AbortReason::IncorrectSerialIdParity);
+		}
+
+		self.received_tx_add_output_count += 1;
+		if self.received_tx_add_output_count > MAX_RECEIVED_TX_ADD_OUTPUT_COUNT {
+			// The receiving node:
+			//  - MUST fail the negotiation if:
+			//     - if has received 4096 `tx_add_output` messages during this negotiation
+			return Err(AbortReason::ReceivedTooManyTxAddOutputs);
+		}
+
+		if msg.sats < msg.script.dust_value().to_sat() {
+			// The receiving node:
+			// - MUST fail the negotiation if:
+			//		- the sats amount is less than the dust_limit
+			return Err(AbortReason::BelowDustLimit);
+		}
+
+		if msg.sats > TOTAL_BITCOIN_SUPPLY_SATOSHIS {
+			// The receiving node:
+			// - MUST fail the negotiation if:
+			//		- the sats amount is greater than 2,100,000,000,000,000 (MAX_MONEY)
+			return Err(AbortReason::ExceededMaximumSatsAllowed);
+		}
+
+		// The receiving node:
+		//   - MUST accept P2WSH, P2WPKH, P2TR scripts
+		//   - MAY fail the negotiation if script is non-standard
+		if !msg.script.is_v0_p2wpkh() && !msg.script.is_v0_p2wsh() && !msg.script.is_v1_p2tr() {
+			return Err(AbortReason::InvalidOutputScript);
+		}
+
+		if self.outputs.contains_key(&msg.serial_id) {
+			// The receiving node:
+			//  - MUST fail the negotiation if:
+			//    - the `serial_id` is already included in the transaction
+			return Err(AbortReason::DuplicateSerialId);
+		}
+
+		self.outputs.insert(msg.serial_id, TxOut { value: msg.sats, script_pubkey: msg.script.clone() });
+		Ok(())
+	}
+
+	fn received_tx_remove_output(&mut self, msg: &msgs::TxRemoveOutput) -> Result<(), AbortReason> {
+		if !self.is_serial_id_valid_for_counterparty(&msg.serial_id) {
+			return Err(AbortReason::IncorrectSerialIdParity);
+		}
+		if self.outputs.remove(&msg.serial_id).is_some() {
+			Ok(())
+		} else {
+			// The receiving node:
+			//  - MUST fail the negotiation if:
+			//    - the input or output identified by the `serial_id` was not added by the sender
+			//    - the `serial_id` does not correspond to a currently added input
+			Err(AbortReason::SerialIdUnknown)
+		}
+	}
+
+	fn sent_tx_add_input(&mut self, msg: &msgs::TxAddInput) -> Result<(), AbortReason> {
+		let prev_tx = msg.prevtx.as_transaction();
+		let prev_outpoint = OutPoint { txid: prev_tx.txid(), vout: msg.prevtx_out };
+		let input = TxIn {
+			previous_output: prev_outpoint,
+			sequence: bitcoin::Sequence(msg.sequence),
+			..Default::default()
+		};
+		// Our own contributions aren't validated up front, so we may have been handed an input
+		// whose previous transaction doesn't actually contain the spent output.
+		let prev_output = match prev_tx.output.get(msg.prevtx_out as usize) {
+			Some(prev_output) => prev_output.clone(),
+			None => return Err(AbortReason::PrevTxOutInvalid),
+		};
+		self.prevtx_outpoints.insert(prev_outpoint);
+		self.inputs.insert(msg.serial_id, TxInputWithPrevOutput { input, prev_output });
+		Ok(())
+	}
+
+	fn sent_tx_add_output(&mut self, msg: &msgs::TxAddOutput) -> Result<(), AbortReason> {
+		self.outputs.insert(msg.serial_id, TxOut { value: msg.sats, script_pubkey: msg.script.clone() });
+		Ok(())
+	}
+
+	fn check_counterparty_fees(&self, counterparty_inputs_value: u64, counterparty_outputs_value: u64) -> Result<(), AbortReason> {
+		// The counterparty's contribution to the shared funding output is accounted as part of
+		// its outputs, regardless of which party actually added the output.
+		let counterparty_spent_value = counterparty_outputs_value + self.counterparty_funding_satoshis;
+		if counterparty_inputs_value < counterparty_spent_value {
+			// The receiving node:
+			//  - MUST fail the negotiation if:
+			//     - the peer's total input satoshis is less than their outputs
+			return Err(AbortReason::OutputsValueExceedsInputsValue);
+		}
+		let counterparty_fees_contributed = counterparty_inputs_value - counterparty_spent_value;
+
+		let mut counterparty_weight_contributed: u64 = self.counterparty_inputs_contributed()
+			.map(|input| estimate_input_weight(&input.prev_output))
+			.sum::<u64>() +
+			self.counterparty_outputs_contributed()
+				.filter(|output| !self.is_funding_output(output))
+				.map(|output| output_weight(output))
+				.sum::<u64>();
+		if !self.holder_is_initiator {
+			// The initiator pays for the common fields and the shared funding output.
+			counterparty_weight_contributed += TX_COMMON_FIELDS_WEIGHT +
+				output_weight(&TxOut { value: 0, script_pubkey: self.funding_output_script.clone() });
+		}
+
+		// The receiving node:
+		//  - MUST fail the negotiation if:
+		//     - the peer's paid feerate does not meet or exceed the agreed feerate
+		if counterparty_fees_contributed < fee_for_weight(self.feerate_sat_per_kw, counterparty_weight_contributed) {
+			return Err(AbortReason::InsufficientFees);
+		}
+		Ok(())
+	}
+
+	fn build_transaction(self) -> Result<Transaction, AbortReason> {
+		// The receiving node:
+		// MUST fail the negotiation if:
+
+		// - there are more than 252 inputs or outputs
+		if self.inputs.len() > MAX_INPUTS_OUTPUTS_COUNT || self.outputs.len() > MAX_INPUTS_OUTPUTS_COUNT {
+			return Err(AbortReason::ExceededNumberOfInputsOrOutputs);
+		}
+
+		// - the shared funding output is missing or added more than once
+		if self.outputs.values().filter(|output| self.is_funding_output(output)).count() != 1 {
+			return Err(AbortReason::MissingFundingOutput);
+		}
+
+		// - the peer's total input satoshis is less than their outputs, or the peer's paid
+		//   feerate does not meet or exceed the agreed feerate
+		let counterparty_inputs_value: u64 = self.counterparty_inputs_contributed()
+			.map(|input| input.prev_output.value).sum();
+		let counterparty_outputs_value: u64 = self.counterparty_outputs_contributed()
+			.filter(|output| !self.is_funding_output(output))
+			.map(|output| output.value).sum();
+		self.check_counterparty_fees(counterparty_inputs_value, counterparty_outputs_value)?;
+
+		// - the estimated weight of the transaction is above MAX_STANDARD_TX_WEIGHT
+		let estimated_weight = TX_COMMON_FIELDS_WEIGHT +
+			self.inputs.values().map(|input| estimate_input_weight(&input.prev_output)).sum::<u64>() +
+			self.outputs.values().map(|output| output_weight(output)).sum::<u64>();
+		if estimated_weight > MAX_STANDARD_TX_WEIGHT {
+			return Err(AbortReason::TransactionTooLarge);
+		}
+
+		// Inputs and outputs are ordered by their serial IDs.
+		let mut inputs: Vec<(SerialId, TxInputWithPrevOutput)> = self.inputs.into_iter().collect();
+		inputs.sort_unstable_by_key(|(serial_id, _)| *serial_id);
+		let mut outputs: Vec<(SerialId, TxOut)> = self.outputs.into_iter().collect();
+		outputs.sort_unstable_by_key(|(serial_id, _)| *serial_id);
+
+		Ok(Transaction {
+			version: 2,
+			lock_time: self.tx_locktime,
+			input: inputs.into_iter().map(|(_, input)| input.input).collect(),
+			output: outputs.into_iter().map(|(_, output)| output).collect(),
+		})
+	}
+}
+
+/// The state of an interactive transaction negotiation.
+///
+/// Each side keeps track of whose turn it is to send a message: after we send a message we wait
+/// for the counterparty's next message and vice versa. The negotiation completes once both sides
+/// send consecutive `tx_complete` messages.
+#[derive(Debug)]
+enum StateMachine {
+	/// Transient state while a transition is being processed.
+	Indeterminate,
+	/// We sent a `tx_add_*`/`tx_remove_*` message and are waiting on the counterparty.
+	SentChangeMsg(NegotiationContext),
+	/// We received a `tx_add_*`/`tx_remove_*` message and need to respond.
+	ReceivedChangeMsg(NegotiationContext),
+	/// We sent a `tx_complete`, completing the negotiation if the counterparty responds in kind.
+	SentTxComplete(NegotiationContext),
+	/// We received a `tx_complete`, completing the negotiation if we have nothing more to add.
+	ReceivedTxComplete(NegotiationContext),
+	/// Both sides sent `tx_complete` and the transaction passed all checks.
+	NegotiationComplete(Transaction),
+	/// The negotiation failed and the counterparty should be sent a `tx_abort`.
+	NegotiationAborted(AbortReason),
+}
+
+macro_rules! define_received_change_transition {
+	($fn_name: ident, $msg_type: ty, $context_fn: ident) => {
+		fn $fn_name(&mut self, msg: &$msg_type) -> Result<(), AbortReason> {
+			let mut context = match core::mem::replace(self, StateMachine::Indeterminate) {
+				StateMachine::SentChangeMsg(context) | StateMachine::SentTxComplete(context) => context,
+				_ => return self.abort(AbortReason::UnexpectedCounterpartyMessage),
+			};
+			match context.$context_fn(msg) {
+				Ok(()) => {
+					*self = StateMachine::ReceivedChangeMsg(context);
+					Ok(())
+				},
+				Err(abort_reason) => self.abort(abort_reason),
+			}
+		}
+	};
+}
+
+impl StateMachine {
+	fn new(
+		feerate_sat_per_kw: u32, is_initiator: bool, tx_locktime: PackedLockTime,
+		funding_output_script: Script, holder_funding_satoshis: u64, counterparty_funding_satoshis: u64,
+	) -> Self {
+		let context = NegotiationContext {
+			holder_is_initiator: is_initiator,
+			received_tx_add_input_count: 0,
+			received_tx_add_output_count: 0,
+			inputs: HashMap::new(),
+			prevtx_outpoints: HashSet::new(),
+			outputs: HashMap::new(),
+			tx_locktime,
+			feerate_sat_per_kw,
+			funding_output_script,
+			holder_funding_satoshis,
+			counterparty_funding_satoshis,
+		};
+		// The initiator sends the first message, so it starts out as if it had just received one,
+		// and vice versa.
+		if is_initiator {
+			Self::ReceivedChangeMsg(context)
+		} else {
+			Self::SentChangeMsg(context)
+		}
+	}
+
+	fn abort(&mut self, reason: AbortReason) -> Result<(), AbortReason> {
+		*self = StateMachine::NegotiationAborted(reason);
+		Err(reason)
+	}
+
+	define_received_change_transition!(received_tx_add_input, msgs::TxAddInput, received_tx_add_input);
+	define_received_change_transition!(received_tx_remove_input, msgs::TxRemoveInput, received_tx_remove_input);
+	define_received_change_transition!(received_tx_add_output, msgs::TxAddOutput, received_tx_add_output);
+	define_received_change_transition!(received_tx_remove_output, msgs::TxRemoveOutput, received_tx_remove_output);
+
+	fn sent_change_msg<F: FnOnce(&mut NegotiationContext) -> Result<(), AbortReason>>(
+		&mut self, update_context: F,
+	) -> Result<(), AbortReason> {
+		let mut context = match core::mem::replace(self, StateMachine::Indeterminate) {
+			StateMachine::ReceivedChangeMsg(context) | StateMachine::ReceivedTxComplete(context) => context,
+			_ => {
+				debug_assert!(false, "We should never send a message out of turn");
+				return self.abort(AbortReason::UnexpectedCounterpartyMessage);
+			},
+		};
+		if let Err(abort_reason) = update_context(&mut context) {
+			return self.abort(abort_reason);
+		}
+		*self = StateMachine::SentChangeMsg(context);
+		Ok(())
+	}
+
+	fn sent_tx_complete(&mut self) -> Result<(), AbortReason> {
+		match core::mem::replace(self, StateMachine::Indeterminate) {
+			StateMachine::ReceivedChangeMsg(context) => {
+				*self = StateMachine::SentTxComplete(context);
+				Ok(())
+			},
+			StateMachine::ReceivedTxComplete(context) => self.complete(context),
+			_ => {
+				debug_assert!(false, "We should never send a message out of turn");
+				self.abort(AbortReason::UnexpectedCounterpartyMessage)
+			},
+		}
+	}
+
+	fn received_tx_complete(&mut self) -> Result<(), AbortReason> {
+		match core::mem::replace(self, StateMachine::Indeterminate) {
+			StateMachine::SentChangeMsg(context) => {
+				*self = StateMachine::ReceivedTxComplete(context);
+				Ok(())
+			},
+			StateMachine::SentTxComplete(context) => self.complete(context),
+			_ => self.abort(AbortReason::UnexpectedCounterpartyMessage),
+		}
+	}
+
+	fn complete(&mut self, context: NegotiationContext) -> Result<(), AbortReason> {
+		match context.build_transaction() {
+			Ok(tx) => {
+				*self = StateMachine::NegotiationComplete(tx);
+				Ok(())
+			},
+			Err(abort_reason) => self.abort(abort_reason),
+		}
+	}
+}
+
+/// A message to send to our counterparty as part of an interactive transaction negotiation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum InteractiveTxMessageSend {
+	TxAddInput(msgs::TxAddInput),
+	TxAddOutput(msgs::TxAddOutput),
+	TxComplete(msgs::TxComplete),
+}
+
+/// Drives an interactive transaction negotiation with a counterparty, contributing the given
+/// inputs and outputs and validating the counterparty's contributions.
+///
+/// Each `handle_*` method returns the message which should be sent to the counterparty in
+/// response. Once both peers have sent `tx_complete`, [`Self::handle_tx_complete`] returns the
+/// negotiated (unsigned) transaction. If any method returns an [`AbortReason`], the negotiation is
+/// over and the counterparty should be sent a `tx_abort`.
+pub(crate) struct InteractiveTxConstructor {
+	state_machine: StateMachine,
+	channel_id: [u8; 32],
+	inputs_to_contribute: Vec<(SerialId, TxIn, TransactionU16LenLimited)>,
+	outputs_to_contribute: Vec<(SerialId, TxOut)>,
+}
+
+impl InteractiveTxConstructor {
+	/// Instantiates a new `InteractiveTxConstructor`.
+	///
+	/// The `funding_output_script` is the script of the shared output, whose value must be the sum
+	/// of `holder_funding_satoshis` and `counterparty_funding_satoshis`. If we are the initiator it
+	/// is contributed automatically, otherwise the counterparty must add it.
+	///
+	/// If the holder is the initiator, they need to send the first message which is a `TxAddInput`
+	/// message. If no message is returned for an initiator, one of the inputs to contribute spends
+	/// an output its previous transaction doesn't have and the negotiation was aborted.
+	pub fn new<ES: Deref>(
+		entropy_source: &ES, channel_id: [u8; 32], feerate_sat_per_kw: u32, is_initiator: bool,
+		funding_tx_locktime: PackedLockTime, funding_output_script: Script,
+		holder_funding_satoshis: u64, counterparty_funding_satoshis: u64,
+		inputs_to_contribute: Vec<(TxIn, TransactionU16LenLimited)>, outputs_to_contribute: Vec<TxOut>,
+	) -> (Self, Option<InteractiveTxMessageSend>)
+	where ES::Target: EntropySource,
+	{
+		let state_machine = StateMachine::new(feerate_sat_per_kw, is_initiator, funding_tx_locktime,
+			funding_output_script.clone(), holder_funding_satoshis, counterparty_funding_satoshis);
+
+		let mut outputs_to_contribute = outputs_to_contribute;
+		if is_initiator {
+			outputs_to_contribute.push(TxOut {
+				value: holder_funding_satoshis + counterparty_funding_satoshis,
+				script_pubkey: funding_output_script,
+			});
+		}
+
+		// We pop contributions off the back of the vectors, so reverse them to preserve the
+		// order we were given them in.
+		let mut inputs_to_contribute: Vec<(SerialId, TxIn, TransactionU16LenLimited)> = inputs_to_contribute
+			.into_iter()
+			.map(|(input, tx)| (Self::generate_local_serial_id(entropy_source, is_initiator), input, tx))
+			.collect();
+		inputs_to_contribute.reverse();
+		let mut outputs_to_contribute: Vec<(SerialId, TxOut)> = outputs_to_contribute
+			.into_iter()
+			.map(|output| (Self::generate_local_serial_id(entropy_source, is_initiator), output))
+			.collect();
+		outputs_to_contribute.reverse();
+
+		let mut constructor = Self { state_machine, channel_id, inputs_to_contribute, outputs_to_contribute };
+		let message_send = if is_initiator {
+			match constructor.maybe_send_message() {
+				Ok(message_send) => Some(message_send),
+				// Our first input may be invalid, in which case the negotiation is aborted before
+				// it started, which callers can check with `is_aborted`.
+				Err(_) => None,
+			}
+		} else {
+			None
+		};
+
+		(constructor, message_send)
+	}
+
+	fn generate_local_serial_id<ES: Deref>(entropy_source: &ES, is_initiator: bool) -> SerialId
+	where ES::Target: EntropySource,
+	{
+		let rand_bytes = entropy_source.get_secure_random_bytes();
+		let mut serial_id_bytes = [0u8; 8];
+		serial_id_bytes.copy_from_slice(&rand_bytes[..8]);
+		let serial_id = u64::from_be_bytes(serial_id_bytes);
+		// The initiator uses even serial IDs, the non-initiator odd ones.
+		if is_initiator { serial_id & !1 } else { serial_id | 1 }
+	}
+
+	fn maybe_send_message(&mut self) -> Result<InteractiveTxMessageSend, AbortReason> {
+		// We first attempt to send inputs we want to add, then outputs. Once we are done sending
+		// them both, then we always send tx_complete.
+		if let Some((serial_id, input, prevtx)) = self.inputs_to_contribute.pop() {
+			let msg = msgs::TxAddInput {
+				channel_id: self.channel_id,
+				serial_id,
+				prevtx,
+				prevtx_out: input.previous_output.vout,
+				sequence: input.sequence.to_consensus_u32(),
+			};
+			self.state_machine.sent_change_msg(|context| context.sent_tx_add_input(&msg))?;
+			Ok(InteractiveTxMessageSend::TxAddInput(msg))
+		} else if let Some((serial_id, output)) = self.outputs_to_contribute.pop() {
+			let msg = msgs::TxAddOutput {
+				channel_id: self.channel_id,
+				serial_id,
+				sats: output.value,
+				script: output.script_pubkey,
+			};
+			self.state_machine.sent_change_msg(|context| context.sent_tx_add_output(&msg))?;
+			Ok(InteractiveTxMessageSend::TxAddOutput(msg))
+		} else {
+			let msg = msgs::TxComplete { channel_id: self.channel_id };
+			self.state_machine.sent_tx_complete()?;
+			Ok(InteractiveTxMessageSend::TxComplete(msg))
+		}
+	}
+
+	/// Handles a `tx_add_input` from the counterparty, returning our response.
+	pub fn handle_tx_add_input(&mut self, msg: &msgs::TxAddInput) -> Result<InteractiveTxMessageSend, AbortReason> {
+		self.state_machine.received_tx_add_input(msg)?;
+		self.maybe_send_message()
+	}
+
+	/// Handles a `tx_remove_input` from the counterparty, returning our response.
+	pub fn handle_tx_remove_input(&mut self, msg: &msgs::TxRemoveInput) -> Result<InteractiveTxMessageSend, AbortReason> {
+		self.state_machine.received_tx_remove_input(msg)?;
+		self.maybe_send_message()
+	}
+
+	/// Handles a `tx_add_output` from the counterparty, returning our response.
+	pub fn handle_tx_add_output(&mut self, msg: &msgs::TxAddOutput) -> Result<InteractiveTxMessageSend, AbortReason> {
+		self.state_machine.received_tx_add_output(msg)?;
+		self.maybe_send_message()
+	}
+
+	/// Handles a `tx_remove_output` from the counterparty, returning our response.
+	pub fn handle_tx_remove_output(&mut self, msg: &msgs::TxRemoveOutput) -> Result<InteractiveTxMessageSend, AbortReason> {
+		self.state_machine.received_tx_remove_output(msg)?;
+		self.maybe_send_message()
+	}
+
+	/// Handles a `tx_complete` from the counterparty.
+	///
+	/// Returns the message we should send in response, if any, and the negotiated transaction if
+	/// the negotiation has completed.
+	pub fn handle_tx_complete(&mut self, _msg: &msgs::TxComplete) -> Result<(Option<InteractiveTxMessageSend>, Option<Transaction>), AbortReason> {
+		self.state_machine.received_tx_complete()?;
+		let msg_send = match &self.state_machine {
+			StateMachine::ReceivedTxComplete(_) => Some(self.maybe_send_message()?),
+			_ => None,
+		};
+		let tx = match &self.state_machine {
+			StateMachine::NegotiationComplete(tx) => Some(tx.clone()),
+			_ => None,
+		};
+		Ok((msg_send, tx))
+	}
+
+	/// Whether the negotiation has been aborted.
+	pub fn is_aborted(&self) -> bool {
+		matches!(self.state_machine, StateMachine::NegotiationAborted(_))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::chain::chaininterface::FEERATE_FLOOR_SATS_PER_KW;
+	use crate::ln::chan_utils::make_funding_redeemscript;
+	use crate::sign::EntropySource;
+	use crate::util::atomic_counter::AtomicCounter;
+
+	use bitcoin::hashes::Hash;
+	use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+	use bitcoin::{PubkeyHash, Sequence, WPubkeyHash, Witness};
+
+	struct TestEntropySource(AtomicCounter);
+	impl EntropySource for TestEntropySource {
+		fn get_secure_random_bytes(&self) -> [u8; 32] {
+			let mut res = [0u8; 32];
+			let increment = self.0.get_increment();
+			for i in 0..32 {
+				// Rotate the increment value by 'i' bits to the right, to avoid clashes
+				// when `generate_local_serial_id` does a parity flip on consecutive calls for the
+				// same party.
+				let rotated_increment = increment.rotate_right(i as u32);
+				res[i] = (rotated_increment & 0xff) as u8;
+			}
+			res
+		}
+	}
+
+	struct TestSession {
+		inputs_a: Vec<(TxIn, TransactionU16LenLimited)>,
+		outputs_a: Vec<TxOut>,
+		funding_a: u64,
+		inputs_b: Vec<(TxIn, TransactionU16LenLimited)>,
+		outputs_b: Vec<TxOut>,
+		funding_b: u64,
+		expect_error: Option<AbortReason>,
+	}
+
+	fn funding_script() -> Script {
+		let secp_ctx = Secp256k1::new();
+		let pubkey_a = PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[42; 32]).unwrap());
+		let pubkey_b = PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[43; 32]).unwrap());
+		make_funding_redeemscript(&pubkey_a, &pubkey_b).to_v0_p2wsh()
+	}
+
+	fn p2wpkh_script(byte: u8) -> Script {
+		Script::new_v0_p2wpkh(&WPubkeyHash::from_slice(&[byte; 20]).unwrap())
+	}
+
+	fn generate_inputs(values: &[u64]) -> Vec<(TxIn, TransactionU16LenLimited)> {
+		let tx = TransactionU16LenLimited::new(Transaction {
+			version: 2,
+			lock_time: PackedLockTime::ZERO,
+			input: vec![TxIn::default()],
+			output: values.iter().enumerate().map(|(idx, value)| TxOut {
+				value: *value, script_pubkey: p2wpkh_script(idx as u8),
+			}).collect(),
+		}).unwrap();
+		let txid = tx.as_transaction().txid();
+		values.iter().enumerate().map(|(idx, _)| (TxIn {
+			previous_output: OutPoint { txid, vout: idx as u32 },
+			script_sig: Script::new(),
+			sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+			witness: Witness::new(),
+		}, tx.clone())).collect()
+	}
+
+	fn generate_outputs(values: &[u64]) -> Vec<TxOut> {
+		values.iter().map(|value| TxOut { value: *value, script_pubkey: p2wpkh_script(0x42) }).collect()
+	}
+
+	fn do_test_interactive_tx_constructor(session: TestSession) {
+		let entropy_source = TestEntropySource(AtomicCounter::new());
+		let channel_id = [42; 32];
+		let tx_locktime = PackedLockTime(1337);
+		let funding_script = funding_script();
+
+		let (mut constructor_a, first_message_a) = InteractiveTxConstructor::new(
+			&&entropy_source, channel_id, FEERATE_FLOOR_SATS_PER_KW * 10, true, tx_locktime,
+			funding_script.clone(), session.funding_a, session.funding_b,
+			session.inputs_a.clone(), session.outputs_a.clone(),
+		);
+		let (mut constructor_b, first_message_b) = InteractiveTxConstructor::new(
+			&&entropy_source, channel_id, FEERATE_FLOOR_SATS_PER_KW * 10, false, tx_locktime,
+			funding_script.clone(), session.funding_b, session.funding_a,
+			session.inputs_b.clone(), session.outputs_b.clone(),
+		);
+		assert!(first_message_b.is_none());
+
+		fn handle_message_send(
+			msg: InteractiveTxMessageSend, for_constructor: &mut InteractiveTxConstructor,
+		) -> Result<(Option<InteractiveTxMessageSend>, Option<Transaction>), AbortReason> {
+			match msg {
+				InteractiveTxMessageSend::TxAddInput(msg) =>
+					for_constructor.handle_tx_add_input(&msg).map(|msg_send| (Some(msg_send), None)),
+				InteractiveTxMessageSend::TxAddOutput(msg) =>
+					for_constructor.handle_tx_add_output(&msg).map(|msg_send| (Some(msg_send), None)),
+				InteractiveTxMessageSend::TxComplete(msg) =>
+					for_constructor.handle_tx_complete(&msg),
+			}
+		}
+
+		let mut message_send_a = first_message_a;
+		let mut message_send_b = None;
+		let mut final_tx_a = None;
+		let mut final_tx_b = None;
+		while final_tx_a.is_none() || final_tx_b.is_none() {
+			if let Some(message_send_a) = message_send_a.take() {
+				match handle_message_send(message_send_a, &mut constructor_b) {
+					Ok((msg_send, final_tx)) => {
+						message_send_b = msg_send;
+						final_tx_b = final_tx;
+					},
+					Err(abort_reason) => {
+						assert_eq!(Some(abort_reason), session.expect_error);
+						assert!(constructor_b.is_aborted());
+						return;
+					},
+				}
+			}
+			if let Some(message_send_b) = message_send_b.take() {
+				match handle_message_send(message_send_b, &mut constructor_a) {
+					Ok((msg_send, final_tx)) => {
+						message_send_a = msg_send;
+						final_tx_a = final_tx;
+					},
+					Err(abort_reason) => {
+						assert_eq!(Some(abort_reason), session.expect_error);
+						assert!(constructor_a.is_aborted());
+						return;
+					},
+				}
+			}
+		}
+		assert!(message_send_a.is_none());
+		assert!(message_send_b.is_none());
+		assert_eq!(final_tx_a, final_tx_b);
+		assert!(session.expect_error.is_none());
+
+		let final_tx = final_tx_a.unwrap();
+		assert_eq!(final_tx.lock_time, tx_locktime);
+		assert_eq!(final_tx.input.len(), session.inputs_a.len() + session.inputs_b.len());
+		assert_eq!(final_tx.output.len(), session.outputs_a.len() + session.outputs_b.len() + 1);
+		assert_eq!(final_tx.output.iter()
+			.filter(|output| output.script_pubkey == funding_script &&
+				output.value == session.funding_a + session.funding_b)
+			.count(), 1);
+	}
+
+	#[test]
+	fn test_interactive_tx_constructor() {
+		// Single-funded: only the initiator contributes.
+		do_test_interactive_tx_constructor(TestSession {
+			inputs_a: generate_inputs(&[1_000_000]),
+			outputs_a: generate_outputs(&[400_000]),
+			funding_a: 500_000,
+			inputs_b: vec![],
+			outputs_b: vec![],
+			funding_b: 0,
+			expect_error: None,
+		});
+		// Dual-funded: both sides contribute inputs and change outputs.
+		do_test_interactive_tx_constructor(TestSession {
+			inputs_a: generate_inputs(&[1_000_000, 500_000]),
+			outputs_a: generate_outputs(&[900_000]),
+			funding_a: 500_000,
+			inputs_b: generate_inputs(&[1_000_000]),
+			outputs_b: generate_outputs(&[400_000]),
+			funding_b: 500_000,
+			expect_error: None,
+		});
+		// The non-initiator's outputs are worth more than its inputs.
+		do_test_interactive_tx_constructor(TestSession {
+			inputs_a: generate_inputs(&[1_000_000]),
+			outputs_a: vec![],
+			funding_a: 500_000,
+			inputs_b: generate_inputs(&[100_000]),
+			outputs_b: generate_outputs(&[50_000]),
+			funding_b: 100_000,
+			expect_error: Some(AbortReason::OutputsValueExceedsInputsValue),
+		});
+		// The initiator doesn't leave anything for fees.
+		do_test_interactive_tx_constructor(TestSession {
+			inputs_a: generate_inputs(&[1_000_000]),
+			outputs_a: generate_outputs(&[500_000]),
+			funding_a: 500_000,
+			inputs_b: vec![],
+			outputs_b: vec![],
+			funding_b: 0,
+			expect_error: Some(AbortReason::InsufficientFees),
+		});
+		// The non-initiator contributes a dust output.
+		do_test_interactive_tx_constructor(TestSession {
+			inputs_a: generate_inputs(&[1_000_000]),
+			outputs_a: vec![],
+			funding_a: 500_000,
+			inputs_b: generate_inputs(&[900_000]),
+			outputs_b: generate_outputs(&[1]),
+			funding_b: 500_000,
+			expect_error: Some(AbortReason::BelowDustLimit),
+		});
+	}
+
+	#[test]
+	fn test_invalid_counterparty_messages() {
+		let entropy_source = TestEntropySource(AtomicCounter::new());
+		let channel_id = [42; 32];
+		let new_constructor = || InteractiveTxConstructor::new(
+			&&entropy_source, channel_id, FEERATE_FLOOR_SATS_PER_KW, false, PackedLockTime::ZERO,
+			funding_script(), 0, 500_000, vec![], vec![],
+		).0;
+		let (_, prevtx) = generate_inputs(&[1_000_000]).pop().unwrap();
+
+		// The initiator must use even serial IDs.
+		let mut constructor = new_constructor();
+		assert_eq!(constructor.handle_tx_add_input(&msgs::TxAddInput {
+			channel_id, serial_id: 1, prevtx: prevtx.clone(), prevtx_out: 0, sequence: 0,
+		}), Err(AbortReason::IncorrectSerialIdParity));
+		assert!(constructor.is_aborted());
+
+		// Inputs must signal RBF.
+		let mut constructor = new_constructor();
+		assert_eq!(constructor.handle_tx_add_input(&msgs::TxAddInput {
+			channel_id, serial_id: 2, prevtx: prevtx.clone(), prevtx_out: 0, sequence: 0xFFFFFFFF,
+		}), Err(AbortReason::IncorrectInputSequenceValue));
+
+		// The spent output must exist.
+		let mut constructor = new_constructor();
+		assert_eq!(constructor.handle_tx_add_input(&msgs::TxAddInput {
+			channel_id, serial_id: 2, prevtx: prevtx.clone(), prevtx_out: 1, sequence: 0,
+		}), Err(AbortReason::PrevTxOutInvalid));
+
+		// The same outpoint can't be spent twice, even with distinct serial IDs.
+		let mut constructor = new_constructor();
+		assert!(constructor.handle_tx_add_input(&msgs::TxAddInput {
+			channel_id, serial_id: 2, prevtx: prevtx.clone(), prevtx_out: 0, sequence: 0,
+		}).is_ok());
+		assert_eq!(constructor.handle_tx_add_input(&msgs::TxAddInput {
+			channel_id, serial_id: 4, prevtx: prevtx.clone(), prevtx_out: 0, sequence: 0,
+		}), Err(AbortReason::PrevTxOutInvalid));
+
+		// Only inputs which were added can be removed.
+		let mut constructor = new_constructor();
+		assert_eq!(constructor.handle_tx_remove_input(&msgs::TxRemoveInput {
+			channel_id, serial_id: 2,
+		}), Err(AbortReason::SerialIdUnknown));
+
+		// Outputs must pay to a standard SegWit script.
+		let mut constructor = new_constructor();
+		assert_eq!(constructor.handle_tx_add_output(&msgs::TxAddOutput {
+			channel_id, serial_id: 2, sats: 1_000_000,
+			script: Script::new_p2pkh(&PubkeyHash::from_slice(&[1; 20]).unwrap()),
+		}), Err(AbortReason::InvalidOutputScript));
+
+		// Receiving a `tx_complete` before anything else completes without the funding output.
+		let mut constructor = new_constructor();
+		assert_eq!(constructor.handle_tx_complete(&msgs::TxComplete { channel_id }),
+			Err(AbortReason::MissingFundingOutput));
+
+		// Messages received after the negotiation was aborted are rejected.
+		assert_eq!(constructor.handle_tx_add_output(&msgs::TxAddOutput {
+			channel_id, serial_id: 2, sats: 1_000_000, script: p2wpkh_script(1),
+		}), Err(AbortReason::UnexpectedCounterpartyMessage));
+	}
+
+	#[test]
+	fn test_invalid_holder_input() {
+		// An input we contribute which spends an output its previous transaction doesn't have
+		// aborts the negotiation instead of panicking.
+		let entropy_source = TestEntropySource(AtomicCounter::new());
+		let (mut input, prevtx) = generate_inputs(&[1_000_000]).pop().unwrap();
+		input.previous_output.vout = 1;
+		let (constructor, first_message) = InteractiveTxConstructor::new(
+			&&entropy_source, [42; 32], FEERATE_FLOOR_SATS_PER_KW, true, PackedLockTime::ZERO,
+			funding_script(), 500_000, 0, vec![(input, prevtx)], vec![],
+		);
+		assert!(first_message.is_none());
+		assert!(constructor.is_aborted());
+	}
+}
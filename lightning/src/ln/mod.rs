@@ -32,6 +32,8 @@ pub mod channel;
 pub(crate) mod channel;
 
 pub(crate) mod onion_utils;
+#[cfg(dual_funding)]
+pub(crate) mod interactivetxs;
 mod outbound_payment;
 pub mod wire;
 
@@ -66,6 +68,18 @@ mod monitor_tests;
 #[cfg(test)]
 #[allow(unused_mut)]
 mod shutdown_tests;
+#[cfg(test)]
+#[allow(unused_mut)]
+mod async_signer_tests;
+#[cfg(test)]
+#[allow(unused_mut)]
+mod trampoline_tests;
+#[cfg(test)]
+#[allow(unused_mut)]
+mod offers_tests;
+#[cfg(test)]
+#[allow(unused_mut)]
+mod blinded_payment_tests;
 
 pub use self::peer_channel_encryptor::LN_MAX_MSG_LEN;
 
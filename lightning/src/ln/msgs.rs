@@ -31,19 +31,23 @@ use bitcoin::{secp256k1, Witness};
 use bitcoin::blockdata::script::Script;
 use bitcoin::hash_types::{Txid, BlockHash};
 
+use crate::blinded_path::payment::{BlindedPaymentTlvs, ForwardTlvs, ReceiveTlvs};
 use crate::ln::features::{ChannelFeatures, ChannelTypeFeatures, InitFeatures, NodeFeatures};
 use crate::ln::onion_utils;
 use crate::onion_message;
+use crate::sign::{NodeSigner, Recipient};
 
 use crate::prelude::*;
 use core::fmt;
 use core::fmt::Debug;
-use crate::io::{self, Read};
+use core::ops::Deref;
+use crate::io::{self, Cursor, Read};
 use crate::io_extras::read_to_end;
 
 use crate::events::{MessageSendEventsProvider, OnionMessageProvider};
+use crate::util::chacha20poly1305rfc::ChaChaPolyReadAdapter;
 use crate::util::logger;
-use crate::util::ser::{LengthReadable, Readable, ReadableArgs, Writeable, Writer, WithoutLength, FixedLengthReader, HighZeroBytesDroppedBigSize, Hostname, TransactionU16LenLimited};
+use crate::util::ser::{LengthReadable, LengthReadableArgs, Readable, ReadableArgs, Writeable, Writer, WithoutLength, FixedLengthReader, HighZeroBytesDroppedBigSize, Hostname, TransactionU16LenLimited};
 
 use crate::ln::{PaymentPreimage, PaymentHash, PaymentSecret};
 
@@ -616,6 +620,9 @@ pub struct UpdateAddHTLC {
 	/// [`ChannelConfig::accept_underpaying_htlcs`]: crate::util::config::ChannelConfig::accept_underpaying_htlcs
 	pub skimmed_fee_msat: Option<u64>,
 	pub(crate) onion_routing_packet: OnionPacket,
+	/// Provided if we are relaying or receiving a payment within a blinded path, to decrypt the onion
+	/// routing packet and the recipient-provided encrypted payload within.
+	pub blinding_point: Option<PublicKey>,
 }
 
  /// An onion message to be sent to or received from a peer.
@@ -1417,8 +1424,11 @@ pub trait OnionMessageHandler : OnionMessageProvider {
 }
 
 mod fuzzy_internal_msgs {
+	use bitcoin::secp256k1::PublicKey;
+	use crate::blinded_path::payment::{PaymentConstraints, PaymentRelay};
 	use crate::prelude::*;
 	use crate::ln::{PaymentPreimage, PaymentSecret};
+	use crate::ln::features::BlindedHopFeatures;
 
 	// These types aren't intended to be pub, but are exposed for direct fuzzing (as we deserialize
 	// them from untrusted input):
@@ -1439,6 +1449,56 @@ mod fuzzy_internal_msgs {
 			payment_metadata: Option<Vec<u8>>,
 			keysend_preimage: Option<PaymentPreimage>,
 		},
+		/// A forwarding hop within a blinded path. The containing [`OnionHopData::amt_to_forward`]
+		/// and [`OnionHopData::outgoing_cltv_value`] aren't provided by the sender but are instead
+		/// computed from `payment_relay` by the forwarding node.
+		BlindedForward {
+			short_channel_id: u64,
+			payment_relay: PaymentRelay,
+			payment_constraints: PaymentConstraints,
+			features: BlindedHopFeatures,
+			intro_node_blinding_point: Option<PublicKey>,
+		},
+		/// The final hop of a blinded path.
+		BlindedReceive {
+			total_msat: u64,
+			payment_secret: PaymentSecret,
+			payment_constraints: PaymentConstraints,
+			intro_node_blinding_point: Option<PublicKey>,
+		},
+		/// A hop within a blinded path we're sending to, whose recipient-provided `encrypted_tlvs`
+		/// we can't read. Only the final hop, which sets `total_msat`, carries the containing
+		/// [`OnionHopData::amt_to_forward`] and [`OnionHopData::outgoing_cltv_value`]. Never
+		/// produced when decoding an onion.
+		OutboundBlinded {
+			encrypted_tlvs: Vec<u8>,
+			intro_node_blinding_point: Option<PublicKey>,
+			total_msat: Option<u64>,
+		},
+		/// The final hop of the outer onion when paying via a trampoline node, carrying the inner
+		/// onion which tells the trampoline node where to forward the payment.
+		TrampolineEntry {
+			payment_data: FinalOnionHopData,
+			trampoline_packet: TrampolineOnionPacket,
+		},
+		/// A hop within a trampoline onion, instructing the trampoline node to find a route to and
+		/// pay `outgoing_node_id`.
+		TrampolineForward {
+			outgoing_node_id: PublicKey,
+		},
+	}
+
+	/// An onion packet nested within the final hop payload of a payment onion, routing a payment
+	/// through one or more trampoline nodes. Unlike [`OnionPacket`]s, the length of the hop data
+	/// is variable.
+	///
+	/// [`OnionPacket`]: super::OnionPacket
+	#[derive(Clone, Debug, PartialEq, Eq)]
+	pub(crate) struct TrampolineOnionPacket {
+		pub(crate) version: u8,
+		pub(crate) public_key: PublicKey,
+		pub(crate) hop_data: Vec<u8>,
+		pub(crate) hmac: [u8; 32],
 	}
 
 	pub struct OnionHopData {
@@ -1914,6 +1974,7 @@ impl_writeable_msg!(UpdateAddHTLC, {
 	cltv_expiry,
 	onion_routing_packet,
 }, {
+	(0, blinding_point, option),
 	(65537, skimmed_fee_msat, option)
 });
 
@@ -1940,6 +2001,43 @@ impl Writeable for OnionMessage {
 	}
 }
 
+impl onion_utils::Packet for TrampolineOnionPacket {
+	type Data = Vec<u8>;
+	fn new(public_key: PublicKey, hop_data: Vec<u8>, hmac: [u8; 32]) -> Self {
+		Self {
+			version: 0,
+			public_key,
+			hop_data,
+			hmac,
+		}
+	}
+}
+
+impl Writeable for TrampolineOnionPacket {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		self.version.write(w)?;
+		self.public_key.write(w)?;
+		w.write_all(&self.hop_data)?;
+		self.hmac.write(w)?;
+		Ok(())
+	}
+}
+
+// Trampoline packets are only ever read from within a TLV stream, so the hop data is taken to be
+// whatever remains in the reader once the HMAC is split off the end.
+impl Readable for TrampolineOnionPacket {
+	fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
+		let version = Readable::read(r)?;
+		let public_key = Readable::read(r)?;
+		let mut hop_data = read_to_end(r)?;
+		if hop_data.len() < 32 { return Err(DecodeError::ShortRead); }
+		let mut hmac = [0; 32];
+		hmac.copy_from_slice(&hop_data[hop_data.len() - 32..]);
+		hop_data.truncate(hop_data.len() - 32);
+		Ok(Self { version, public_key, hop_data, hmac })
+	}
+}
+
 impl Writeable for FinalOnionHopData {
 	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
 		self.payment_secret.0.write(w)?;
@@ -1974,28 +2072,64 @@ impl Writeable for OnionHopData {
 					(5482373484, keysend_preimage, option)
 				});
 			},
+			OnionHopDataFormat::TrampolineEntry { ref payment_data, ref trampoline_packet } => {
+				_encode_varint_length_prefixed_tlv!(w, {
+					(2, HighZeroBytesDroppedBigSize(self.amt_to_forward), required),
+					(4, HighZeroBytesDroppedBigSize(self.outgoing_cltv_value), required),
+					(8, payment_data, required),
+					// See the comment in `OnionHopData`'s `Readable` implementation.
+					(66100, trampoline_packet, required)
+				});
+			},
+			OnionHopDataFormat::TrampolineForward { ref outgoing_node_id } => {
+				_encode_varint_length_prefixed_tlv!(w, {
+					(2, HighZeroBytesDroppedBigSize(self.amt_to_forward), required),
+					(4, HighZeroBytesDroppedBigSize(self.outgoing_cltv_value), required),
+					// See the comment in `OnionHopData`'s `Readable` implementation.
+					(66098, outgoing_node_id, required)
+				});
+			},
+			OnionHopDataFormat::OutboundBlinded { ref encrypted_tlvs, intro_node_blinding_point, total_msat: None } => {
+				_encode_varint_length_prefixed_tlv!(w, {
+					(10, WithoutLength(encrypted_tlvs), required),
+					(12, intro_node_blinding_point, option)
+				});
+			},
+			OnionHopDataFormat::OutboundBlinded { ref encrypted_tlvs, intro_node_blinding_point, total_msat: Some(total_msat) } => {
+				_encode_varint_length_prefixed_tlv!(w, {
+					(2, HighZeroBytesDroppedBigSize(self.amt_to_forward), required),
+					(4, HighZeroBytesDroppedBigSize(self.outgoing_cltv_value), required),
+					(10, WithoutLength(encrypted_tlvs), required),
+					(12, intro_node_blinding_point, option),
+					(18, HighZeroBytesDroppedBigSize(total_msat), required)
+				});
+			},
+			OnionHopDataFormat::BlindedForward { .. } | OnionHopDataFormat::BlindedReceive { .. } => {
+				// Blinded payloads are only ever decrypted from a received onion; the encrypted
+				// recipient data needed to re-encode them is not retained.
+				debug_assert!(false, "Blinded onion payloads cannot be written");
+				return Err(io::Error::new(io::ErrorKind::InvalidInput, "Blinded onion payloads cannot be written"));
+			},
 		}
 		Ok(())
 	}
 }
 
-impl Readable for OnionHopData {
-	fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
-		let mut amt = HighZeroBytesDroppedBigSize(0u64);
-		let mut cltv_value = HighZeroBytesDroppedBigSize(0u32);
-		let mut short_id: Option<u64> = None;
-		let mut payment_data: Option<FinalOnionHopData> = None;
-		let mut payment_metadata: Option<WithoutLength<Vec<u8>>> = None;
-		let mut keysend_preimage: Option<PaymentPreimage> = None;
-		read_tlv_fields!(r, {
-			(2, amt, required),
-			(4, cltv_value, required),
-			(6, short_id, option),
-			(8, payment_data, option),
-			(16, payment_metadata, option),
-			// See https://github.com/lightning/blips/blob/master/blip-0003.md
-			(5482373484, keysend_preimage, option)
-		});
+impl OnionHopData {
+	fn from_unblinded_tlvs(
+		amt: Option<HighZeroBytesDroppedBigSize<u64>>, cltv_value: Option<HighZeroBytesDroppedBigSize<u32>>,
+		short_id: Option<u64>, payment_data: Option<FinalOnionHopData>,
+		payment_metadata: Option<WithoutLength<Vec<u8>>>, keysend_preimage: Option<PaymentPreimage>,
+		outgoing_node_id: Option<PublicKey>, trampoline_packet: Option<TrampolineOnionPacket>,
+	) -> Result<Self, DecodeError> {
+		let amt = amt.ok_or(DecodeError::InvalidValue)?;
+		let cltv_value = cltv_value.ok_or(DecodeError::InvalidValue)?;
+
+		if (outgoing_node_id.is_some() || trampoline_packet.is_some()) &&
+			(short_id.is_some() || payment_metadata.is_some() || keysend_preimage.is_some())
+		{
+			return Err(DecodeError::InvalidValue);
+		}
 
 		let format = if let Some(short_channel_id) = short_id {
 			if payment_data.is_some() { return Err(DecodeError::InvalidValue); }
@@ -2003,6 +2137,22 @@ impl Readable for OnionHopData {
 			OnionHopDataFormat::NonFinalNode {
 				short_channel_id,
 			}
+		} else if let Some(outgoing_node_id) = outgoing_node_id {
+			if payment_data.is_some() || trampoline_packet.is_some() {
+				return Err(DecodeError::InvalidValue);
+			}
+			OnionHopDataFormat::TrampolineForward {
+				outgoing_node_id,
+			}
+		} else if let Some(trampoline_packet) = trampoline_packet {
+			let payment_data = payment_data.ok_or(DecodeError::InvalidValue)?;
+			if payment_data.total_msat > MAX_VALUE_MSAT {
+				return Err(DecodeError::InvalidValue);
+			}
+			OnionHopDataFormat::TrampolineEntry {
+				payment_data,
+				trampoline_packet,
+			}
 		} else {
 			if let Some(data) = &payment_data {
 				if data.total_msat > MAX_VALUE_MSAT {
@@ -2027,6 +2177,36 @@ impl Readable for OnionHopData {
 	}
 }
 
+impl Readable for OnionHopData {
+	fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
+		let mut amt: Option<HighZeroBytesDroppedBigSize<u64>> = None;
+		let mut cltv_value: Option<HighZeroBytesDroppedBigSize<u32>> = None;
+		let mut short_id: Option<u64> = None;
+		let mut payment_data: Option<FinalOnionHopData> = None;
+		let mut payment_metadata: Option<WithoutLength<Vec<u8>>> = None;
+		let mut outgoing_node_id: Option<PublicKey> = None;
+		let mut trampoline_packet: Option<TrampolineOnionPacket> = None;
+		let mut keysend_preimage: Option<PaymentPreimage> = None;
+		read_tlv_fields!(r, {
+			(2, amt, option),
+			(4, cltv_value, option),
+			(6, short_id, option),
+			(8, payment_data, option),
+			(16, payment_metadata, option),
+			// The trampoline node id and onion from the trampoline routing proposal, see
+			// https://github.com/lightning/bolts/pull/836. As it isn't final yet, these use types
+			// from the experimental range, matching those used by other implementations.
+			(66098, outgoing_node_id, option),
+			(66100, trampoline_packet, option),
+			// See https://github.com/lightning/blips/blob/master/blip-0003.md
+			(5482373484, keysend_preimage, option)
+		});
+
+		Self::from_unblinded_tlvs(amt, cltv_value, short_id, payment_data, payment_metadata,
+			keysend_preimage, outgoing_node_id, trampoline_packet)
+	}
+}
+
 // ReadableArgs because we need onion_utils::decode_next_hop to accommodate payment packets and
 // onion message packets.
 impl ReadableArgs<()> for OnionHopData {
@@ -2035,6 +2215,106 @@ impl ReadableArgs<()> for OnionHopData {
 	}
 }
 
+// Used when decoding a payment onion, where the blinding point from the `update_add_htlc`, if
+// any, and a `NodeSigner` are needed to decrypt the recipient-provided data of a blinded path.
+impl<NS: Deref> ReadableArgs<(Option<PublicKey>, &NS)> for OnionHopData where NS::Target: NodeSigner {
+	fn read<R: Read>(r: &mut R, args: (Option<PublicKey>, &NS)) -> Result<Self, DecodeError> {
+		let (update_add_blinding_point, node_signer) = args;
+
+		let mut amt: Option<HighZeroBytesDroppedBigSize<u64>> = None;
+		let mut cltv_value: Option<HighZeroBytesDroppedBigSize<u32>> = None;
+		let mut short_id: Option<u64> = None;
+		let mut payment_data: Option<FinalOnionHopData> = None;
+		let mut encrypted_tlvs_opt: Option<WithoutLength<Vec<u8>>> = None;
+		let mut intro_node_blinding_point: Option<PublicKey> = None;
+		let mut payment_metadata: Option<WithoutLength<Vec<u8>>> = None;
+		let mut total_msat: Option<HighZeroBytesDroppedBigSize<u64>> = None;
+		let mut outgoing_node_id: Option<PublicKey> = None;
+		let mut trampoline_packet: Option<TrampolineOnionPacket> = None;
+		let mut keysend_preimage: Option<PaymentPreimage> = None;
+		read_tlv_fields!(r, {
+			(2, amt, option),
+			(4, cltv_value, option),
+			(6, short_id, option),
+			(8, payment_data, option),
+			(10, encrypted_tlvs_opt, option),
+			(12, intro_node_blinding_point, option),
+			(16, payment_metadata, option),
+			(18, total_msat, option),
+			// The trampoline node id and onion from the trampoline routing proposal, see
+			// https://github.com/lightning/bolts/pull/836. As it isn't final yet, these use types
+			// from the experimental range, matching those used by other implementations.
+			(66098, outgoing_node_id, option),
+			(66100, trampoline_packet, option),
+			// See https://github.com/lightning/blips/blob/master/blip-0003.md
+			(5482373484, keysend_preimage, option)
+		});
+
+		if intro_node_blinding_point.is_some() && update_add_blinding_point.is_some() {
+			return Err(DecodeError::InvalidValue)
+		}
+
+		if let Some(blinding_point) = intro_node_blinding_point.or(update_add_blinding_point) {
+			if short_id.is_some() || payment_data.is_some() || payment_metadata.is_some() ||
+				keysend_preimage.is_some() || outgoing_node_id.is_some() || trampoline_packet.is_some()
+			{
+				return Err(DecodeError::InvalidValue)
+			}
+			let enc_tlvs = encrypted_tlvs_opt.ok_or(DecodeError::InvalidValue)?.0;
+			let enc_tlvs_ss = node_signer.ecdh(Recipient::Node, &blinding_point, None)
+				.map_err(|_| DecodeError::InvalidValue)?;
+			let rho = onion_utils::gen_rho_from_shared_secret(&enc_tlvs_ss.secret_bytes());
+			let mut s = Cursor::new(&enc_tlvs);
+			let mut reader = FixedLengthReader::new(&mut s, enc_tlvs.len() as u64);
+			match ChaChaPolyReadAdapter::read(&mut reader, rho)? {
+				ChaChaPolyReadAdapter { readable: BlindedPaymentTlvs::Forward(ForwardTlvs {
+					short_channel_id, payment_relay, payment_constraints, features
+				})} => {
+					if amt.is_some() || cltv_value.is_some() || total_msat.is_some() {
+						return Err(DecodeError::InvalidValue)
+					}
+					Ok(OnionHopData {
+						format: OnionHopDataFormat::BlindedForward {
+							short_channel_id,
+							payment_relay,
+							payment_constraints,
+							features,
+							intro_node_blinding_point,
+						},
+						amt_to_forward: 0,
+						outgoing_cltv_value: 0,
+					})
+				},
+				ChaChaPolyReadAdapter { readable: BlindedPaymentTlvs::Receive(ReceiveTlvs {
+					payment_secret, payment_constraints
+				})} => {
+					let amt = amt.ok_or(DecodeError::InvalidValue)?.0;
+					let total_msat = total_msat.ok_or(DecodeError::InvalidValue)?.0;
+					if amt > MAX_VALUE_MSAT || total_msat > MAX_VALUE_MSAT {
+						return Err(DecodeError::InvalidValue)
+					}
+					Ok(OnionHopData {
+						format: OnionHopDataFormat::BlindedReceive {
+							total_msat,
+							payment_secret,
+							payment_constraints,
+							intro_node_blinding_point,
+						},
+						amt_to_forward: amt,
+						outgoing_cltv_value: cltv_value.ok_or(DecodeError::InvalidValue)?.0,
+					})
+				},
+			}
+		} else {
+			if encrypted_tlvs_opt.is_some() || total_msat.is_some() {
+				return Err(DecodeError::InvalidValue)
+			}
+			Self::from_unblinded_tlvs(amt, cltv_value, short_id, payment_data, payment_metadata,
+				keysend_preimage, outgoing_node_id, trampoline_packet)
+		}
+	}
+}
+
 impl Writeable for Ping {
 	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
 		self.ponglen.write(w)?;
@@ -3343,12 +3623,38 @@ mod tests {
 			cltv_expiry: 821716,
 			onion_routing_packet,
 			skimmed_fee_msat: None,
+			blinding_point: None,
 		};
 		let encoded_value = update_add_htlc.encode();
 		let target_value = hex::decode("020202020202020202020202020202020202020202020202020202020202020200083a840000034d32144668701144760101010101010101010101010101010101010101010101010101010101010101000c89d4ff031b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010202020202020202020202020202020202020202020202020202020202020202").unwrap();
 		assert_eq!(encoded_value, target_value);
 	}
 
+	#[test]
+	fn encoding_update_add_htlc_with_blinding_point() {
+		let secp_ctx = Secp256k1::new();
+		let (_, pubkey_1) = get_keys_from!("0101010101010101010101010101010101010101010101010101010101010101", secp_ctx);
+		let (_, pubkey_2) = get_keys_from!("0202020202020202020202020202020202020202020202020202020202020202", secp_ctx);
+		let update_add_htlc = msgs::UpdateAddHTLC {
+			channel_id: [2; 32],
+			htlc_id: 2316138423780173,
+			amount_msat: 3608586615801332854,
+			payment_hash: PaymentHash([1; 32]),
+			cltv_expiry: 821716,
+			onion_routing_packet: msgs::OnionPacket {
+				version: 0,
+				public_key: Ok(pubkey_1),
+				hop_data: [1; 20*65],
+				hmac: [2; 32]
+			},
+			skimmed_fee_msat: None,
+			blinding_point: Some(pubkey_2),
+		};
+		let encoded_value = update_add_htlc.encode();
+		let decoded: msgs::UpdateAddHTLC = Readable::read(&mut Cursor::new(&encoded_value)).unwrap();
+		assert_eq!(decoded, update_add_htlc);
+	}
+
 	#[test]
 	fn encoding_update_fulfill_htlc() {
 		let update_fulfill_htlc = msgs::UpdateFulfillHTLC {
@@ -3601,6 +3907,51 @@ mod tests {
 		assert_eq!(msg.outgoing_cltv_value, 0xffffffff);
 	}
 
+	#[test]
+	fn encoding_trampoline_onion_hop_data() {
+		let secp_ctx = Secp256k1::new();
+		let (_, pubkey) = get_keys_from!("0101010101010101010101010101010101010101010101010101010101010101", secp_ctx);
+		let trampoline_packet = msgs::TrampolineOnionPacket {
+			version: 0,
+			public_key: pubkey,
+			hop_data: vec![0x42; 400],
+			hmac: [0x24; 32],
+		};
+		let msg = msgs::OnionHopData {
+			format: OnionHopDataFormat::TrampolineEntry {
+				payment_data: FinalOnionHopData {
+					payment_secret: PaymentSecret([0x42; 32]),
+					total_msat: 0x1badca1f,
+				},
+				trampoline_packet: trampoline_packet.clone(),
+			},
+			amt_to_forward: 0x0badf00d01020304,
+			outgoing_cltv_value: 0xffffffff,
+		};
+		let encoded_value = msg.encode();
+		let decoded: msgs::OnionHopData = Readable::read(&mut Cursor::new(&encoded_value[..])).unwrap();
+		if let OnionHopDataFormat::TrampolineEntry {
+			payment_data: FinalOnionHopData { payment_secret, total_msat: 0x1badca1f },
+			trampoline_packet: decoded_packet,
+		} = decoded.format {
+			assert_eq!(payment_secret, PaymentSecret([0x42; 32]));
+			assert_eq!(decoded_packet, trampoline_packet);
+		} else { panic!(); }
+		assert_eq!(decoded.amt_to_forward, 0x0badf00d01020304);
+		assert_eq!(decoded.outgoing_cltv_value, 0xffffffff);
+
+		let msg = msgs::OnionHopData {
+			format: OnionHopDataFormat::TrampolineForward { outgoing_node_id: pubkey },
+			amt_to_forward: 0x0badf00d01020304,
+			outgoing_cltv_value: 0xffffffff,
+		};
+		let encoded_value = msg.encode();
+		let decoded: msgs::OnionHopData = Readable::read(&mut Cursor::new(&encoded_value[..])).unwrap();
+		if let OnionHopDataFormat::TrampolineForward { outgoing_node_id } = decoded.format {
+			assert_eq!(outgoing_node_id, pubkey);
+		} else { panic!(); }
+	}
+
 	#[test]
 	fn query_channel_range_end_blocknum() {
 		let tests: Vec<(u32, u32, u32)> = vec![
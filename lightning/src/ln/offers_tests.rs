@@ -0,0 +1,217 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Tests for paying BOLT 12 offers via [`ChannelManager`], with the offers messages
+//! handed between nodes directly rather than via an `OnionMessenger`.
+//!
+//! [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+
+use crate::events::{Event, MessageSendEventsProvider, PaymentPurpose};
+use crate::ln::channelmanager::{self, PaymentId, RecentPaymentDetails};
+use crate::ln::functional_test_utils::*;
+use crate::ln::outbound_payment::{INVOICE_REQUEST_TIMEOUT_TICKS, Retry};
+use crate::offers::invoice::Bolt12Invoice;
+use crate::offers::invoice_request::InvoiceRequest;
+use crate::offers::parse::Bolt12SemanticError;
+use crate::onion_message::{OffersMessage, OffersMessageHandler};
+use crate::prelude::*;
+
+use core::time::Duration;
+
+/// Creates a network of `payer -- forwarder -- payee` in which all nodes support onion messages,
+/// which [`ChannelManager`] requires of a peer to use it in a blinded path.
+///
+/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+macro_rules! create_offers_network {
+	($node_cfgs: ident, $node_chanmgrs: ident, $nodes: ident) => {
+		let chanmon_cfgs = create_chanmon_cfgs(3);
+		let $node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+		let mut features = channelmanager::provided_init_features(&test_default_channel_config());
+		features.set_onion_messages_optional();
+		for node_cfg in $node_cfgs.iter() {
+			*node_cfg.override_init_features.borrow_mut() = Some(features.clone());
+		}
+		let $node_chanmgrs = create_node_chanmgrs(3, &$node_cfgs, &[None, None, None]);
+		let $nodes = create_network(3, &$node_cfgs, &$node_chanmgrs);
+		create_announced_chan_between_nodes_with_value(&$nodes, 0, 1, 10_000_000, 1_000_000_000);
+		create_announced_chan_between_nodes_with_value(&$nodes, 1, 2, 10_000_000, 1_000_000_000);
+	}
+}
+
+fn extract_invoice_request(node: &Node) -> InvoiceRequest {
+	let mut pending_messages = node.node.release_pending_messages();
+	assert_eq!(pending_messages.len(), 1);
+	match pending_messages.remove(0).contents {
+		OffersMessage::InvoiceRequest(invoice_request) => invoice_request,
+		_ => panic!("Unexpected offers message"),
+	}
+}
+
+fn extract_refund_invoice(node: &Node) -> Bolt12Invoice {
+	let mut pending_messages = node.node.release_pending_messages();
+	assert_eq!(pending_messages.len(), 1);
+	extract_invoice(Some(pending_messages.remove(0).contents))
+}
+
+fn extract_invoice(message: Option<OffersMessage>) -> Bolt12Invoice {
+	match message {
+		Some(OffersMessage::Invoice(invoice)) => invoice,
+		_ => panic!("Expected an invoice, got {:?}", message),
+	}
+}
+
+/// Passes the payment for `invoice` sent by `nodes[0]` along to `nodes[2]`, returning the
+/// preimage `nodes[2]` learned from the payment.
+fn pass_bolt12_payment(nodes: &Vec<Node>, invoice: &Bolt12Invoice) -> crate::ln::PaymentPreimage {
+	check_added_monitors!(nodes[0], 1);
+	let mut events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	let ev = remove_first_msg_event_to_node(&nodes[1].node.get_our_node_id(), &mut events);
+	do_pass_along_path(&nodes[0], &[&nodes[1], &nodes[2]], invoice.amount_msats(),
+		invoice.payment_hash(), None, ev, false, false, None);
+
+	let events = nodes[2].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match &events[0] {
+		Event::PaymentClaimable { payment_hash, amount_msat, purpose, .. } => {
+			assert_eq!(*payment_hash, invoice.payment_hash());
+			assert_eq!(*amount_msat, invoice.amount_msats());
+			match purpose {
+				PaymentPurpose::InvoicePayment { payment_preimage: Some(payment_preimage), .. } =>
+					*payment_preimage,
+				_ => panic!("Unexpected payment purpose"),
+			}
+		},
+		_ => panic!("Unexpected event"),
+	}
+}
+
+#[test]
+fn pays_for_offer_using_blinded_payment_paths() {
+	create_offers_network!(node_cfgs, node_chanmgrs, nodes);
+
+	let offer = nodes[2].node.create_offer_builder("coffee".to_string())
+		.amount_msats(10_000_000)
+		.build().unwrap();
+
+	let payment_id = PaymentId([42; 32]);
+	nodes[0].node.pay_for_offer(&offer, None, None, payment_id, Retry::Attempts(0)).unwrap();
+	assert!(nodes[0].node.list_recent_payments().iter().any(|payment| match payment {
+		RecentPaymentDetails::AwaitingInvoice { payment_id: id } => *id == payment_id,
+		_ => false,
+	}));
+
+	let invoice_request = extract_invoice_request(&nodes[0]);
+	let invoice = extract_invoice(
+		nodes[2].node.handle_message(OffersMessage::InvoiceRequest(invoice_request))
+	);
+	assert_eq!(invoice.amount_msats(), 10_000_000);
+	// The payee's only channel counterparty is the introduction node of its payment paths.
+	assert!(!invoice.payment_paths().is_empty());
+	for (_, path) in invoice.payment_paths() {
+		assert_eq!(path.introduction_node_id, nodes[1].node.get_our_node_id());
+		assert_eq!(path.blinded_hops.len(), 2);
+	}
+
+	assert!(nodes[0].node.handle_message(OffersMessage::Invoice(invoice.clone())).is_none());
+	let payment_preimage = pass_bolt12_payment(&nodes, &invoice);
+	claim_payment(&nodes[0], &[&nodes[1], &nodes[2]], payment_preimage);
+
+	// A second invoice for the same payment isn't paid.
+	assert!(nodes[0].node.handle_message(OffersMessage::Invoice(invoice)).is_some());
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+}
+
+#[test]
+fn pays_for_refund_using_blinded_payment_paths() {
+	create_offers_network!(node_cfgs, node_chanmgrs, nodes);
+
+	let absolute_expiry = std::time::SystemTime::now()
+		.duration_since(std::time::SystemTime::UNIX_EPOCH).unwrap() + Duration::from_secs(3600);
+	let payment_id = PaymentId([42; 32]);
+	let refund = nodes[0].node
+		.create_refund_builder(
+			"refund".to_string(), 10_000_000, absolute_expiry, payment_id, Retry::Attempts(0)
+		)
+		.unwrap()
+		.build().unwrap();
+	assert_eq!(refund.amount_msats(), 10_000_000);
+	assert_eq!(refund.absolute_expiry(), Some(absolute_expiry));
+	assert_ne!(refund.payer_id(), nodes[0].node.get_our_node_id());
+	assert_eq!(refund.paths().len(), 1);
+	// All nodes are peers, so the refund's message path may be introduced by either node.
+	let introduction_node_id = refund.paths()[0].introduction_node_id;
+	assert!(introduction_node_id == nodes[1].node.get_our_node_id() ||
+		introduction_node_id == nodes[2].node.get_our_node_id());
+	assert!(nodes[0].node.list_recent_payments().iter().any(|payment| match payment {
+		RecentPaymentDetails::AwaitingInvoice { payment_id: id } => *id == payment_id,
+		_ => false,
+	}));
+
+	// A refund with the same payment id can't be created while the first is outstanding.
+	match nodes[0].node.create_refund_builder(
+		"refund".to_string(), 10_000_000, absolute_expiry, payment_id, Retry::Attempts(0)
+	) {
+		Err(e) => assert_eq!(e, Bolt12SemanticError::DuplicatePaymentId),
+		Ok(_) => panic!("Expected a duplicate payment id error"),
+	}
+
+	nodes[2].node.request_refund_payment(&refund).unwrap();
+	let invoice = extract_refund_invoice(&nodes[2]);
+	assert_eq!(invoice.amount_msats(), 10_000_000);
+	for (_, path) in invoice.payment_paths() {
+		assert_eq!(path.introduction_node_id, nodes[1].node.get_our_node_id());
+	}
+
+	assert!(nodes[0].node.handle_message(OffersMessage::Invoice(invoice.clone())).is_none());
+	let payment_preimage = pass_bolt12_payment(&nodes, &invoice);
+	claim_payment(&nodes[0], &[&nodes[1], &nodes[2]], payment_preimage);
+
+	// A second invoice for the same refund isn't paid.
+	nodes[2].node.request_refund_payment(&refund).unwrap();
+	let invoice = extract_refund_invoice(&nodes[2]);
+	assert!(nodes[0].node.handle_message(OffersMessage::Invoice(invoice)).is_some());
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+}
+
+#[test]
+fn fails_paying_for_expired_refund() {
+	create_offers_network!(node_cfgs, node_chanmgrs, nodes);
+
+	let absolute_expiry = std::time::SystemTime::now()
+		.duration_since(std::time::SystemTime::UNIX_EPOCH).unwrap() - Duration::from_secs(1);
+	let payment_id = PaymentId([42; 32]);
+	let refund = nodes[0].node
+		.create_refund_builder(
+			"refund".to_string(), 10_000_000, absolute_expiry, payment_id, Retry::Attempts(0)
+		)
+		.unwrap()
+		.build().unwrap();
+	assert!(refund.is_expired());
+
+	// The payee won't respond to an expired refund with an invoice.
+	assert_eq!(
+		nodes[2].node.request_refund_payment(&refund), Err(Bolt12SemanticError::AlreadyExpired)
+	);
+	assert!(nodes[2].node.release_pending_messages().is_empty());
+
+	// Without an invoice, the payer eventually gives up on the refund.
+	for _ in 0..INVOICE_REQUEST_TIMEOUT_TICKS {
+		nodes[0].node.timer_tick_occurred();
+		assert!(nodes[0].node.get_and_clear_pending_events().is_empty());
+	}
+	nodes[0].node.timer_tick_occurred();
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		Event::InvoiceRequestFailed { payment_id: id } => assert_eq!(id, payment_id),
+		_ => panic!("Unexpected event"),
+	}
+	assert!(nodes[0].node.list_recent_payments().is_empty());
+}
@@ -12,7 +12,8 @@ use crate::ln::channelmanager::{HTLCSource, RecipientOnionFields};
 use crate::ln::msgs;
 use crate::ln::wire::Encode;
 use crate::routing::gossip::NetworkUpdate;
-use crate::routing::router::{Path, RouteHop};
+use crate::routing::router::{BlindedTail, Path, RouteHop};
+use crate::sign::{NodeSigner, Recipient};
 use crate::util::chacha20::{ChaCha20, ChaChaReader};
 use crate::util::errors::{self, APIError};
 use crate::util::ser::{Readable, ReadableArgs, Writeable, Writer, LengthCalculatingWriter};
@@ -30,6 +31,7 @@ use bitcoin::secp256k1;
 
 use crate::prelude::*;
 use crate::io::{Cursor, Read};
+use core::cmp;
 use core::convert::{AsMut, TryInto};
 use core::ops::Deref;
 
@@ -102,14 +104,49 @@ pub(crate) fn next_hop_packet_pubkey<T: secp256k1::Signing + secp256k1::Verifica
 	packet_pubkey.mul_tweak(secp_ctx, &Scalar::from_be_bytes(blinding_factor).unwrap())
 }
 
+/// Computes the tweak to apply to our node id to get the blinded node id a sender used to encrypt
+/// a payment onion for us, given the `blinding_point` of the blinded path we're in.
+pub(crate) fn blinded_node_id_tweak<NS: Deref>(
+	blinding_point: &PublicKey, node_signer: &NS
+) -> Result<Scalar, ()> where NS::Target: NodeSigner {
+	let encrypted_data_ss = node_signer.ecdh(Recipient::Node, blinding_point, None)?;
+	let mut hmac = HmacEngine::<Sha256>::new(b"blinded_node_id");
+	hmac.input(encrypted_data_ss.as_ref());
+	Scalar::from_be_bytes(Hmac::from_engine(hmac).into_inner()).map_err(|_| ())
+}
+
+/// Computes the blinding point to hand to the next hop of a blinded path, given the
+/// `blinding_point` we received.
+pub(crate) fn next_blinding_point<NS: Deref, T: secp256k1::Verification>(
+	secp_ctx: &Secp256k1<T>, blinding_point: PublicKey, node_signer: &NS
+) -> Result<PublicKey, ()> where NS::Target: NodeSigner {
+	let encrypted_data_ss = node_signer.ecdh(Recipient::Node, &blinding_point, None)?;
+	let blinding_factor = {
+		let mut sha = Sha256::engine();
+		sha.input(&blinding_point.serialize()[..]);
+		sha.input(encrypted_data_ss.as_ref());
+		Sha256::from_engine(sha).into_inner()
+	};
+	let blinding_factor = Scalar::from_be_bytes(blinding_factor).map_err(|_| ())?;
+	blinding_point.mul_tweak(secp_ctx, &blinding_factor).map_err(|_| ())
+}
+
 // can only fail if an intermediary hop has an invalid public key or session_priv is invalid
+//
+// The callback is given `None` in place of a `RouteHop` for hops within the path's blinded tail,
+// other than its introduction node, which is the last of the path's unblinded hops.
 #[inline]
-pub(super) fn construct_onion_keys_callback<T: secp256k1::Signing, FType: FnMut(SharedSecret, [u8; 32], PublicKey, &RouteHop, usize)> (secp_ctx: &Secp256k1<T>, path: &Vec<RouteHop>, session_priv: &SecretKey, mut callback: FType) -> Result<(), secp256k1::Error> {
+pub(super) fn construct_onion_keys_callback<T: secp256k1::Signing, FType: FnMut(SharedSecret, [u8; 32], PublicKey, Option<&RouteHop>, usize)> (secp_ctx: &Secp256k1<T>, path: &Path, session_priv: &SecretKey, mut callback: FType) -> Result<(), secp256k1::Error> {
 	let mut blinded_priv = session_priv.clone();
 	let mut blinded_pub = PublicKey::from_secret_key(secp_ctx, &blinded_priv);
 
-	for (idx, hop) in path.iter().enumerate() {
-		let shared_secret = SharedSecret::new(&hop.pubkey, &blinded_priv);
+	let unblinded_hops_iter = path.hops.iter().map(|hop| (&hop.pubkey, Some(hop)));
+	let blinded_pks_iter = path.blinded_tail.as_ref()
+		.map(|tail| tail.hops.iter()).unwrap_or([].iter())
+		.skip(1) // The introduction node is included in the unblinded hops
+		.map(|hop| (&hop.blinded_node_id, None));
+	for (idx, (pubkey, route_hop_opt)) in unblinded_hops_iter.chain(blinded_pks_iter).enumerate() {
+		let shared_secret = SharedSecret::new(pubkey, &blinded_priv);
 
 		let mut sha = Sha256::engine();
 		sha.input(&blinded_pub.serialize()[..]);
@@ -121,7 +158,7 @@ pub(super) fn construct_onion_keys_callback<T: secp256k1::Signing, FType: FnMut(
 		blinded_priv = blinded_priv.mul_tweak(&Scalar::from_be_bytes(blinding_factor).unwrap())?;
 		blinded_pub = PublicKey::from_secret_key(secp_ctx, &blinded_priv);
 
-		callback(shared_secret, blinding_factor, ephemeral_pubkey, hop, idx);
+		callback(shared_secret, blinding_factor, ephemeral_pubkey, route_hop_opt, idx);
 	}
 
 	Ok(())
@@ -131,7 +168,7 @@ pub(super) fn construct_onion_keys_callback<T: secp256k1::Signing, FType: FnMut(
 pub(super) fn construct_onion_keys<T: secp256k1::Signing>(secp_ctx: &Secp256k1<T>, path: &Path, session_priv: &SecretKey) -> Result<Vec<OnionKeys>, secp256k1::Error> {
 	let mut res = Vec::with_capacity(path.hops.len());
 
-	construct_onion_keys_callback(secp_ctx, &path.hops, session_priv, |shared_secret, _blinding_factor, ephemeral_pubkey, _, _| {
+	construct_onion_keys_callback(secp_ctx, &path, session_priv, |shared_secret, _blinding_factor, ephemeral_pubkey, _, _| {
 		let (rho, mu) = gen_rho_mu_from_shared_secret(shared_secret.as_ref());
 
 		res.push(OnionKeys {
@@ -148,12 +185,100 @@ pub(super) fn construct_onion_keys<T: secp256k1::Signing>(secp_ctx: &Secp256k1<T
 	Ok(res)
 }
 
+// can only fail if a trampoline hop has an invalid public key or session_priv is invalid
+pub(super) fn construct_trampoline_onion_keys<T: secp256k1::Signing>(secp_ctx: &Secp256k1<T>, node_ids: &[PublicKey], session_priv: &SecretKey) -> Result<Vec<OnionKeys>, secp256k1::Error> {
+	let mut res = Vec::with_capacity(node_ids.len());
+	let mut blinded_priv = session_priv.clone();
+	let mut blinded_pub = PublicKey::from_secret_key(secp_ctx, &blinded_priv);
+
+	for node_id in node_ids.iter() {
+		let shared_secret = SharedSecret::new(node_id, &blinded_priv);
+
+		let mut sha = Sha256::engine();
+		sha.input(&blinded_pub.serialize()[..]);
+		sha.input(shared_secret.as_ref());
+		let blinding_factor = Sha256::from_engine(sha).into_inner();
+
+		let (rho, mu) = gen_rho_mu_from_shared_secret(shared_secret.as_ref());
+		res.push(OnionKeys {
+			#[cfg(test)]
+			shared_secret,
+			#[cfg(test)]
+			blinding_factor,
+			ephemeral_pubkey: blinded_pub,
+			rho,
+			mu,
+		});
+
+		blinded_priv = blinded_priv.mul_tweak(&Scalar::from_be_bytes(blinding_factor).unwrap())?;
+		blinded_pub = PublicKey::from_secret_key(secp_ctx, &blinded_priv);
+	}
+
+	Ok(res)
+}
+
+/// Builds the trampoline onion telling `trampoline_node_id` to pay `recipient_amt_msat` to
+/// `recipient_node_id`, along with the recipient's own payload.
+///
+/// The returned packet is placed in the final hop payload of the outer onion, which itself only
+/// routes to the trampoline node.
+pub(super) fn create_trampoline_onion<T: secp256k1::Signing>(
+	secp_ctx: &Secp256k1<T>, trampoline_node_id: PublicKey, recipient_node_id: PublicKey,
+	recipient_amt_msat: u64, recipient_cltv: u32, mut recipient_onion: RecipientOnionFields,
+	keysend_preimage: &Option<PaymentPreimage>, session_priv: &SecretKey, prng_seed: [u8; 32],
+	payment_hash: &PaymentHash,
+) -> Result<msgs::TrampolineOnionPacket, APIError> {
+	let payloads = vec![
+		msgs::OnionHopData {
+			format: msgs::OnionHopDataFormat::TrampolineForward {
+				outgoing_node_id: recipient_node_id,
+			},
+			amt_to_forward: recipient_amt_msat,
+			outgoing_cltv_value: recipient_cltv,
+		},
+		msgs::OnionHopData {
+			format: msgs::OnionHopDataFormat::FinalNode {
+				payment_data: recipient_onion.payment_secret.take().map(|payment_secret| {
+					msgs::FinalOnionHopData { payment_secret, total_msat: recipient_amt_msat }
+				}),
+				payment_metadata: recipient_onion.payment_metadata.take(),
+				keysend_preimage: *keysend_preimage,
+			},
+			amt_to_forward: recipient_amt_msat,
+			outgoing_cltv_value: recipient_cltv,
+		},
+	];
+	let onion_keys = construct_trampoline_onion_keys(secp_ctx, &[trampoline_node_id, recipient_node_id], session_priv)
+		.map_err(|_| APIError::InvalidRoute { err: "Trampoline node or recipient had an invalid public key".to_owned() })?;
+
+	let packet_data_len = cmp::max(TRAMPOLINE_ONION_DATA_LEN, payloads_serialized_length(&payloads));
+	let mut packet_data = vec![0; packet_data_len];
+	let mut chacha = ChaCha20::new(&prng_seed, &[0; 8]);
+	chacha.process_in_place(&mut packet_data);
+
+	construct_onion_packet_with_init_noise::<_, msgs::TrampolineOnionPacket>(
+		payloads, onion_keys, packet_data, Some(payment_hash))
+		.map_err(|_| APIError::InvalidRoute { err: "Trampoline onion data too large".to_owned() })
+}
+
 /// returns the hop data, as well as the first-hop value_msat and CLTV value we should send.
-pub(super) fn build_onion_payloads(path: &Path, total_msat: u64, mut recipient_onion: RecipientOnionFields, starting_htlc_offset: u32, keysend_preimage: &Option<PaymentPreimage>) -> Result<(Vec<msgs::OnionHopData>, u64, u32), APIError> {
+#[cfg(test)]
+pub(super) fn build_onion_payloads(path: &Path, total_msat: u64, recipient_onion: RecipientOnionFields, starting_htlc_offset: u32, keysend_preimage: &Option<PaymentPreimage>) -> Result<(Vec<msgs::OnionHopData>, u64, u32), APIError> {
+	build_onion_payloads_with_trampoline(path, total_msat, recipient_onion, None, starting_htlc_offset, keysend_preimage)
+}
+
+/// returns the hop data, as well as the first-hop value_msat and CLTV value we should send.
+///
+/// If a `trampoline_packet` is given, the final hop is a trampoline node which gets the packet
+/// along with the `recipient_onion`'s payment secret, which must then be one picked for the
+/// trampoline hop rather than one provided by the recipient.
+pub(super) fn build_onion_payloads_with_trampoline(path: &Path, total_msat: u64, mut recipient_onion: RecipientOnionFields, mut trampoline_packet: Option<msgs::TrampolineOnionPacket>, starting_htlc_offset: u32, keysend_preimage: &Option<PaymentPreimage>) -> Result<(Vec<msgs::OnionHopData>, u64, u32), APIError> {
 	let mut cur_value_msat = 0u64;
 	let mut cur_cltv = starting_htlc_offset;
 	let mut last_short_channel_id = 0;
-	let mut res: Vec<msgs::OnionHopData> = Vec::with_capacity(path.hops.len());
+	let mut res: Vec<msgs::OnionHopData> = Vec::with_capacity(
+		path.hops.len() + path.blinded_tail.as_ref().map_or(0, |tail| tail.hops.len())
+	);
 
 	for (idx, hop) in path.hops.iter().rev().enumerate() {
 		// First hop gets special values so that it can check, on receipt, that everything is
@@ -161,17 +286,72 @@ pub(super) fn build_onion_payloads(path: &Path, total_msat: u64, mut recipient_o
 		// the intended recipient).
 		let value_msat = if cur_value_msat == 0 { hop.fee_msat } else { cur_value_msat };
 		let cltv = if cur_cltv == starting_htlc_offset { hop.cltv_expiry_delta + starting_htlc_offset } else { cur_cltv };
+		if idx == 0 {
+			if let Some(BlindedTail {
+				blinding_point, hops, final_value_msat, excess_final_cltv_expiry_delta, ..
+			}) = &path.blinded_tail {
+				if trampoline_packet.is_some() || keysend_preimage.is_some() {
+					return Err(APIError::InvalidRoute {
+						err: "Blinded paths can only be used for payments to the path's recipient".to_owned()
+					});
+				}
+				// The last unblinded hop is the introduction node, whose fee and CLTV delta are
+				// those of the whole blinded path, which only its (blinded) hops can compute. Thus
+				// they only get the recipient-provided data, plus the blinding point for the
+				// introduction node, while the recipient additionally gets the amount and CLTV.
+				// As we don't know the recipient's share of the path's CLTV delta, its CLTV is
+				// only the current height, plus any excess delta.
+				let final_cltv = cur_cltv + excess_final_cltv_expiry_delta;
+				let mut blinding_point = Some(*blinding_point);
+				for (i, blinded_hop) in hops.iter().enumerate() {
+					let is_final_hop = i == hops.len() - 1;
+					res.push(msgs::OnionHopData {
+						format: msgs::OnionHopDataFormat::OutboundBlinded {
+							encrypted_tlvs: blinded_hop.encrypted_payload.clone(),
+							intro_node_blinding_point: blinding_point.take(),
+							total_msat: if is_final_hop { Some(total_msat) } else { None },
+						},
+						amt_to_forward: if is_final_hop { *final_value_msat } else { 0 },
+						outgoing_cltv_value: if is_final_hop { final_cltv } else { 0 },
+					});
+				}
+				cur_value_msat += final_value_msat;
+				cur_cltv += excess_final_cltv_expiry_delta;
+				cur_value_msat += hop.fee_msat;
+				if cur_value_msat >= 21000000 * 100000000 * 1000 {
+					return Err(APIError::InvalidRoute{err: "Channel fees overflowed?".to_owned()});
+				}
+				cur_cltv += hop.cltv_expiry_delta as u32;
+				if cur_cltv >= 500000000 {
+					return Err(APIError::InvalidRoute{err: "Channel CLTV overflowed?".to_owned()});
+				}
+				last_short_channel_id = hop.short_channel_id;
+				continue;
+			}
+		}
 		res.insert(0, msgs::OnionHopData {
 			format: if idx == 0 {
-				msgs::OnionHopDataFormat::FinalNode {
-					payment_data: if let Some(secret) = recipient_onion.payment_secret.take() {
-						Some(msgs::FinalOnionHopData {
-							payment_secret: secret,
+				if let Some(trampoline_packet) = trampoline_packet.take() {
+					msgs::OnionHopDataFormat::TrampolineEntry {
+						payment_data: msgs::FinalOnionHopData {
+							payment_secret: recipient_onion.payment_secret.take().ok_or_else(|| APIError::APIMisuseError {
+								err: "A payment secret is required when paying via a trampoline node".to_owned()
+							})?,
 							total_msat,
-						})
-					} else { None },
-					payment_metadata: recipient_onion.payment_metadata.take(),
-					keysend_preimage: *keysend_preimage,
+						},
+						trampoline_packet,
+					}
+				} else {
+					msgs::OnionHopDataFormat::FinalNode {
+						payment_data: if let Some(secret) = recipient_onion.payment_secret.take() {
+							Some(msgs::FinalOnionHopData {
+								payment_secret: secret,
+								total_msat,
+							})
+						} else { None },
+						payment_metadata: recipient_onion.payment_metadata.take(),
+						keysend_preimage: *keysend_preimage,
+					}
 				}
 			} else {
 				msgs::OnionHopDataFormat::NonFinalNode {
@@ -194,10 +374,27 @@ pub(super) fn build_onion_payloads(path: &Path, total_msat: u64, mut recipient_o
 	Ok((res, cur_value_msat, cur_cltv))
 }
 
+/// The failure code used for any failure of an HTLC received within a blinded path, which hides
+/// the actual cause of the failure from the sender.
+pub(crate) const INVALID_ONION_BLINDING: u16 = 0x8000 | 0x4000 | 24;
+
 /// Length of the onion data packet. Before TLV-based onions this was 20 65-byte hops, though now
 /// the hops can be of variable length.
 pub(crate) const ONION_DATA_LEN: usize = 20*65;
 
+/// The minimum length of the hop data in a trampoline onion packet. Trampoline onions are padded to
+/// at least this length so that the number of trampoline hops isn't revealed, but may be larger if
+/// the payloads don't fit.
+pub(crate) const TRAMPOLINE_ONION_DATA_LEN: usize = 400;
+
+/// The failure code used when the fee we were paid for forwarding a trampoline payment doesn't
+/// cover the cost of routing it.
+pub(crate) const TRAMPOLINE_FEE_INSUFFICIENT: u16 = 0x2000 | 51;
+
+/// The failure code used when the CLTV expiry of a trampoline payment we received leaves too little
+/// room to route it onwards.
+pub(crate) const TRAMPOLINE_EXPIRY_TOO_SOON: u16 = 0x2000 | 52;
+
 #[inline]
 fn shift_slice_right(arr: &mut [u8], amt: usize) {
 	for i in (amt..arr.len()).rev() {
@@ -390,9 +587,24 @@ pub(super) fn process_onion_failure<T: secp256k1::Signing, L: Deref>(secp_ctx: &
 		let mut is_from_final_node = false;
 
 		// Handle packed channel/node updates for passing back for the route handler
-		construct_onion_keys_callback(secp_ctx, &path.hops, session_priv, |shared_secret, _, _, route_hop, route_hop_idx| {
+		construct_onion_keys_callback(secp_ctx, &path, session_priv, |shared_secret, _, _, route_hop_opt, route_hop_idx| {
 			if res.is_some() { return; }
 
+			let route_hop = match route_hop_opt {
+				Some(hop) => hop,
+				// We never get past the introduction node of a blinded path, see below.
+				None => return,
+			};
+
+			// Nodes from the introduction node of a blinded path onwards mask all of their failures
+			// as `invalid_onion_blinding`, which doesn't tell us which hop failed or why.
+			if path.blinded_tail.is_some() && route_hop_idx + 1 == path.hops.len() {
+				error_code_ret = Some(INVALID_ONION_BLINDING);
+				error_packet_ret = Some(vec![0; 32]);
+				res = Some((None, None, true));
+				return;
+			}
+
 			let amt_to_forward = htlc_msat - route_hop.fee_msat;
 			htlc_msat = amt_to_forward;
 
@@ -684,6 +896,7 @@ impl HTLCFailReason {
 		else if failure_code == 21 { debug_assert!(data.is_empty()) }
 		else if failure_code == 22 | PERM { debug_assert!(data.len() <= 11) }
 		else if failure_code == 23 { debug_assert!(data.is_empty()) }
+		else if failure_code == INVALID_ONION_BLINDING { debug_assert_eq!(data.len(), 32) }
 		else if failure_code & BADONION != 0 {
 			// We set some bogus BADONION failure codes in test, so ignore unknown ones.
 		}
@@ -790,8 +1003,11 @@ pub(crate) enum OnionDecodeErr {
 	},
 }
 
-pub(crate) fn decode_next_payment_hop(shared_secret: [u8; 32], hop_data: &[u8], hmac_bytes: [u8; 32], payment_hash: PaymentHash) -> Result<Hop, OnionDecodeErr> {
-	match decode_next_hop(shared_secret, hop_data, hmac_bytes, Some(payment_hash), ()) {
+pub(crate) fn decode_next_payment_hop<NS: Deref>(
+	shared_secret: [u8; 32], hop_data: &[u8], hmac_bytes: [u8; 32], payment_hash: PaymentHash,
+	blinding_point: Option<PublicKey>, node_signer: &NS,
+) -> Result<Hop, OnionDecodeErr> where NS::Target: NodeSigner {
+	match decode_next_hop(shared_secret, hop_data, hmac_bytes, Some(payment_hash), (blinding_point, node_signer)) {
 		Ok((next_hop_data, None)) => Ok(Hop::Receive(next_hop_data)),
 		Ok((next_hop_data, Some((next_hop_hmac, FixedSizeOnionPacket(new_packet_bytes))))) => {
 			Ok(Hop::Forward {
@@ -804,6 +1020,15 @@ pub(crate) fn decode_next_payment_hop(shared_secret: [u8; 32], hop_data: &[u8],
 	}
 }
 
+/// Decodes the next hop of a trampoline onion received within a payment's final hop payload. If
+/// we're to forward the payment, the bytes of the trampoline packet to pass on are returned along
+/// with its HMAC.
+pub(crate) fn decode_next_trampoline_hop(
+	shared_secret: [u8; 32], hop_data: &[u8], hmac_bytes: [u8; 32], payment_hash: PaymentHash,
+) -> Result<(msgs::OnionHopData, Option<([u8; 32], Vec<u8>)>), OnionDecodeErr> {
+	decode_next_hop(shared_secret, hop_data, hmac_bytes, Some(payment_hash), ())
+}
+
 pub(crate) fn decode_next_untagged_hop<T, R: ReadableArgs<T>, N: NextPacketBytes>(shared_secret: [u8; 32], hop_data: &[u8], hmac_bytes: [u8; 32], read_args: T) -> Result<(R, Option<([u8; 32], N)>), OnionDecodeErr> {
 	decode_next_hop(shared_secret, hop_data, hmac_bytes, None, read_args)
 }
@@ -889,16 +1114,18 @@ fn decode_next_hop<T, R: ReadableArgs<T>, N: NextPacketBytes>(shared_secret: [u8
 mod tests {
 	use crate::io;
 	use crate::prelude::*;
-	use crate::ln::PaymentHash;
+	use crate::ln::{PaymentHash, PaymentSecret};
 	use crate::ln::features::{ChannelFeatures, NodeFeatures};
+	use crate::ln::outbound_payment::RecipientOnionFields;
 	use crate::routing::router::{Path, Route, RouteHop};
 	use crate::ln::msgs;
-	use crate::util::ser::{Writeable, Writer, VecWriter};
+	use crate::util::ser::{Readable, Writeable, Writer, VecWriter};
 
 	use hex;
 
 	use bitcoin::secp256k1::Secp256k1;
 	use bitcoin::secp256k1::{PublicKey,SecretKey};
+	use bitcoin::secp256k1::ecdh::SharedSecret;
 
 	use super::OnionKeys;
 
@@ -1097,6 +1324,56 @@ mod tests {
 		assert_eq!(onion_packet_5.data, hex::decode("9c5add3963fc7f6ed7f148623c84134b5647e1306419dbe2174e523fa9e2fbed3a06a19f899145610741c83ad40b7712aefaddec8c6baf7325d92ea4ca4d1df8bce517f7e54554608bf2bd8071a4f52a7a2f7ffbb1413edad81eeea5785aa9d990f2865dc23b4bc3c301a94eec4eabebca66be5cf638f693ec256aec514620cc28ee4a94bd9565bc4d4962b9d3641d4278fb319ed2b84de5b665f307a2db0f7fbb757366067d88c50f7e829138fde4f78d39b5b5802f1b92a8a820865af5cc79f9f30bc3f461c66af95d13e5e1f0381c184572a91dee1c849048a647a1158cf884064deddbf1b0b88dfe2f791428d0ba0f6fb2f04e14081f69165ae66d9297c118f0907705c9c4954a199bae0bb96fad763d690e7daa6cfda59ba7f2c8d11448b604d12d").unwrap());
 	}
 
+	#[test]
+	fn trampoline_onion_roundtrip() {
+		// Build a trampoline onion and check that the trampoline node and then the recipient can
+		// each peel their layer of it.
+		let secp_ctx = Secp256k1::new();
+		let trampoline_secret = SecretKey::from_slice(&[42; 32]).unwrap();
+		let recipient_secret = SecretKey::from_slice(&[43; 32]).unwrap();
+		let trampoline_node_id = PublicKey::from_secret_key(&secp_ctx, &trampoline_secret);
+		let recipient_node_id = PublicKey::from_secret_key(&secp_ctx, &recipient_secret);
+		let payment_hash = PaymentHash([1; 32]);
+		let payment_secret = PaymentSecret([2; 32]);
+
+		let packet = super::create_trampoline_onion(&secp_ctx, trampoline_node_id, recipient_node_id,
+			10_000, 800_000, RecipientOnionFields::secret_only(payment_secret), &None,
+			&get_test_session_key(), [3; 32], &payment_hash).unwrap();
+		assert_eq!(packet.hop_data.len(), super::TRAMPOLINE_ONION_DATA_LEN);
+		let mut encoded_packet = io::Cursor::new(packet.encode());
+		assert_eq!(msgs::TrampolineOnionPacket::read(&mut encoded_packet).unwrap(), packet);
+
+		let shared_secret = SharedSecret::new(&packet.public_key, &trampoline_secret).secret_bytes();
+		let (hop_data, next_hop) = super::decode_next_trampoline_hop(
+			shared_secret, &packet.hop_data, packet.hmac, payment_hash).unwrap();
+		match hop_data.format {
+			msgs::OnionHopDataFormat::TrampolineForward { outgoing_node_id } =>
+				assert_eq!(outgoing_node_id, recipient_node_id),
+			_ => panic!("Unexpected trampoline hop data"),
+		}
+		assert_eq!(hop_data.amt_to_forward, 10_000);
+		assert_eq!(hop_data.outgoing_cltv_value, 800_000);
+		let (next_hmac, next_hop_data) = next_hop.unwrap();
+
+		let next_pubkey = super::next_hop_packet_pubkey(&secp_ctx, packet.public_key, &shared_secret).unwrap();
+		let shared_secret = SharedSecret::new(&next_pubkey, &recipient_secret).secret_bytes();
+		let (hop_data, next_hop) = super::decode_next_trampoline_hop(
+			shared_secret, &next_hop_data, next_hmac, payment_hash).unwrap();
+		assert!(next_hop.is_none());
+		match hop_data.format {
+			msgs::OnionHopDataFormat::FinalNode { payment_data: Some(payment_data), .. } => {
+				assert_eq!(payment_data.payment_secret, payment_secret);
+				assert_eq!(payment_data.total_msat, 10_000);
+			},
+			_ => panic!("Unexpected recipient hop data"),
+		}
+
+		// The trampoline onion is committed to the payment hash.
+		let shared_secret = SharedSecret::new(&packet.public_key, &trampoline_secret).secret_bytes();
+		assert!(super::decode_next_trampoline_hop(
+			shared_secret, &packet.hop_data, packet.hmac, PaymentHash([4; 32])).is_err());
+	}
+
 	struct RawOnionHopData {
 		data: Vec<u8>
 	}
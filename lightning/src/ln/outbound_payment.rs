@@ -9,7 +9,7 @@
 
 //! Utilities to send payments and manage outbound payment information.
 
-use bitcoin::hashes::Hash;
+use bitcoin::hashes::{Hash, HashEngine};
 use bitcoin::hashes::sha256::Hash as Sha256;
 use bitcoin::secp256k1::{self, Secp256k1, SecretKey};
 
@@ -17,27 +17,45 @@ use crate::sign::{EntropySource, NodeSigner, Recipient};
 use crate::events::{self, PaymentFailureReason};
 use crate::ln::{PaymentHash, PaymentPreimage, PaymentSecret};
 use crate::ln::channelmanager::{ChannelDetails, EventCompletionAction, HTLCSource, IDEMPOTENCY_TIMEOUT_TICKS, PaymentId};
+use crate::ln::msgs::DecodeError;
 use crate::ln::onion_utils::HTLCFailReason;
+use crate::offers::invoice::Bolt12Invoice;
 use crate::routing::router::{InFlightHtlcs, Path, PaymentParameters, Route, RouteParameters, Router};
 use crate::util::errors::APIError;
 use crate::util::logger::Logger;
 use crate::util::time::Time;
 #[cfg(all(not(feature = "no-std"), test))]
 use crate::util::time::tests::SinceEpoch;
-use crate::util::ser::ReadableArgs;
+use crate::util::ser::{Readable, ReadableArgs, Writeable, Writer};
 
 use core::fmt::{self, Display, Formatter};
 use core::ops::Deref;
 
+use crate::io;
 use crate::prelude::*;
 use crate::sync::Mutex;
 
+/// The number of ticks of [`ChannelManager::timer_tick_occurred`] until an invoice request without
+/// a response is timed out.
+///
+/// [`ChannelManager::timer_tick_occurred`]: crate::ln::channelmanager::ChannelManager::timer_tick_occurred
+pub(crate) const INVOICE_REQUEST_TIMEOUT_TICKS: u8 = 3;
+
 /// Stores the session_priv for each part of a payment that is still pending. For versions 0.0.102
 /// and later, also stores information for retrying the payment.
 pub(crate) enum PendingOutboundPayment {
 	Legacy {
 		session_privs: HashSet<[u8; 32]>,
 	},
+	/// An [`InvoiceRequest`] was sent for an [`Offer`] and we are waiting for the corresponding
+	/// [`Bolt12Invoice`] before the payment can be sent.
+	///
+	/// [`InvoiceRequest`]: crate::offers::invoice_request::InvoiceRequest
+	/// [`Offer`]: crate::offers::offer::Offer
+	AwaitingInvoice {
+		timer_ticks_without_response: u8,
+		retry_strategy: Retry,
+	},
 	Retryable {
 		retry_strategy: Option<Retry>,
 		attempts: PaymentAttempts,
@@ -119,6 +137,12 @@ impl PendingOutboundPayment {
 			_ => false,
 		}
 	}
+	fn is_awaiting_invoice(&self) -> bool {
+		match self {
+			PendingOutboundPayment::AwaitingInvoice { .. } => true,
+			_ => false,
+		}
+	}
 	fn get_pending_fee_msat(&self) -> Option<u64> {
 		match self {
 			PendingOutboundPayment::Retryable { pending_fee_msat, .. } => pending_fee_msat.clone(),
@@ -129,6 +153,7 @@ impl PendingOutboundPayment {
 	fn payment_hash(&self) -> Option<PaymentHash> {
 		match self {
 			PendingOutboundPayment::Legacy { .. } => None,
+			PendingOutboundPayment::AwaitingInvoice { .. } => None,
 			PendingOutboundPayment::Retryable { payment_hash, .. } => Some(*payment_hash),
 			PendingOutboundPayment::Fulfilled { payment_hash, .. } => *payment_hash,
 			PendingOutboundPayment::Abandoned { payment_hash, .. } => Some(*payment_hash),
@@ -141,8 +166,11 @@ impl PendingOutboundPayment {
 			PendingOutboundPayment::Legacy { session_privs } |
 				PendingOutboundPayment::Retryable { session_privs, .. } |
 				PendingOutboundPayment::Fulfilled { session_privs, .. } |
-				PendingOutboundPayment::Abandoned { session_privs, .. }
-			=> session_privs,
+				PendingOutboundPayment::Abandoned { session_privs, .. } => session_privs,
+			PendingOutboundPayment::AwaitingInvoice { .. } => {
+				debug_assert!(false);
+				return;
+			},
 		});
 		let payment_hash = self.payment_hash();
 		*self = PendingOutboundPayment::Fulfilled { session_privs, payment_hash, timer_ticks_without_htlcs: 0 };
@@ -168,7 +196,8 @@ impl PendingOutboundPayment {
 				PendingOutboundPayment::Fulfilled { session_privs, .. } |
 				PendingOutboundPayment::Abandoned { session_privs, .. } => {
 					session_privs.remove(session_priv)
-				}
+				},
+			PendingOutboundPayment::AwaitingInvoice { .. } => false,
 		};
 		if remove_res {
 			if let PendingOutboundPayment::Retryable { ref mut pending_amt_msat, ref mut pending_fee_msat, .. } = self {
@@ -188,6 +217,7 @@ impl PendingOutboundPayment {
 				PendingOutboundPayment::Retryable { session_privs, .. } => {
 					session_privs.insert(session_priv)
 				}
+			PendingOutboundPayment::AwaitingInvoice { .. } => false,
 			PendingOutboundPayment::Fulfilled { .. } => false,
 			PendingOutboundPayment::Abandoned { .. } => false,
 		};
@@ -209,7 +239,8 @@ impl PendingOutboundPayment {
 				PendingOutboundPayment::Fulfilled { session_privs, .. } |
 				PendingOutboundPayment::Abandoned { session_privs, .. } => {
 					session_privs.len()
-				}
+				},
+			PendingOutboundPayment::AwaitingInvoice { .. } => 0,
 		}
 	}
 }
@@ -247,6 +278,42 @@ impl Retry {
 	}
 }
 
+impl Writeable for Retry {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		match self {
+			Retry::Attempts(max_retry_count) => {
+				0u8.write(w)?;
+				(*max_retry_count as u64).write(w)
+			},
+			#[cfg(not(feature = "no-std"))]
+			Retry::Timeout(max_duration) => {
+				2u8.write(w)?;
+				max_duration.as_secs().write(w)?;
+				max_duration.subsec_nanos().write(w)
+			},
+		}
+	}
+}
+
+impl Readable for Retry {
+	fn read<R: io::Read>(r: &mut R) -> Result<Self, DecodeError> {
+		let id: u8 = Readable::read(r)?;
+		match id {
+			0 => {
+				let max_retry_count: u64 = Readable::read(r)?;
+				Ok(Retry::Attempts(max_retry_count as usize))
+			},
+			#[cfg(not(feature = "no-std"))]
+			2 => {
+				let secs: u64 = Readable::read(r)?;
+				let nanos: u32 = Readable::read(r)?;
+				Ok(Retry::Timeout(core::time::Duration::new(secs, nanos)))
+			},
+			_ => Err(DecodeError::InvalidValue),
+		}
+	}
+}
+
 #[cfg(feature = "std")]
 pub(super) fn has_expired(route_params: &RouteParameters) -> bool {
 	if let Some(expiry_time) = route_params.payment_params.expiry_time {
@@ -330,6 +397,29 @@ pub enum RetryableSendFailure {
 	DuplicatePayment,
 }
 
+/// Indicates that we failed to send a payment probe. Further errors may be surfaced later via
+/// [`Event::ProbeFailed`].
+///
+/// [`Event::ProbeFailed`]: crate::events::Event::ProbeFailed
+#[derive(Clone, Debug)]
+pub enum ProbeSendFailure {
+	/// We were unable to find a route to the destination.
+	RouteNotFound,
+	/// We failed to send the payment probes.
+	SendingFailed(PaymentSendFailure),
+}
+
+/// An error when attempting to pay a [`Bolt12Invoice`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) enum Bolt12PaymentError {
+	/// The invoice was not requested.
+	UnexpectedInvoice,
+	/// Payment for an invoice with the corresponding [`PaymentId`] was already initiated.
+	DuplicateInvoice,
+	/// The invoice was valid for the corresponding [`PaymentId`], but sending the payment failed.
+	SendingFailed(RetryableSendFailure),
+}
+
 /// If a payment fails to send with [`ChannelManager::send_payment_with_route`], it can be in one
 /// of several states. This enum is returned as the Err() type describing which state the payment
 /// is in, see the description of individual enum states for more.
@@ -483,6 +573,9 @@ pub(super) struct SendAlongPathArgs<'a> {
 	pub payment_id: PaymentId,
 	pub keysend_preimage: &'a Option<PaymentPreimage>,
 	pub session_priv_bytes: [u8; 32],
+	/// The parameters the route was found with, if any, used to determine whether the payment is
+	/// to be sent via a trampoline node.
+	pub payment_params: Option<&'a PaymentParameters>,
 }
 
 pub(super) struct OutboundPayments {
@@ -518,6 +611,60 @@ impl OutboundPayments {
 			best_block_height, logger, pending_events, &send_payment_along_path)
 	}
 
+	pub(super) fn send_payment_for_bolt12_invoice<R: Deref, ES: Deref, NS: Deref, IH, SP, L: Deref>(
+		&self, invoice: &Bolt12Invoice, payment_id: PaymentId, router: &R,
+		first_hops: Vec<ChannelDetails>, inflight_htlcs: IH, entropy_source: &ES, node_signer: &NS,
+		best_block_height: u32, logger: &L,
+		pending_events: &Mutex<VecDeque<(events::Event, Option<EventCompletionAction>)>>,
+		send_payment_along_path: SP,
+	) -> Result<(), Bolt12PaymentError>
+	where
+		R::Target: Router,
+		ES::Target: EntropySource,
+		NS::Target: NodeSigner,
+		L::Target: Logger,
+		IH: Fn() -> InFlightHtlcs,
+		SP: Fn(SendAlongPathArgs) -> Result<(), APIError>,
+	{
+		let payment_hash = invoice.payment_hash();
+		let retry_strategy = match self.pending_outbound_payments.lock().unwrap().entry(payment_id) {
+			hash_map::Entry::Occupied(entry) => match entry.get() {
+				PendingOutboundPayment::AwaitingInvoice { retry_strategy, .. } => {
+					let retry_strategy = *retry_strategy;
+					entry.remove();
+					retry_strategy
+				},
+				_ => return Err(Bolt12PaymentError::DuplicateInvoice),
+			},
+			hash_map::Entry::Vacant(_) => return Err(Bolt12PaymentError::UnexpectedInvoice),
+		};
+
+		let route_params = RouteParameters {
+			payment_params: PaymentParameters::from_bolt12_invoice(&invoice),
+			final_value_msat: invoice.amount_msats(),
+		};
+
+		if let Err(e) = self.send_payment_internal(
+			payment_id, payment_hash, RecipientOnionFields::spontaneous_empty(), None,
+			retry_strategy, route_params, router, first_hops, inflight_htlcs, entropy_source,
+			node_signer, best_block_height, logger, pending_events, send_payment_along_path
+		) {
+			// The payment was removed while awaiting the invoice, so a failure event is needed to
+			// let the user know the payment will not be completed.
+			let reason = match e {
+				RetryableSendFailure::PaymentExpired => PaymentFailureReason::PaymentExpired,
+				RetryableSendFailure::RouteNotFound => PaymentFailureReason::RouteNotFound,
+				RetryableSendFailure::DuplicatePayment => PaymentFailureReason::UnexpectedError,
+			};
+			pending_events.lock().unwrap().push_back((events::Event::PaymentFailed {
+				payment_id, payment_hash, reason: Some(reason),
+			}, None));
+			return Err(Bolt12PaymentError::SendingFailed(e));
+		}
+
+		Ok(())
+	}
+
 	pub(super) fn send_payment_with_route<ES: Deref, NS: Deref, F>(
 		&self, route: &Route, payment_hash: PaymentHash, recipient_onion: RecipientOnionFields,
 		payment_id: PaymentId, entropy_source: &ES, node_signer: &NS, best_block_height: u32,
@@ -644,7 +791,8 @@ impl OutboundPayments {
 	pub(super) fn needs_abandon(&self) -> bool {
 		let outbounds = self.pending_outbound_payments.lock().unwrap();
 		outbounds.iter().any(|(_, pmt)|
-			!pmt.is_auto_retryable_now() && pmt.remaining_parts() == 0 && !pmt.is_fulfilled())
+			!pmt.is_auto_retryable_now() && pmt.remaining_parts() == 0 && !pmt.is_fulfilled() &&
+			!pmt.is_awaiting_invoice())
 	}
 
 	/// Errors immediately on [`RetryableSendFailure`] error conditions. Otherwise, further errors may
@@ -694,7 +842,7 @@ impl OutboundPayments {
 	}
 
 	fn retry_payment_internal<R: Deref, NS: Deref, ES: Deref, IH, SP, L: Deref>(
-		&self, payment_hash: PaymentHash, payment_id: PaymentId, route_params: RouteParameters,
+		&self, payment_hash: PaymentHash, payment_id: PaymentId, mut route_params: RouteParameters,
 		router: &R, first_hops: Vec<ChannelDetails>, inflight_htlcs: &IH, entropy_source: &ES,
 		node_signer: &NS, best_block_height: u32, logger: &L,
 		pending_events: &Mutex<VecDeque<(events::Event, Option<EventCompletionAction>)>>, send_payment_along_path: &SP,
@@ -715,6 +863,16 @@ impl OutboundPayments {
 			}
 		}
 
+		// Any per-payment fee limit applies to the payment as a whole, so on retry we only allow
+		// whatever is left of it after accounting for the fees of paths still pending.
+		if let Some(PendingOutboundPayment::Retryable {
+			payment_params: Some(params), total_msat, pending_fee_msat, ..
+		}) = self.pending_outbound_payments.lock().unwrap().get(&payment_id) {
+			route_params.payment_params.max_total_routing_fee_msat = params.max_total_routing_fee(*total_msat)
+				.map(|max_fee_msat| max_fee_msat.saturating_sub(pending_fee_msat.unwrap_or(0)));
+			route_params.payment_params.max_total_routing_fee_proportional_millionths = None;
+		}
+
 		let route = match router.find_route_with_id(
 			&node_signer.get_node_id(Recipient::Node).unwrap(), &route_params,
 			Some(&first_hops.iter().collect::<Vec<_>>()), inflight_htlcs(),
@@ -779,6 +937,10 @@ impl OutboundPayments {
 							log_error!(logger, "Unable to retry payments that were initially sent on LDK versions prior to 0.0.102");
 							return
 						},
+						PendingOutboundPayment::AwaitingInvoice { .. } => {
+							log_error!(logger, "Payment not yet sent");
+							return
+						},
 						PendingOutboundPayment::Fulfilled { .. } => {
 							log_error!(logger, "Payment already completed");
 							return
@@ -946,6 +1108,23 @@ impl OutboundPayments {
 		self.add_new_pending_payment(payment_hash, recipient_onion, payment_id, None, route, retry_strategy, None, entropy_source, best_block_height)
 	}
 
+	pub(super) fn add_new_awaiting_invoice(
+		&self, payment_id: PaymentId, retry_strategy: Retry
+	) -> Result<(), ()> {
+		let mut pending_outbounds = self.pending_outbound_payments.lock().unwrap();
+		match pending_outbounds.entry(payment_id) {
+			hash_map::Entry::Occupied(_) => Err(()),
+			hash_map::Entry::Vacant(entry) => {
+				entry.insert(PendingOutboundPayment::AwaitingInvoice {
+					timer_ticks_without_response: 0,
+					retry_strategy,
+				});
+
+				Ok(())
+			},
+		}
+	}
+
 	pub(super) fn add_new_pending_payment<ES: Deref>(
 		&self, payment_hash: PaymentHash, recipient_onion: RecipientOnionFields, payment_id: PaymentId,
 		keysend_preimage: Option<PaymentPreimage>, route: &Route, retry_strategy: Option<Retry>,
@@ -1008,10 +1187,6 @@ impl OutboundPayments {
 				path_errs.push(Err(APIError::InvalidRoute{err: "Path didn't go anywhere/had bogus size".to_owned()}));
 				continue 'path_check;
 			}
-			if path.blinded_tail.is_some() {
-				path_errs.push(Err(APIError::InvalidRoute{err: "Sending to blinded paths isn't supported yet".to_owned()}));
-				continue 'path_check;
-			}
 			let dest_hop_idx = if path.blinded_tail.is_some() && path.blinded_tail.as_ref().unwrap().hops.len() > 1 {
 				usize::max_value() } else { path.hops.len() - 1 };
 			for (idx, hop) in path.hops.iter().enumerate() {
@@ -1036,7 +1211,8 @@ impl OutboundPayments {
 		for (path, session_priv_bytes) in route.paths.iter().zip(onion_session_privs.into_iter()) {
 			let mut path_res = send_payment_along_path(SendAlongPathArgs {
 				path: &path, payment_hash: &payment_hash, recipient_onion: recipient_onion.clone(),
-				total_value, cur_height, payment_id, keysend_preimage: &keysend_preimage, session_priv_bytes
+				total_value, cur_height, payment_id, keysend_preimage: &keysend_preimage, session_priv_bytes,
+				payment_params: route.payment_params.as_ref(),
 			});
 			match path_res {
 				Ok(_) => {},
@@ -1198,8 +1374,11 @@ impl OutboundPayments {
 		// removal. This should be more than sufficient to ensure the idempotency of any
 		// `send_payment` calls that were made at the same time the `PaymentSent` event was being
 		// processed.
+		//
+		// Payments still awaiting an invoice are also removed here, after
+		// INVOICE_REQUEST_TIMEOUT_TICKS, generating an `InvoiceRequestFailed` event.
 		let mut pending_outbound_payments = self.pending_outbound_payments.lock().unwrap();
-		let pending_events = pending_events.lock().unwrap();
+		let mut pending_events = pending_events.lock().unwrap();
 		pending_outbound_payments.retain(|payment_id, payment| {
 			if let PendingOutboundPayment::Fulfilled { session_privs, timer_ticks_without_htlcs, .. } = payment {
 				let mut no_remaining_entries = session_privs.is_empty();
@@ -1225,6 +1404,16 @@ impl OutboundPayments {
 					*timer_ticks_without_htlcs = 0;
 					true
 				}
+			} else if let PendingOutboundPayment::AwaitingInvoice { timer_ticks_without_response, .. } = payment {
+				*timer_ticks_without_response += 1;
+				if *timer_ticks_without_response <= INVOICE_REQUEST_TIMEOUT_TICKS {
+					true
+				} else {
+					pending_events.push_back(
+						(events::Event::InvoiceRequestFailed { payment_id: *payment_id }, None)
+					);
+					false
+				}
 			} else { true }
 		});
 	}
@@ -1351,6 +1540,15 @@ impl OutboundPayments {
 		pending_retry_ev
 	}
 
+	/// Returns whether the given payment has failed, i.e. has been abandoned with no HTLCs left
+	/// in-flight or is no longer tracked at all.
+	pub(super) fn has_failed(&self, payment_id: &PaymentId) -> bool {
+		match self.pending_outbound_payments.lock().unwrap().get(payment_id) {
+			Some(payment) => payment.abandoned() && payment.remaining_parts() == 0,
+			None => true,
+		}
+	}
+
 	pub(super) fn abandon_payment(
 		&self, payment_id: PaymentId, reason: PaymentFailureReason,
 		pending_events: &Mutex<VecDeque<(events::Event, Option<EventCompletionAction>)>>
@@ -1367,6 +1565,11 @@ impl OutboundPayments {
 					}, None));
 					payment.remove();
 				}
+			} else if let PendingOutboundPayment::AwaitingInvoice { .. } = payment.get() {
+				pending_events.lock().unwrap().push_back((events::Event::InvoiceRequestFailed {
+					payment_id,
+				}, None));
+				payment.remove();
 			}
 		}
 	}
@@ -1399,6 +1602,25 @@ fn probing_cookie_from_id(payment_id: &PaymentId, probing_cookie_secret: [u8; 32
 	PaymentHash(Sha256::hash(&preimage).into_inner())
 }
 
+/// Returns the [`PaymentId`] of the payment we make to the next hop when forwarding the trampoline
+/// payment with the given [`PaymentHash`]. Deriving it from a secret keeps it from colliding with
+/// the [`PaymentId`]s of payments made by the user, while allowing us to recognize it later.
+pub(super) fn trampoline_forward_payment_id(payment_hash: &PaymentHash, trampoline_secret: [u8; 32]) -> PaymentId {
+	let mut engine = Sha256::engine();
+	engine.input(b"LDK trampoline forward payment id");
+	engine.input(&trampoline_secret);
+	engine.input(&payment_hash.0);
+	PaymentId(Sha256::from_engine(engine).into_inner())
+}
+
+/// Returns whether a payment with the given [`PaymentHash`] and [`PaymentId`] is one we made to
+/// forward a trampoline payment.
+pub(super) fn payment_is_trampoline_forward(payment_hash: &PaymentHash, payment_id: &PaymentId,
+	trampoline_secret: [u8; 32]) -> bool
+{
+	trampoline_forward_payment_id(payment_hash, trampoline_secret) == *payment_id
+}
+
 impl_writeable_tlv_based_enum_upgradable!(PendingOutboundPayment,
 	(0, Legacy) => {
 		(0, session_privs, required),
@@ -1429,6 +1651,10 @@ impl_writeable_tlv_based_enum_upgradable!(PendingOutboundPayment,
 		(1, reason, option),
 		(2, payment_hash, required),
 	},
+	(5, AwaitingInvoice) => {
+		(0, timer_ticks_without_response, required),
+		(2, retry_strategy, required),
+	},
 );
 
 #[cfg(test)]
@@ -1441,10 +1667,10 @@ mod tests {
 	use crate::ln::channelmanager::{PaymentId, RecipientOnionFields};
 	use crate::ln::features::{ChannelFeatures, NodeFeatures};
 	use crate::ln::msgs::{ErrorAction, LightningError};
-	use crate::ln::outbound_payment::{OutboundPayments, Retry, RetryableSendFailure};
+	use crate::ln::outbound_payment::{INVOICE_REQUEST_TIMEOUT_TICKS, OutboundPayments, Retry, RetryableSendFailure};
 	use crate::routing::gossip::NetworkGraph;
 	use crate::routing::router::{InFlightHtlcs, Path, PaymentParameters, Route, RouteHop, RouteParameters};
-	use crate::sync::{Arc, Mutex};
+	use crate::sync::{Arc, Mutex, RwLock};
 	use crate::util::errors::APIError;
 	use crate::util::test_utils;
 
@@ -1461,7 +1687,7 @@ mod tests {
 		let outbound_payments = OutboundPayments::new();
 		let logger = test_utils::TestLogger::new();
 		let network_graph = Arc::new(NetworkGraph::new(Network::Testnet, &logger));
-		let scorer = Mutex::new(test_utils::TestScorer::new());
+		let scorer = RwLock::new(test_utils::TestScorer::new());
 		let router = test_utils::TestRouter::new(network_graph, &scorer);
 		let secp_ctx = Secp256k1::new();
 		let keys_manager = test_utils::TestKeysInterface::new(&[0; 32], Network::Testnet);
@@ -1508,7 +1734,7 @@ mod tests {
 		let outbound_payments = OutboundPayments::new();
 		let logger = test_utils::TestLogger::new();
 		let network_graph = Arc::new(NetworkGraph::new(Network::Testnet, &logger));
-		let scorer = Mutex::new(test_utils::TestScorer::new());
+		let scorer = RwLock::new(test_utils::TestScorer::new());
 		let router = test_utils::TestRouter::new(network_graph, &scorer);
 		let secp_ctx = Secp256k1::new();
 		let keys_manager = test_utils::TestKeysInterface::new(&[0; 32], Network::Testnet);
@@ -1545,12 +1771,65 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn retry_limits_routing_fee_to_remaining_budget() {
+		// On retry, the fees of any paths still pending are deducted from the payment's fee limit.
+		let outbound_payments = OutboundPayments::new();
+		let logger = test_utils::TestLogger::new();
+		let network_graph = Arc::new(NetworkGraph::new(Network::Testnet, &logger));
+		let scorer = RwLock::new(test_utils::TestScorer::new());
+		let router = test_utils::TestRouter::new(network_graph, &scorer);
+		let secp_ctx = Secp256k1::new();
+		let keys_manager = test_utils::TestKeysInterface::new(&[0; 32], Network::Testnet);
+
+		let intermediate_pk = PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[41; 32]).unwrap());
+		let receiver_pk = PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[42; 32]).unwrap());
+		// The proportional limit of 5% of the 10_000 msat payment is the lower of the two.
+		let payment_params = PaymentParameters::from_node_id(receiver_pk, 0)
+			.with_max_total_routing_fee_msat(1_000)
+			.with_max_total_routing_fee_proportional_millionths(50_000);
+		let route = Route {
+			paths: vec![Path { hops: vec![RouteHop {
+				pubkey: intermediate_pk,
+				node_features: NodeFeatures::empty(),
+				short_channel_id: 42,
+				channel_features: ChannelFeatures::empty(),
+				fee_msat: 300,
+				cltv_expiry_delta: 0,
+			}, RouteHop {
+				pubkey: receiver_pk,
+				node_features: NodeFeatures::empty(),
+				short_channel_id: 43,
+				channel_features: ChannelFeatures::empty(),
+				fee_msat: 10_000,
+				cltv_expiry_delta: 0,
+			}], blinded_tail: None }],
+			payment_params: Some(payment_params.clone()),
+		};
+		outbound_payments.add_new_pending_payment(PaymentHash([0; 32]), RecipientOnionFields::spontaneous_empty(),
+			PaymentId([0; 32]), None, &route, Some(Retry::Attempts(1)), Some(payment_params.clone()),
+			&&keys_manager, 0).unwrap();
+
+		let route_params = RouteParameters { payment_params, final_value_msat: 10_000 };
+		let mut expected_route_params = route_params.clone();
+		expected_route_params.payment_params.max_total_routing_fee_msat = Some(200);
+		expected_route_params.payment_params.max_total_routing_fee_proportional_millionths = None;
+		router.expect_find_route(expected_route_params,
+			Err(LightningError { err: String::new(), action: ErrorAction::IgnoreError }));
+
+		let pending_events = Mutex::new(VecDeque::new());
+		outbound_payments.retry_payment_internal(
+			PaymentHash([0; 32]), PaymentId([0; 32]), route_params, &&router, vec![],
+			&|| InFlightHtlcs::new(), &&keys_manager, &&keys_manager, 0, &&logger, &pending_events,
+			&|_| Ok(()));
+	}
+
 	#[test]
 	fn initial_send_payment_path_failed_evs() {
 		let outbound_payments = OutboundPayments::new();
 		let logger = test_utils::TestLogger::new();
 		let network_graph = Arc::new(NetworkGraph::new(Network::Testnet, &logger));
-		let scorer = Mutex::new(test_utils::TestScorer::new());
+		let scorer = RwLock::new(test_utils::TestScorer::new());
 		let router = test_utils::TestRouter::new(network_graph, &scorer);
 		let secp_ctx = Secp256k1::new();
 		let keys_manager = test_utils::TestKeysInterface::new(&[0; 32], Network::Testnet);
@@ -1625,4 +1904,57 @@ mod tests {
 		} else { panic!("Unexpected event"); }
 		if let Event::PaymentFailed { .. } = events[1].0 { } else { panic!("Unexpected event"); }
 	}
+
+	#[test]
+	fn removes_stale_awaiting_invoice() {
+		let pending_events = Mutex::new(VecDeque::new());
+		let outbound_payments = OutboundPayments::new();
+		let payment_id = PaymentId([0; 32]);
+
+		assert!(!outbound_payments.has_pending_payments());
+		assert!(outbound_payments.add_new_awaiting_invoice(payment_id, Retry::Attempts(0)).is_ok());
+		assert!(outbound_payments.has_pending_payments());
+
+		for _ in 0..INVOICE_REQUEST_TIMEOUT_TICKS {
+			outbound_payments.remove_stale_resolved_payments(&pending_events);
+			assert!(outbound_payments.has_pending_payments());
+			assert!(pending_events.lock().unwrap().is_empty());
+		}
+
+		outbound_payments.remove_stale_resolved_payments(&pending_events);
+		assert!(!outbound_payments.has_pending_payments());
+		assert!(!pending_events.lock().unwrap().is_empty());
+		assert_eq!(
+			pending_events.lock().unwrap().pop_front(),
+			Some((Event::InvoiceRequestFailed { payment_id }, None)),
+		);
+		assert!(pending_events.lock().unwrap().is_empty());
+
+		assert!(outbound_payments.add_new_awaiting_invoice(payment_id, Retry::Attempts(0)).is_ok());
+		assert!(outbound_payments.has_pending_payments());
+
+		assert!(outbound_payments.add_new_awaiting_invoice(payment_id, Retry::Attempts(0)).is_err());
+	}
+
+	#[test]
+	fn removes_abandoned_awaiting_invoice() {
+		let pending_events = Mutex::new(VecDeque::new());
+		let outbound_payments = OutboundPayments::new();
+		let payment_id = PaymentId([0; 32]);
+
+		assert!(!outbound_payments.has_pending_payments());
+		assert!(outbound_payments.add_new_awaiting_invoice(payment_id, Retry::Attempts(0)).is_ok());
+		assert!(outbound_payments.has_pending_payments());
+
+		outbound_payments.abandon_payment(
+			payment_id, PaymentFailureReason::UserAbandoned, &pending_events
+		);
+		assert!(!outbound_payments.has_pending_payments());
+		assert!(!pending_events.lock().unwrap().is_empty());
+		assert_eq!(
+			pending_events.lock().unwrap().pop_front(),
+			Some((Event::InvoiceRequestFailed { payment_id }, None)),
+		);
+		assert!(pending_events.lock().unwrap().is_empty());
+	}
 }
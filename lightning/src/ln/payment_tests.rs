@@ -1145,6 +1145,102 @@ fn successful_probe_yields_event() {
 	assert!(!nodes[0].node.has_pending_payments());
 }
 
+#[test]
+fn preflight_probes_yield_event_and_skip() {
+	let chanmon_cfgs = create_chanmon_cfgs(5);
+	let node_cfgs = create_node_cfgs(5, &chanmon_cfgs);
+
+	// We alleviate the HTLC max-in-flight limit, as otherwise we'd always be limited through that.
+	let mut no_htlc_limit_config = test_default_channel_config();
+	no_htlc_limit_config.channel_handshake_config.max_inbound_htlc_value_in_flight_percent_of_channel = 100;
+
+	let user_configs = std::iter::repeat(no_htlc_limit_config).take(5).map(|c| Some(c)).collect::<Vec<_>>();
+	let node_chanmgrs = create_node_chanmgrs(5, &node_cfgs, &user_configs);
+	let nodes = create_network(5, &node_cfgs, &node_chanmgrs);
+
+	// Setup channel topology:
+	//                    (30k:0)- N2 -(1M:0)
+	//                   /                  \
+	//  N0 -(100k:0)-> N1                    N4
+	//                   \                  /
+	//                    (70k:0)- N3 -(1M:0)
+	//
+	let first_chan_update = create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 100_000, 0).0;
+	create_announced_chan_between_nodes_with_value(&nodes, 1, 2, 30_000, 0);
+	create_announced_chan_between_nodes_with_value(&nodes, 1, 3, 70_000, 0);
+	create_announced_chan_between_nodes_with_value(&nodes, 2, 4, 1_000_000, 0);
+	create_announced_chan_between_nodes_with_value(&nodes, 3, 4, 1_000_000, 0);
+
+	// Probing our direct peer only covers our own channel, so no probe is sent.
+	let res = nodes[0].node.send_spontaneous_preflight_probes(nodes[1].node.get_our_node_id(), 10_000_000, TEST_FINAL_CLTV, None).unwrap();
+	assert!(res.is_empty());
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+
+	let mut invoice_features = Bolt11InvoiceFeatures::empty();
+	invoice_features.set_basic_mpp_optional();
+
+	let payment_params = PaymentParameters::from_node_id(nodes[4].node.get_our_node_id(), TEST_FINAL_CLTV)
+		.with_bolt11_features(invoice_features).unwrap();
+
+	let route_params = RouteParameters { payment_params, final_value_msat: 80_000_000 };
+	let res = nodes[0].node.send_preflight_probes(route_params, None).unwrap();
+
+	// We check that only one probe was sent, the other one was skipped due to limited liquidity.
+	assert_eq!(res.len(), 1);
+	let log_msg = format!("Skipped sending payment probe to avoid putting channel {} under the liquidity limit.",
+		first_chan_update.contents.short_channel_id);
+	node_cfgs[0].logger.assert_log_contains("lightning::ln::channelmanager", &log_msg, 1);
+
+	let (payment_hash, payment_id) = res.first().unwrap();
+	assert!(nodes[0].node.payment_is_probe(payment_hash, payment_id));
+
+	// node[0] -- update_add_htlcs -> node[1]
+	check_added_monitors!(nodes[0], 1);
+	let probe_event = SendEvent::from_node(&nodes[0]);
+	assert_eq!(probe_event.node_id, nodes[1].node.get_our_node_id());
+	assert_eq!(probe_event.msgs.len(), 1);
+
+	// An excessive liquidity limit multiplier saturates instead of overflowing, skipping all probes.
+	let res = nodes[0].node.send_spontaneous_preflight_probes(nodes[4].node.get_our_node_id(),
+		1_000_000, TEST_FINAL_CLTV, Some(u64::max_value())).unwrap();
+	assert!(res.is_empty());
+	check_added_monitors!(nodes[0], 0);
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+}
+
+#[test]
+fn estimates_payment_fees_without_sending() {
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+	create_announced_chan_between_nodes(&nodes, 1, 2);
+
+	let payment_params = PaymentParameters::from_node_id(nodes[2].node.get_our_node_id(), TEST_FINAL_CLTV);
+	let (route, _, _, _) = get_route_and_payment_hash!(nodes[0], nodes[2], payment_params.clone(), 100_000);
+	let route_params = RouteParameters { payment_params, final_value_msat: 100_000 };
+
+	let estimate = nodes[0].node.estimate_payment_fees(&route_params).unwrap();
+	assert_eq!(estimate.min_fee_msat, route.get_total_fees());
+	assert_eq!(estimate.max_fee_msat, route.get_total_fees());
+	// The test router doesn't estimate success probabilities.
+	assert_eq!(estimate.success_probability, None);
+
+	// Nothing was sent and no payment is being tracked.
+	check_added_monitors!(nodes[0], 0);
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+	assert!(nodes[0].node.list_recent_payments().is_empty());
+
+	let route_params = RouteParameters {
+		payment_params: PaymentParameters::from_node_id(nodes[0].node.get_our_node_id(), TEST_FINAL_CLTV),
+		final_value_msat: 100_000,
+	};
+	let err = nodes[0].node.estimate_payment_fees(&route_params).unwrap_err();
+	assert_eq!(err.err, "Cannot generate a route to ourselves");
+}
+
 #[test]
 fn failed_probe_yields_event() {
 	let chanmon_cfgs = create_chanmon_cfgs(3);
@@ -2471,7 +2567,7 @@ fn retry_multi_path_single_failed_payment() {
 		}, Ok(route.clone()));
 
 	{
-		let scorer = chanmon_cfgs[0].scorer.lock().unwrap();
+		let scorer = chanmon_cfgs[0].scorer.read().unwrap();
 		// The initial send attempt, 2 paths
 		scorer.expect_usage(chans[0].short_channel_id.unwrap(), ChannelUsage { amount_msat: 10_000, inflight_htlc_msat: 0, effective_capacity: EffectiveCapacity::Unknown });
 		scorer.expect_usage(chans[1].short_channel_id.unwrap(), ChannelUsage { amount_msat: 100_000_001, inflight_htlc_msat: 0, effective_capacity: EffectiveCapacity::Unknown });
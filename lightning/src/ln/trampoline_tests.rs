@@ -0,0 +1,396 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Tests for sending payments via, and forwarding payments as, a trampoline node.
+
+use crate::chain::channelmonitor::HTLC_FAIL_BACK_BUFFER;
+use crate::events::{Event, HTLCDestination, MessageSendEvent, MessageSendEventsProvider, PathFailure};
+use crate::ln::{PaymentHash, PaymentPreimage, PaymentSecret};
+use crate::ln::channelmanager::{MIN_CLTV_EXPIRY_DELTA, PaymentId, RecentPaymentDetails, RecipientOnionFields};
+use crate::ln::msgs::ChannelMessageHandler;
+use crate::ln::outbound_payment::Retry;
+use crate::routing::router::{Path, PaymentParameters, Route, RouteHop, RouteParameters, TrampolineHop};
+use crate::prelude::*;
+
+use bitcoin::hashes::Hash;
+use bitcoin::hashes::sha256::Hash as Sha256;
+
+use crate::ln::functional_test_utils::*;
+
+const TRAMPOLINE_FEE_MSAT: u64 = 1_000;
+
+fn trampoline_route_params(nodes: &Vec<Node>, amt_msat: u64, trampoline_fee_msat: u64) -> RouteParameters {
+	let payment_params = PaymentParameters::from_node_id(nodes[2].node.get_our_node_id(), TEST_FINAL_CLTV)
+		.with_bolt11_features(nodes[2].node.invoice_features()).unwrap()
+		.with_trampoline_hop(TrampolineHop {
+			node_id: nodes[1].node.get_our_node_id(),
+			fee_msat: trampoline_fee_msat,
+			cltv_expiry_delta: 144,
+		}).unwrap();
+	RouteParameters { payment_params, final_value_msat: amt_msat + trampoline_fee_msat }
+}
+
+/// Sends a payment from `nodes[0]` to `nodes[2]` via the trampoline `nodes[1]`, returning the
+/// `expected_paths` HTLCs to `nodes[1]` without delivering them.
+fn send_trampoline_payment(nodes: &Vec<Node>, route_params: RouteParameters, expected_paths: usize)
+-> (PaymentPreimage, PaymentHash, PaymentSecret, Vec<SendEvent>) {
+	let (payment_preimage, payment_hash, payment_secret) = get_payment_preimage_hash!(nodes[2]);
+	nodes[0].node.send_payment(payment_hash, RecipientOnionFields::secret_only(payment_secret),
+		PaymentId(payment_hash.0), route_params, Retry::Attempts(0)).unwrap();
+	check_added_monitors!(nodes[0], expected_paths);
+
+	let events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), expected_paths);
+	let payment_events = events.into_iter().map(SendEvent::from_event).collect::<Vec<_>>();
+	for payment_event in payment_events.iter() {
+		assert_eq!(payment_event.node_id, nodes[1].node.get_our_node_id());
+	}
+	(payment_preimage, payment_hash, payment_secret, payment_events)
+}
+
+/// Delivers an HTLC sent by `nodes[0]` to the trampoline `nodes[1]`, leaving it pending
+/// forwarding.
+fn pass_trampoline_htlc(nodes: &Vec<Node>, payment_event: &SendEvent) {
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &payment_event.msgs[0]);
+	commitment_signed_dance!(nodes[1], nodes[0], payment_event.commitment_msg, false);
+	expect_pending_htlcs_forwardable_ignore!(nodes[1]);
+}
+
+/// Checks that the trampoline node surfaces only path-level events for the payment it made to
+/// forward a trampoline payment, as the payment itself is resolved with the HTLCs paying us.
+fn expect_trampoline_forward_path_events(node: &Node, successful_paths: usize, failed_paths: usize) {
+	let events = node.node.get_and_clear_pending_events();
+	assert_eq!(events.len(), successful_paths + failed_paths, "{:?}", events);
+	assert_eq!(events.iter().filter(|ev| matches!(ev, Event::PaymentPathSuccessful { .. })).count(), successful_paths);
+	assert_eq!(events.iter().filter(|ev| matches!(ev, Event::PaymentPathFailed { .. })).count(), failed_paths);
+}
+
+/// Claims a payment received by `nodes[2]` over the `outbound_paths` HTLCs sent by the trampoline
+/// `nodes[1]`, checking the trampoline node then claims the `inbound_paths` HTLCs paying it.
+fn claim_trampoline_payment(nodes: &Vec<Node>, payment_preimage: PaymentPreimage, amt_msat: u64,
+	outbound_paths: usize, inbound_paths: usize
+) {
+	let payment_hash = PaymentHash(Sha256::hash(&payment_preimage.0).into_inner());
+	nodes[2].node.claim_funds(payment_preimage);
+	expect_payment_claimed!(nodes[2], payment_hash, amt_msat);
+	check_added_monitors!(nodes[2], outbound_paths);
+
+	// Once the recipient claims, the trampoline node claims the HTLCs paying it.
+	let events = nodes[2].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), outbound_paths);
+	let mut trampoline_events = Vec::new();
+	for event in events {
+		let updates = match event {
+			MessageSendEvent::UpdateHTLCs { node_id, updates } => {
+				assert_eq!(node_id, nodes[1].node.get_our_node_id());
+				updates
+			},
+			_ => panic!("Unexpected event"),
+		};
+		nodes[1].node.handle_update_fulfill_htlc(&nodes[2].node.get_our_node_id(), &updates.update_fulfill_htlcs[0]);
+		if trampoline_events.is_empty() {
+			check_added_monitors!(nodes[1], inbound_paths);
+			trampoline_events = nodes[1].node.get_and_clear_pending_msg_events();
+			assert_eq!(trampoline_events.len(), inbound_paths);
+		}
+		commitment_signed_dance!(nodes[1], nodes[2], updates.commitment_signed, false);
+	}
+	expect_trampoline_forward_path_events(&nodes[1], outbound_paths, 0);
+
+	for event in trampoline_events {
+		let updates = match event {
+			MessageSendEvent::UpdateHTLCs { node_id, updates } => {
+				assert_eq!(node_id, nodes[0].node.get_our_node_id());
+				updates
+			},
+			_ => panic!("Unexpected event"),
+		};
+		nodes[0].node.handle_update_fulfill_htlc(&nodes[1].node.get_our_node_id(), &updates.update_fulfill_htlcs[0]);
+		commitment_signed_dance!(nodes[0], nodes[1], updates.commitment_signed, false);
+	}
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1 + inbound_paths);
+	assert!(events.iter().any(|ev| matches!(ev, Event::PaymentSent { payment_preimage: preimage, .. } if *preimage == payment_preimage)));
+	assert_eq!(events.iter().filter(|ev| matches!(ev, Event::PaymentPathSuccessful { .. })).count(), inbound_paths);
+}
+
+#[test]
+fn trampoline_payment_success() {
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let mut trampoline_config = test_default_channel_config();
+	trampoline_config.accept_trampoline_forwards = true;
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, Some(trampoline_config), None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+	create_announced_chan_between_nodes(&nodes, 1, 2);
+
+	let amt_msat = 100_000;
+	let route_params = trampoline_route_params(&nodes, amt_msat, TRAMPOLINE_FEE_MSAT);
+	let (payment_preimage, payment_hash, payment_secret, payment_events) =
+		send_trampoline_payment(&nodes, route_params, 1);
+	pass_trampoline_htlc(&nodes, &payment_events[0]);
+	nodes[1].node.process_pending_htlc_forwards();
+
+	// The trampoline node has received the full payment and pays the recipient itself.
+	match &nodes[1].node.list_recent_payments()[..] {
+		[RecentPaymentDetails::Pending { payment_hash: hash, total_msat }] => {
+			assert_eq!(*hash, payment_hash);
+			assert_eq!(*total_msat, amt_msat);
+		},
+		payments => panic!("Unexpected payments {:?}", payments),
+	}
+	check_added_monitors!(nodes[1], 1);
+	let mut events = nodes[1].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	let payment_event = SendEvent::from_event(events.remove(0));
+	assert_eq!(payment_event.node_id, nodes[2].node.get_our_node_id());
+	assert_eq!(payment_event.msgs[0].amount_msat, amt_msat);
+	nodes[2].node.handle_update_add_htlc(&nodes[1].node.get_our_node_id(), &payment_event.msgs[0]);
+	commitment_signed_dance!(nodes[2], nodes[1], payment_event.commitment_msg, false);
+	expect_pending_htlcs_forwardable!(nodes[2]);
+	expect_payment_claimable!(nodes[2], payment_hash, payment_secret, amt_msat);
+
+	claim_trampoline_payment(&nodes, payment_preimage, amt_msat, 1, 1);
+}
+
+#[test]
+fn trampoline_payment_mpp_success() {
+	// Tests that a trampoline node waits for all parts of an MPP payment paying it before paying
+	// the recipient.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let mut trampoline_config = test_default_channel_config();
+	trampoline_config.accept_trampoline_forwards = true;
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, Some(trampoline_config), None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	// Our default max-HTLC-value is 10% of the channel value, so the payment has to be split
+	// across both channels to the trampoline node.
+	create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 100_000, 0);
+	create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 100_000, 0);
+	create_announced_chan_between_nodes_with_value(&nodes, 1, 2, 1_000_000, 0);
+
+	let amt_msat = 15_000_000;
+	let route_params = trampoline_route_params(&nodes, amt_msat, TRAMPOLINE_FEE_MSAT);
+	let (payment_preimage, payment_hash, payment_secret, payment_events) =
+		send_trampoline_payment(&nodes, route_params, 2);
+
+	// Nothing is forwarded until the trampoline node has received all parts.
+	pass_trampoline_htlc(&nodes, &payment_events[0]);
+	nodes[1].node.process_pending_htlc_forwards();
+	assert!(nodes[1].node.get_and_clear_pending_msg_events().is_empty());
+	assert!(nodes[1].node.list_recent_payments().is_empty());
+
+	pass_trampoline_htlc(&nodes, &payment_events[1]);
+	nodes[1].node.process_pending_htlc_forwards();
+	check_added_monitors!(nodes[1], 1);
+	let mut events = nodes[1].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	let payment_event = SendEvent::from_event(events.remove(0));
+	assert_eq!(payment_event.msgs[0].amount_msat, amt_msat);
+	nodes[2].node.handle_update_add_htlc(&nodes[1].node.get_our_node_id(), &payment_event.msgs[0]);
+	commitment_signed_dance!(nodes[2], nodes[1], payment_event.commitment_msg, false);
+	expect_pending_htlcs_forwardable!(nodes[2]);
+	expect_payment_claimable!(nodes[2], payment_hash, payment_secret, amt_msat);
+
+	claim_trampoline_payment(&nodes, payment_preimage, amt_msat, 1, 2);
+}
+
+#[test]
+fn trampoline_forward_retries_failed_path() {
+	// Tests that if a path of the trampoline node's payment to the recipient fails to send, the
+	// trampoline node retries it rather than failing the HTLC paying it back.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let mut trampoline_config = test_default_channel_config();
+	trampoline_config.accept_trampoline_forwards = true;
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, Some(trampoline_config), None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 10_000_000, 0);
+	create_announced_chan_between_nodes_with_value(&nodes, 1, 2, 1_000_000, 0);
+	create_announced_chan_between_nodes_with_value(&nodes, 1, 2, 1_000_000, 0);
+	create_announced_chan_between_nodes_with_value(&nodes, 1, 2, 1_000_000, 0);
+
+	let amt_msat = 100_010_001;
+	let route_params = trampoline_route_params(&nodes, amt_msat, TRAMPOLINE_FEE_MSAT);
+	let (payment_preimage, payment_hash, payment_secret, payment_events) =
+		send_trampoline_payment(&nodes, route_params, 1);
+	pass_trampoline_htlc(&nodes, &payment_events[0]);
+
+	// The trampoline node's payment is limited to the fee and CLTV delta left to it by the HTLC
+	// paying it. Note that the sender set the recipient's CLTV expiry relative to the next block at
+	// its own height, which is behind the trampoline node's as it has opened fewer channels.
+	let cur_height = nodes[1].best_block_info().1;
+	let final_cltv_expiry_delta = nodes[0].best_block_info().1 + 1 + TEST_FINAL_CLTV - cur_height;
+	let payment_params = PaymentParameters::for_keysend(nodes[2].node.get_our_node_id(), final_cltv_expiry_delta, true)
+		.with_max_total_cltv_expiry_delta(payment_events[0].msgs[0].cltv_expiry - MIN_CLTV_EXPIRY_DELTA as u32 - cur_height)
+		.with_max_total_routing_fee_msat(TRAMPOLINE_FEE_MSAT);
+	let chans = nodes[1].node.list_usable_channels().into_iter()
+		.filter(|chan| chan.counterparty.node_id == nodes[2].node.get_our_node_id())
+		.map(|chan| chan.short_channel_id.unwrap())
+		.collect::<Vec<_>>();
+	let route_hop = |short_channel_id, fee_msat| RouteHop {
+		pubkey: nodes[2].node.get_our_node_id(),
+		node_features: nodes[2].node.node_features(),
+		short_channel_id,
+		channel_features: nodes[2].node.channel_features(),
+		fee_msat,
+		cltv_expiry_delta: final_cltv_expiry_delta,
+	};
+	let route = Route {
+		paths: vec![
+			Path { hops: vec![route_hop(chans[0], 10_000)], blinded_tail: None },
+			// Our default max-HTLC-value is 10% of the channel value, which this is one more than
+			Path { hops: vec![route_hop(chans[1], 100_000_001)], blinded_tail: None },
+		],
+		payment_params: Some(payment_params.clone()),
+	};
+	nodes[1].router.expect_find_route(RouteParameters {
+		payment_params: payment_params.clone(), final_value_msat: amt_msat,
+	}, Ok(route));
+	// On retry, split the failed path across the channels which are still unused.
+	let retry_route = Route {
+		paths: vec![
+			Path { hops: vec![route_hop(chans[1], 50_000_001)], blinded_tail: None },
+			Path { hops: vec![route_hop(chans[2], 50_000_000)], blinded_tail: None },
+		],
+		payment_params: Some(payment_params.clone()),
+	};
+	let mut retry_payment_params = payment_params;
+	retry_payment_params.previously_failed_channels.push(chans[1]);
+	nodes[1].router.expect_find_route(RouteParameters {
+		payment_params: retry_payment_params, final_value_msat: 100_000_001,
+	}, Ok(retry_route));
+
+	nodes[1].node.process_pending_htlc_forwards();
+	let events = nodes[1].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		Event::PaymentPathFailed { payment_failed_permanently: false, failure: PathFailure::InitialSend { .. },
+			short_channel_id: Some(scid), .. } => assert_eq!(scid, chans[1]),
+		_ => panic!("Unexpected event"),
+	}
+	check_added_monitors!(nodes[1], 3);
+	let mut events = nodes[1].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 3);
+	for idx in 0..3 {
+		let ev = remove_first_msg_event_to_node(&nodes[2].node.get_our_node_id(), &mut events);
+		pass_along_path(&nodes[1], &[&nodes[2]], amt_msat, payment_hash, Some(payment_secret), ev, idx == 2, None);
+	}
+
+	claim_trampoline_payment(&nodes, payment_preimage, amt_msat, 3, 1);
+}
+
+#[test]
+fn trampoline_payment_recipient_failure() {
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let mut trampoline_config = test_default_channel_config();
+	trampoline_config.accept_trampoline_forwards = true;
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, Some(trampoline_config), None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+	create_announced_chan_between_nodes(&nodes, 1, 2);
+
+	let route_params = trampoline_route_params(&nodes, 100_000, TRAMPOLINE_FEE_MSAT);
+	let (_, payment_hash, _, payment_events) = send_trampoline_payment(&nodes, route_params, 1);
+	pass_trampoline_htlc(&nodes, &payment_events[0]);
+	nodes[1].node.process_pending_htlc_forwards();
+
+	check_added_monitors!(nodes[1], 1);
+	let mut events = nodes[1].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	let payment_event = SendEvent::from_event(events.remove(0));
+	nodes[2].node.handle_update_add_htlc(&nodes[1].node.get_our_node_id(), &payment_event.msgs[0]);
+	commitment_signed_dance!(nodes[2], nodes[1], payment_event.commitment_msg, false);
+	expect_pending_htlcs_forwardable!(nodes[2]);
+	nodes[2].node.get_and_clear_pending_events();
+
+	nodes[2].node.fail_htlc_backwards(&payment_hash);
+	expect_pending_htlcs_forwardable_and_htlc_handling_failed!(nodes[2], vec![HTLCDestination::FailedPayment { payment_hash }]);
+	check_added_monitors!(nodes[2], 1);
+
+	// Once the trampoline node's payment to the recipient fails, it fails the HTLC paying it. As
+	// the failure is permanent the payment isn't retried, and no `PaymentFailed` is surfaced for
+	// it.
+	let updates = get_htlc_update_msgs!(nodes[2], nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_update_fail_htlc(&nodes[2].node.get_our_node_id(), &updates.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[1], nodes[2], updates.commitment_signed, false);
+	let events = nodes[1].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 3, "{:?}", events);
+	assert!(events.iter().any(|ev| matches!(ev, Event::PaymentPathFailed { payment_failed_permanently: true, .. })));
+	assert!(events.iter().any(|ev| matches!(ev, Event::HTLCHandlingFailed {
+		failed_next_destination: HTLCDestination::FailedPayment { .. }, ..
+	})));
+	assert!(events.iter().any(|ev| matches!(ev, Event::PendingHTLCsForwardable { .. })));
+	nodes[1].node.process_pending_htlc_forwards();
+	check_added_monitors!(nodes[1], 1);
+
+	let updates = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &updates.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], updates.commitment_signed, false);
+	// The trampoline node doesn't relay the recipient's failure, so to the sender it looks like a
+	// temporary failure at the trampoline node.
+	expect_payment_failed!(nodes[0], payment_hash, false, 0x2000 | 2, [0; 0]);
+}
+
+#[test]
+fn trampoline_forward_fails_back_near_expiry() {
+	// Tests that a trampoline node fails back the parts of a payment it has received if it hasn't
+	// received the rest before they get close to expiring.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let mut trampoline_config = test_default_channel_config();
+	trampoline_config.accept_trampoline_forwards = true;
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, Some(trampoline_config), None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 100_000, 0);
+	create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 100_000, 0);
+	create_announced_chan_between_nodes_with_value(&nodes, 1, 2, 1_000_000, 0);
+
+	let amt_msat = 15_000_000;
+	let route_params = trampoline_route_params(&nodes, amt_msat, TRAMPOLINE_FEE_MSAT);
+	let (_, payment_hash, _, payment_events) = send_trampoline_payment(&nodes, route_params, 2);
+	pass_trampoline_htlc(&nodes, &payment_events[0]);
+	nodes[1].node.process_pending_htlc_forwards();
+	assert!(nodes[1].node.get_and_clear_pending_msg_events().is_empty());
+
+	let htlc_cltv_expiry = payment_events[0].msgs[0].cltv_expiry;
+	connect_blocks(&nodes[1], htlc_cltv_expiry - HTLC_FAIL_BACK_BUFFER - nodes[1].best_block_info().1 - 1);
+	assert!(nodes[1].node.get_and_clear_pending_events().is_empty());
+
+	connect_blocks(&nodes[1], 1);
+	expect_pending_htlcs_forwardable_and_htlc_handling_failed!(nodes[1], vec![HTLCDestination::FailedPayment { payment_hash }]);
+	check_added_monitors!(nodes[1], 1);
+	let updates = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	assert_eq!(updates.update_fail_htlcs.len(), 1);
+	assert!(nodes[1].node.list_recent_payments().is_empty());
+}
+
+#[test]
+fn trampoline_forward_rejected_by_default() {
+	// Nodes which haven't set `UserConfig::accept_trampoline_forwards` refuse to act as a
+	// trampoline node.
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+	create_announced_chan_between_nodes(&nodes, 1, 2);
+
+	let (_, payment_hash, payment_secret) = get_payment_preimage_hash!(nodes[2]);
+	let route_params = trampoline_route_params(&nodes, 100_000, TRAMPOLINE_FEE_MSAT);
+	nodes[0].node.send_payment(payment_hash, RecipientOnionFields::secret_only(payment_secret),
+		PaymentId(payment_hash.0), route_params, Retry::Attempts(0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+
+	let updates = get_htlc_update_msgs!(nodes[0], nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &updates.update_add_htlcs[0]);
+	nodes[1].logger.assert_log_contains("lightning::ln::channelmanager", "We don't forward trampoline payments", 1);
+}
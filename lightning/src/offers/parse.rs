@@ -189,6 +189,8 @@ pub enum Bolt12SemanticError {
 	MissingPaymentHash,
 	/// A signature was expected but was missing.
 	MissingSignature,
+	/// A payment id was already in use when creating an invoice request or refund.
+	DuplicatePaymentId,
 }
 
 impl From<bech32::Error> for Bolt12ParseError {
@@ -15,7 +15,8 @@ use bitcoin::hashes::hmac::{Hmac, HmacEngine};
 use bitcoin::hashes::sha256::Hash as Sha256;
 use bitcoin::secp256k1::{self, PublicKey, Scalar, Secp256k1, SecretKey};
 
-use crate::blinded_path::{BlindedPath, ForwardTlvs, ReceiveTlvs, utils};
+use crate::blinded_path::{BlindedPath, utils};
+use crate::blinded_path::message::{ForwardTlvs, ReceiveTlvs};
 use crate::sign::{EntropySource, KeysManager, NodeSigner, Recipient};
 use crate::events::OnionMessageProvider;
 use crate::ln::features::{InitFeatures, NodeFeatures};
@@ -28,6 +29,7 @@ use super::packet::{BIG_PACKET_HOP_DATA_LEN, ForwardControlTlvs, Packet, Payload
 use crate::util::logger::Logger;
 use crate::util::ser::Writeable;
 
+use core::fmt;
 use core::ops::Deref;
 use crate::io;
 use crate::sync::{Arc, Mutex};
@@ -152,14 +154,19 @@ pub trait MessageRouter {
 	) -> Result<OnionMessagePath, ()>;
 }
 
-/// A [`MessageRouter`] that always fails.
+/// A [`MessageRouter`] that can only route to a directly connected [`Destination`].
 pub struct DefaultMessageRouter;
 
 impl MessageRouter for DefaultMessageRouter {
 	fn find_path(
-		&self, _sender: PublicKey, _peers: Vec<PublicKey>, _destination: Destination
+		&self, sender: PublicKey, peers: Vec<PublicKey>, destination: Destination
 	) -> Result<OnionMessagePath, ()> {
-		Err(())
+		let first_node = destination.first_node();
+		if first_node == sender || peers.contains(&first_node) {
+			Ok(OnionMessagePath { intermediate_nodes: vec![], destination })
+		} else {
+			Err(())
+		}
 	}
 }
 
@@ -189,6 +196,29 @@ impl Destination {
 			Destination::BlindedPath(BlindedPath { blinded_hops, .. }) => blinded_hops.len(),
 		}
 	}
+
+	fn first_node(&self) -> PublicKey {
+		match self {
+			Destination::Node(node_id) => *node_id,
+			Destination::BlindedPath(BlindedPath { introduction_node_id, .. }) => *introduction_node_id,
+		}
+	}
+}
+
+/// An [`OnionMessage`] queued by a message handler to be sent by the [`OnionMessenger`] the next
+/// time it is asked for messages to send to its peers.
+///
+/// [`OnionMessage`]: msgs::OnionMessage
+#[derive(Clone)]
+pub struct PendingOnionMessage<T> {
+	/// The message contents to send.
+	pub contents: T,
+
+	/// The destination of the message.
+	pub destination: Destination,
+
+	/// A reply path to include in the message, if a response is expected.
+	pub reply_path: Option<BlindedPath>,
 }
 
 /// Errors that may occur when [sending an onion message].
@@ -337,19 +367,6 @@ where
 		&self, response: OnionMessageContents<T>, path_id: Option<[u8; 32]>,
 		reply_path: Option<BlindedPath>
 	) {
-		let sender = match self.node_signer.get_node_id(Recipient::Node) {
-			Ok(node_id) => node_id,
-			Err(_) => {
-				log_warn!(
-					self.logger, "Unable to retrieve node id when responding to onion message with \
-					path_id {:02x?}", path_id
-				);
-				return;
-			}
-		};
-
-		let peers = self.pending_messages.lock().unwrap().keys().copied().collect();
-
 		let destination = match reply_path {
 			Some(reply_path) => Destination::BlindedPath(reply_path),
 			None => {
@@ -361,24 +378,37 @@ where
 			},
 		};
 
+		log_trace!(self.logger, "Responding to onion message with path_id {:02x?}", path_id);
+
+		self.find_path_and_enqueue_onion_message(
+			response, destination, None,
+			format_args!("when responding to onion message with path_id {:02x?}", path_id)
+		);
+	}
+
+	fn find_path_and_enqueue_onion_message<T: CustomOnionMessageContents>(
+		&self, contents: OnionMessageContents<T>, destination: Destination,
+		reply_path: Option<BlindedPath>, log_suffix: fmt::Arguments
+	) {
+		let sender = match self.node_signer.get_node_id(Recipient::Node) {
+			Ok(node_id) => node_id,
+			Err(_) => {
+				log_warn!(self.logger, "Unable to retrieve node id {}", log_suffix);
+				return;
+			}
+		};
+
+		let peers = self.pending_messages.lock().unwrap().keys().copied().collect();
 		let path = match self.message_router.find_path(sender, peers, destination) {
 			Ok(path) => path,
 			Err(()) => {
-				log_trace!(
-					self.logger, "Failed to find path when responding to onion message with \
-					path_id {:02x?}", path_id
-				);
+				log_trace!(self.logger, "Failed to find path {}", log_suffix);
 				return;
 			},
 		};
 
-		log_trace!(self.logger, "Responding to onion message with path_id {:02x?}", path_id);
-
-		if let Err(e) = self.send_onion_message(path, response, None) {
-			log_trace!(
-				self.logger, "Failed responding to onion message with path_id {:02x?}: {:?}",
-				path_id, e
-			);
+		if let Err(e) = self.send_onion_message(path, contents, reply_path) {
+			log_trace!(self.logger, "Failed sending onion message {}: {:?}", log_suffix, e);
 			return;
 		}
 	}
@@ -592,6 +622,15 @@ where
 	CMH::Target: CustomOnionMessageHandler,
 {
 	fn next_onion_message_for_peer(&self, peer_node_id: PublicKey) -> Option<msgs::OnionMessage> {
+		// Enqueue any initiating `OffersMessage`s to send.
+		for message in self.offers_handler.release_pending_messages() {
+			let PendingOnionMessage { contents, destination, reply_path } = message;
+			self.find_path_and_enqueue_onion_message::<<<CMH as Deref>::Target as CustomOnionMessageHandler>::CustomMessage>(
+				OnionMessageContents::Offers(contents), destination, reply_path,
+				format_args!("when sending OffersMessage")
+			);
+		}
+
 		let mut pending_msgs = self.pending_messages.lock().unwrap();
 		if let Some(msgs) = pending_msgs.get_mut(&peer_node_id) {
 			return msgs.pop_front()
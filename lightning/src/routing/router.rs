@@ -17,10 +17,10 @@ use crate::blinded_path::{BlindedHop, BlindedPath};
 use crate::ln::PaymentHash;
 use crate::ln::channelmanager::{ChannelDetails, PaymentId};
 use crate::ln::features::{Bolt11InvoiceFeatures, Bolt12InvoiceFeatures, ChannelFeatures, NodeFeatures};
-use crate::ln::msgs::{DecodeError, ErrorAction, LightningError, MAX_VALUE_MSAT};
+use crate::ln::msgs::{DecodeError, ErrorAction, LightningError, NetAddress, MAX_VALUE_MSAT};
 use crate::offers::invoice::{BlindedPayInfo, Bolt12Invoice};
 use crate::routing::gossip::{DirectedChannelInfo, EffectiveCapacity, ReadOnlyNetworkGraph, NetworkGraph, NodeId, RoutingFees};
-use crate::routing::scoring::{ChannelUsage, LockableScore, Score};
+use crate::routing::scoring::{ChannelUsage, LockableScore, ScoreLookUp};
 use crate::util::ser::{Writeable, Readable, ReadableArgs, Writer};
 use crate::util::logger::{Level, Logger};
 use crate::util::chacha20::ChaCha20;
@@ -30,12 +30,12 @@ use crate::prelude::*;
 use crate::sync::{Mutex};
 use alloc::collections::BinaryHeap;
 use core::{cmp, fmt};
-use core::ops::{Deref, DerefMut};
+use core::ops::Deref;
 
 /// A [`Router`] implemented using [`find_route`].
-pub struct DefaultRouter<G: Deref<Target = NetworkGraph<L>>, L: Deref, S: Deref, SP: Sized, Sc: Score<ScoreParams = SP>> where
+pub struct DefaultRouter<G: Deref<Target = NetworkGraph<L>>, L: Deref, S: Deref, SP: Sized, Sc: ScoreLookUp<ScoreParams = SP>> where
 	L::Target: Logger,
-	S::Target: for <'a> LockableScore<'a, Score = Sc>,
+	S::Target: for <'a> LockableScore<'a, ScoreLookUp = Sc>,
 {
 	network_graph: G,
 	logger: L,
@@ -44,20 +44,26 @@ pub struct DefaultRouter<G: Deref<Target = NetworkGraph<L>>, L: Deref, S: Deref,
 	score_params: SP
 }
 
-impl<G: Deref<Target = NetworkGraph<L>>, L: Deref, S: Deref, SP: Sized, Sc: Score<ScoreParams = SP>> DefaultRouter<G, L, S, SP, Sc> where
+impl<G: Deref<Target = NetworkGraph<L>>, L: Deref, S: Deref, SP: Sized, Sc: ScoreLookUp<ScoreParams = SP>> DefaultRouter<G, L, S, SP, Sc> where
 	L::Target: Logger,
-	S::Target: for <'a> LockableScore<'a, Score = Sc>,
+	S::Target: for <'a> LockableScore<'a, ScoreLookUp = Sc>,
 {
 	/// Creates a new router.
 	pub fn new(network_graph: G, logger: L, random_seed_bytes: [u8; 32], scorer: S, score_params: SP) -> Self {
 		let random_seed_bytes = Mutex::new(random_seed_bytes);
 		Self { network_graph, logger, random_seed_bytes, scorer, score_params }
 	}
+
+	fn next_random_seed_bytes(&self) -> [u8; 32] {
+		let mut locked_random_seed_bytes = self.random_seed_bytes.lock().unwrap();
+		*locked_random_seed_bytes = Sha256::hash(&*locked_random_seed_bytes).into_inner();
+		*locked_random_seed_bytes
+	}
 }
 
-impl< G: Deref<Target = NetworkGraph<L>>, L: Deref, S: Deref, SP: Sized, Sc: Score<ScoreParams = SP>> Router for DefaultRouter<G, L, S, SP, Sc> where
+impl< G: Deref<Target = NetworkGraph<L>>, L: Deref, S: Deref, SP: Sized, Sc: ScoreLookUp<ScoreParams = SP>> Router for DefaultRouter<G, L, S, SP, Sc> where
 	L::Target: Logger,
-	S::Target: for <'a> LockableScore<'a, Score = Sc>,
+	S::Target: for <'a> LockableScore<'a, ScoreLookUp = Sc>,
 {
 	fn find_route(
 		&self,
@@ -66,18 +72,35 @@ impl< G: Deref<Target = NetworkGraph<L>>, L: Deref, S: Deref, SP: Sized, Sc: Sco
 		first_hops: Option<&[&ChannelDetails]>,
 		inflight_htlcs: InFlightHtlcs
 	) -> Result<Route, LightningError> {
-		let random_seed_bytes = {
-			let mut locked_random_seed_bytes = self.random_seed_bytes.lock().unwrap();
-			*locked_random_seed_bytes = Sha256::hash(&*locked_random_seed_bytes).into_inner();
-			*locked_random_seed_bytes
-		};
+		let random_seed_bytes = self.next_random_seed_bytes();
 		find_route(
 			payer, params, &self.network_graph, first_hops, &*self.logger,
-			&ScorerAccountingForInFlightHtlcs::new(self.scorer.lock().deref_mut(), &inflight_htlcs),
+			&ScorerAccountingForInFlightHtlcs::new(&*self.scorer.read_lock(), &inflight_htlcs),
 			&self.score_params,
 			&random_seed_bytes
 		)
 	}
+
+	fn estimate_payment_fees(
+		&self, payer: &PublicKey, params: &RouteParameters, first_hops: Option<&[&ChannelDetails]>,
+		inflight_htlcs: InFlightHtlcs
+	) -> Result<PaymentFeeEstimate, LightningError> {
+		let random_seed_bytes = self.next_random_seed_bytes();
+		let scorer_lock = self.scorer.read_lock();
+		let scorer = ScorerAccountingForInFlightHtlcs::new(&*scorer_lock, &inflight_htlcs);
+		let ranked_routes = find_routes(
+			payer, params, &self.network_graph, first_hops, &*self.logger, &scorer,
+			&self.score_params, &random_seed_bytes, MAX_FEE_ESTIMATE_ROUTES
+		)?;
+		let success_probability = route_success_probability(&ranked_routes[0].route,
+			NodeId::from_pubkey(payer), &self.network_graph.read_only(), first_hops, &scorer,
+			&self.score_params);
+		Ok(PaymentFeeEstimate {
+			min_fee_msat: ranked_routes.iter().map(|ranked_route| ranked_route.fee_msat).min().unwrap_or(0),
+			max_fee_msat: ranked_routes.iter().map(|ranked_route| ranked_route.fee_msat).max().unwrap_or(0),
+			success_probability,
+		})
+	}
 }
 
 /// A trait defining behavior for routing a payment.
@@ -104,23 +127,52 @@ pub trait Router {
 	) -> Result<Route, LightningError> {
 		self.find_route(payer, route_params, first_hops, inflight_htlcs)
 	}
+	/// Estimates the fees a payment between the given `payer` and a payee would cost and how
+	/// likely it is to succeed, without sending it.
+	///
+	/// By default, this finds a single [`Route`] via [`Self::find_route`] and doesn't estimate the
+	/// payment's success probability.
+	fn estimate_payment_fees(
+		&self, payer: &PublicKey, route_params: &RouteParameters,
+		first_hops: Option<&[&ChannelDetails]>, inflight_htlcs: InFlightHtlcs
+	) -> Result<PaymentFeeEstimate, LightningError> {
+		let fee_msat = self.find_route(payer, route_params, first_hops, inflight_htlcs)?.get_total_fees();
+		Ok(PaymentFeeEstimate { min_fee_msat: fee_msat, max_fee_msat: fee_msat, success_probability: None })
+	}
+}
+
+/// The number of alternative routes [`DefaultRouter`] considers when estimating the range of fees
+/// a payment may cost.
+const MAX_FEE_ESTIMATE_ROUTES: usize = 3;
+
+/// An estimate of what a payment will cost, as returned by [`Router::estimate_payment_fees`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PaymentFeeEstimate {
+	/// The lowest total routing fee we expect to pay.
+	pub min_fee_msat: u64,
+	/// The highest total routing fee we expect to pay, e.g. if the payment has to be retried over
+	/// a more expensive route.
+	pub max_fee_msat: u64,
+	/// The estimated probability, between 0 and 1, that the payment succeeds over the cheapest
+	/// route we found, or `None` if the scorer doesn't estimate success probabilities.
+	pub success_probability: Option<f64>,
 }
 
-/// [`Score`] implementation that factors in in-flight HTLC liquidity.
+/// [`ScoreLookUp`] implementation that factors in in-flight HTLC liquidity.
 ///
-/// Useful for custom [`Router`] implementations to wrap their [`Score`] on-the-fly when calling
+/// Useful for custom [`Router`] implementations to wrap their [`ScoreLookUp`] on-the-fly when calling
 /// [`find_route`].
 ///
-/// [`Score`]: crate::routing::scoring::Score
-pub struct ScorerAccountingForInFlightHtlcs<'a, S: Score<ScoreParams = SP>, SP: Sized> {
-	scorer: &'a mut S,
+/// [`ScoreLookUp`]: crate::routing::scoring::ScoreLookUp
+pub struct ScorerAccountingForInFlightHtlcs<'a, S: ScoreLookUp<ScoreParams = SP>, SP: Sized> {
+	scorer: &'a S,
 	// Maps a channel's short channel id and its direction to the liquidity used up.
 	inflight_htlcs: &'a InFlightHtlcs,
 }
 
-impl<'a, S: Score<ScoreParams = SP>, SP: Sized> ScorerAccountingForInFlightHtlcs<'a, S, SP> {
+impl<'a, S: ScoreLookUp<ScoreParams = SP>, SP: Sized> ScorerAccountingForInFlightHtlcs<'a, S, SP> {
 	/// Initialize a new `ScorerAccountingForInFlightHtlcs`.
-	pub fn new(scorer: &'a mut S, inflight_htlcs: &'a InFlightHtlcs) -> Self {
+	pub fn new(scorer: &'a S, inflight_htlcs: &'a InFlightHtlcs) -> Self {
 		ScorerAccountingForInFlightHtlcs {
 			scorer,
 			inflight_htlcs
@@ -129,11 +181,11 @@ impl<'a, S: Score<ScoreParams = SP>, SP: Sized> ScorerAccountingForInFlightHtlcs
 }
 
 #[cfg(c_bindings)]
-impl<'a, S: Score<ScoreParams = SP>, SP: Sized> Writeable for ScorerAccountingForInFlightHtlcs<'a, S, SP> {
+impl<'a, S: ScoreLookUp<ScoreParams = SP>, SP: Sized> Writeable for ScorerAccountingForInFlightHtlcs<'a, S, SP> {
 	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> { self.scorer.write(writer) }
 }
 
-impl<'a, S: Score<ScoreParams = SP>, SP: Sized> Score for ScorerAccountingForInFlightHtlcs<'a, S, SP>  {
+impl<'a, S: ScoreLookUp<ScoreParams = SP>, SP: Sized> ScoreLookUp for ScorerAccountingForInFlightHtlcs<'a, S, SP>  {
 	type ScoreParams = S::ScoreParams;
 	fn channel_penalty_msat(&self, short_channel_id: u64, source: &NodeId, target: &NodeId, usage: ChannelUsage, score_params: &Self::ScoreParams) -> u64 {
 		if let Some(used_liquidity) = self.inflight_htlcs.used_liquidity_msat(
@@ -150,20 +202,13 @@ impl<'a, S: Score<ScoreParams = SP>, SP: Sized> Score for ScorerAccountingForInF
 		}
 	}
 
-	fn payment_path_failed(&mut self, path: &Path, short_channel_id: u64) {
-		self.scorer.payment_path_failed(path, short_channel_id)
-	}
-
-	fn payment_path_successful(&mut self, path: &Path) {
-		self.scorer.payment_path_successful(path)
-	}
-
-	fn probe_failed(&mut self, path: &Path, short_channel_id: u64) {
-		self.scorer.probe_failed(path, short_channel_id)
-	}
-
-	fn probe_successful(&mut self, path: &Path) {
-		self.scorer.probe_successful(path)
+	fn channel_success_probability(&self, short_channel_id: u64, source: &NodeId, target: &NodeId, usage: ChannelUsage, score_params: &Self::ScoreParams) -> Option<f64> {
+		let used_liquidity = self.inflight_htlcs.used_liquidity_msat(source, target, short_channel_id).unwrap_or(0);
+		let usage = ChannelUsage {
+			inflight_htlc_msat: usage.inflight_htlc_msat + used_liquidity,
+			..usage
+		};
+		self.scorer.channel_success_probability(short_channel_id, source, target, usage, score_params)
 	}
 }
 
@@ -547,6 +592,53 @@ pub struct PaymentParameters {
 	/// payment to fail. Future attempts for the same payment shouldn't be relayed through any of
 	/// these SCIDs.
 	pub previously_failed_channels: Vec<u64>,
+
+	/// A trampoline node to route the payment through. If set, we only find a route to the
+	/// trampoline node, leaving it to find a route to the payee.
+	///
+	/// See [`PaymentParameters::with_trampoline_hop`] for more details.
+	pub trampoline_hop: Option<TrampolineHop>,
+
+	/// Whether to split multi-path payments by treating the payment as a min-cost flow problem,
+	/// where the cost of each channel is its fee plus the scorer's penalty.
+	///
+	/// This generally finds cheaper and more reliable splits for large payments than our default
+	/// algorithm, which collects paths greedily, but is slower. It is only used if the payee
+	/// supports MPP and is not blinded. If no suitable flow is found, we fall back to our default
+	/// algorithm. Note that [`Self::max_channel_saturation_power_of_half`] is ignored, as the
+	/// scorer's penalty already accounts for saturating channels.
+	///
+	/// Default value: false
+	pub use_min_cost_flow: bool,
+
+	/// A list of nodes which this payment, including any retries of it, must not be routed
+	/// through. Unlike [`ProbabilisticScoringFeeParameters::add_banned`], which applies to all
+	/// payments, this only applies to this payment.
+	///
+	/// Note that if the payee is in this list, no route will be found.
+	///
+	/// [`ProbabilisticScoringFeeParameters::add_banned`]: crate::routing::scoring::ProbabilisticScoringFeeParameters::add_banned
+	pub avoided_nodes: Vec<NodeId>,
+
+	/// A list of SCIDs which this payment, including any retries of it, must not be routed over.
+	pub avoided_channels: Vec<u64>,
+
+	/// The maximum total fee, in msats, we're willing to pay to route this payment across all of
+	/// its paths. If [`Self::max_total_routing_fee_proportional_millionths`] is also set, the lower
+	/// of the two limits applies. The router ignores any channels which would make a path exceed
+	/// the limit, such that a more expensive path is only found if it fits the remaining budget.
+	///
+	/// When retrying, the fees of any paths still pending are subtracted from this limit. Note
+	/// that this does not include the [`TrampolineHop::fee_msat`], if any.
+	///
+	/// Default value: None
+	pub max_total_routing_fee_msat: Option<u64>,
+
+	/// The maximum total fee we're willing to pay to route this payment across all of its paths,
+	/// in millionths of the amount being sent. See [`Self::max_total_routing_fee_msat`].
+	///
+	/// Default value: None
+	pub max_total_routing_fee_proportional_millionths: Option<u32>,
 }
 
 impl Writeable for PaymentParameters {
@@ -568,6 +660,12 @@ impl Writeable for PaymentParameters {
 			(7, self.previously_failed_channels, required_vec),
 			(8, *blinded_hints, optional_vec),
 			(9, self.payee.final_cltv_expiry_delta(), option),
+			(11, self.trampoline_hop, option),
+			(13, self.use_min_cost_flow, required),
+			(15, self.avoided_nodes, optional_vec),
+			(17, self.avoided_channels, optional_vec),
+			(19, self.max_total_routing_fee_msat, option),
+			(21, self.max_total_routing_fee_proportional_millionths, option),
 		});
 		Ok(())
 	}
@@ -586,6 +684,12 @@ impl ReadableArgs<u32> for PaymentParameters {
 			(7, previously_failed_channels, optional_vec),
 			(8, blinded_route_hints, optional_vec),
 			(9, final_cltv_expiry_delta, (default_value, default_final_cltv_expiry_delta)),
+			(11, trampoline_hop, option),
+			(13, use_min_cost_flow, (default_value, false)),
+			(15, avoided_nodes, optional_vec),
+			(17, avoided_channels, optional_vec),
+			(19, max_total_routing_fee_msat, option),
+			(21, max_total_routing_fee_proportional_millionths, option),
 		});
 		let blinded_route_hints = blinded_route_hints.unwrap_or(vec![]);
 		let payee = if blinded_route_hints.len() != 0 {
@@ -609,6 +713,12 @@ impl ReadableArgs<u32> for PaymentParameters {
 			max_channel_saturation_power_of_half: _init_tlv_based_struct_field!(max_channel_saturation_power_of_half, (default_value, unused)),
 			expiry_time,
 			previously_failed_channels: previously_failed_channels.unwrap_or(Vec::new()),
+			trampoline_hop,
+			use_min_cost_flow: _init_tlv_based_struct_field!(use_min_cost_flow, (default_value, unused)),
+			avoided_nodes: avoided_nodes.unwrap_or(Vec::new()),
+			avoided_channels: avoided_channels.unwrap_or(Vec::new()),
+			max_total_routing_fee_msat,
+			max_total_routing_fee_proportional_millionths,
 		})
 	}
 }
@@ -627,6 +737,12 @@ impl PaymentParameters {
 			max_path_count: DEFAULT_MAX_PATH_COUNT,
 			max_channel_saturation_power_of_half: DEFAULT_MAX_CHANNEL_SATURATION_POW_HALF,
 			previously_failed_channels: Vec::new(),
+			trampoline_hop: None,
+			use_min_cost_flow: false,
+			avoided_nodes: Vec::new(),
+			avoided_channels: Vec::new(),
+			max_total_routing_fee_msat: None,
+			max_total_routing_fee_proportional_millionths: None,
 		}
 	}
 
@@ -665,6 +781,12 @@ impl PaymentParameters {
 			max_path_count: DEFAULT_MAX_PATH_COUNT,
 			max_channel_saturation_power_of_half: DEFAULT_MAX_CHANNEL_SATURATION_POW_HALF,
 			previously_failed_channels: Vec::new(),
+			trampoline_hop: None,
+			use_min_cost_flow: false,
+			avoided_nodes: Vec::new(),
+			avoided_channels: Vec::new(),
+			max_total_routing_fee_msat: None,
+			max_total_routing_fee_proportional_millionths: None,
 		}
 	}
 
@@ -740,8 +862,142 @@ impl PaymentParameters {
 	pub fn with_max_channel_saturation_power_of_half(self, max_channel_saturation_power_of_half: u8) -> Self {
 		Self { max_channel_saturation_power_of_half, ..self }
 	}
+
+	/// Sets whether to split multi-path payments via min-cost flow. See
+	/// [`PaymentParameters::use_min_cost_flow`].
+	///
+	/// This is not exported to bindings users since bindings don't support move semantics
+	pub fn with_min_cost_flow(self, use_min_cost_flow: bool) -> Self {
+		Self { use_min_cost_flow, ..self }
+	}
+
+	/// Avoids routing this payment through the given nodes. See
+	/// [`PaymentParameters::avoided_nodes`].
+	///
+	/// This is not exported to bindings users since bindings don't support move semantics
+	pub fn with_avoided_nodes(self, avoided_nodes: Vec<NodeId>) -> Self {
+		Self { avoided_nodes, ..self }
+	}
+
+	/// Avoids routing this payment over the given channels. See
+	/// [`PaymentParameters::avoided_channels`].
+	///
+	/// This is not exported to bindings users since bindings don't support move semantics
+	pub fn with_avoided_channels(self, avoided_channels: Vec<u64>) -> Self {
+		Self { avoided_channels, ..self }
+	}
+
+	/// Avoids routing this payment through any node which announced an address matching
+	/// `avoid_address`, adding them to [`PaymentParameters::avoided_nodes`].
+	///
+	/// This can be used to avoid nodes hosted in certain countries or autonomous systems (ASNs),
+	/// given a closure which looks up the country or ASN of an address. Note that only nodes in
+	/// the `network_graph` at the time of calling are considered, and that nodes which haven't
+	/// announced any addresses are never avoided.
+	///
+	/// This is not exported to bindings users since bindings don't support move semantics
+	pub fn with_avoided_node_addresses<F: Fn(&NetAddress) -> bool>(
+		mut self, network_graph: &ReadOnlyNetworkGraph, avoid_address: F
+	) -> Self {
+		for (node_id, node) in network_graph.nodes().unordered_iter() {
+			let avoid_node = node.announcement_info.as_ref()
+				.map_or(false, |info| info.addresses().iter().any(|address| avoid_address(address)));
+			if avoid_node && !self.avoided_nodes.contains(node_id) {
+				self.avoided_nodes.push(*node_id);
+			}
+		}
+		self
+	}
+
+	/// Includes a limit for the total fee, in msats, paid to route this payment. See
+	/// [`PaymentParameters::max_total_routing_fee_msat`].
+	///
+	/// This is not exported to bindings users since bindings don't support move semantics
+	pub fn with_max_total_routing_fee_msat(self, max_total_routing_fee_msat: u64) -> Self {
+		Self { max_total_routing_fee_msat: Some(max_total_routing_fee_msat), ..self }
+	}
+
+	/// Includes a limit for the total fee paid to route this payment, in millionths of the amount
+	/// being sent. See [`PaymentParameters::max_total_routing_fee_proportional_millionths`].
+	///
+	/// This is not exported to bindings users since bindings don't support move semantics
+	pub fn with_max_total_routing_fee_proportional_millionths(self, proportional_millionths: u32) -> Self {
+		Self { max_total_routing_fee_proportional_millionths: Some(proportional_millionths), ..self }
+	}
+
+	/// Gets the maximum total fee, in msats, we're willing to pay to route `final_value_msat`,
+	/// considering both the absolute and proportional limits.
+	pub(crate) fn max_total_routing_fee(&self, final_value_msat: u64) -> Option<u64> {
+		let proportional_max_msat = self.max_total_routing_fee_proportional_millionths
+			.map(|millionths| {
+				let max_msat = (final_value_msat as u128) * (millionths as u128) / 1_000_000;
+				cmp::min(max_msat, u64::max_value() as u128) as u64
+			});
+		match (self.max_total_routing_fee_msat, proportional_max_msat) {
+			(Some(max_msat), Some(proportional_max_msat)) => Some(cmp::min(max_msat, proportional_max_msat)),
+			(max_msat, None) => max_msat,
+			(None, proportional_max_msat) => proportional_max_msat,
+		}
+	}
+
+	/// Routes the payment through the given trampoline node, which will find a route to the payee
+	/// itself. Errors if the parameters were initialized with
+	/// [`PaymentParameters::from_bolt12_invoice`].
+	///
+	/// This allows paying without a full view of the network graph, as we only need to find a
+	/// route to the trampoline node. Note that [`RouteParameters::final_value_msat`] must include
+	/// the [`TrampolineHop::fee_msat`] on top of the amount the payee should receive, and that the
+	/// payee must support receiving trampoline payments.
+	///
+	/// This is not exported to bindings users since bindings don't support move semantics
+	pub fn with_trampoline_hop(self, trampoline_hop: TrampolineHop) -> Result<Self, ()> {
+		match self.payee {
+			Payee::Blinded { .. } => Err(()),
+			Payee::Clear { .. } => Ok(Self { trampoline_hop: Some(trampoline_hop), ..self }),
+		}
+	}
+
+	/// Builds the parameters for routing to our [`Self::trampoline_hop`], if any, in place of the
+	/// payee. The trampoline node's CLTV delta is added to the payee's final CLTV delta to give the
+	/// final CLTV delta of the route.
+	fn for_trampoline_hop(&self) -> Option<Self> {
+		let trampoline_hop = self.trampoline_hop.as_ref()?;
+		let final_cltv_expiry_delta = self.payee.final_cltv_expiry_delta()?
+			.saturating_add(trampoline_hop.cltv_expiry_delta);
+		let mut features = Bolt11InvoiceFeatures::empty();
+		features.set_variable_length_onion_optional();
+		features.set_basic_mpp_optional();
+		Some(Self {
+			payee: Payee::Clear {
+				node_id: trampoline_hop.node_id, route_hints: vec![], features: Some(features),
+				final_cltv_expiry_delta,
+			},
+			previously_failed_channels: self.previously_failed_channels.clone(),
+			trampoline_hop: None,
+			avoided_nodes: self.avoided_nodes.clone(),
+			avoided_channels: self.avoided_channels.clone(),
+			..*self
+		})
+	}
 }
 
+/// A trampoline node which a payment is routed through, leaving it to find a route to the payee.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct TrampolineHop {
+	/// The node id of the trampoline node.
+	pub node_id: PublicKey,
+	/// The fee, in msats, the trampoline node charges for finding a route to and paying the payee.
+	pub fee_msat: u64,
+	/// The CLTV delta the trampoline node requires for finding a route to and paying the payee.
+	pub cltv_expiry_delta: u32,
+}
+
+impl_writeable_tlv_based!(TrampolineHop, {
+	(0, node_id, required),
+	(2, fee_msat, required),
+	(4, cltv_expiry_delta, required),
+});
+
 /// The recipient of a payment, differing based on whether they've hidden their identity with route
 /// blinding.
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
@@ -974,10 +1230,11 @@ enum CandidateRouteHop<'a> {
 		hint: &'a (BlindedPayInfo, BlindedPath),
 		hint_idx: usize,
 	},
-	/// Similar to [`Self::Blinded`], but the path here has 1 blinded hop. `BlindedPayInfo` provided
-	/// for 1-hop blinded paths is ignored because it is meant to apply to the hops *between* the
-	/// introduction node and the destination. Useful for tracking that we need to include a blinded
-	/// path at the end of our [`Route`].
+	/// Similar to [`Self::Blinded`], but the path here has 1 blinded hop. The fees and HTLC limits
+	/// in the `BlindedPayInfo` provided for 1-hop blinded paths are ignored because they are meant
+	/// to apply to the hops *between* the introduction node and the destination, though its
+	/// `cltv_expiry_delta` is used as it includes the recipient's minimum final CLTV expiry delta.
+	/// Useful for tracking that we need to include a blinded path at the end of our [`Route`].
 	OneHopBlinded {
 		hint: &'a (BlindedPayInfo, BlindedPath),
 		hint_idx: usize,
@@ -1011,8 +1268,8 @@ impl<'a> CandidateRouteHop<'a> {
 			CandidateRouteHop::FirstHop { .. } => 0,
 			CandidateRouteHop::PublicHop { info, .. } => info.direction().cltv_expiry_delta as u32,
 			CandidateRouteHop::PrivateHop { hint } => hint.cltv_expiry_delta as u32,
-			CandidateRouteHop::Blinded { hint, .. } => hint.0.cltv_expiry_delta as u32,
-			CandidateRouteHop::OneHopBlinded { .. } => 0,
+			CandidateRouteHop::Blinded { hint, .. } | CandidateRouteHop::OneHopBlinded { hint, .. } =>
+				hint.0.cltv_expiry_delta as u32,
 		}
 	}
 
@@ -1410,21 +1667,202 @@ fn sort_first_hop_channels(
 /// [`ChannelManager::list_usable_channels`]: crate::ln::channelmanager::ChannelManager::list_usable_channels
 /// [`Event::PaymentPathFailed`]: crate::events::Event::PaymentPathFailed
 /// [`NetworkGraph`]: crate::routing::gossip::NetworkGraph
-pub fn find_route<L: Deref, GL: Deref, S: Score>(
+pub fn find_route<L: Deref, GL: Deref, S: ScoreLookUp>(
 	our_node_pubkey: &PublicKey, route_params: &RouteParameters,
 	network_graph: &NetworkGraph<GL>, first_hops: Option<&[&ChannelDetails]>, logger: L,
 	scorer: &S, score_params: &S::ScoreParams, random_seed_bytes: &[u8; 32]
 ) -> Result<Route, LightningError>
 where L::Target: Logger, GL::Target: Logger {
 	let graph_lock = network_graph.read_only();
-	let mut route = get_route(our_node_pubkey, &route_params.payment_params, &graph_lock, first_hops,
+	// When paying via a trampoline node, we only need a route to the trampoline node.
+	let trampoline_params = route_params.payment_params.for_trampoline_hop();
+	let payment_params = trampoline_params.as_ref().unwrap_or(&route_params.payment_params);
+	let mut route = get_route(our_node_pubkey, payment_params, &graph_lock, first_hops,
 		route_params.final_value_msat, logger, scorer, score_params,
 		random_seed_bytes)?;
-	add_random_cltv_offset(&mut route, &route_params.payment_params, &graph_lock, random_seed_bytes);
+	add_random_cltv_offset(&mut route, payment_params, &graph_lock, random_seed_bytes);
+	route.payment_params = Some(route_params.payment_params.clone());
 	Ok(route)
 }
 
-pub(crate) fn get_route<L: Deref, S: Score>(
+/// A [`Route`] returned by [`find_routes`], along with a breakdown of what it costs.
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub struct RankedRoute {
+	/// The route itself.
+	pub route: Route,
+	/// The total fees paid to intermediary nodes, see [`Route::get_total_fees`].
+	pub fee_msat: u64,
+	/// The largest total CLTV expiry delta of any of the route's paths, i.e. the number of blocks
+	/// our funds may be locked up for if the payment gets stuck. Includes the final CLTV expiry
+	/// delta and any random offset added to it.
+	pub cltv_expiry_delta: u32,
+	/// The sum of the penalties the scorer assigned to each channel used by the route.
+	pub scorer_penalty_msat: u64,
+}
+
+impl RankedRoute {
+	/// The cost routes are ranked by, i.e. the fees plus the scorer's penalty.
+	pub fn cost_msat(&self) -> u64 {
+		self.fee_msat.saturating_add(self.scorer_penalty_msat)
+	}
+
+	fn channels(&self) -> Vec<Vec<u64>> {
+		self.route.paths.iter()
+			.map(|path| path.hops.iter().map(|hop| hop.short_channel_id).collect())
+			.collect()
+	}
+}
+
+/// Finds up to `max_routes` distinct routes for the given payment, sorted by
+/// [`RankedRoute::cost_msat`] with the cheapest first.
+///
+/// The first route is the one [`find_route`] would return. Alternatives are found by repeatedly
+/// excluding one of the channels used by an already-found route and routing again, so each
+/// alternative avoids at least one channel of every route ranked before the one it deviates from.
+/// Thus, this calls into the router once per channel in each returned route and is considerably
+/// more expensive than [`find_route`].
+///
+/// Fewer than `max_routes` routes are returned if no more distinct routes exist. An error is
+/// only returned if no route at all could be found.
+///
+/// See [`find_route`] for a description of the other parameters.
+pub fn find_routes<L: Deref, GL: Deref, S: ScoreLookUp>(
+	our_node_pubkey: &PublicKey, route_params: &RouteParameters,
+	network_graph: &NetworkGraph<GL>, first_hops: Option<&[&ChannelDetails]>, logger: L,
+	scorer: &S, score_params: &S::ScoreParams, random_seed_bytes: &[u8; 32], max_routes: usize
+) -> Result<Vec<RankedRoute>, LightningError>
+where L::Target: Logger, GL::Target: Logger {
+	let mut ranked_routes = Vec::new();
+	if max_routes == 0 { return Ok(ranked_routes); }
+
+	let graph_lock = network_graph.read_only();
+	let our_node_id = NodeId::from_pubkey(our_node_pubkey);
+	let trampoline_params = route_params.payment_params.for_trampoline_hop();
+	let payment_params = trampoline_params.as_ref().unwrap_or(&route_params.payment_params);
+
+	let find_ranked_route = |excluded_channels: &Vec<u64>| -> Result<RankedRoute, LightningError> {
+		let mut params = payment_params.clone();
+		params.previously_failed_channels = excluded_channels.clone();
+		let mut route = get_route(our_node_pubkey, &params, &graph_lock, first_hops,
+			route_params.final_value_msat, &*logger, scorer, score_params, random_seed_bytes)?;
+		add_random_cltv_offset(&mut route, &params, &graph_lock, random_seed_bytes);
+		route.payment_params = Some(route_params.payment_params.clone());
+		let cltv_expiry_delta = route.paths.iter().map(|path| {
+			let excess_cltv_delta = path.blinded_tail.as_ref().map_or(0, |tail| tail.excess_final_cltv_expiry_delta);
+			path.hops.iter().map(|hop| hop.cltv_expiry_delta).sum::<u32>().saturating_add(excess_cltv_delta)
+		}).max().unwrap_or(0);
+		Ok(RankedRoute {
+			fee_msat: route.get_total_fees(),
+			cltv_expiry_delta,
+			scorer_penalty_msat: route_penalty_msat(&route, our_node_id, &graph_lock, first_hops, scorer, score_params),
+			route,
+		})
+	};
+
+	// Each candidate is stored along with the channels we excluded when finding it, which any
+	// deviation from it will exclude as well.
+	let mut candidates: Vec<(RankedRoute, Vec<u64>)> = Vec::new();
+	let base_excluded_channels = payment_params.previously_failed_channels.clone();
+	let mut next_route = Some((find_ranked_route(&base_excluded_channels)?, base_excluded_channels));
+	while let Some((ranked_route, excluded_channels)) = next_route.take() {
+		let route_channels = ranked_route.channels();
+		if ranked_routes.len() + 1 < max_routes {
+			for scid in route_channels.iter().flatten() {
+				if excluded_channels.contains(scid) { continue; }
+				let mut deviation_excluded_channels = excluded_channels.clone();
+				deviation_excluded_channels.push(*scid);
+				let candidate = match find_ranked_route(&deviation_excluded_channels) {
+					Ok(candidate) => candidate,
+					Err(_) => continue,
+				};
+				let candidate_channels = candidate.channels();
+				let is_duplicate = candidate_channels == route_channels ||
+					ranked_routes.iter().any(|ranked: &RankedRoute| ranked.channels() == candidate_channels) ||
+					candidates.iter().any(|(other, _)| other.channels() == candidate_channels);
+				if !is_duplicate {
+					candidates.push((candidate, deviation_excluded_channels));
+				}
+			}
+		}
+		ranked_routes.push(ranked_route);
+		if ranked_routes.len() >= max_routes { break; }
+
+		let cheapest_idx = candidates.iter().enumerate()
+			.min_by_key(|(_, (candidate, _))| candidate.cost_msat())
+			.map(|(idx, _)| idx);
+		next_route = cheapest_idx.map(|idx| candidates.remove(idx));
+	}
+
+	// The router's heuristics mean a deviation may turn out cheaper than the route it deviates
+	// from, so sort to ensure we always return routes cheapest first.
+	ranked_routes.sort_by_key(|ranked_route| ranked_route.cost_msat());
+	Ok(ranked_routes)
+}
+
+/// Calls `f` with the source, target and [`ChannelUsage`] of each channel used by `route`,
+/// accounting for the liquidity used by the route's other paths.
+fn for_each_route_channel<F: FnMut(u64, &NodeId, &NodeId, ChannelUsage)>(
+	route: &Route, our_node_id: NodeId, network_graph: &ReadOnlyNetworkGraph,
+	first_hops: Option<&[&ChannelDetails]>, mut f: F
+) {
+	let mut used_liquidities: HashMap<(u64, NodeId), u64> = HashMap::new();
+	for path in route.paths.iter() {
+		let mut amount_msat = path.fee_msat().saturating_add(path.final_value_msat());
+		let mut source = our_node_id;
+		for hop in path.hops.iter() {
+			let target = NodeId::from_pubkey(&hop.pubkey);
+			let first_hop = first_hops.filter(|_| source == our_node_id).and_then(|hops| hops.iter()
+				.find(|details| details.get_outbound_payment_scid() == Some(hop.short_channel_id)));
+			let effective_capacity = match first_hop {
+				Some(details) => EffectiveCapacity::ExactLiquidity {
+					liquidity_msat: details.next_outbound_htlc_limit_msat,
+				},
+				None => network_graph.channel(hop.short_channel_id)
+					.and_then(|channel| channel.as_directed_to(&target))
+					.map_or(EffectiveCapacity::Unknown, |(directed_channel, _)| directed_channel.effective_capacity()),
+			};
+			let used_liquidity_msat = used_liquidities.entry((hop.short_channel_id, source)).or_insert(0);
+			f(hop.short_channel_id, &source, &target, ChannelUsage {
+				amount_msat,
+				inflight_htlc_msat: *used_liquidity_msat,
+				effective_capacity,
+			});
+			*used_liquidity_msat = used_liquidity_msat.saturating_add(amount_msat);
+			amount_msat = amount_msat.saturating_sub(hop.fee_msat);
+			source = target;
+		}
+	}
+}
+
+/// Sums the penalties `scorer` assigns to each channel used by `route`.
+fn route_penalty_msat<S: ScoreLookUp>(
+	route: &Route, our_node_id: NodeId, network_graph: &ReadOnlyNetworkGraph,
+	first_hops: Option<&[&ChannelDetails]>, scorer: &S, score_params: &S::ScoreParams
+) -> u64 {
+	let mut penalty_msat: u64 = 0;
+	for_each_route_channel(route, our_node_id, network_graph, first_hops, |scid, source, target, usage| {
+		penalty_msat = penalty_msat.saturating_add(
+			scorer.channel_penalty_msat(scid, source, target, usage, score_params));
+	});
+	penalty_msat
+}
+
+/// Estimates the probability that all of `route`'s paths succeed, if `scorer` estimates the
+/// success probability of each of its channels.
+fn route_success_probability<S: ScoreLookUp>(
+	route: &Route, our_node_id: NodeId, network_graph: &ReadOnlyNetworkGraph,
+	first_hops: Option<&[&ChannelDetails]>, scorer: &S, score_params: &S::ScoreParams
+) -> Option<f64> {
+	let mut success_probability = Some(1.0);
+	for_each_route_channel(route, our_node_id, network_graph, first_hops, |scid, source, target, usage| {
+		success_probability = success_probability.and_then(|probability: f64|
+			scorer.channel_success_probability(scid, source, target, usage, score_params)
+				.map(|channel_probability| probability * channel_probability));
+	});
+	success_probability
+}
+
+pub(crate) fn get_route<L: Deref, S: ScoreLookUp>(
 	our_node_pubkey: &PublicKey, payment_params: &PaymentParameters, network_graph: &ReadOnlyNetworkGraph,
 	first_hops: Option<&[&ChannelDetails]>, final_value_msat: u64, logger: L, scorer: &S, score_params: &S::ScoreParams,
 	_random_seed_bytes: &[u8; 32]
@@ -1443,6 +1881,10 @@ where L::Target: Logger {
 		return Err(LightningError{err: "Cannot generate a route to ourselves".to_owned(), action: ErrorAction::IgnoreError});
 	}
 
+	if payee_node_id_opt.map_or(false, |payee| payment_params.avoided_nodes.contains(&payee)) {
+		return Err(LightningError{err: "Cannot generate a route to a node we were asked to avoid".to_owned(), action: ErrorAction::IgnoreError});
+	}
+
 	if final_value_msat > MAX_VALUE_MSAT {
 		return Err(LightningError{err: "Cannot generate a route of more value than all existing satoshis".to_owned(), action: ErrorAction::IgnoreError});
 	}
@@ -1587,6 +2029,17 @@ where L::Target: Logger {
 		}
 	}
 
+	if payment_params.use_min_cost_flow && allow_mpp {
+		match get_route_min_cost_flow(our_node_pubkey, payment_params, network_graph, first_hops,
+			final_value_msat, &*logger, scorer, score_params)
+		{
+			Ok(route) => return Ok(route),
+			Err(e) => {
+				log_debug!(logger, "Failed to find a route via min-cost flow, falling back to our default algorithm: {}", e.err);
+			},
+		}
+	}
+
 	// The main heap containing all candidate next-hops sorted by their score (max(fee,
 	// htlc_minimum)). Ideally this would be a heap which allowed cheap score reduction instead of
 	// adding duplicate entries when we find a better path to a given node.
@@ -1641,6 +2094,12 @@ where L::Target: Logger {
 	// when we want to stop looking for new paths.
 	let mut already_collected_value_msat = 0;
 
+	// The fees we may pay across all paths, and those the paths we already collected pay. Any
+	// candidate hop which would make a new path exceed the remaining fee budget is ignored.
+	let max_total_routing_fee_msat = payment_params.max_total_routing_fee(final_value_msat)
+		.unwrap_or(u64::max_value());
+	let mut already_collected_fee_msat = 0;
+
 	for (_, channels) in first_hop_targets.iter_mut() {
 		sort_first_hop_channels(channels, &used_liquidities, recommended_value_msat,
 			our_node_pubkey);
@@ -1654,6 +2113,8 @@ where L::Target: Logger {
 	let mut num_ignored_path_length_limit = 0;
 	let mut num_ignored_cltv_delta_limit = 0;
 	let mut num_ignored_previously_failed = 0;
+	let mut num_ignored_avoided = 0;
+	let mut num_ignored_total_fee_limit = 0;
 
 	macro_rules! add_entry {
 		// Adds entry which goes from $src_node_id to $dest_node_id over the $candidate hop.
@@ -1729,6 +2190,21 @@ where L::Target: Logger {
 					let payment_failed_on_this_channel = scid_opt.map_or(false,
 						|scid| payment_params.previously_failed_channels.contains(&scid));
 
+					// Channels leading to us are never used, so we only need to check the source
+					// node of each hop for whether it is to be avoided.
+					let avoided_by_payment = scid_opt.map_or(false,
+						|scid| payment_params.avoided_channels.contains(&scid)) ||
+						($src_node_id != our_node_id && payment_params.avoided_nodes.contains(&$src_node_id));
+
+					// Do not consider candidates whose fees would make the path exceed the fee
+					// budget left after accounting for the paths we already collected. Note that we
+					// don't pay fees for the use of our own channels.
+					let hop_fee_msat: u64 = if $src_node_id != our_node_id {
+						compute_fees_saturating(amount_to_transfer_over_msat, $candidate.fees())
+					} else { 0 };
+					let exceeds_total_fee_limit = hop_fee_msat.saturating_add($next_hops_fee_msat) >
+						max_total_routing_fee_msat.saturating_sub(already_collected_fee_msat);
+
 					let should_log_candidate = match $candidate {
 						CandidateRouteHop::FirstHop { .. } => true,
 						CandidateRouteHop::PrivateHop { .. } => true,
@@ -1760,6 +2236,16 @@ where L::Target: Logger {
 							log_trace!(logger, "Ignoring {} due to a failed previous payment attempt.", LoggedCandidateHop(&$candidate));
 						}
 						num_ignored_previously_failed += 1;
+					} else if avoided_by_payment {
+						if should_log_candidate {
+							log_trace!(logger, "Ignoring {} as the payment parameters avoid it.", LoggedCandidateHop(&$candidate));
+						}
+						num_ignored_avoided += 1;
+					} else if exceeds_total_fee_limit {
+						if should_log_candidate {
+							log_trace!(logger, "Ignoring {} due to exceeding the remaining fee budget.", LoggedCandidateHop(&$candidate));
+						}
+						num_ignored_total_fee_limit += 1;
 					} else if may_overpay_to_meet_path_minimum_msat {
 						hit_minimum_limit = true;
 					} else if over_path_minimum_msat {
@@ -2302,6 +2788,7 @@ where L::Target: Logger {
 				// Track the total amount all our collected paths allow to send so that we know
 				// when to stop looking for more paths
 				already_collected_value_msat += value_contribution_msat;
+				already_collected_fee_msat += payment_path.get_total_fee_paid_msat();
 
 				payment_paths.push(payment_path);
 				found_new_path = true;
@@ -2362,12 +2849,20 @@ where L::Target: Logger {
 	}
 
 	let num_ignored_total = num_ignored_value_contribution + num_ignored_path_length_limit +
-		num_ignored_cltv_delta_limit + num_ignored_previously_failed;
+		num_ignored_cltv_delta_limit + num_ignored_previously_failed + num_ignored_avoided +
+		num_ignored_total_fee_limit;
 	if num_ignored_total > 0 {
-		log_trace!(logger, "Ignored {} candidate hops due to insufficient value contribution, {} due to path length limit, {} due to CLTV delta limit, {} due to previous payment failure. Total: {} ignored candidates.", num_ignored_value_contribution, num_ignored_path_length_limit, num_ignored_cltv_delta_limit, num_ignored_previously_failed, num_ignored_total);
+		log_trace!(logger, "Ignored {} candidate hops due to insufficient value contribution, {} due to path length limit, {} due to CLTV delta limit, {} due to previous payment failure, {} due to being avoided by the payment, {} due to exceeding the fee budget. Total: {} ignored candidates.", num_ignored_value_contribution, num_ignored_path_length_limit, num_ignored_cltv_delta_limit, num_ignored_previously_failed, num_ignored_avoided, num_ignored_total_fee_limit, num_ignored_total);
 	}
 
 	// Step (5).
+	if already_collected_value_msat < final_value_msat && num_ignored_total_fee_limit > 0 {
+		return Err(LightningError{
+			err: format!("Failed to find a route with total fees of at most {} msat", max_total_routing_fee_msat),
+			action: ErrorAction::IgnoreError,
+		});
+	}
+
 	if payment_paths.len() == 0 {
 		return Err(LightningError{err: "Failed to find a path to the given destination".to_owned(), action: ErrorAction::IgnoreError});
 	}
@@ -2492,10 +2987,392 @@ where L::Target: Logger {
 	}
 
 	let route = Route { paths, payment_params: Some(payment_params.clone()) };
+	check_max_total_routing_fee(&route, payment_params, final_value_msat)?;
 	log_info!(logger, "Got route: {}", log_route!(route));
 	Ok(route)
 }
 
+/// Fails if the total fees paid by the `route` exceed the payment's
+/// [`PaymentParameters::max_total_routing_fee_msat`] or
+/// [`PaymentParameters::max_total_routing_fee_proportional_millionths`].
+fn check_max_total_routing_fee(
+	route: &Route, payment_params: &PaymentParameters, final_value_msat: u64
+) -> Result<(), LightningError> {
+	if let Some(max_total_routing_fee_msat) = payment_params.max_total_routing_fee(final_value_msat) {
+		let total_fee_msat = route.get_total_fees();
+		if total_fee_msat > max_total_routing_fee_msat {
+			return Err(LightningError{
+				err: format!("Failed to find a route with total fees of at most {} msat, the cheapest found route pays {} msat",
+					max_total_routing_fee_msat, total_fee_msat),
+				action: ErrorAction::IgnoreError,
+			});
+		}
+	}
+	Ok(())
+}
+
+/// The number of linear pieces we approximate each channel's cost with when routing via min-cost
+/// flow, see [`PaymentParameters::use_min_cost_flow`].
+const MIN_COST_FLOW_CHANNEL_PIECES: u64 = 4;
+
+/// The number of units we split a payment into when routing via min-cost flow. Flows are only
+/// tracked at this granularity.
+const MIN_COST_FLOW_PAYMENT_UNITS: u64 = 1_000;
+
+/// The maximum number of augmenting paths we search for before giving up on finding a min-cost
+/// flow.
+const MIN_COST_FLOW_MAX_AUGMENTATIONS: usize = 100;
+
+/// The cost per payment unit we cap each arc at, ensuring path costs can't overflow.
+const MIN_COST_FLOW_MAX_UNIT_COST: u64 = 1 << 40;
+
+/// A channel in the graph we route min-cost flows over.
+struct FlowChannel<'a> {
+	candidate: CandidateRouteHop<'a>,
+	source: usize,
+	target: usize,
+}
+
+/// An arc in the residual graph we route min-cost flows over. Each [`FlowChannel`] is represented
+/// by up to [`MIN_COST_FLOW_CHANNEL_PIECES`] forward arcs of non-decreasing cost, each paired with
+/// a reverse arc which allows later flows to undo earlier ones.
+struct FlowArc {
+	target: usize,
+	channel_idx: usize,
+	/// The capacity left on this arc, in payment units.
+	residual_units: u64,
+	/// The cost of sending a payment unit over this arc. Negative for reverse arcs.
+	unit_cost: i64,
+	/// The index of the arc in the opposite direction.
+	reverse_idx: usize,
+	is_forward: bool,
+}
+
+fn min_cost_flow_err(err: &str) -> LightningError {
+	LightningError { err: err.to_owned(), action: ErrorAction::IgnoreError }
+}
+
+/// Finds a route by treating the payment as a min-cost flow problem, where the cost of sending
+/// over a channel is its fee plus the scorer's penalty.
+///
+/// Each channel's cost is approximated as a convex piecewise linear function of the amount sent
+/// over it, by evaluating the scorer's penalty at [`MIN_COST_FLOW_CHANNEL_PIECES`] points. A
+/// channel's base fee is amortized over the whole payment value. The flow is then found via
+/// successive shortest paths, decomposed into payment paths and checked against the channels'
+/// actual HTLC limits. Only payments to unblinded payees are supported.
+fn get_route_min_cost_flow<L: Deref, S: ScoreLookUp>(
+	our_node_pubkey: &PublicKey, payment_params: &PaymentParameters, network_graph: &ReadOnlyNetworkGraph,
+	first_hops: Option<&[&ChannelDetails]>, final_value_msat: u64, logger: L, scorer: &S,
+	score_params: &S::ScoreParams
+) -> Result<Route, LightningError>
+where L::Target: Logger {
+	let (payee_pubkey, route_hints, final_cltv_expiry_delta) = match &payment_params.payee {
+		Payee::Clear { node_id, route_hints, final_cltv_expiry_delta, .. } =>
+			(*node_id, route_hints, *final_cltv_expiry_delta),
+		Payee::Blinded { .. } => return Err(min_cost_flow_err("Cannot route to blinded payees via min-cost flow")),
+	};
+	let our_node_id = NodeId::from_pubkey(our_node_pubkey);
+	let payee_node_id = NodeId::from_pubkey(&payee_pubkey);
+	let network_nodes = network_graph.nodes();
+
+	let unit_msat = cmp::max(1, (final_value_msat + MIN_COST_FLOW_PAYMENT_UNITS - 1) / MIN_COST_FLOW_PAYMENT_UNITS);
+	let payment_units = (final_value_msat + unit_msat - 1) / unit_msat;
+
+	// Step (1): collect the channels we may route over.
+	let mut node_ids = vec![our_node_id, payee_node_id];
+	let mut node_indices: HashMap<NodeId, usize> = HashMap::new();
+	node_indices.insert(our_node_id, 0);
+	node_indices.insert(payee_node_id, 1);
+	let mut channels = Vec::new();
+	macro_rules! add_flow_channel {
+		($candidate: expr, $source: expr, $target: expr) => { {
+			let (source, target): (NodeId, NodeId) = ($source, $target);
+			let candidate = $candidate;
+			let failed_previously = candidate.short_channel_id()
+				.map_or(false, |scid| payment_params.previously_failed_channels.contains(&scid));
+			let avoided_by_payment = candidate.short_channel_id()
+				.map_or(false, |scid| payment_params.avoided_channels.contains(&scid)) ||
+				(source != our_node_id && payment_params.avoided_nodes.contains(&source));
+			// Channels to us or from the payee are of no use.
+			if target != our_node_id && source != payee_node_id && !failed_previously && !avoided_by_payment {
+				let mut node_idx = |node_id: NodeId| *node_indices.entry(node_id).or_insert_with(|| {
+					node_ids.push(node_id);
+					node_ids.len() - 1
+				});
+				let (source, target) = (node_idx(source), node_idx(target));
+				channels.push(FlowChannel { candidate, source, target });
+			}
+		} }
+	}
+
+	if let Some(hops) = first_hops {
+		for details in hops.iter() {
+			add_flow_channel!(CandidateRouteHop::FirstHop { details }, our_node_id,
+				NodeId::from_pubkey(&details.counterparty.node_id));
+		}
+	}
+
+	let mut hint_scids = HashSet::new();
+	for route_hint in route_hints.iter() {
+		for (idx, hint) in route_hint.0.iter().enumerate() {
+			let target = route_hint.0.get(idx + 1)
+				.map_or(payee_node_id, |next_hint| NodeId::from_pubkey(&next_hint.src_node_id));
+			hint_scids.insert(hint.short_channel_id);
+			add_flow_channel!(CandidateRouteHop::PrivateHop { hint }, NodeId::from_pubkey(&hint.src_node_id), target);
+		}
+	}
+
+	let node_requires_unknown_bits = |node_id: &NodeId| network_nodes.get(node_id)
+		.and_then(|node| node.announcement_info.as_ref())
+		.map_or(false, |info| info.features.requires_unknown_bits());
+	for (short_channel_id, channel) in network_graph.channels().unordered_iter() {
+		if channel.features.requires_unknown_bits() || hint_scids.contains(short_channel_id) { continue; }
+		if node_requires_unknown_bits(&channel.node_one) || node_requires_unknown_bits(&channel.node_two) {
+			continue;
+		}
+		for source in [&channel.node_one, &channel.node_two].iter() {
+			if first_hops.is_some() && **source == our_node_id { continue; }
+			if let Some((info, target)) = channel.as_directed_from(source) {
+				if info.direction().enabled {
+					add_flow_channel!(CandidateRouteHop::PublicHop { info, short_channel_id: *short_channel_id },
+						**source, *target);
+				}
+			}
+		}
+	}
+
+	// Step (2): build the residual graph, splitting each channel into arcs of increasing cost.
+	let mut arcs: Vec<FlowArc> = Vec::new();
+	let mut node_arcs: Vec<Vec<usize>> = vec![Vec::new(); node_ids.len()];
+	for (channel_idx, channel) in channels.iter().enumerate() {
+		let candidate = &channel.candidate;
+		if candidate.htlc_minimum_msat() > final_value_msat { continue; }
+		let effective_capacity = candidate.effective_capacity();
+		let capacity_units = cmp::min(max_htlc_from_capacity(effective_capacity, 0) / unit_msat, payment_units);
+		if capacity_units == 0 { continue; }
+
+		let fees = candidate.fees();
+		let fee_unit_cost = (fees.proportional_millionths as u64).saturating_mul(unit_msat)
+			.saturating_add(999_999) / 1_000_000 + (fees.base_msat as u64 + payment_units - 1) / payment_units;
+		let scid = candidate.short_channel_id().unwrap();
+		let (source_id, target_id) = (node_ids[channel.source], node_ids[channel.target]);
+
+		let pieces = cmp::min(MIN_COST_FLOW_CHANNEL_PIECES, capacity_units);
+		let mut used_units = 0;
+		let mut prev_penalty_msat = 0;
+		let mut prev_unit_cost = 0;
+		for piece in 0..pieces {
+			let piece_units = capacity_units / pieces + if piece < capacity_units % pieces { 1 } else { 0 };
+			used_units += piece_units;
+			let channel_usage = ChannelUsage {
+				amount_msat: used_units * unit_msat,
+				inflight_htlc_msat: 0,
+				effective_capacity,
+			};
+			let penalty_msat = scorer.channel_penalty_msat(scid, &source_id, &target_id, channel_usage, score_params);
+			if penalty_msat == u64::max_value() { break; }
+			let unit_penalty_msat = (penalty_msat.saturating_sub(prev_penalty_msat) + piece_units - 1) / piece_units;
+			prev_penalty_msat = penalty_msat;
+			// Ensure costs are non-decreasing (i.e. convex) so that we always use cheaper pieces of a
+			// channel first.
+			let unit_cost = cmp::max(prev_unit_cost,
+				cmp::min(fee_unit_cost.saturating_add(unit_penalty_msat), MIN_COST_FLOW_MAX_UNIT_COST));
+			prev_unit_cost = unit_cost;
+
+			let (forward_idx, reverse_idx) = (arcs.len(), arcs.len() + 1);
+			arcs.push(FlowArc {
+				target: channel.target, channel_idx, residual_units: piece_units,
+				unit_cost: unit_cost as i64, reverse_idx, is_forward: true,
+			});
+			arcs.push(FlowArc {
+				target: channel.source, channel_idx, residual_units: 0,
+				unit_cost: -(unit_cost as i64), reverse_idx: forward_idx, is_forward: false,
+			});
+			node_arcs[channel.source].push(forward_idx);
+			node_arcs[channel.target].push(reverse_idx);
+		}
+	}
+
+	// Step (3): find the min-cost flow via successive shortest paths, using Dijkstra's algorithm
+	// with node potentials to handle the negative costs of reverse arcs.
+	let (source_idx, sink_idx) = (0, 1);
+	let mut potentials = vec![0i64; node_ids.len()];
+	let mut remaining_units = payment_units;
+	let mut augmentations = 0;
+	while remaining_units > 0 {
+		if augmentations == MIN_COST_FLOW_MAX_AUGMENTATIONS {
+			return Err(min_cost_flow_err("Min-cost flow required too many augmenting paths"));
+		}
+		augmentations += 1;
+
+		let mut dist = vec![i64::max_value(); node_ids.len()];
+		let mut parent_arc: Vec<Option<usize>> = vec![None; node_ids.len()];
+		let mut visited = vec![false; node_ids.len()];
+		let mut heap = BinaryHeap::new();
+		dist[source_idx] = 0;
+		heap.push(cmp::Reverse((0i64, source_idx)));
+		while let Some(cmp::Reverse((node_dist, node))) = heap.pop() {
+			if visited[node] { continue; }
+			visited[node] = true;
+			// Once we've reached the payee we're done, as all other nodes' distances only matter
+			// for updating the potentials.
+			if node == sink_idx { break; }
+			for arc_idx in node_arcs[node].iter() {
+				let arc = &arcs[*arc_idx];
+				if arc.residual_units == 0 || visited[arc.target] { continue; }
+				let reduced_cost = arc.unit_cost + potentials[node] - potentials[arc.target];
+				debug_assert!(reduced_cost >= 0);
+				let target_dist = node_dist + cmp::max(reduced_cost, 0);
+				if target_dist < dist[arc.target] {
+					dist[arc.target] = target_dist;
+					parent_arc[arc.target] = Some(*arc_idx);
+					heap.push(cmp::Reverse((target_dist, arc.target)));
+				}
+			}
+		}
+		if !visited[sink_idx] {
+			return Err(min_cost_flow_err("Failed to find a min-cost flow with sufficient capacity"));
+		}
+
+		// Nodes we didn't finalize are at least as far away as the payee, which is all we need to
+		// keep reduced costs non-negative.
+		let sink_dist = dist[sink_idx];
+		for (potential, node_dist) in potentials.iter_mut().zip(dist.iter()) {
+			*potential += cmp::min(*node_dist, sink_dist);
+		}
+
+		let mut augment_units = remaining_units;
+		let mut node = sink_idx;
+		while let Some(arc_idx) = parent_arc[node] {
+			augment_units = cmp::min(augment_units, arcs[arc_idx].residual_units);
+			node = arcs[arcs[arc_idx].reverse_idx].target;
+		}
+		let mut node = sink_idx;
+		while let Some(arc_idx) = parent_arc[node] {
+			arcs[arc_idx].residual_units -= augment_units;
+			let reverse_idx = arcs[arc_idx].reverse_idx;
+			arcs[reverse_idx].residual_units += augment_units;
+			node = arcs[reverse_idx].target;
+		}
+		remaining_units -= augment_units;
+	}
+
+	// Step (4): decompose the flow into paths, canceling any (zero-cost) cycles it contains.
+	let mut channel_flow_units = vec![0u64; channels.len()];
+	for arc in arcs.iter().filter(|arc| !arc.is_forward) {
+		channel_flow_units[arc.channel_idx] += arc.residual_units;
+	}
+	let mut node_channels: Vec<Vec<usize>> = vec![Vec::new(); node_ids.len()];
+	for (channel_idx, _) in channel_flow_units.iter().enumerate().filter(|(_, flow)| **flow > 0) {
+		node_channels[channels[channel_idx].source].push(channel_idx);
+	}
+	let mut flow_paths: Vec<(Vec<usize>, u64)> = Vec::new();
+	let mut decomposed_units = 0;
+	while decomposed_units < payment_units {
+		let mut path_channels: Vec<usize> = Vec::new();
+		let mut path_nodes = vec![source_idx];
+		while *path_nodes.last().unwrap() != sink_idx {
+			let node = *path_nodes.last().unwrap();
+			let channel_idx = *node_channels[node].iter().find(|idx| channel_flow_units[**idx] > 0)
+				.ok_or_else(|| min_cost_flow_err("Min-cost flow did not conserve flow"))?;
+			let target = channels[channel_idx].target;
+			path_channels.push(channel_idx);
+			if let Some(cycle_start) = path_nodes.iter().position(|node| *node == target) {
+				let cycle_units = path_channels[cycle_start..].iter()
+					.map(|idx| channel_flow_units[*idx]).min().unwrap();
+				for idx in path_channels[cycle_start..].iter() {
+					channel_flow_units[*idx] -= cycle_units;
+				}
+				path_channels.truncate(cycle_start);
+				path_nodes.truncate(cycle_start + 1);
+			} else {
+				path_nodes.push(target);
+			}
+		}
+		let path_units = path_channels.iter().map(|idx| channel_flow_units[*idx]).min().unwrap()
+			.min(payment_units - decomposed_units);
+		for idx in path_channels.iter() {
+			channel_flow_units[*idx] -= path_units;
+		}
+		decomposed_units += path_units;
+		flow_paths.push((path_channels, path_units));
+	}
+	if flow_paths.len() > payment_params.max_path_count as usize {
+		return Err(min_cost_flow_err("Min-cost flow requires more paths than allowed"));
+	}
+
+	// Step (5): build the route, only sending exactly the payment value and checking the
+	// channels' actual HTLC limits now that we know the fees.
+	let mut path_values_msat: Vec<u64> = flow_paths.iter().map(|(_, units)| units * unit_msat).collect();
+	let overpaid_value_msat = payment_units * unit_msat - final_value_msat;
+	if let Some(largest_value_msat) = path_values_msat.iter_mut().max() {
+		*largest_value_msat -= overpaid_value_msat;
+	}
+
+	let mut used_liquidities_msat = vec![0u64; channels.len()];
+	let mut paths = Vec::with_capacity(flow_paths.len());
+	for ((path_channels, _), value_msat) in flow_paths.iter().zip(path_values_msat.iter()) {
+		if path_channels.len() > MAX_PATH_LENGTH_ESTIMATE as usize {
+			return Err(min_cost_flow_err("Min-cost flow path is too long"));
+		}
+		let mut hops = Vec::with_capacity(path_channels.len());
+		let mut amount_msat = *value_msat;
+		let mut cltv_expiry_delta = final_cltv_expiry_delta;
+		let mut total_cltv_expiry_delta = final_cltv_expiry_delta;
+		let mut fee_msat = *value_msat;
+		for channel_idx in path_channels.iter().rev() {
+			let channel = &channels[*channel_idx];
+			let candidate = &channel.candidate;
+			if amount_msat < candidate.htlc_minimum_msat() {
+				return Err(min_cost_flow_err("Min-cost flow path does not meet a channel's HTLC minimum"));
+			}
+			used_liquidities_msat[*channel_idx] += amount_msat;
+			if used_liquidities_msat[*channel_idx] > max_htlc_from_capacity(candidate.effective_capacity(), 0) {
+				return Err(min_cost_flow_err("Min-cost flow path exceeds a channel's liquidity"));
+			}
+
+			let target_node_id = node_ids[channel.target];
+			let node_features = match candidate {
+				CandidateRouteHop::FirstHop { details } => details.counterparty.features.to_context(),
+				_ => network_nodes.get(&target_node_id)
+					.and_then(|node| node.announcement_info.as_ref())
+					.map_or_else(default_node_features, |info| info.features.clone()),
+			};
+			hops.push(RouteHop {
+				pubkey: PublicKey::from_slice(target_node_id.as_slice()).map_err(|_| LightningError{err: format!("Public key {:?} is invalid", &target_node_id), action: ErrorAction::IgnoreAndLog(Level::Trace)})?,
+				node_features,
+				short_channel_id: candidate.short_channel_id().unwrap(),
+				channel_features: candidate.features(),
+				fee_msat,
+				cltv_expiry_delta,
+			});
+
+			fee_msat = compute_fees(amount_msat, candidate.fees())
+				.ok_or_else(|| min_cost_flow_err("Min-cost flow path fees overflowed"))?;
+			amount_msat = amount_msat.checked_add(fee_msat)
+				.ok_or_else(|| min_cost_flow_err("Min-cost flow path fees overflowed"))?;
+			cltv_expiry_delta = candidate.cltv_expiry_delta();
+			total_cltv_expiry_delta = total_cltv_expiry_delta.saturating_add(cltv_expiry_delta);
+		}
+		if total_cltv_expiry_delta > payment_params.max_total_cltv_expiry_delta {
+			return Err(min_cost_flow_err("Min-cost flow path exceeds the maximum total CLTV expiry delta"));
+		}
+		hops.reverse();
+		paths.push(Path { hops, blinded_tail: None });
+	}
+
+	if let Some(node_features) = payment_params.payee.node_features() {
+		for path in paths.iter_mut() {
+			path.hops.last_mut().unwrap().node_features = node_features.clone();
+		}
+	}
+
+	let route = Route { paths, payment_params: Some(payment_params.clone()) };
+	check_max_total_routing_fee(&route, payment_params, final_value_msat)?;
+	log_info!(logger, "Got min-cost flow route: {}", log_route!(route));
+	Ok(route)
+}
+
 // When an adversarial intermediary node observes a payment, it may be able to infer its
 // destination, if the remaining CLTV expiry delta exactly matches a feasible path in the network
 // graph. In order to improve privacy, this method obfuscates the CLTV expiry deltas along the
@@ -2614,7 +3491,7 @@ fn build_route_from_hops_internal<L: Deref>(
 		hop_ids: [Option<NodeId>; MAX_PATH_LENGTH_ESTIMATE as usize],
 	}
 
-	impl Score for HopScorer {
+	impl ScoreLookUp for HopScorer {
 		type ScoreParams = ();
 		fn channel_penalty_msat(&self, _short_channel_id: u64, source: &NodeId, target: &NodeId,
 			_usage: ChannelUsage, _score_params: &Self::ScoreParams) -> u64
@@ -2632,14 +3509,6 @@ fn build_route_from_hops_internal<L: Deref>(
 			}
 			u64::max_value()
 		}
-
-		fn payment_path_failed(&mut self, _path: &Path, _short_channel_id: u64) {}
-
-		fn payment_path_successful(&mut self, _path: &Path) {}
-
-		fn probe_failed(&mut self, _path: &Path, _short_channel_id: u64) {}
-
-		fn probe_successful(&mut self, _path: &Path) {}
 	}
 
 	impl<'a> Writeable for HopScorer {
@@ -2670,21 +3539,21 @@ mod tests {
 	use crate::blinded_path::{BlindedHop, BlindedPath};
 	use crate::routing::gossip::{NetworkGraph, P2PGossipSync, NodeId, EffectiveCapacity};
 	use crate::routing::utxo::UtxoResult;
-	use crate::routing::router::{get_route, build_route_from_hops_internal, add_random_cltv_offset, default_node_features,
-		BlindedTail, InFlightHtlcs, Path, PaymentParameters, Route, RouteHint, RouteHintHop, RouteHop, RoutingFees,
+	use crate::routing::router::{get_route, find_routes, build_route_from_hops_internal, add_random_cltv_offset, default_node_features,
+		BlindedTail, DefaultRouter, InFlightHtlcs, Path, PaymentParameters, Route, RouteHint, RouteHintHop, RouteHop, RouteParameters, Router, RoutingFees,
 		DEFAULT_MAX_TOTAL_CLTV_EXPIRY_DELTA, MAX_PATH_LENGTH_ESTIMATE};
-	use crate::routing::scoring::{ChannelUsage, FixedPenaltyScorer, Score, ProbabilisticScorer, ProbabilisticScoringFeeParameters, ProbabilisticScoringDecayParameters};
-	use crate::routing::test_utils::{add_channel, add_or_update_node, build_graph, build_line_graph, id_to_feature_flags, get_nodes, update_channel};
+	use crate::routing::scoring::{ChannelUsage, FixedPenaltyScorer, ScoreLookUp, ProbabilisticScorer, ProbabilisticScoringFeeParameters, ProbabilisticScoringDecayParameters};
+	use crate::routing::test_utils::{add_channel, add_or_update_node, add_or_update_node_with_addresses, build_graph, build_line_graph, id_to_feature_flags, get_nodes, update_channel};
 	use crate::chain::transaction::OutPoint;
 	use crate::sign::EntropySource;
 	use crate::ln::features::{BlindedHopFeatures, Bolt12InvoiceFeatures, ChannelFeatures, InitFeatures, NodeFeatures};
-	use crate::ln::msgs::{ErrorAction, LightningError, UnsignedChannelUpdate, MAX_VALUE_MSAT};
+	use crate::ln::msgs::{ErrorAction, LightningError, NetAddress, UnsignedChannelUpdate, MAX_VALUE_MSAT};
 	use crate::ln::channelmanager;
 	use crate::offers::invoice::BlindedPayInfo;
 	use crate::util::config::UserConfig;
 	use crate::util::test_utils as ln_test_utils;
 	use crate::util::chacha20::ChaCha20;
-	use crate::util::ser::{Readable, Writeable};
+	use crate::util::ser::{Readable, ReadableArgs, Writeable};
 	#[cfg(c_bindings)]
 	use crate::util::ser::Writer;
 
@@ -2698,11 +3567,11 @@ mod tests {
 	use hex;
 
 	use bitcoin::secp256k1::{PublicKey,SecretKey};
-	use bitcoin::secp256k1::Secp256k1;
+	use bitcoin::secp256k1::{All, Secp256k1};
 
 	use crate::io::Cursor;
 	use crate::prelude::*;
-	use crate::sync::Arc;
+	use crate::sync::{Arc, RwLock};
 
 	use core::convert::TryInto;
 
@@ -5721,16 +6590,11 @@ mod tests {
 	impl Writeable for BadChannelScorer {
 		fn write<W: Writer>(&self, _w: &mut W) -> Result<(), crate::io::Error> { unimplemented!() }
 	}
-	impl Score for BadChannelScorer {
+	impl ScoreLookUp for BadChannelScorer {
 		type ScoreParams = ();
 		fn channel_penalty_msat(&self, short_channel_id: u64, _: &NodeId, _: &NodeId, _: ChannelUsage, _score_params:&Self::ScoreParams) -> u64 {
 			if short_channel_id == self.short_channel_id { u64::max_value() } else { 0 }
 		}
-
-		fn payment_path_failed(&mut self, _path: &Path, _short_channel_id: u64) {}
-		fn payment_path_successful(&mut self, _path: &Path) {}
-		fn probe_failed(&mut self, _path: &Path, _short_channel_id: u64) {}
-		fn probe_successful(&mut self, _path: &Path) {}
 	}
 
 	struct BadNodeScorer {
@@ -5742,16 +6606,11 @@ mod tests {
 		fn write<W: Writer>(&self, _w: &mut W) -> Result<(), crate::io::Error> { unimplemented!() }
 	}
 
-	impl Score for BadNodeScorer {
+	impl ScoreLookUp for BadNodeScorer {
 		type ScoreParams = ();
 		fn channel_penalty_msat(&self, _: u64, _: &NodeId, target: &NodeId, _: ChannelUsage, _score_params:&Self::ScoreParams) -> u64 {
 			if *target == self.node_id { u64::max_value() } else { 0 }
 		}
-
-		fn payment_path_failed(&mut self, _path: &Path, _short_channel_id: u64) {}
-		fn payment_path_successful(&mut self, _path: &Path) {}
-		fn probe_failed(&mut self, _path: &Path, _short_channel_id: u64) {}
-		fn probe_successful(&mut self, _path: &Path) {}
 	}
 
 	#[test]
@@ -6581,11 +7440,11 @@ mod tests {
 		if tail.hops.len() > 1 {
 			assert_eq!(final_hop.fee_msat,
 				blinded_payinfo.fee_base_msat as u64 + blinded_payinfo.fee_proportional_millionths as u64 * tail.final_value_msat / 1000000);
-			assert_eq!(final_hop.cltv_expiry_delta, blinded_payinfo.cltv_expiry_delta as u32);
 		} else {
 			assert_eq!(final_hop.fee_msat, 0);
-			assert_eq!(final_hop.cltv_expiry_delta, 0);
 		}
+		// The recipient's final CLTV expiry delta is included in the payinfo even for 1-hop paths.
+		assert_eq!(final_hop.cltv_expiry_delta, blinded_payinfo.cltv_expiry_delta as u32);
 	}
 
 	#[test]
@@ -6711,6 +7570,363 @@ mod tests {
 		}
 		assert_eq!(total_amount_paid_msat, 100_000);
 	}
+
+	#[test]
+	fn finds_ranked_alternative_routes() {
+		let (secp_ctx, network_graph, _, _, logger) = build_graph();
+		let (_, our_id, _, nodes) = get_nodes(&secp_ctx);
+		let route_params = RouteParameters {
+			payment_params: PaymentParameters::from_node_id(nodes[2], 42),
+			final_value_msat: 100,
+		};
+		let scorer = FixedPenaltyScorer::with_penalty(100);
+		let keys_manager = ln_test_utils::TestKeysInterface::new(&[0u8; 32], Network::Testnet);
+		let random_seed_bytes = keys_manager.get_secure_random_bytes();
+
+		assert!(find_routes(&our_id, &route_params, &network_graph, None, Arc::clone(&logger),
+			&scorer, &(), &random_seed_bytes, 0).unwrap().is_empty());
+
+		let ranked_routes = find_routes(&our_id, &route_params, &network_graph, None,
+			Arc::clone(&logger), &scorer, &(), &random_seed_bytes, 3).unwrap();
+		assert!(ranked_routes.len() >= 2 && ranked_routes.len() <= 3);
+
+		// The cheapest route is the one we'd usually find, via nodes[1].
+		let best = &ranked_routes[0];
+		let best_route = get_route(&our_id, &route_params.payment_params, &network_graph.read_only(),
+			None, 100, Arc::clone(&logger), &scorer, &(), &random_seed_bytes).unwrap();
+		assert_eq!(best.channels(), vec![vec![2, 4]]);
+		assert_eq!(best.fee_msat, best_route.get_total_fees());
+		assert_eq!(best_route.paths[0].hops.iter().map(|hop| hop.short_channel_id).collect::<Vec<_>>(), vec![2, 4]);
+		assert_eq!(best.fee_msat, 100);
+		assert_eq!(best.scorer_penalty_msat, 200);
+		assert_eq!(best.cost_msat(), 300);
+		assert!(best.cltv_expiry_delta >= ((4 << 4) | 1) + 42);
+		assert_eq!(best.route.payment_params, Some(route_params.payment_params.clone()));
+
+		// The runner-up goes via nodes[7], which charges twice as much.
+		let second = &ranked_routes[1];
+		assert_eq!(second.channels(), vec![vec![12, 13]]);
+		assert_eq!(second.fee_msat, 200);
+		assert_eq!(second.scorer_penalty_msat, 200);
+
+		for (idx, ranked_route) in ranked_routes.iter().enumerate() {
+			if idx > 0 { assert!(ranked_routes[idx - 1].cost_msat() <= ranked_route.cost_msat()); }
+			assert!(ranked_routes.iter().skip(idx + 1).all(|other| other.channels() != ranked_route.channels()));
+		}
+	}
+
+	#[test]
+	fn default_router_estimates_payment_fees() {
+		let (secp_ctx, network_graph, _, _, logger) = build_graph();
+		let (_, our_id, _, nodes) = get_nodes(&secp_ctx);
+		let scorer = RwLock::new(ProbabilisticScorer::new(ProbabilisticScoringDecayParameters::default(),
+			Arc::clone(&network_graph), Arc::clone(&logger)));
+		let router = DefaultRouter::new(Arc::clone(&network_graph), Arc::clone(&logger), [42; 32],
+			&scorer, ProbabilisticScoringFeeParameters::default());
+		let route_params = RouteParameters {
+			payment_params: PaymentParameters::from_node_id(nodes[2], 42),
+			final_value_msat: 100,
+		};
+
+		let estimate = router.estimate_payment_fees(&our_id, &route_params, None, InFlightHtlcs::new()).unwrap();
+		assert!(estimate.min_fee_msat >= 100);
+		assert!(estimate.min_fee_msat <= estimate.max_fee_msat);
+		let success_probability = estimate.success_probability.unwrap();
+		assert!(success_probability > 0.0 && success_probability <= 1.0);
+	}
+
+	#[test]
+	fn find_routes_fails_without_any_route() {
+		let (secp_ctx, network_graph, _, _, logger) = build_graph();
+		let (_, our_id, _, nodes) = get_nodes(&secp_ctx);
+		let mut payment_params = PaymentParameters::from_node_id(nodes[2], 42);
+		payment_params.previously_failed_channels = vec![2, 12];
+		let route_params = RouteParameters { payment_params, final_value_msat: 100 };
+		let scorer = ln_test_utils::TestScorer::new();
+		let keys_manager = ln_test_utils::TestKeysInterface::new(&[0u8; 32], Network::Testnet);
+		let random_seed_bytes = keys_manager.get_secure_random_bytes();
+
+		if let Err(LightningError{err, action: ErrorAction::IgnoreError}) = find_routes(&our_id,
+			&route_params, &network_graph, None, Arc::clone(&logger), &scorer, &(), &random_seed_bytes, 3) {
+			assert_eq!(err, "Failed to find a path to the given destination");
+		} else { panic!(); }
+	}
+
+	fn limit_mpp_test_channels(gossip_sync: &P2PGossipSync<Arc<NetworkGraph<Arc<ln_test_utils::TestLogger>>>,
+		Arc<ln_test_utils::TestChainSource>, Arc<ln_test_utils::TestLogger>>, secp_ctx: &Secp256k1<All>,
+	) {
+		// Limit the three paths to node2 (via node0, node7 and node1) to an aggregate 290 sats.
+		let (our_privkey, _, privkeys, _) = get_nodes(secp_ctx);
+		let limits = [(&our_privkey, 1, 100_000), (&privkeys[0], 3, 50_000), (&our_privkey, 12, 60_000),
+			(&privkeys[7], 13, 60_000), (&our_privkey, 2, 200_000), (&privkeys[1], 4, 180_000)];
+		for (privkey, short_channel_id, htlc_maximum_msat) in limits.iter() {
+			update_channel(gossip_sync, secp_ctx, privkey, UnsignedChannelUpdate {
+				chain_hash: genesis_block(Network::Testnet).header.block_hash(),
+				short_channel_id: *short_channel_id,
+				timestamp: 2,
+				flags: 0,
+				cltv_expiry_delta: 0,
+				htlc_minimum_msat: 0,
+				htlc_maximum_msat: *htlc_maximum_msat,
+				fee_base_msat: 0,
+				fee_proportional_millionths: 0,
+				excess_data: Vec::new()
+			});
+		}
+	}
+
+	#[test]
+	fn min_cost_flow_splits_payment() {
+		let (secp_ctx, network_graph, gossip_sync, _, logger) = build_graph();
+		let (_, our_id, _, nodes) = get_nodes(&secp_ctx);
+		let config = UserConfig::default();
+		let payment_params = PaymentParameters::from_node_id(nodes[2], 42)
+			.with_bolt11_features(channelmanager::provided_invoice_features(&config)).unwrap()
+			.with_min_cost_flow(true);
+		let scorer = ln_test_utils::TestScorer::new();
+		let keys_manager = ln_test_utils::TestKeysInterface::new(&[0u8; 32], Network::Testnet);
+		let random_seed_bytes = keys_manager.get_secure_random_bytes();
+		limit_mpp_test_channels(&gossip_sync, &secp_ctx);
+
+		let route = get_route(&our_id, &payment_params, &network_graph.read_only(), None, 250_000,
+			Arc::clone(&logger), &scorer, &(), &random_seed_bytes).unwrap();
+		logger.assert_log_contains("lightning::routing::router", "Got min-cost flow route", 1);
+		assert!(route.paths.len() > 1);
+		assert_eq!(route.get_total_amount(), 250_000);
+		for path in route.paths.iter() {
+			assert_eq!(path.hops.last().unwrap().pubkey, nodes[2]);
+		}
+
+		// The per-channel limits must be respected across all paths.
+		let mut channel_amounts: HashMap<u64, u64> = HashMap::new();
+		for path in route.paths.iter() {
+			for hop in path.hops.iter() {
+				*channel_amounts.entry(hop.short_channel_id).or_insert(0) += path.final_value_msat();
+			}
+		}
+		assert!(channel_amounts.get(&3).map_or(true, |amt| *amt <= 50_000));
+		assert!(channel_amounts.get(&13).map_or(true, |amt| *amt <= 60_000));
+		assert!(channel_amounts.get(&4).map_or(true, |amt| *amt <= 180_000));
+	}
+
+	#[test]
+	fn min_cost_flow_falls_back_on_failure() {
+		let (secp_ctx, network_graph, gossip_sync, _, logger) = build_graph();
+		let (_, our_id, _, nodes) = get_nodes(&secp_ctx);
+		let config = UserConfig::default();
+		let payment_params = PaymentParameters::from_node_id(nodes[2], 42)
+			.with_bolt11_features(channelmanager::provided_invoice_features(&config)).unwrap()
+			.with_min_cost_flow(true);
+		let scorer = ln_test_utils::TestScorer::new();
+		let keys_manager = ln_test_utils::TestKeysInterface::new(&[0u8; 32], Network::Testnet);
+		let random_seed_bytes = keys_manager.get_secure_random_bytes();
+		limit_mpp_test_channels(&gossip_sync, &secp_ctx);
+
+		// 300 sats exceeds the aggregate capacity of all paths, so both algorithms fail.
+		if let Err(LightningError{err, action: ErrorAction::IgnoreError}) = get_route(&our_id, &payment_params,
+			&network_graph.read_only(), None, 300_000, Arc::clone(&logger), &scorer, &(), &random_seed_bytes) {
+			assert_eq!(err, "Failed to find a sufficient route to the given destination");
+		} else { panic!(); }
+		logger.assert_log_contains("lightning::routing::router", "falling back to our default algorithm", 1);
+	}
+
+	#[test]
+	fn min_cost_flow_beats_default_algorithm() {
+		let (secp_ctx, network_graph, gossip_sync, _, logger) = build_graph();
+		let (our_privkey, our_id, privkeys, nodes) = get_nodes(&secp_ctx);
+		let config = UserConfig::default();
+		let payment_params = PaymentParameters::from_node_id(nodes[2], 42)
+			.with_bolt11_features(channelmanager::provided_invoice_features(&config)).unwrap();
+		let scorer = ln_test_utils::TestScorer::new();
+		let keys_manager = ln_test_utils::TestKeysInterface::new(&[0u8; 32], Network::Testnet);
+		let random_seed_bytes = keys_manager.get_secure_random_bytes();
+
+		// Leave two 100 sat paths to node2: a cheap one via node0 and an expensive one via node1.
+		// Disable the path via node7.
+		let updates = [(&our_privkey, 1, 0, 200_000, 0), (&privkeys[0], 3, 0, 100_000, 100),
+			(&our_privkey, 2, 0, 200_000, 0), (&privkeys[1], 4, 0, 100_000, 10_000),
+			(&our_privkey, 12, 2, 200_000, 0)];
+		for (privkey, short_channel_id, flags, htlc_maximum_msat, fee_proportional_millionths) in updates.iter() {
+			update_channel(&gossip_sync, &secp_ctx, privkey, UnsignedChannelUpdate {
+				chain_hash: genesis_block(Network::Testnet).header.block_hash(),
+				short_channel_id: *short_channel_id,
+				timestamp: 2,
+				flags: *flags,
+				cltv_expiry_delta: 0,
+				htlc_minimum_msat: 0,
+				htlc_maximum_msat: *htlc_maximum_msat,
+				fee_base_msat: 0,
+				fee_proportional_millionths: *fee_proportional_millionths,
+				excess_data: Vec::new()
+			});
+		}
+
+		// Sending 150 sats optimally fills the cheap path and sends the remaining 50 sats over the
+		// expensive one, paying about 10 + 500 msat in fees. The default algorithm instead splits the
+		// payment evenly across both paths, paying 7 + 750 msat.
+		let default_route = get_route(&our_id, &payment_params, &network_graph.read_only(), None,
+			150_000, Arc::clone(&logger), &scorer, &(), &random_seed_bytes).unwrap();
+		assert_eq!(default_route.get_total_amount(), 150_000);
+		assert_eq!(default_route.paths.len(), 2);
+		for path in default_route.paths.iter() {
+			assert_eq!(path.final_value_msat(), 75_000);
+		}
+		assert_eq!(default_route.get_total_fees(), 757);
+
+		let min_cost_flow_route = get_route(&our_id, &payment_params.with_min_cost_flow(true),
+			&network_graph.read_only(), None, 150_000, Arc::clone(&logger), &scorer, &(),
+			&random_seed_bytes).unwrap();
+		logger.assert_log_contains("lightning::routing::router", "Got min-cost flow route", 1);
+		assert_eq!(min_cost_flow_route.get_total_amount(), 150_000);
+		assert_eq!(min_cost_flow_route.get_total_fees(), 510);
+		assert!(min_cost_flow_route.get_total_fees() < default_route.get_total_fees());
+	}
+
+	#[test]
+	fn min_cost_flow_payment_params_roundtrip() {
+		let secp_ctx = Secp256k1::new();
+		let (_, _, _, nodes) = get_nodes(&secp_ctx);
+		let payment_params = PaymentParameters::from_node_id(nodes[2], 42).with_min_cost_flow(true);
+		let encoded = payment_params.encode();
+		let decoded = PaymentParameters::read(&mut Cursor::new(&encoded[..]), 42).unwrap();
+		assert!(decoded.use_min_cost_flow);
+		assert_eq!(decoded, payment_params);
+	}
+
+	#[test]
+	fn avoids_nodes_and_channels_per_payment() {
+		let (secp_ctx, network_graph, _, _, logger) = build_graph();
+		let (_, our_id, _, nodes) = get_nodes(&secp_ctx);
+		let scorer = ln_test_utils::TestScorer::new();
+		let keys_manager = ln_test_utils::TestKeysInterface::new(&[0u8; 32], Network::Testnet);
+		let random_seed_bytes = keys_manager.get_secure_random_bytes();
+
+		// By default, we route to node2 via node1 over channels 2 and 4.
+		let payment_params = PaymentParameters::from_node_id(nodes[2], 42);
+		let route = get_route(&our_id, &payment_params, &network_graph.read_only(), None, 100,
+			Arc::clone(&logger), &scorer, &(), &random_seed_bytes).unwrap();
+		assert_eq!(route.paths[0].hops[0].pubkey, nodes[1]);
+
+		let avoided_node_params = payment_params.clone()
+			.with_avoided_nodes(vec![NodeId::from_pubkey(&nodes[1])]);
+		let route = get_route(&our_id, &avoided_node_params, &network_graph.read_only(), None, 100,
+			Arc::clone(&logger), &scorer, &(), &random_seed_bytes).unwrap();
+		assert!(route.paths[0].hops.iter().all(|hop| hop.pubkey != nodes[1]));
+
+		let avoided_channel_params = payment_params.clone().with_avoided_channels(vec![4]);
+		let route = get_route(&our_id, &avoided_channel_params, &network_graph.read_only(), None, 100,
+			Arc::clone(&logger), &scorer, &(), &random_seed_bytes).unwrap();
+		assert!(route.paths[0].hops.iter().all(|hop| hop.short_channel_id != 4));
+
+		let avoided_payee_params = payment_params.with_avoided_nodes(vec![NodeId::from_pubkey(&nodes[2])]);
+		let route_res = get_route(&our_id, &avoided_payee_params, &network_graph.read_only(), None,
+			100, Arc::clone(&logger), &scorer, &(), &random_seed_bytes);
+		if let Err(LightningError{err, action: ErrorAction::IgnoreError}) = route_res {
+			assert_eq!(err, "Cannot generate a route to a node we were asked to avoid");
+		} else { panic!(); }
+	}
+
+	#[test]
+	fn avoids_nodes_by_announced_address() {
+		let (secp_ctx, network_graph, gossip_sync, _, logger) = build_graph();
+		let (_, our_id, privkeys, nodes) = get_nodes(&secp_ctx);
+		let scorer = ln_test_utils::TestScorer::new();
+		let keys_manager = ln_test_utils::TestKeysInterface::new(&[0u8; 32], Network::Testnet);
+		let random_seed_bytes = keys_manager.get_secure_random_bytes();
+
+		let avoided_address = NetAddress::IPv4 { addr: [192, 0, 2, 1], port: 9735 };
+		add_or_update_node_with_addresses(&gossip_sync, &secp_ctx, &privkeys[1],
+			NodeFeatures::from_le_bytes(id_to_feature_flags(2)), 1, vec![avoided_address.clone()]);
+
+		let payment_params = PaymentParameters::from_node_id(nodes[2], 42)
+			.with_avoided_node_addresses(&network_graph.read_only(), |address| *address == avoided_address);
+		assert_eq!(payment_params.avoided_nodes, vec![NodeId::from_pubkey(&nodes[1])]);
+
+		let route = get_route(&our_id, &payment_params, &network_graph.read_only(), None, 100,
+			Arc::clone(&logger), &scorer, &(), &random_seed_bytes).unwrap();
+		assert!(route.paths[0].hops.iter().all(|hop| hop.pubkey != nodes[1]));
+	}
+
+	#[test]
+	fn respects_max_total_routing_fee() {
+		let (secp_ctx, network_graph, _, _, logger) = build_graph();
+		let (_, our_id, _, nodes) = get_nodes(&secp_ctx);
+		let scorer = ln_test_utils::TestScorer::new();
+		let keys_manager = ln_test_utils::TestKeysInterface::new(&[0u8; 32], Network::Testnet);
+		let random_seed_bytes = keys_manager.get_secure_random_bytes();
+
+		// The cheapest route to node2 pays 100 msat in fees to send 100 msat.
+		let payment_params = PaymentParameters::from_node_id(nodes[2], 42);
+		let route = get_route(&our_id, &payment_params.clone().with_max_total_routing_fee_msat(100),
+			&network_graph.read_only(), None, 100, Arc::clone(&logger), &scorer, &(), &random_seed_bytes).unwrap();
+		assert_eq!(route.get_total_fees(), 100);
+
+		let too_low_fee_params = [
+			payment_params.clone().with_max_total_routing_fee_msat(99),
+			payment_params.clone().with_max_total_routing_fee_proportional_millionths(999_999),
+			payment_params.with_max_total_routing_fee_msat(1_000).with_max_total_routing_fee_proportional_millionths(500_000),
+		];
+		for params in too_low_fee_params.iter() {
+			if let Err(LightningError{err, action: ErrorAction::IgnoreError}) = get_route(&our_id, params,
+				&network_graph.read_only(), None, 100, Arc::clone(&logger), &scorer, &(), &random_seed_bytes) {
+				assert!(err.starts_with("Failed to find a route with total fees of at most"));
+			} else { panic!(); }
+		}
+	}
+
+	struct ChannelPenaltyScorer {
+		short_channel_id: u64,
+		penalty_msat: u64,
+	}
+
+	#[cfg(c_bindings)]
+	impl Writeable for ChannelPenaltyScorer {
+		fn write<W: Writer>(&self, _w: &mut W) -> Result<(), crate::io::Error> { unimplemented!() }
+	}
+
+	impl ScoreLookUp for ChannelPenaltyScorer {
+		type ScoreParams = ();
+		fn channel_penalty_msat(&self, short_channel_id: u64, _: &NodeId, _: &NodeId, _: ChannelUsage, _score_params:&Self::ScoreParams) -> u64 {
+			if short_channel_id == self.short_channel_id { self.penalty_msat } else { 0 }
+		}
+	}
+
+	#[test]
+	fn prunes_candidates_exceeding_max_total_routing_fee() {
+		let (secp_ctx, network_graph, _, _, logger) = build_graph();
+		let (_, our_id, _, nodes) = get_nodes(&secp_ctx);
+		let keys_manager = ln_test_utils::TestKeysInterface::new(&[0u8; 32], Network::Testnet);
+		let random_seed_bytes = keys_manager.get_secure_random_bytes();
+
+		// With the cheapest route to node2 penalized, the lowest-penalty route pays more fees.
+		let scorer = ChannelPenaltyScorer { short_channel_id: 4, penalty_msat: 1_000_000 };
+		let payment_params = PaymentParameters::from_node_id(nodes[2], 42);
+		let route = get_route(&our_id, &payment_params, &network_graph.read_only(), None, 100,
+			Arc::clone(&logger), &scorer, &(), &random_seed_bytes).unwrap();
+		assert!(route.paths[0].hops.iter().all(|hop| hop.short_channel_id != 4));
+		assert!(route.get_total_fees() > 100);
+
+		// Once it exceeds the fee budget, the cheapest route is found instead of failing.
+		let route = get_route(&our_id, &payment_params.with_max_total_routing_fee_msat(100),
+			&network_graph.read_only(), None, 100, Arc::clone(&logger), &scorer, &(), &random_seed_bytes).unwrap();
+		let path = route.paths[0].hops.iter().map(|hop| hop.short_channel_id).collect::<Vec<_>>();
+		assert_eq!(path, vec![2, 4]);
+		assert_eq!(route.get_total_fees(), 100);
+	}
+
+	#[test]
+	fn payment_params_avoidance_roundtrip() {
+		let secp_ctx = Secp256k1::new();
+		let (_, _, _, nodes) = get_nodes(&secp_ctx);
+		let payment_params = PaymentParameters::from_node_id(nodes[2], 42)
+			.with_avoided_nodes(vec![NodeId::from_pubkey(&nodes[1])])
+			.with_avoided_channels(vec![4])
+			.with_max_total_routing_fee_msat(1_000)
+			.with_max_total_routing_fee_proportional_millionths(5_000);
+		let encoded = payment_params.encode();
+		let decoded = PaymentParameters::read(&mut Cursor::new(&encoded[..]), 42).unwrap();
+		assert_eq!(decoded, payment_params);
+	}
 }
 
 #[cfg(all(any(test, ldk_bench), not(feature = "no-std")))]
@@ -6726,6 +7942,7 @@ pub(crate) mod bench_utils {
 	use crate::ln::channelmanager::{self, ChannelCounterparty, ChannelDetails};
 	use crate::ln::features::Bolt11InvoiceFeatures;
 	use crate::routing::gossip::NetworkGraph;
+	use crate::routing::scoring::ScoreUpdate;
 	use crate::util::config::UserConfig;
 	use crate::util::ser::ReadableArgs;
 	use crate::util::test_utils::TestLogger;
@@ -6815,7 +8032,7 @@ pub(crate) mod bench_utils {
 		}
 	}
 
-	pub(crate) fn generate_test_routes<S: Score>(graph: &NetworkGraph<&TestLogger>, scorer: &mut S,
+	pub(crate) fn generate_test_routes<S: ScoreLookUp + ScoreUpdate>(graph: &NetworkGraph<&TestLogger>, scorer: &mut S,
 		score_params: &S::ScoreParams, features: Bolt11InvoiceFeatures, mut seed: u64,
 		starting_amount: u64, route_count: usize,
 	) -> Vec<(ChannelDetails, PaymentParameters, u64)> {
@@ -6899,7 +8116,7 @@ pub mod benches {
 	use crate::ln::channelmanager;
 	use crate::ln::features::Bolt11InvoiceFeatures;
 	use crate::routing::gossip::NetworkGraph;
-	use crate::routing::scoring::{FixedPenaltyScorer, ProbabilisticScorer, ProbabilisticScoringFeeParameters, ProbabilisticScoringDecayParameters};
+	use crate::routing::scoring::{FixedPenaltyScorer, ProbabilisticScorer, ProbabilisticScoringFeeParameters, ProbabilisticScoringDecayParameters, ScoreUpdate};
 	use crate::util::config::UserConfig;
 	use crate::util::logger::{Logger, Record};
 	use crate::util::test_utils::TestLogger;
@@ -6916,7 +8133,7 @@ pub mod benches {
 		let network_graph = bench_utils::read_network_graph(&logger).unwrap();
 		let scorer = FixedPenaltyScorer::with_penalty(0);
 		generate_routes(bench, &network_graph, scorer, &(), Bolt11InvoiceFeatures::empty(), 0,
-			"generate_routes_with_zero_penalty_scorer");
+			false, "generate_routes_with_zero_penalty_scorer");
 	}
 
 	pub fn generate_mpp_routes_with_zero_penalty_scorer(bench: &mut Criterion) {
@@ -6925,7 +8142,7 @@ pub mod benches {
 		let scorer = FixedPenaltyScorer::with_penalty(0);
 		generate_routes(bench, &network_graph, scorer, &(),
 			channelmanager::provided_invoice_features(&UserConfig::default()), 0,
-			"generate_mpp_routes_with_zero_penalty_scorer");
+			false, "generate_mpp_routes_with_zero_penalty_scorer");
 	}
 
 	pub fn generate_routes_with_probabilistic_scorer(bench: &mut Criterion) {
@@ -6934,7 +8151,7 @@ pub mod benches {
 		let params = ProbabilisticScoringFeeParameters::default();
 		let scorer = ProbabilisticScorer::new(ProbabilisticScoringDecayParameters::default(), &network_graph, &logger);
 		generate_routes(bench, &network_graph, scorer, &params, Bolt11InvoiceFeatures::empty(), 0,
-			"generate_routes_with_probabilistic_scorer");
+			false, "generate_routes_with_probabilistic_scorer");
 	}
 
 	pub fn generate_mpp_routes_with_probabilistic_scorer(bench: &mut Criterion) {
@@ -6944,7 +8161,7 @@ pub mod benches {
 		let scorer = ProbabilisticScorer::new(ProbabilisticScoringDecayParameters::default(), &network_graph, &logger);
 		generate_routes(bench, &network_graph, scorer, &params,
 			channelmanager::provided_invoice_features(&UserConfig::default()), 0,
-			"generate_mpp_routes_with_probabilistic_scorer");
+			false, "generate_mpp_routes_with_probabilistic_scorer");
 	}
 
 	pub fn generate_large_mpp_routes_with_probabilistic_scorer(bench: &mut Criterion) {
@@ -6954,20 +8171,32 @@ pub mod benches {
 		let scorer = ProbabilisticScorer::new(ProbabilisticScoringDecayParameters::default(), &network_graph, &logger);
 		generate_routes(bench, &network_graph, scorer, &params,
 			channelmanager::provided_invoice_features(&UserConfig::default()), 100_000_000,
-			"generate_large_mpp_routes_with_probabilistic_scorer");
+			false, "generate_large_mpp_routes_with_probabilistic_scorer");
+	}
+
+	pub fn generate_large_mpp_routes_with_min_cost_flow(bench: &mut Criterion) {
+		let logger = TestLogger::new();
+		let network_graph = bench_utils::read_network_graph(&logger).unwrap();
+		let params = ProbabilisticScoringFeeParameters::default();
+		let scorer = ProbabilisticScorer::new(ProbabilisticScoringDecayParameters::default(), &network_graph, &logger);
+		generate_routes(bench, &network_graph, scorer, &params,
+			channelmanager::provided_invoice_features(&UserConfig::default()), 100_000_000,
+			true, "generate_large_mpp_routes_with_min_cost_flow");
 	}
 
-	fn generate_routes<S: Score>(
+	fn generate_routes<S: ScoreLookUp + ScoreUpdate>(
 		bench: &mut Criterion, graph: &NetworkGraph<&TestLogger>, mut scorer: S,
 		score_params: &S::ScoreParams, features: Bolt11InvoiceFeatures, starting_amount: u64,
-		bench_name: &'static str,
+		use_min_cost_flow: bool, bench_name: &'static str,
 	) {
 		let payer = bench_utils::payer_pubkey();
 		let keys_manager = KeysManager::new(&[0u8; 32], 42, 42);
 		let random_seed_bytes = keys_manager.get_secure_random_bytes();
 
 		// First, get 100 (source, destination) pairs for which route-getting actually succeeds...
-		let route_endpoints = bench_utils::generate_test_routes(graph, &mut scorer, score_params, features, 0xdeadbeef, starting_amount, 50);
+		let route_endpoints = bench_utils::generate_test_routes(graph, &mut scorer, score_params, features, 0xdeadbeef, starting_amount, 50)
+			.into_iter().map(|(first_hop, params, amt)| (first_hop, params.with_min_cost_flow(use_min_cost_flow), amt))
+			.collect::<Vec<_>>();
 
 		// ...then benchmark finding paths between the nodes we learned.
 		let mut idx = 0;
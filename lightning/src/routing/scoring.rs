@@ -10,7 +10,7 @@
 //! Utilities for scoring payment channels.
 //!
 //! [`ProbabilisticScorer`] may be given to [`find_route`] to score payment channels during path
-//! finding when a custom [`Score`] implementation is not needed.
+//! finding when a custom [`ScoreLookUp`] implementation is not needed.
 //!
 //! # Example
 //!
@@ -57,20 +57,20 @@
 //! [`find_route`]: crate::routing::router::find_route
 
 use crate::ln::msgs::DecodeError;
-use crate::routing::gossip::{EffectiveCapacity, NetworkGraph, NodeId};
+use crate::routing::gossip::{ChannelInfo, EffectiveCapacity, NetworkGraph, NodeId};
 use crate::routing::router::Path;
-use crate::util::ser::{Readable, ReadableArgs, Writeable, Writer};
+use crate::util::ser::{Readable, ReadableArgs, VecWriter, Writeable, Writer};
 use crate::util::logger::Logger;
 use crate::util::time::Time;
 
 use crate::prelude::*;
 use core::{cmp, fmt};
-use core::cell::{RefCell, RefMut};
+use core::cell::{RefCell, RefMut, Ref};
 use core::convert::TryInto;
 use core::ops::{Deref, DerefMut};
 use core::time::Duration;
 use crate::io::{self, Read};
-use crate::sync::{Mutex, MutexGuard};
+use crate::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 /// We define Score ever-so-slightly differently based on whether we are being built for C bindings
 /// or not. For users, `LockableScore` must somehow be writeable to disk. For Rust users, this is
@@ -86,8 +86,10 @@ use crate::sync::{Mutex, MutexGuard};
 macro_rules! define_score { ($($supertrait: path)*) => {
 /// An interface used to score payment channels for path finding.
 ///
+/// `ScoreLookUp` is used to determine the penalty for a given channel.
+///
 ///	Scoring is in terms of fees willing to be paid in order to avoid routing through a channel.
-pub trait Score $(: $supertrait)* {
+pub trait ScoreLookUp $(: $supertrait)* {
 	/// A configurable type which should contain various passed-in parameters for configuring the scorer,
 	/// on a per-routefinding-call basis through to the scorer methods,
 	/// which are used to determine the parameters for the suitability of channels for use.
@@ -104,6 +106,20 @@ pub trait Score $(: $supertrait)* {
 		&self, short_channel_id: u64, source: &NodeId, target: &NodeId, usage: ChannelUsage, score_params: &Self::ScoreParams
 	) -> u64;
 
+	/// Returns the estimated probability, between 0 and 1, of successfully sending
+	/// `usage.amount_msat` through the given channel in the direction from `source` to `target`.
+	///
+	/// Returns `None` if the scorer does not estimate success probabilities, which is the default.
+	fn channel_success_probability(
+		&self, _short_channel_id: u64, _source: &NodeId, _target: &NodeId, _usage: ChannelUsage,
+		_score_params: &Self::ScoreParams
+	) -> Option<f64> {
+		None
+	}
+}
+
+/// `ScoreUpdate` is used to update the scorer's internal state after a payment attempt.
+pub trait ScoreUpdate $(: $supertrait)* {
 	/// Handles updating channel penalties after failing to route through a channel.
 	fn payment_path_failed(&mut self, path: &Path, short_channel_id: u64);
 
@@ -117,7 +133,15 @@ pub trait Score $(: $supertrait)* {
 	fn probe_successful(&mut self, path: &Path);
 }
 
-impl<S: Score, T: DerefMut<Target=S> $(+ $supertrait)*> Score for T {
+/// A trait which can both lookup and update routing channel penalty scores.
+///
+/// This is used in places where both bounds are required and implemented for all types which
+/// implement [`ScoreLookUp`] and [`ScoreUpdate`].
+pub trait Score : ScoreLookUp + ScoreUpdate $(+ $supertrait)* {}
+
+impl<T: ScoreLookUp + ScoreUpdate $(+ $supertrait)*> Score for T {}
+
+impl<S: ScoreLookUp, T: Deref<Target=S> $(+ $supertrait)*> ScoreLookUp for T {
 	type ScoreParams = S::ScoreParams;
 	fn channel_penalty_msat(
 		&self, short_channel_id: u64, source: &NodeId, target: &NodeId, usage: ChannelUsage, score_params: &Self::ScoreParams
@@ -125,6 +149,14 @@ impl<S: Score, T: DerefMut<Target=S> $(+ $supertrait)*> Score for T {
 		self.deref().channel_penalty_msat(short_channel_id, source, target, usage, score_params)
 	}
 
+	fn channel_success_probability(
+		&self, short_channel_id: u64, source: &NodeId, target: &NodeId, usage: ChannelUsage, score_params: &Self::ScoreParams
+	) -> Option<f64> {
+		self.deref().channel_success_probability(short_channel_id, source, target, usage, score_params)
+	}
+}
+
+impl<S: ScoreUpdate, T: DerefMut<Target=S> $(+ $supertrait)*> ScoreUpdate for T {
 	fn payment_path_failed(&mut self, path: &Path, short_channel_id: u64) {
 		self.deref_mut().payment_path_failed(path, short_channel_id)
 	}
@@ -150,21 +182,32 @@ define_score!();
 
 /// A scorer that is accessed under a lock.
 ///
-/// Needed so that calls to [`Score::channel_penalty_msat`] in [`find_route`] can be made while
-/// having shared ownership of a scorer but without requiring internal locking in [`Score`]
+/// Needed so that calls to [`ScoreLookUp::channel_penalty_msat`] in [`find_route`] can be made while
+/// having shared ownership of a scorer but without requiring internal locking in [`ScoreUpdate`]
 /// implementations. Internal locking would be detrimental to route finding performance and could
-/// result in [`Score::channel_penalty_msat`] returning a different value for the same channel.
+/// result in [`ScoreLookUp::channel_penalty_msat`] returning a different value for the same channel.
+///
+/// Lookups only require a read lock, so implementations backed by a read-write lock (such as
+/// `RwLock`) allow many route calculations to proceed in parallel.
 ///
 /// [`find_route`]: crate::routing::router::find_route
 pub trait LockableScore<'a> {
-	/// The [`Score`] type.
-	type Score: 'a + Score;
+	/// The [`ScoreUpdate`] type.
+	type ScoreUpdate: 'a + ScoreUpdate;
+	/// The [`ScoreLookUp`] type.
+	type ScoreLookUp: 'a + ScoreLookUp;
+
+	/// The write locked [`ScoreUpdate`] type.
+	type WriteLocked: DerefMut<Target = Self::ScoreUpdate> + Sized;
 
-	/// The locked [`Score`] type.
-	type Locked: DerefMut<Target = Self::Score> + Sized;
+	/// The read locked [`ScoreLookUp`] type.
+	type ReadLocked: Deref<Target = Self::ScoreLookUp> + Sized;
 
-	/// Returns the locked scorer.
-	fn lock(&'a self) -> Self::Locked;
+	/// Returns read locked scorer.
+	fn read_lock(&'a self) -> Self::ReadLocked;
+
+	/// Returns write locked scorer.
+	fn write_lock(&'a self) -> Self::WriteLocked;
 }
 
 /// Refers to a scorer that is accessible under lock and also writeable to disk
@@ -177,44 +220,81 @@ pub trait WriteableScore<'a>: LockableScore<'a> + Writeable {}
 impl<'a, T> WriteableScore<'a> for T where T: LockableScore<'a> + Writeable {}
 #[cfg(not(c_bindings))]
 impl<'a, T: 'a + Score> LockableScore<'a> for Mutex<T> {
-	type Score = T;
-	type Locked = MutexGuard<'a, T>;
+	type ScoreUpdate = T;
+	type ScoreLookUp = T;
+
+	type WriteLocked = MutexGuard<'a, Self::ScoreUpdate>;
+	type ReadLocked = MutexGuard<'a, Self::ScoreLookUp>;
+
+	fn read_lock(&'a self) -> Self::ReadLocked {
+		Mutex::lock(self).unwrap()
+	}
 
-	fn lock(&'a self) -> Self::Locked {
+	fn write_lock(&'a self) -> Self::WriteLocked {
 		Mutex::lock(self).unwrap()
 	}
 }
 
 #[cfg(not(c_bindings))]
 impl<'a, T: 'a + Score> LockableScore<'a> for RefCell<T> {
-	type Score = T;
-	type Locked = RefMut<'a, T>;
+	type ScoreUpdate = T;
+	type ScoreLookUp = T;
 
-	fn lock(&'a self) -> Self::Locked {
+	type WriteLocked = RefMut<'a, Self::ScoreUpdate>;
+	type ReadLocked = Ref<'a, Self::ScoreLookUp>;
+
+	fn write_lock(&'a self) -> Self::WriteLocked {
 		self.borrow_mut()
 	}
+
+	fn read_lock(&'a self) -> Self::ReadLocked {
+		self.borrow()
+	}
+}
+
+#[cfg(not(c_bindings))]
+impl<'a, T: 'a + Score> LockableScore<'a> for RwLock<T> {
+	type ScoreUpdate = T;
+	type ScoreLookUp = T;
+
+	type WriteLocked = RwLockWriteGuard<'a, Self::ScoreUpdate>;
+	type ReadLocked = RwLockReadGuard<'a, Self::ScoreLookUp>;
+
+	fn read_lock(&'a self) -> Self::ReadLocked {
+		RwLock::read(self).unwrap()
+	}
+
+	fn write_lock(&'a self) -> Self::WriteLocked {
+		RwLock::write(self).unwrap()
+	}
 }
 
 #[cfg(c_bindings)]
 /// A concrete implementation of [`LockableScore`] which supports multi-threading.
 pub struct MultiThreadedLockableScore<T: Score> {
-	score: Mutex<T>,
+	score: RwLock<T>,
 }
 
 #[cfg(c_bindings)]
 impl<'a, T: 'a + Score> LockableScore<'a> for MultiThreadedLockableScore<T> {
-	type Score = T;
-	type Locked = MultiThreadedScoreLock<'a, T>;
+	type ScoreUpdate = T;
+	type ScoreLookUp = T;
+	type WriteLocked = MultiThreadedScoreLockWrite<'a, Self::ScoreUpdate>;
+	type ReadLocked = MultiThreadedScoreLockRead<'a, Self::ScoreLookUp>;
+
+	fn read_lock(&'a self) -> Self::ReadLocked {
+		MultiThreadedScoreLockRead(self.score.read().unwrap())
+	}
 
-	fn lock(&'a self) -> Self::Locked {
-		MultiThreadedScoreLock(Mutex::lock(&self.score).unwrap())
+	fn write_lock(&'a self) -> Self::WriteLocked {
+		MultiThreadedScoreLockWrite(self.score.write().unwrap())
 	}
 }
 
 #[cfg(c_bindings)]
 impl<T: Score> Writeable for MultiThreadedLockableScore<T> {
 	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
-		self.lock().write(writer)
+		self.score.read().unwrap().write(writer)
 	}
 }
 
@@ -225,40 +305,52 @@ impl<'a, T: 'a + Score> WriteableScore<'a> for MultiThreadedLockableScore<T> {}
 impl<T: Score> MultiThreadedLockableScore<T> {
 	/// Creates a new [`MultiThreadedLockableScore`] given an underlying [`Score`].
 	pub fn new(score: T) -> Self {
-		MultiThreadedLockableScore { score: Mutex::new(score) }
+		MultiThreadedLockableScore { score: RwLock::new(score) }
 	}
 }
 
 #[cfg(c_bindings)]
-/// A locked `MultiThreadedLockableScore`.
-pub struct MultiThreadedScoreLock<'a, T: Score>(MutexGuard<'a, T>);
+/// A locked `MultiThreadedLockableScore`, which allows lookups.
+pub struct MultiThreadedScoreLockRead<'a, T: Score>(RwLockReadGuard<'a, T>);
 
 #[cfg(c_bindings)]
-impl<'a, T: 'a + Score> Writeable for MultiThreadedScoreLock<'a, T> {
-	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
-		self.0.write(writer)
+/// A locked `MultiThreadedLockableScore`, which allows updates.
+pub struct MultiThreadedScoreLockWrite<'a, T: Score>(RwLockWriteGuard<'a, T>);
+
+#[cfg(c_bindings)]
+impl<'a, T: 'a + Score> Deref for MultiThreadedScoreLockRead<'a, T> {
+	type Target = T;
+
+	fn deref(&self) -> &Self::Target {
+		self.0.deref()
 	}
 }
 
 #[cfg(c_bindings)]
-impl<'a, T: 'a + Score> DerefMut for MultiThreadedScoreLock<'a, T> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        self.0.deref_mut()
-    }
+impl<'a, T: 'a + Score> Writeable for MultiThreadedScoreLockWrite<'a, T> {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), io::Error> {
+		self.0.write(writer)
+	}
 }
 
 #[cfg(c_bindings)]
-impl<'a, T: 'a + Score> Deref for MultiThreadedScoreLock<'a, T> {
+impl<'a, T: 'a + Score> Deref for MultiThreadedScoreLockWrite<'a, T> {
 	type Target = T;
 
-    fn deref(&self) -> &Self::Target {
-        self.0.deref()
-    }
+	fn deref(&self) -> &Self::Target {
+		self.0.deref()
+	}
 }
 
+#[cfg(c_bindings)]
+impl<'a, T: 'a + Score> DerefMut for MultiThreadedScoreLockWrite<'a, T> {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		self.0.deref_mut()
+	}
+}
 
 
-/// Proposed use of a channel passed as a parameter to [`Score::channel_penalty_msat`].
+/// Proposed use of a channel passed as a parameter to [`ScoreLookUp::channel_penalty_msat`].
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct ChannelUsage {
 	/// The amount to send through the channel, denominated in millisatoshis.
@@ -285,12 +377,14 @@ impl FixedPenaltyScorer {
 	}
 }
 
-impl Score for FixedPenaltyScorer {
+impl ScoreLookUp for FixedPenaltyScorer {
 	type ScoreParams = ();
 	fn channel_penalty_msat(&self, _: u64, _: &NodeId, _: &NodeId, _: ChannelUsage, _score_params: &Self::ScoreParams) -> u64 {
 		self.penalty_msat
 	}
+}
 
+impl ScoreUpdate for FixedPenaltyScorer {
 	fn payment_path_failed(&mut self, _path: &Path, _short_channel_id: u64) {}
 
 	fn payment_path_successful(&mut self, _path: &Path) {}
@@ -371,6 +465,9 @@ where L::Target: Logger {
 	logger: L,
 	// TODO: Remove entries of closed channels.
 	channel_liquidities: HashMap<u64, ChannelLiquidity<T>>,
+	/// The liquidity data of the last snapshot given to [`Self::import_snapshot`], kept apart from
+	/// our own so that each import replaces the previous one.
+	imported_channel_liquidities: HashMap<u64, ChannelLiquidity<T>>,
 }
 
 /// Parameters for configuring [`ProbabilisticScorer`].
@@ -650,6 +747,12 @@ struct HistoricalBucketRangeTracker {
 
 impl HistoricalBucketRangeTracker {
 	fn new() -> Self { Self { buckets: [0; 8] } }
+	/// Merges the datapoints tracked by `other` into ours, weighting them half as much as our own.
+	fn merge(&mut self, other: &Self) {
+		for (bucket, other_bucket) in self.buckets.iter_mut().zip(other.buckets.iter()) {
+			*bucket = bucket.saturating_add(*other_bucket / 2);
+		}
+	}
 	fn track_datapoint(&mut self, liquidity_offset_msat: u64, capacity_msat: u64) {
 		// We have 8 leaky buckets for min and max liquidity. Each bucket tracks the amount of time
 		// we spend in each bucket as a 16-bit fixed-point number with a 5 bit fractional part.
@@ -787,6 +890,7 @@ impl HistoricalMinMaxBuckets<'_> {
 /// Direction is defined in terms of [`NodeId`] partial ordering, where the source node is the
 /// first node in the ordering of the channel's counterparties. Thus, swapping the two liquidity
 /// offset fields gives the opposite direction.
+#[derive(Clone, Copy)]
 struct ChannelLiquidity<T: Time> {
 	/// Lower channel liquidity bound in terms of an offset from zero.
 	min_liquidity_offset_msat: u64,
@@ -824,6 +928,7 @@ impl<G: Deref<Target = NetworkGraph<L>>, L: Deref, T: Time> ProbabilisticScorerU
 			network_graph,
 			logger,
 			channel_liquidities: HashMap::new(),
+			imported_channel_liquidities: HashMap::new(),
 		}
 	}
 
@@ -841,8 +946,9 @@ impl<G: Deref<Target = NetworkGraph<L>>, L: Deref, T: Time> ProbabilisticScorerU
 		let now = T::now();
 
 		let graph = self.network_graph.read_only();
-		for (scid, liq) in self.channel_liquidities.iter() {
-			if let Some(chan_debug) = graph.channels().get(scid) {
+		for scid in self.scored_channel_ids() {
+			let liq = match self.channel_liquidity(scid) { Some(liq) => liq, None => continue };
+			if let Some(chan_debug) = graph.channels().get(&scid) {
 				let log_direction = |source, target| {
 					if let Some((directed_info, _)) = chan_debug.as_directed_to(target) {
 						let amt = directed_info.effective_capacity().as_msat();
@@ -879,13 +985,79 @@ impl<G: Deref<Target = NetworkGraph<L>>, L: Deref, T: Time> ProbabilisticScorerU
 		}
 	}
 
+	/// Exports a snapshot of this scorer's liquidity estimates and historical data, which may be
+	/// given to another node's scorer via [`Self::import_snapshot`].
+	///
+	/// Only our own observations are included, not any data imported via
+	/// [`Self::import_snapshot`].
+	///
+	/// The snapshot uses the same format as a serialized [`ProbabilisticScorer`], so a scorer
+	/// persisted to disk may be imported as-is.
+	pub fn export_snapshot(&self) -> Vec<u8> {
+		let mut snapshot = VecWriter(Vec::new());
+		self.write_snapshot(&mut snapshot).expect("In-memory writes cannot fail");
+		snapshot.0
+	}
+
+	fn write_snapshot<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		write_tlv_fields!(w, {
+			(0, self.channel_liquidities, required),
+		});
+		Ok(())
+	}
+
+	/// Imports a snapshot of liquidity estimates, e.g. fetched from a trusted source, replacing any
+	/// snapshot imported previously.
+	///
+	/// This allows a new node to route well from the start rather than waiting days for its own
+	/// observations to accumulate. The imported data is kept apart from our own observations,
+	/// which take precedence: for channels we have liquidity bounds for, we keep our bounds and
+	/// add the imported historical data to ours, weighting it half as much as our own. Entries for
+	/// channels which are not in our [`NetworkGraph`] are ignored.
+	///
+	/// The snapshot may be one produced by [`Self::export_snapshot`] or a serialized
+	/// [`ProbabilisticScorer`].
+	pub fn import_snapshot<R: Read>(&mut self, r: &mut R) -> Result<(), DecodeError> {
+		let mut channel_liquidities: HashMap<u64, ChannelLiquidity<T>> = HashMap::new();
+		read_tlv_fields!(r, {
+			(0, channel_liquidities, required),
+		});
+
+		{
+			let graph = self.network_graph.read_only();
+			channel_liquidities.retain(|scid, _| graph.channels().get(scid).is_some());
+		}
+		log_debug!(self.logger, "Imported liquidity data for {} channels from scorer snapshot", channel_liquidities.len());
+		self.imported_channel_liquidities = channel_liquidities;
+		Ok(())
+	}
+
+	/// Returns the short channel ids of all channels we have liquidity data for, either from our
+	/// own observations or from an imported snapshot.
+	fn scored_channel_ids(&self) -> impl Iterator<Item = u64> + '_ {
+		let local_scids = self.channel_liquidities.keys();
+		let imported_scids = self.imported_channel_liquidities.keys()
+			.filter(move |scid| !self.channel_liquidities.contains_key(scid));
+		local_scids.chain(imported_scids).cloned()
+	}
+
+	/// Returns the liquidity data to score the channel with `scid` with. These are our own
+	/// liquidity bounds, if any, with the historical data of the last imported snapshot merged in.
+	fn channel_liquidity(&self, scid: u64) -> Option<ChannelLiquidity<T>> {
+		match (self.channel_liquidities.get(&scid), self.imported_channel_liquidities.get(&scid)) {
+			(Some(local), Some(imported)) => Some(local.merged_with(imported)),
+			(Some(liquidity), None) | (None, Some(liquidity)) => Some(*liquidity),
+			(None, None) => None,
+		}
+	}
+
 	/// Query the estimated minimum and maximum liquidity available for sending a payment over the
 	/// channel with `scid` towards the given `target` node.
 	pub fn estimated_channel_liquidity_range(&self, scid: u64, target: &NodeId) -> Option<(u64, u64)> {
 		let graph = self.network_graph.read_only();
 
 		if let Some(chan) = graph.channels().get(&scid) {
-			if let Some(liq) = self.channel_liquidities.get(&scid) {
+			if let Some(liq) = self.channel_liquidity(scid) {
 				if let Some((directed_info, source)) = chan.as_directed_to(target) {
 					let amt = directed_info.effective_capacity().as_msat();
 					let dir_liq = liq.as_directed(source, target, 0, amt, self.decay_params);
@@ -922,7 +1094,7 @@ impl<G: Deref<Target = NetworkGraph<L>>, L: Deref, T: Time> ProbabilisticScorerU
 		let graph = self.network_graph.read_only();
 
 		if let Some(chan) = graph.channels().get(&scid) {
-			if let Some(liq) = self.channel_liquidities.get(&scid) {
+			if let Some(liq) = self.channel_liquidity(scid) {
 				if let Some((directed_info, source)) = chan.as_directed_to(target) {
 					let amt = directed_info.effective_capacity().as_msat();
 					let dir_liq = liq.as_directed(source, target, 0, amt, self.decay_params);
@@ -942,6 +1114,111 @@ impl<G: Deref<Target = NetworkGraph<L>>, L: Deref, T: Time> ProbabilisticScorerU
 		}
 		None
 	}
+
+	/// Returns a snapshot of our knowledge of the liquidity of every channel we have data for, in
+	/// both directions, including the estimated probability of successfully sending `amount_msat`
+	/// over each.
+	///
+	/// This is intended for building dashboards or debugging why a route was chosen. Note that this
+	/// returns two entries per channel for which we have a liquidity estimate, which may be a
+	/// substantial amount of data.
+	pub fn channel_liquidity_diagnostics(&self, amount_msat: u64) -> Vec<ChannelLiquidityDiagnostics> {
+		let graph = self.network_graph.read_only();
+		let mut diagnostics = Vec::with_capacity(self.channel_liquidities.len() * 2);
+		for scid in self.scored_channel_ids() {
+			let liq = match self.channel_liquidity(scid) { Some(liq) => liq, None => continue };
+			if let Some(chan) = graph.channels().get(&scid) {
+				for target in [&chan.node_one, &chan.node_two].iter() {
+					if let Some(directed_diagnostics) = self.directed_liquidity_diagnostics(scid, &liq, chan, target, amount_msat) {
+						diagnostics.push(directed_diagnostics);
+					}
+				}
+			}
+		}
+		diagnostics
+	}
+
+	/// Returns a snapshot of our knowledge of the liquidity available for sending a payment over the
+	/// channel with `scid` towards the given `target` node, including the estimated probability of
+	/// successfully sending `amount_msat`.
+	///
+	/// See [`Self::channel_liquidity_diagnostics`] for a snapshot of all channels.
+	pub fn directed_channel_liquidity_diagnostics(&self, scid: u64, target: &NodeId, amount_msat: u64)
+	-> Option<ChannelLiquidityDiagnostics> {
+		let graph = self.network_graph.read_only();
+		let chan = graph.channels().get(&scid)?;
+		let liq = self.channel_liquidity(scid)?;
+		self.directed_liquidity_diagnostics(scid, &liq, chan, target, amount_msat)
+	}
+
+	fn directed_liquidity_diagnostics(
+		&self, scid: u64, liq: &ChannelLiquidity<T>, chan: &ChannelInfo, target: &NodeId, amount_msat: u64
+	) -> Option<ChannelLiquidityDiagnostics> {
+		let (directed_info, source) = chan.as_directed_to(target)?;
+		let capacity_msat = directed_info.effective_capacity().as_msat();
+		let dir_liq = liq.as_directed(source, target, 0, capacity_msat, self.decay_params);
+
+		let buckets = HistoricalMinMaxBuckets {
+			min_liquidity_offset_history: &dir_liq.min_liquidity_offset_history,
+			max_liquidity_offset_history: &dir_liq.max_liquidity_offset_history,
+		};
+		let (min_buckets, mut max_buckets, _) = buckets.get_decayed_buckets(dir_liq.now,
+			*dir_liq.last_updated, self.decay_params.historical_no_updates_half_life);
+		// Note that the liquidity buckets are an offset from the edge, so we inverse the max order
+		// to get the probabilities from zero.
+		max_buckets.reverse();
+
+		Some(ChannelLiquidityDiagnostics {
+			short_channel_id: scid,
+			source: *source,
+			target: *target,
+			capacity_msat,
+			min_liquidity_msat: dir_liq.min_liquidity_msat(),
+			max_liquidity_msat: dir_liq.max_liquidity_msat(),
+			last_updated_elapsed: dir_liq.now.duration_since(*dir_liq.last_updated),
+			historical_min_liquidity_buckets: min_buckets,
+			historical_max_liquidity_buckets: max_buckets,
+			success_probability: dir_liq.success_probability(amount_msat),
+			historical_success_probability: dir_liq.historical_success_probability(amount_msat),
+		})
+	}
+}
+
+/// A snapshot of a [`ProbabilisticScorer`]'s knowledge of the liquidity of a channel in one
+/// direction, as returned by [`ProbabilisticScorerUsingTime::channel_liquidity_diagnostics`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelLiquidityDiagnostics {
+	/// The short channel id of the channel.
+	pub short_channel_id: u64,
+	/// The node sending over the channel in this direction.
+	pub source: NodeId,
+	/// The node receiving over the channel in this direction.
+	pub target: NodeId,
+	/// The effective capacity of the channel in this direction, see [`EffectiveCapacity`].
+	pub capacity_msat: u64,
+	/// The estimated lower bound of the liquidity available in this direction, after decaying.
+	pub min_liquidity_msat: u64,
+	/// The estimated upper bound of the liquidity available in this direction, after decaying.
+	pub max_liquidity_msat: u64,
+	/// The time elapsed since the liquidity bounds were last updated.
+	///
+	/// This is always zero if the scorer doesn't track time, e.g. when built with `no-std`.
+	pub last_updated_elapsed: Duration,
+	/// The relative frequency at which we've seen the lower bound of the liquidity in each octile of
+	/// the channel's capacity, see
+	/// [`ProbabilisticScorerUsingTime::historical_estimated_channel_liquidity_probabilities`].
+	pub historical_min_liquidity_buckets: [u16; 8],
+	/// The relative frequency at which we've seen the upper bound of the liquidity in each octile of
+	/// the channel's capacity, see
+	/// [`ProbabilisticScorerUsingTime::historical_estimated_channel_liquidity_probabilities`].
+	pub historical_max_liquidity_buckets: [u16; 8],
+	/// The estimated probability of successfully sending the queried amount, assuming the liquidity
+	/// is uniformly distributed between [`Self::min_liquidity_msat`] and
+	/// [`Self::max_liquidity_msat`].
+	pub success_probability: f64,
+	/// The estimated probability of successfully sending the queried amount based on the
+	/// historical liquidity bounds, or `None` if we don't have enough (recent) historical data.
+	pub historical_success_probability: Option<f64>,
 }
 
 impl<T: Time> ChannelLiquidity<T> {
@@ -956,6 +1233,27 @@ impl<T: Time> ChannelLiquidity<T> {
 		}
 	}
 
+	/// Returns a fresh entry to track our own observations in, starting from the liquidity bounds
+	/// of `imported` but none of its historical data, if given.
+	fn from_imported(imported: Option<&Self>) -> Self {
+		match imported {
+			Some(imported) => Self {
+				min_liquidity_offset_history: HistoricalBucketRangeTracker::new(),
+				max_liquidity_offset_history: HistoricalBucketRangeTracker::new(),
+				..*imported
+			},
+			None => Self::new(),
+		}
+	}
+
+	/// Returns our liquidity bounds with the historical data of `imported` merged into ours.
+	fn merged_with(&self, imported: &Self) -> Self {
+		let mut merged = *self;
+		merged.min_liquidity_offset_history.merge(&imported.min_liquidity_offset_history);
+		merged.max_liquidity_offset_history.merge(&imported.max_liquidity_offset_history);
+		merged
+	}
+
 	/// Returns a view of the channel liquidity directed from `source` to `target` assuming
 	/// `capacity_msat`.
 	fn as_directed(
@@ -1060,15 +1358,7 @@ impl<L: Deref<Target = u64>, BRT: Deref<Target = HistoricalBucketRangeTracker>,
 
 		if score_params.historical_liquidity_penalty_multiplier_msat != 0 ||
 		   score_params.historical_liquidity_penalty_amount_multiplier_msat != 0 {
-			let payment_amt_64th_bucket = if amount_msat < u64::max_value() / 64 {
-				amount_msat * 64 / self.capacity_msat.saturating_add(1)
-			} else {
-				// Only use 128-bit arithmetic when multiplication will overflow to avoid 128-bit
-				// division. This branch should only be hit in fuzz testing since the amount would
-				// need to be over 2.88 million BTC in practice.
-				((amount_msat as u128) * 64 / (self.capacity_msat as u128).saturating_add(1))
-					.try_into().unwrap_or(65)
-			};
+			let payment_amt_64th_bucket = self.payment_amt_64th_bucket(amount_msat);
 			#[cfg(not(fuzzing))]
 			debug_assert!(payment_amt_64th_bucket <= 64);
 			if payment_amt_64th_bucket > 64 { return res; }
@@ -1103,6 +1393,54 @@ impl<L: Deref<Target = u64>, BRT: Deref<Target = HistoricalBucketRangeTracker>,
 		res
 	}
 
+	/// Returns the probability of successfully routing the given HTLC `amount_msat` through the
+	/// channel in this direction, assuming the liquidity is uniformly distributed between our
+	/// current bounds.
+	fn success_probability(&self, amount_msat: u64) -> f64 {
+		let max_liquidity_msat = self.max_liquidity_msat();
+		let min_liquidity_msat = core::cmp::min(self.min_liquidity_msat(), max_liquidity_msat);
+
+		if amount_msat <= min_liquidity_msat {
+			1.0
+		} else if amount_msat >= max_liquidity_msat {
+			0.0
+		} else {
+			let numerator = (max_liquidity_msat - amount_msat).saturating_add(1);
+			let denominator = (max_liquidity_msat - min_liquidity_msat).saturating_add(1);
+			numerator as f64 / denominator as f64
+		}
+	}
+
+	/// Returns the probability of successfully routing the given HTLC `amount_msat` through the
+	/// channel in this direction based on our historical liquidity bounds, or `None` if we don't
+	/// have enough historical data.
+	fn historical_success_probability(&self, amount_msat: u64) -> Option<f64> {
+		let payment_amt_64th_bucket = self.payment_amt_64th_bucket(amount_msat);
+		if payment_amt_64th_bucket > 64 { return Some(0.0); }
+
+		let buckets = HistoricalMinMaxBuckets {
+			min_liquidity_offset_history: &self.min_liquidity_offset_history,
+			max_liquidity_offset_history: &self.max_liquidity_offset_history,
+		};
+		buckets.calculate_success_probability_times_billion(self.now, *self.last_updated,
+				self.decay_params.historical_no_updates_half_life, payment_amt_64th_bucket as u8)
+			.map(|success_prob_times_billion| success_prob_times_billion as f64 / (1024 * 1024 * 1024) as f64)
+	}
+
+	/// Returns the 64th of the channel's capacity the given `amount_msat` falls in, which is more
+	/// than 64 if the amount exceeds the capacity.
+	fn payment_amt_64th_bucket(&self, amount_msat: u64) -> u64 {
+		if amount_msat < u64::max_value() / 64 {
+			amount_msat * 64 / self.capacity_msat.saturating_add(1)
+		} else {
+			// Only use 128-bit arithmetic when multiplication will overflow to avoid 128-bit
+			// division. This branch should only be hit in fuzz testing since the amount would
+			// need to be over 2.88 million BTC in practice.
+			((amount_msat as u128) * 64 / (self.capacity_msat as u128).saturating_add(1))
+				.try_into().unwrap_or(65)
+		}
+	}
+
 	/// Computes the liquidity penalty from the penalty multipliers.
 	#[inline(always)]
 	fn combined_penalty_msat(amount_msat: u64, negative_log10_times_2048: u64,
@@ -1220,7 +1558,7 @@ impl<L: DerefMut<Target = u64>, BRT: DerefMut<Target = HistoricalBucketRangeTrac
 	}
 }
 
-impl<G: Deref<Target = NetworkGraph<L>>, L: Deref, T: Time> Score for ProbabilisticScorerUsingTime<G, L, T> where L::Target: Logger {
+impl<G: Deref<Target = NetworkGraph<L>>, L: Deref, T: Time> ScoreLookUp for ProbabilisticScorerUsingTime<G, L, T> where L::Target: Logger {
 	type ScoreParams = ProbabilisticScoringFeeParameters;
 	fn channel_penalty_msat(
 		&self, short_channel_id: u64, source: &NodeId, target: &NodeId, usage: ChannelUsage, score_params: &ProbabilisticScoringFeeParameters
@@ -1255,19 +1593,40 @@ impl<G: Deref<Target = NetworkGraph<L>>, L: Deref, T: Time> Score for Probabilis
 		let amount_msat = usage.amount_msat;
 		let capacity_msat = usage.effective_capacity.as_msat();
 		let inflight_htlc_msat = usage.inflight_htlc_msat;
-		self.channel_liquidities
-			.get(&short_channel_id)
-			.unwrap_or(&ChannelLiquidity::new())
+		self.channel_liquidity(short_channel_id)
+			.unwrap_or_else(ChannelLiquidity::new)
 			.as_directed(source, target, inflight_htlc_msat, capacity_msat, self.decay_params)
 			.penalty_msat(amount_msat, score_params)
 			.saturating_add(anti_probing_penalty_msat)
 			.saturating_add(base_penalty_msat)
 	}
 
+	fn channel_success_probability(
+		&self, short_channel_id: u64, source: &NodeId, target: &NodeId, usage: ChannelUsage, _score_params: &ProbabilisticScoringFeeParameters
+	) -> Option<f64> {
+		match usage.effective_capacity {
+			EffectiveCapacity::ExactLiquidity { liquidity_msat: amount_msat } |
+				EffectiveCapacity::HintMaxHTLC { amount_msat } =>
+			{
+				return Some(if usage.amount_msat > amount_msat { 0.0 } else { 1.0 });
+			},
+			_ => {},
+		}
+
+		let capacity_msat = usage.effective_capacity.as_msat();
+		Some(self.channel_liquidity(short_channel_id)
+			.unwrap_or_else(ChannelLiquidity::new)
+			.as_directed(source, target, usage.inflight_htlc_msat, capacity_msat, self.decay_params)
+			.success_probability(usage.amount_msat))
+	}
+}
+
+impl<G: Deref<Target = NetworkGraph<L>>, L: Deref, T: Time> ScoreUpdate for ProbabilisticScorerUsingTime<G, L, T> where L::Target: Logger {
 	fn payment_path_failed(&mut self, path: &Path, short_channel_id: u64) {
 		let amount_msat = path.final_value_msat();
 		log_trace!(self.logger, "Scoring path through to SCID {} as having failed at {} msat", short_channel_id, amount_msat);
 		let network_graph = self.network_graph.read_only();
+		let imported_liquidities = &self.imported_channel_liquidities;
 		for (hop_idx, hop) in path.hops.iter().enumerate() {
 			let target = NodeId::from_pubkey(&hop.pubkey);
 			let channel_directed_from_source = network_graph.channels()
@@ -1285,13 +1644,13 @@ impl<G: Deref<Target = NetworkGraph<L>>, L: Deref, T: Time> Score for Probabilis
 				if at_failed_channel {
 					self.channel_liquidities
 						.entry(hop.short_channel_id)
-						.or_insert_with(ChannelLiquidity::new)
+						.or_insert_with(|| ChannelLiquidity::from_imported(imported_liquidities.get(&hop.short_channel_id)))
 						.as_directed_mut(source, &target, 0, capacity_msat, self.decay_params)
 						.failed_at_channel(amount_msat, format_args!("SCID {}, towards {:?}", hop.short_channel_id, target), &self.logger);
 				} else {
 					self.channel_liquidities
 						.entry(hop.short_channel_id)
-						.or_insert_with(ChannelLiquidity::new)
+						.or_insert_with(|| ChannelLiquidity::from_imported(imported_liquidities.get(&hop.short_channel_id)))
 						.as_directed_mut(source, &target, 0, capacity_msat, self.decay_params)
 						.failed_downstream(amount_msat, format_args!("SCID {}, towards {:?}", hop.short_channel_id, target), &self.logger);
 				}
@@ -1308,6 +1667,7 @@ impl<G: Deref<Target = NetworkGraph<L>>, L: Deref, T: Time> Score for Probabilis
 		log_trace!(self.logger, "Scoring path through SCID {} as having succeeded at {} msat.",
 			path.hops.split_last().map(|(hop, _)| hop.short_channel_id).unwrap_or(0), amount_msat);
 		let network_graph = self.network_graph.read_only();
+		let imported_liquidities = &self.imported_channel_liquidities;
 		for hop in &path.hops {
 			let target = NodeId::from_pubkey(&hop.pubkey);
 			let channel_directed_from_source = network_graph.channels()
@@ -1319,7 +1679,7 @@ impl<G: Deref<Target = NetworkGraph<L>>, L: Deref, T: Time> Score for Probabilis
 				let capacity_msat = channel.effective_capacity().as_msat();
 				self.channel_liquidities
 					.entry(hop.short_channel_id)
-					.or_insert_with(ChannelLiquidity::new)
+					.or_insert_with(|| ChannelLiquidity::from_imported(imported_liquidities.get(&hop.short_channel_id)))
 					.as_directed_mut(source, &target, 0, capacity_msat, self.decay_params)
 					.successful(amount_msat, format_args!("SCID {}, towards {:?}", hop.short_channel_id, target), &self.logger);
 			} else {
@@ -1653,6 +2013,7 @@ impl<G: Deref<Target = NetworkGraph<L>>, L: Deref, T: Time> Writeable for Probab
 	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
 		write_tlv_fields!(w, {
 			(0, self.channel_liquidities, required),
+			(1, self.imported_channel_liquidities, required),
 		});
 		Ok(())
 	}
@@ -1666,14 +2027,17 @@ ReadableArgs<(ProbabilisticScoringDecayParameters, G, L)> for ProbabilisticScore
 	) -> Result<Self, DecodeError> {
 		let (decay_params, network_graph, logger) = args;
 		let mut channel_liquidities = HashMap::new();
+		let mut imported_channel_liquidities = None;
 		read_tlv_fields!(r, {
 			(0, channel_liquidities, required),
+			(1, imported_channel_liquidities, option),
 		});
 		Ok(Self {
 			decay_params,
 			network_graph,
 			logger,
 			channel_liquidities,
+			imported_channel_liquidities: imported_channel_liquidities.unwrap_or_else(HashMap::new),
 		})
 	}
 }
@@ -1732,7 +2096,7 @@ impl<T: Time> Readable for ChannelLiquidity<T> {
 
 #[cfg(test)]
 mod tests {
-	use super::{ChannelLiquidity, HistoricalBucketRangeTracker, ProbabilisticScoringFeeParameters, ProbabilisticScoringDecayParameters, ProbabilisticScorerUsingTime};
+	use super::{ChannelLiquidity, FixedPenaltyScorer, HistoricalBucketRangeTracker, ProbabilisticScoringFeeParameters, ProbabilisticScoringDecayParameters, ProbabilisticScorerUsingTime};
 	use crate::blinded_path::{BlindedHop, BlindedPath};
 	use crate::util::config::UserConfig;
 	use crate::util::time::Time;
@@ -1742,7 +2106,7 @@ mod tests {
 	use crate::ln::msgs::{ChannelAnnouncement, ChannelUpdate, UnsignedChannelAnnouncement, UnsignedChannelUpdate};
 	use crate::routing::gossip::{EffectiveCapacity, NetworkGraph, NodeId};
 	use crate::routing::router::{BlindedTail, Path, RouteHop};
-	use crate::routing::scoring::{ChannelUsage, Score};
+	use crate::routing::scoring::{ChannelUsage, ScoreLookUp, ScoreUpdate};
 	use crate::util::ser::{ReadableArgs, Writeable};
 	use crate::util::test_utils::{self, TestLogger};
 
@@ -2142,6 +2506,82 @@ mod tests {
 		assert_eq!(scorer.channel_penalty_msat(42, &source, &target, usage, &params), 902);
 	}
 
+	#[test]
+	fn estimates_success_probability_from_liquidity_bounds() {
+		let logger = TestLogger::new();
+		let last_updated = SinceEpoch::now();
+		let network_graph = network_graph(&logger);
+		let params = ProbabilisticScoringFeeParameters::zero_penalty();
+		let decay_params = ProbabilisticScoringDecayParameters::zero_penalty();
+		let scorer = ProbabilisticScorer::new(decay_params, &network_graph, &logger)
+			.with_channel(42,
+				ChannelLiquidity {
+					min_liquidity_offset_msat: 40, max_liquidity_offset_msat: 40, last_updated,
+					min_liquidity_offset_history: HistoricalBucketRangeTracker::new(),
+					max_liquidity_offset_history: HistoricalBucketRangeTracker::new(),
+				});
+		let source = source_node_id();
+		let target = target_node_id();
+
+		let usage = ChannelUsage {
+			amount_msat: 40,
+			inflight_htlc_msat: 0,
+			effective_capacity: EffectiveCapacity::Total { capacity_msat: 100, htlc_maximum_msat: 1_000 },
+		};
+		assert_eq!(scorer.channel_success_probability(42, &source, &target, usage, &params), Some(1.0));
+		let usage = ChannelUsage { amount_msat: 50, ..usage };
+		assert_eq!(scorer.channel_success_probability(42, &source, &target, usage, &params), Some(11.0 / 21.0));
+		let usage = ChannelUsage { amount_msat: 60, ..usage };
+		assert_eq!(scorer.channel_success_probability(42, &source, &target, usage, &params), Some(0.0));
+
+		// Channels whose liquidity we know exactly either succeed or fail.
+		let usage = ChannelUsage {
+			amount_msat: 1_000,
+			inflight_htlc_msat: 0,
+			effective_capacity: EffectiveCapacity::ExactLiquidity { liquidity_msat: 1_000 },
+		};
+		assert_eq!(scorer.channel_success_probability(43, &source, &target, usage, &params), Some(1.0));
+		let usage = ChannelUsage { amount_msat: 1_001, ..usage };
+		assert_eq!(scorer.channel_success_probability(43, &source, &target, usage, &params), Some(0.0));
+
+		assert_eq!(FixedPenaltyScorer::with_penalty(0).channel_success_probability(42, &source, &target, usage, &()), None);
+	}
+
+	#[test]
+	fn exposes_liquidity_diagnostics() {
+		let logger = TestLogger::new();
+		let network_graph = network_graph(&logger);
+		let decay_params = ProbabilisticScoringDecayParameters::default();
+		let mut scorer = ProbabilisticScorer::new(decay_params, &network_graph, &logger);
+		let source = source_node_id();
+		let target = target_node_id();
+		assert!(scorer.channel_liquidity_diagnostics(250).is_empty());
+		assert_eq!(scorer.directed_channel_liquidity_diagnostics(42, &target, 250), None);
+
+		scorer.payment_path_failed(&payment_path_for_amount(500), 42);
+		SinceEpoch::advance(Duration::from_secs(10));
+
+		let diagnostics = scorer.directed_channel_liquidity_diagnostics(42, &target, 250).unwrap();
+		assert_eq!(diagnostics.short_channel_id, 42);
+		assert_eq!(diagnostics.source, source);
+		assert_eq!(diagnostics.target, target);
+		assert_eq!(diagnostics.capacity_msat, 1_000);
+		assert_eq!((diagnostics.min_liquidity_msat, diagnostics.max_liquidity_msat),
+			scorer.estimated_channel_liquidity_range(42, &target).unwrap());
+		assert_eq!(diagnostics.last_updated_elapsed, Duration::from_secs(10));
+		assert_eq!((diagnostics.historical_min_liquidity_buckets, diagnostics.historical_max_liquidity_buckets),
+			scorer.historical_estimated_channel_liquidity_probabilities(42, &target).unwrap());
+		assert!(diagnostics.success_probability > 0.0 && diagnostics.success_probability < 1.0);
+		assert!(diagnostics.historical_success_probability.is_some());
+
+		// We only have data for channel 42, which we get in both directions.
+		let all_diagnostics = scorer.channel_liquidity_diagnostics(250);
+		assert_eq!(all_diagnostics.len(), 2);
+		assert!(all_diagnostics.contains(&diagnostics));
+		assert!(all_diagnostics.iter().any(|diag| diag.source == target && diag.target == source));
+		assert_eq!(scorer.directed_channel_liquidity_diagnostics(43, &recipient_node_id(), 250), None);
+	}
+
 	#[test]
 	fn constant_penalty_outside_liquidity_bounds() {
 		let logger = TestLogger::new();
@@ -2974,4 +3414,137 @@ mod tests {
 		assert_eq!(liquidity.min_liquidity_msat(), 256);
 		assert_eq!(liquidity.max_liquidity_msat(), 768);
 	}
+
+	#[test]
+	#[cfg(not(c_bindings))]
+	fn scores_under_read_write_lock() {
+		use crate::routing::scoring::LockableScore;
+		use crate::sync::RwLock;
+
+		let logger = TestLogger::new();
+		let network_graph = network_graph(&logger);
+		let params = ProbabilisticScoringFeeParameters {
+			liquidity_penalty_multiplier_msat: 1_000,
+			considered_impossible_penalty_msat: u64::max_value(),
+			..ProbabilisticScoringFeeParameters::zero_penalty()
+		};
+		let scorer = RwLock::new(ProbabilisticScorer::new(ProbabilisticScoringDecayParameters::default(), &network_graph, &logger));
+		let source = source_node_id();
+		let target = target_node_id();
+		let usage = ChannelUsage {
+			amount_msat: 500,
+			inflight_htlc_msat: 0,
+			effective_capacity: EffectiveCapacity::Total { capacity_msat: 1_000, htlc_maximum_msat: 1_000 },
+		};
+
+		// Lookups only require a read lock while updates take the write lock.
+		assert_eq!(scorer.read_lock().channel_penalty_msat(42, &source, &target, usage, &params), 301);
+		scorer.write_lock().payment_path_failed(&payment_path_for_amount(500), 42);
+		assert_eq!(scorer.read_lock().channel_penalty_msat(42, &source, &target, usage, &params), u64::max_value());
+	}
+
+	#[test]
+	fn imports_snapshot_without_overriding_local_data() {
+		let logger = TestLogger::new();
+		let network_graph = network_graph(&logger);
+		let decay_params = ProbabilisticScoringDecayParameters::default();
+
+		// The external scorer failed at the second hop, learning about both channels.
+		let mut external_scorer = ProbabilisticScorer::new(decay_params, &network_graph, &logger);
+		external_scorer.payment_path_failed(&payment_path_for_amount(500), 43);
+		let external_range_42 = external_scorer.estimated_channel_liquidity_range(42, &target_node_id());
+		let external_range_43 = external_scorer.estimated_channel_liquidity_range(43, &recipient_node_id());
+		assert!(external_range_42.is_some());
+		assert!(external_range_43.is_some());
+
+		// Locally we only know about the first hop.
+		let mut local_scorer = ProbabilisticScorer::new(decay_params, &network_graph, &logger);
+		local_scorer.payment_path_failed(&payment_path_for_amount(300), 42);
+		let local_range_42 = local_scorer.estimated_channel_liquidity_range(42, &target_node_id());
+		assert_ne!(local_range_42, external_range_42);
+		assert_eq!(local_scorer.estimated_channel_liquidity_range(43, &recipient_node_id()), None);
+
+		let (local_min_buckets_42, local_max_buckets_42) = local_scorer
+			.historical_estimated_channel_liquidity_probabilities(42, &target_node_id()).unwrap();
+		let (external_min_buckets_42, external_max_buckets_42) = external_scorer
+			.historical_estimated_channel_liquidity_probabilities(42, &target_node_id()).unwrap();
+		assert_ne!(local_min_buckets_42, external_min_buckets_42);
+		let external_buckets_43 = external_scorer
+			.historical_estimated_channel_liquidity_probabilities(43, &recipient_node_id());
+		assert!(external_buckets_43.is_some());
+
+		let snapshot = external_scorer.export_snapshot();
+		local_scorer.import_snapshot(&mut &snapshot[..]).unwrap();
+
+		// Our own observations take precedence while the gaps are filled in by the snapshot.
+		assert_eq!(local_scorer.estimated_channel_liquidity_range(42, &target_node_id()), local_range_42);
+		assert_eq!(local_scorer.estimated_channel_liquidity_range(43, &recipient_node_id()), external_range_43);
+
+		// The historical data is merged, with imported datapoints counting half as much as ours.
+		let merge = |local: [u16; 8], external: [u16; 8]| {
+			let mut merged = [0; 8];
+			for i in 0..8 { merged[i] = local[i] + external[i] / 2; }
+			merged
+		};
+		assert_eq!(local_scorer.historical_estimated_channel_liquidity_probabilities(42, &target_node_id()),
+			Some((merge(local_min_buckets_42, external_min_buckets_42),
+				merge(local_max_buckets_42, external_max_buckets_42))));
+		assert_eq!(local_scorer.historical_estimated_channel_liquidity_probabilities(43, &recipient_node_id()),
+			external_buckets_43);
+	}
+
+	#[test]
+	fn reimporting_snapshot_replaces_previous_import() {
+		let logger = TestLogger::new();
+		let network_graph = network_graph(&logger);
+		let decay_params = ProbabilisticScoringDecayParameters::default();
+
+		let mut external_scorer = ProbabilisticScorer::new(decay_params, &network_graph, &logger);
+		external_scorer.payment_path_failed(&payment_path_for_amount(500), 43);
+		let snapshot = external_scorer.export_snapshot();
+
+		let mut local_scorer = ProbabilisticScorer::new(decay_params, &network_graph, &logger);
+		local_scorer.payment_path_failed(&payment_path_for_amount(300), 42);
+		let local_range_42 = local_scorer.estimated_channel_liquidity_range(42, &target_node_id());
+		let (local_min_buckets_42, local_max_buckets_42) = local_scorer
+			.historical_estimated_channel_liquidity_probabilities(42, &target_node_id()).unwrap();
+
+		local_scorer.import_snapshot(&mut &snapshot[..]).unwrap();
+		let merged_buckets_42 = local_scorer.historical_estimated_channel_liquidity_probabilities(42, &target_node_id());
+		assert_ne!(merged_buckets_42, Some((local_min_buckets_42, local_max_buckets_42)));
+
+		// Importing the same snapshot again doesn't count its datapoints twice.
+		local_scorer.import_snapshot(&mut &snapshot[..]).unwrap();
+		assert_eq!(local_scorer.estimated_channel_liquidity_range(42, &target_node_id()), local_range_42);
+		assert_eq!(local_scorer.historical_estimated_channel_liquidity_probabilities(42, &target_node_id()),
+			merged_buckets_42);
+
+		// An empty snapshot drops all imported data, leaving only our own.
+		let empty_snapshot = ProbabilisticScorer::new(decay_params, &network_graph, &logger).export_snapshot();
+		local_scorer.import_snapshot(&mut &empty_snapshot[..]).unwrap();
+		assert_eq!(local_scorer.estimated_channel_liquidity_range(42, &target_node_id()), local_range_42);
+		assert_eq!(local_scorer.historical_estimated_channel_liquidity_probabilities(42, &target_node_id()),
+			Some((local_min_buckets_42, local_max_buckets_42)));
+
+		// Imported data is persisted along with our own, but not included in our snapshots.
+		local_scorer.import_snapshot(&mut &snapshot[..]).unwrap();
+		let mut serialized_scorer = Vec::new();
+		local_scorer.write(&mut serialized_scorer).unwrap();
+		let deserialized_scorer = <ProbabilisticScorer>::read(
+			&mut io::Cursor::new(&serialized_scorer), (decay_params, &network_graph, &logger)).unwrap();
+		assert_eq!(deserialized_scorer.historical_estimated_channel_liquidity_probabilities(42, &target_node_id()),
+			merged_buckets_42);
+		let mut reexported_scorer = ProbabilisticScorer::new(decay_params, &network_graph, &logger);
+		reexported_scorer.import_snapshot(&mut &local_scorer.export_snapshot()[..]).unwrap();
+		assert_eq!(reexported_scorer.historical_estimated_channel_liquidity_probabilities(42, &target_node_id()),
+			Some((local_min_buckets_42, local_max_buckets_42)));
+
+		// Once we learn about a channel we only had imported data for, our bounds win over those of
+		// later imports.
+		local_scorer.payment_path_successful(&payment_path_for_amount(100));
+		let local_range_43 = local_scorer.estimated_channel_liquidity_range(43, &recipient_node_id());
+		assert_ne!(local_range_43, external_scorer.estimated_channel_liquidity_range(43, &recipient_node_id()));
+		local_scorer.import_snapshot(&mut &snapshot[..]).unwrap();
+		assert_eq!(local_scorer.estimated_channel_liquidity_range(43, &recipient_node_id()), local_range_43);
+	}
 }
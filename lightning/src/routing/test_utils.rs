@@ -10,7 +10,7 @@
 use crate::routing::gossip::{NetworkGraph, NodeAlias, P2PGossipSync};
 use crate::ln::features::{ChannelFeatures, NodeFeatures};
 use crate::ln::msgs::{UnsignedChannelAnnouncement, ChannelAnnouncement, RoutingMessageHandler,
-	NodeAnnouncement, UnsignedNodeAnnouncement, ChannelUpdate, UnsignedChannelUpdate, MAX_VALUE_MSAT};
+	NetAddress, NodeAnnouncement, UnsignedNodeAnnouncement, ChannelUpdate, UnsignedChannelUpdate, MAX_VALUE_MSAT};
 use crate::util::test_utils;
 use crate::util::ser::Writeable;
 
@@ -65,6 +65,14 @@ pub(super) fn add_channel(
 pub(super) fn add_or_update_node(
 	gossip_sync: &P2PGossipSync<Arc<NetworkGraph<Arc<test_utils::TestLogger>>>, Arc<test_utils::TestChainSource>, Arc<test_utils::TestLogger>>,
 	secp_ctx: &Secp256k1<All>, node_privkey: &SecretKey, features: NodeFeatures, timestamp: u32
+) {
+	add_or_update_node_with_addresses(gossip_sync, secp_ctx, node_privkey, features, timestamp, Vec::new());
+}
+
+pub(super) fn add_or_update_node_with_addresses(
+	gossip_sync: &P2PGossipSync<Arc<NetworkGraph<Arc<test_utils::TestLogger>>>, Arc<test_utils::TestChainSource>, Arc<test_utils::TestLogger>>,
+	secp_ctx: &Secp256k1<All>, node_privkey: &SecretKey, features: NodeFeatures, timestamp: u32,
+	addresses: Vec<NetAddress>
 ) {
 	let node_id = NodeId::from_pubkey(&PublicKey::from_secret_key(&secp_ctx, node_privkey));
 	let unsigned_announcement = UnsignedNodeAnnouncement {
@@ -73,7 +81,7 @@ pub(super) fn add_or_update_node(
 		node_id,
 		rgb: [0; 3],
 		alias: NodeAlias([0; 32]),
-		addresses: Vec::new(),
+		addresses,
 		excess_address_data: Vec::new(),
 		excess_data: Vec::new(),
 	};
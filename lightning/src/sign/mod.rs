@@ -401,10 +401,21 @@ pub trait ChannelSigner {
 /// policies in order to be secure. Please refer to the [VLS Policy
 /// Controls](https://gitlab.com/lightning-signer/validating-lightning-signer/-/blob/main/docs/policy-controls.md)
 /// for an example of such policies.
+///
+/// Signers which sit behind a network round trip (e.g. a remote HSM) may return `Err(())` from
+/// [`Self::sign_counterparty_commitment`] and [`Self::sign_closing_transaction`] to indicate that
+/// a signature is not yet available. The channel will hold the corresponding message until
+/// [`ChannelManager::signer_unblocked`] is called, at which point the signature will be requested
+/// again and the message sent to our counterparty.
+///
+/// [`ChannelManager::signer_unblocked`]: crate::ln::channelmanager::ChannelManager::signer_unblocked
 pub trait EcdsaChannelSigner: ChannelSigner {
 	/// Create a signature for a counterparty's commitment transaction and associated HTLC transactions.
 	///
-	/// Note that if signing fails or is rejected, the channel will be force-closed.
+	/// An `Err` can be returned to signal that the signer is unavailable or not yet able to sign.
+	/// In that case, the `commitment_signed` (or `funding_created`/`funding_signed`) message will
+	/// be held until [`ChannelManager::signer_unblocked`] is called, after which this method will
+	/// be called again.
 	///
 	/// Policy checks should be implemented in this function, including checking the amount
 	/// sent to us and checking the HTLCs.
@@ -415,6 +426,8 @@ pub trait EcdsaChannelSigner: ChannelSigner {
 	///
 	/// Note that all the relevant preimages will be provided, but there may also be additional
 	/// irrelevant or duplicate preimages.
+	///
+	/// [`ChannelManager::signer_unblocked`]: crate::ln::channelmanager::ChannelManager::signer_unblocked
 	//
 	// TODO: Document the things someone using this interface should enforce before signing.
 	fn sign_counterparty_commitment(&self, commitment_tx: &CommitmentTransaction,
@@ -522,6 +535,12 @@ pub trait EcdsaChannelSigner: ChannelSigner {
 	///
 	/// Note that, due to rounding, there may be one "missing" satoshi, and either party may have
 	/// chosen to forgo their output as dust.
+	///
+	/// An `Err` can be returned to signal that the signer is unavailable or not yet able to sign.
+	/// In that case, the `closing_signed` message will be held and this method called again once
+	/// [`ChannelManager::signer_unblocked`] is called.
+	///
+	/// [`ChannelManager::signer_unblocked`]: crate::ln::channelmanager::ChannelManager::signer_unblocked
 	fn sign_closing_transaction(&self, closing_tx: &ClosingTransaction,
 		secp_ctx: &Secp256k1<secp256k1::All>) -> Result<Signature, ()>;
 	/// Computes the signature for a commitment transaction's anchor output used as an
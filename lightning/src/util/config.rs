@@ -764,6 +764,29 @@ pub struct UserConfig {
 	///
 	/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
 	pub accept_mpp_keysend: bool,
+	/// If this is set to true, we'll act as a trampoline node for payments which ask us to, finding
+	/// a route to the next trampoline hop or recipient with our [`Router`] once all parts of the
+	/// payment have arrived.
+	///
+	/// The outbound leg of each trampoline forward is retried a few times if it fails, and its
+	/// inbound HTLCs are claimed or failed back automatically once it resolves. Thus, no
+	/// [`Event::PaymentSent`] or [`Event::PaymentFailed`] is generated for it, though
+	/// [`Event::PaymentPathSuccessful`] and [`Event::PaymentPathFailed`] are, so that the scorer
+	/// can learn from its paths.
+	///
+	/// Setting this to true will break backwards compatibility upon downgrading to an LDK version
+	/// < 0.0.117 while a trampoline forward is pending, as prior versions will fail to read the
+	/// [`ChannelManager`].
+	///
+	/// Default value: false.
+	///
+	/// [`Router`]: crate::routing::router::Router
+	/// [`Event::PaymentSent`]: crate::events::Event::PaymentSent
+	/// [`Event::PaymentFailed`]: crate::events::Event::PaymentFailed
+	/// [`Event::PaymentPathSuccessful`]: crate::events::Event::PaymentPathSuccessful
+	/// [`Event::PaymentPathFailed`]: crate::events::Event::PaymentPathFailed
+	/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+	pub accept_trampoline_forwards: bool,
 }
 
 impl Default for UserConfig {
@@ -777,6 +800,7 @@ impl Default for UserConfig {
 			manually_accept_inbound_channels: false,
 			accept_intercept_htlcs: false,
 			accept_mpp_keysend: false,
+			accept_trampoline_forwards: false,
 		}
 	}
 }
@@ -56,6 +56,9 @@ pub struct EnforcingSigner {
 	/// Channel state used for policy enforcement
 	pub state: Arc<Mutex<EnforcementState>>,
 	pub disable_revocation_policy_check: bool,
+	/// When `false`, signing of counterparty commitment and closing transactions fails as if the
+	/// signer were (temporarily) unavailable. Shared across clones of this signer.
+	pub available: Arc<Mutex<bool>>,
 }
 
 impl PartialEq for EnforcingSigner {
@@ -71,7 +74,8 @@ impl EnforcingSigner {
 		Self {
 			inner,
 			state,
-			disable_revocation_policy_check: false
+			disable_revocation_policy_check: false,
+			available: Arc::new(Mutex::new(true)),
 		}
 	}
 
@@ -84,7 +88,8 @@ impl EnforcingSigner {
 		Self {
 			inner,
 			state,
-			disable_revocation_policy_check
+			disable_revocation_policy_check,
+			available: Arc::new(Mutex::new(true)),
 		}
 	}
 
@@ -94,6 +99,15 @@ impl EnforcingSigner {
 	pub fn get_enforcement_state(&self) -> MutexGuard<EnforcementState> {
 		self.state.lock().unwrap()
 	}
+
+	/// Marks the signer's availability.
+	///
+	/// When `false`, methods are forbidden from returning a signature, simulating a signer which
+	/// is waiting on some external resource (e.g. a remote HSM) to respond.
+	#[cfg(test)]
+	pub fn set_available(&self, available: bool) {
+		*self.available.lock().unwrap() = available;
+	}
 }
 
 impl ChannelSigner for EnforcingSigner {
@@ -132,6 +146,10 @@ impl EcdsaChannelSigner for EnforcingSigner {
 	fn sign_counterparty_commitment(&self, commitment_tx: &CommitmentTransaction, preimages: Vec<PaymentPreimage>, secp_ctx: &Secp256k1<secp256k1::All>) -> Result<(Signature, Vec<Signature>), ()> {
 		self.verify_counterparty_commitment_tx(commitment_tx, secp_ctx);
 
+		if !*self.available.lock().unwrap() {
+			return Err(());
+		}
+
 		{
 			let mut state = self.state.lock().unwrap();
 			let actual_commitment_number = commitment_tx.commitment_number();
@@ -221,6 +239,9 @@ impl EcdsaChannelSigner for EnforcingSigner {
 	fn sign_closing_transaction(&self, closing_tx: &ClosingTransaction, secp_ctx: &Secp256k1<secp256k1::All>) -> Result<Signature, ()> {
 		closing_tx.verify(self.inner.funding_outpoint().into_bitcoin_outpoint())
 			.expect("derived different closing transaction");
+		if !*self.available.lock().unwrap() {
+			return Err(());
+		}
 		Ok(self.inner.sign_closing_transaction(closing_tx, secp_ctx).unwrap())
 	}
 
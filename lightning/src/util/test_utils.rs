@@ -20,6 +20,7 @@ use crate::sign;
 use crate::events;
 use crate::events::bump_transaction::{WalletSource, Utxo};
 use crate::ln::channelmanager;
+use crate::ln::chan_utils::{CommitmentTransaction, HTLCOutputInCommitment};
 use crate::ln::features::{ChannelFeatures, InitFeatures, NodeFeatures};
 use crate::ln::{msgs, wire};
 use crate::ln::msgs::LightningError;
@@ -27,10 +28,11 @@ use crate::ln::script::ShutdownScript;
 use crate::routing::gossip::{EffectiveCapacity, NetworkGraph, NodeId};
 use crate::routing::utxo::{UtxoLookup, UtxoLookupError, UtxoResult};
 use crate::routing::router::{find_route, InFlightHtlcs, Path, Route, RouteParameters, Router, ScorerAccountingForInFlightHtlcs};
-use crate::routing::scoring::{ChannelUsage, Score};
+use crate::routing::scoring::{ChannelUsage, ScoreUpdate, ScoreLookUp};
 use crate::util::config::UserConfig;
 use crate::util::enforcing_trait_impls::{EnforcingSigner, EnforcementState};
 use crate::util::logger::{Logger, Level, Record};
+use crate::util::persist::KVStore;
 use crate::util::ser::{Readable, ReadableArgs, Writer, Writeable};
 
 use bitcoin::EcdsaSighashType;
@@ -54,9 +56,9 @@ use regex;
 use crate::io;
 use crate::prelude::*;
 use core::cell::RefCell;
-use core::ops::DerefMut;
+use core::ops::Deref;
 use core::time::Duration;
-use crate::sync::{Mutex, Arc};
+use crate::sync::{Mutex, Arc, RwLock};
 use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use core::mem;
 use bitcoin::bech32::u5;
@@ -95,11 +97,11 @@ impl chaininterface::FeeEstimator for TestFeeEstimator {
 pub struct TestRouter<'a> {
 	pub network_graph: Arc<NetworkGraph<&'a TestLogger>>,
 	pub next_routes: Mutex<VecDeque<(RouteParameters, Result<Route, LightningError>)>>,
-	pub scorer: &'a Mutex<TestScorer>,
+	pub scorer: &'a RwLock<TestScorer>,
 }
 
 impl<'a> TestRouter<'a> {
-	pub fn new(network_graph: Arc<NetworkGraph<&'a TestLogger>>, scorer: &'a Mutex<TestScorer>) -> Self {
+	pub fn new(network_graph: Arc<NetworkGraph<&'a TestLogger>>, scorer: &'a RwLock<TestScorer>) -> Self {
 		Self { network_graph, next_routes: Mutex::new(VecDeque::new()), scorer }
 	}
 
@@ -117,8 +119,8 @@ impl<'a> Router for TestRouter<'a> {
 		if let Some((find_route_query, find_route_res)) = self.next_routes.lock().unwrap().pop_front() {
 			assert_eq!(find_route_query, *params);
 			if let Ok(ref route) = find_route_res {
-				let mut binding = self.scorer.lock().unwrap();
-				let scorer = ScorerAccountingForInFlightHtlcs::new(binding.deref_mut(), &inflight_htlcs);
+				let scorer = self.scorer.read().unwrap();
+				let scorer = ScorerAccountingForInFlightHtlcs::new(scorer.deref(), &inflight_htlcs);
 				for path in &route.paths {
 					let mut aggregate_msat = 0u64;
 					for (idx, hop) in path.hops.iter().rev().enumerate() {
@@ -145,7 +147,7 @@ impl<'a> Router for TestRouter<'a> {
 		let logger = TestLogger::new();
 		find_route(
 			payer, params, &self.network_graph, first_hops, &logger,
-			&ScorerAccountingForInFlightHtlcs::new(self.scorer.lock().unwrap().deref_mut(), &inflight_htlcs), &(),
+			&ScorerAccountingForInFlightHtlcs::new(self.scorer.read().unwrap().deref(), &inflight_htlcs), &(),
 			&[42; 32]
 		)
 	}
@@ -316,6 +318,219 @@ impl<Signer: sign::WriteableEcdsaChannelSigner> chainmonitor::Persist<Signer> fo
 	}
 }
 
+struct JusticeTxData {
+	/// The unsigned justice transactions for a counterparty commitment transaction, along with the
+	/// value of the output each one spends and the HTLC it claims (or `None` for `to_local`).
+	justice_txs: Vec<(Transaction, u64, Option<HTLCOutputInCommitment>)>,
+	commitment_txid: Txid,
+	commitment_number: u64,
+}
+
+/// A [`chainmonitor::Persist`] which, like a watchtower client, builds justice transactions for
+/// each counterparty commitment transaction it learns about and signs them once revoked.
+pub struct WatchtowerPersister {
+	persister: TestPersister,
+	/// Counterparty commitment transactions we have built justice transactions for but which have
+	/// not yet been revoked, and thus cannot be signed yet.
+	unsigned_justice_tx_data: Mutex<HashMap<OutPoint, VecDeque<JusticeTxData>>>,
+	/// The signed justice transactions, by the txid of the revoked commitment they spend, which
+	/// would be handed to a watchtower.
+	watchtower_state: Mutex<HashMap<OutPoint, HashMap<Txid, Vec<Transaction>>>>,
+	destination_script: Script,
+}
+
+impl WatchtowerPersister {
+	/// The feerate used for all justice transactions.
+	const JUSTICE_TX_FEERATE_PER_KW: u64 = 1000;
+
+	pub fn new(destination_script: Script) -> Self {
+		WatchtowerPersister {
+			persister: TestPersister::new(),
+			unsigned_justice_tx_data: Mutex::new(HashMap::new()),
+			watchtower_state: Mutex::new(HashMap::new()),
+			destination_script,
+		}
+	}
+
+	/// Gets the signed justice transactions spending the given revoked commitment transaction.
+	pub fn justice_txs(&self, funding_txo: OutPoint, commitment_txid: &Txid) -> Vec<Transaction> {
+		self.watchtower_state.lock().unwrap().get(&funding_txo)
+			.and_then(|txs| txs.get(commitment_txid).cloned())
+			.unwrap_or(Vec::new())
+	}
+
+	fn form_justice_data_from_commitment(&self, counterparty_commitment_tx: &CommitmentTransaction) -> JusticeTxData {
+		let trusted_tx = counterparty_commitment_tx.trust();
+		let mut justice_txs = Vec::new();
+		if let Ok(justice_tx) = trusted_tx.build_to_local_justice_tx(
+			Self::JUSTICE_TX_FEERATE_PER_KW, self.destination_script.clone()
+		) {
+			let value = trusted_tx.built_transaction().transaction.output[trusted_tx.revokeable_output_index().unwrap()].value;
+			justice_txs.push((justice_tx, value, None));
+		}
+		for htlc in trusted_tx.htlcs() {
+			if let Ok(justice_tx) = trusted_tx.build_htlc_justice_tx(
+				htlc, Self::JUSTICE_TX_FEERATE_PER_KW, self.destination_script.clone()
+			) {
+				justice_txs.push((justice_tx, htlc.amount_msat / 1000, Some(htlc.clone())));
+			}
+		}
+		JusticeTxData {
+			justice_txs,
+			commitment_txid: trusted_tx.txid(),
+			commitment_number: counterparty_commitment_tx.commitment_number(),
+		}
+	}
+}
+
+impl<Signer: sign::WriteableEcdsaChannelSigner> chainmonitor::Persist<Signer> for WatchtowerPersister {
+	fn persist_new_channel(&self, funding_txo: OutPoint,
+		data: &channelmonitor::ChannelMonitor<Signer>, id: MonitorUpdateId
+	) -> chain::ChannelMonitorUpdateStatus {
+		let res = self.persister.persist_new_channel(funding_txo, data, id);
+
+		assert!(self.unsigned_justice_tx_data.lock().unwrap()
+			.insert(funding_txo, VecDeque::new()).is_none());
+		assert!(self.watchtower_state.lock().unwrap()
+			.insert(funding_txo, HashMap::new()).is_none());
+
+		let initial_counterparty_commitment_tx = data.initial_counterparty_commitment_tx()
+			.expect("First and only call expects Some");
+		let justice_data = self.form_justice_data_from_commitment(&initial_counterparty_commitment_tx);
+		self.unsigned_justice_tx_data.lock().unwrap()
+			.get_mut(&funding_txo).unwrap()
+			.push_back(justice_data);
+		res
+	}
+
+	fn update_persisted_channel(
+		&self, funding_txo: OutPoint, update: Option<&channelmonitor::ChannelMonitorUpdate>,
+		data: &channelmonitor::ChannelMonitor<Signer>, update_id: MonitorUpdateId
+	) -> chain::ChannelMonitorUpdateStatus {
+		let res = self.persister.update_persisted_channel(funding_txo, update, data, update_id);
+
+		if let Some(update) = update {
+			let commitment_txs = data.counterparty_commitment_txs_from_update(update);
+			let justice_datas = commitment_txs.into_iter()
+				.map(|commitment_tx| self.form_justice_data_from_commitment(&commitment_tx));
+			let mut channels_justice_txs = self.unsigned_justice_tx_data.lock().unwrap();
+			let channel_state = channels_justice_txs.get_mut(&funding_txo).unwrap();
+			channel_state.extend(justice_datas);
+
+			// Sign the justice transactions of any commitment transactions which have since been
+			// revoked, in order, stopping at the first one our counterparty hasn't revoked yet.
+			while let Some(JusticeTxData { justice_txs, commitment_txid, commitment_number }) = channel_state.front() {
+				let mut signed_txs = Vec::with_capacity(justice_txs.len());
+				for (justice_tx, value, htlc) in justice_txs.iter() {
+					let signed = match htlc {
+						None => data.sign_to_local_justice_tx(justice_tx.clone(), 0, *value, *commitment_number),
+						Some(htlc) => data.sign_htlc_justice_tx(justice_tx.clone(), 0, *commitment_number, htlc),
+					};
+					match signed {
+						Ok(signed_tx) => signed_txs.push(signed_tx),
+						Err(_) => break,
+					}
+				}
+				if signed_txs.len() != justice_txs.len() { break; }
+				let commitment_txid = *commitment_txid;
+				channel_state.pop_front();
+				self.watchtower_state.lock().unwrap().get_mut(&funding_txo).unwrap()
+					.insert(commitment_txid, signed_txs);
+			}
+		}
+		res
+	}
+}
+
+pub struct TestStore {
+	persisted_bytes: Mutex<HashMap<String, HashMap<String, Vec<u8>>>>,
+	read_only: bool,
+}
+
+impl TestStore {
+	pub fn new(read_only: bool) -> Self {
+		let persisted_bytes = Mutex::new(HashMap::new());
+		Self { persisted_bytes, read_only }
+	}
+}
+
+impl KVStore for TestStore {
+	fn read(&self, namespace: &str, sub_namespace: &str, key: &str) -> io::Result<Vec<u8>> {
+		let persisted_lock = self.persisted_bytes.lock().unwrap();
+		let prefixed = if sub_namespace.is_empty() {
+			namespace.to_string()
+		} else {
+			format!("{}/{}", namespace, sub_namespace)
+		};
+
+		if let Some(outer_ref) = persisted_lock.get(&prefixed) {
+			if let Some(inner_ref) = outer_ref.get(key) {
+				let bytes = inner_ref.clone();
+				Ok(bytes)
+			} else {
+				Err(io::Error::new(io::ErrorKind::NotFound, "Key not found"))
+			}
+		} else {
+			Err(io::Error::new(io::ErrorKind::NotFound, "Namespace not found"))
+		}
+	}
+
+	fn write(&self, namespace: &str, sub_namespace: &str, key: &str, buf: &[u8]) -> io::Result<()> {
+		if self.read_only {
+			return Err(io::Error::new(
+				io::ErrorKind::PermissionDenied,
+				"Cannot modify read-only store",
+			));
+		}
+		let mut persisted_lock = self.persisted_bytes.lock().unwrap();
+
+		let prefixed = if sub_namespace.is_empty() {
+			namespace.to_string()
+		} else {
+			format!("{}/{}", namespace, sub_namespace)
+		};
+		let outer_e = persisted_lock.entry(prefixed).or_insert(HashMap::new());
+		outer_e.insert(key.to_string(), buf.to_vec());
+		Ok(())
+	}
+
+	fn remove(&self, namespace: &str, sub_namespace: &str, key: &str, _lazy: bool) -> io::Result<()> {
+		if self.read_only {
+			return Err(io::Error::new(
+				io::ErrorKind::PermissionDenied,
+				"Cannot modify read-only store",
+			));
+		}
+
+		let mut persisted_lock = self.persisted_bytes.lock().unwrap();
+
+		let prefixed = if sub_namespace.is_empty() {
+			namespace.to_string()
+		} else {
+			format!("{}/{}", namespace, sub_namespace)
+		};
+		if let Some(outer_ref) = persisted_lock.get_mut(&prefixed) {
+			outer_ref.remove(&key.to_string());
+		}
+
+		Ok(())
+	}
+
+	fn list(&self, namespace: &str, sub_namespace: &str) -> io::Result<Vec<String>> {
+		let mut persisted_lock = self.persisted_bytes.lock().unwrap();
+
+		let prefixed = if sub_namespace.is_empty() {
+			namespace.to_string()
+		} else {
+			format!("{}/{}", namespace, sub_namespace)
+		};
+		match persisted_lock.entry(prefixed) {
+			hash_map::Entry::Occupied(e) => Ok(e.get().keys().cloned().collect()),
+			hash_map::Entry::Vacant(_) => Ok(Vec::new()),
+		}
+	}
+}
+
 pub struct TestBroadcaster {
 	pub txn_broadcasted: Mutex<Vec<Transaction>>,
 	pub blocks: Arc<Mutex<Vec<(Block, u32)>>>,
@@ -1029,7 +1244,7 @@ impl crate::util::ser::Writeable for TestScorer {
 	fn write<W: crate::util::ser::Writer>(&self, _: &mut W) -> Result<(), crate::io::Error> { unreachable!(); }
 }
 
-impl Score for TestScorer {
+impl ScoreLookUp for TestScorer {
 	type ScoreParams = ();
 	fn channel_penalty_msat(
 		&self, short_channel_id: u64, _source: &NodeId, _target: &NodeId, usage: ChannelUsage, _score_params: &Self::ScoreParams
@@ -1045,7 +1260,9 @@ impl Score for TestScorer {
 		}
 		0
 	}
+}
 
+impl ScoreUpdate for TestScorer {
 	fn payment_path_failed(&mut self, _actual_path: &Path, _actual_short_channel_id: u64) {}
 
 	fn payment_path_successful(&mut self, _actual_path: &Path) {}